@@ -17,10 +17,14 @@ fn run_locator() {
     let algorithm = 1;
 
     let args = virust_locator::config::Args {
-        query: vec![query.to_string()],
-        reference: reference.to_string(),
-        type_query,
-        algorithm,
+        common: virust_locator::config::CommonArgs {
+            query: vec![query.to_string()],
+            reference: reference.to_string(),
+            type_query,
+            algorithm,
+            ..Default::default()
+        },
+        ..Default::default()
     };
 
     // Call the locator function with the parsed arguments
@@ -0,0 +1,15 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The protobuf bindings are only needed for the `grpc` feature. A vendored `protoc` is used so
+    // the crate builds without a system-wide protobuf installation.
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc not available");
+        // SAFETY: build scripts are single-threaded.
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_prost_build::configure()
+            .compile_protos(&["proto/locator.proto"], &["proto"])
+            .expect("failed to compile proto/locator.proto");
+    }
+}
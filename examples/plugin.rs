@@ -0,0 +1,97 @@
+//! Example plugin for viRust-locator: a CSV formatter and a filter keeping located results.
+//!
+//! Build it with `cargo build --release --example plugin` and load it with
+//! `--format-plugin target/release/examples/libplugin.so` and/or
+//! `--filter-plugin target/release/examples/libplugin.so` (`.dylib` on macOS, `plugin.dll` on
+//! Windows). See the `plugin` module for the plugin ABI.
+
+use serde_json::Value;
+
+fn output(bytes: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) {
+    let bytes = bytes.into_boxed_slice();
+    unsafe {
+        *out_len = bytes.len();
+        *out = Box::into_raw(bytes) as *mut u8;
+    }
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.contains([',', '"', '\n']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn virust_locator_plugin_abi() -> u32 {
+    1
+}
+
+/// Formats the results as comma-separated values.
+///
+/// # Safety
+/// `results` must be valid for `len` bytes, and `out` and `out_len` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn virust_locator_format(
+    results: *const u8,
+    len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    let input = unsafe { std::slice::from_raw_parts(results, len) };
+    let results: Vec<Value> = match serde_json::from_slice(input) {
+        Ok(results) => results,
+        Err(err) => {
+            output(err.to_string().into_bytes(), out, out_len);
+            return 1;
+        }
+    };
+
+    let columns = [
+        "id",
+        "found",
+        "ref_start",
+        "ref_end",
+        "percent_identity",
+        "indel",
+        "error",
+    ];
+    let mut csv = columns.join(",");
+    csv.push('\n');
+    for result in &results {
+        let row: Vec<String> = columns
+            .iter()
+            .map(|column| csv_field(&result[column]))
+            .collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    output(csv.into_bytes(), out, out_len);
+    0
+}
+
+/// Releases a buffer returned by `virust_locator_format`.
+///
+/// # Safety
+/// `ptr` and `len` must come from `virust_locator_format`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn virust_locator_free(ptr: *mut u8, len: usize) {
+    drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) });
+}
+
+/// Keeps the results that were located on the reference.
+///
+/// # Safety
+/// `result` must be valid for `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn virust_locator_filter(result: *const u8, len: usize) -> i32 {
+    let input = unsafe { std::slice::from_raw_parts(result, len) };
+    match serde_json::from_slice::<Value>(input) {
+        Ok(result) => result["found"].as_bool().unwrap_or(false) as i32,
+        Err(_) => -1,
+    }
+}
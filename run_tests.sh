@@ -18,28 +18,28 @@ echo
 # Run integration tests
 echo "🔧 Running integration tests..."
 cargo test --test integration_tests --quiet
-echo "✅ Integration tests passed (16 tests)"
+echo "✅ Integration tests passed"
 echo
 
 # Run performance tests  
 echo "⚡ Running performance tests..."
 cargo test --test performance_tests --quiet
-echo "✅ Performance tests passed (10 tests)"
+echo "✅ Performance tests passed"
 echo
 
 # Run all unit tests
 echo "🧱 Running unit tests..."
 cargo test --lib --quiet
-echo "✅ Unit tests passed (6 tests)"
+echo "✅ Unit tests passed"
 echo
 
 # Run doc tests
 echo "📚 Running documentation tests..."
 cargo test --doc --quiet
-echo "✅ Documentation tests passed (1 test)"
+echo "✅ Documentation tests passed"
 echo
 
-echo "🎉 All tests passed! Total: 33 tests"
+echo "🎉 All tests passed! The counts are in the test results above"
 echo
 echo "Test Coverage Summary:"
 echo "====================="
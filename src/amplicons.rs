@@ -0,0 +1,277 @@
+//! Amplicon schemes: the amplicons of amplicon-based sequencing runs located queries correspond
+//! to, for the QC of the runs.
+//!
+//! With `--amplicon-scheme`, the amplicons overlapped by a location are attached to it as
+//! `AmpliconCoverage`s (see `Locator::amplicons`), with the fraction of each amplicon the location
+//! covers, best covered first: a read or consensus of one amplicon covers it entirely, and a
+//! fraction of its neighbours through their overlaps.
+//!
+//! The scheme is read by `read_scheme`, from either format:
+//!
+//! - BED (a `.bed` or `.bed.gz` file): 0-based, half-open intervals on the reference, the first
+//!   column (the name of the reference) ignored. A primer scheme, whose names all end in `_LEFT`
+//!   or `_RIGHT` (optionally followed by `_alt` and a number), as ARTIC schemes are, has one
+//!   amplicon per name prefix, from the start of its left primers to the end of its right primers;
+//!   otherwise every line is an amplicon, named by its fourth column or its line.
+//! - Tab-separated values: the name of the amplicon and its first and last positions, 1-based and
+//!   inclusive, with an optional header line.
+//!
+//! Lines starting with `#`, and the `track` and `browser` lines of BED files, are skipped.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::amplicons;
+//!
+//! let bed = "K03455\t780\t810\tHIV_1_LEFT\nK03455\t1180\t1210\tHIV_1_RIGHT\n";
+//! let scheme = amplicons::read_scheme(bed.as_bytes(), true).unwrap();
+//! assert_eq!(scheme[0].name, "HIV_1");
+//! assert_eq!((scheme[0].start.get(), scheme[0].end.get()), (781, 1210));
+//! ```
+
+use crate::BoxError;
+use crate::input;
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::sequence_locator::Region;
+use crate::storage;
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::{BufRead, Read};
+use std::path::Path;
+
+/// The suffixes of the primers of an amplicon in a BED primer scheme.
+const PRIMER_SIDES: [&str; 2] = ["_LEFT", "_RIGHT"];
+
+/// The coverage of an amplicon by a location.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmpliconCoverage {
+    /// The name of the amplicon.
+    pub amplicon: String,
+    /// First position of the amplicon on the reference.
+    pub start: RefPosition,
+    /// Last position of the amplicon on the reference (inclusive).
+    pub end: RefPosition,
+    /// The fraction of the positions of the amplicon covered by the location, from 0 to 1.
+    pub fraction: f64,
+}
+
+impl Display for AmpliconCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}-{} ({:.1}%)",
+            self.amplicon,
+            self.start,
+            self.end,
+            self.fraction * 100.0
+        )
+    }
+}
+
+/// Reads the amplicon scheme at `path`, in BED if its name ends in `.bed` or `.bed.gz`, as
+/// tab-separated values otherwise. The file may be gzip-compressed.
+pub fn load(path: &Path) -> Result<Vec<Region>, BoxError> {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    let bed = name.trim_end_matches(".gz").ends_with(".bed");
+    read_scheme(storage::open(path)?, bed)
+}
+
+/// Reads the amplicons of a scheme, in BED if `bed` is set and as tab-separated values otherwise
+/// (see the module documentation), in the order of the scheme. Errors on a malformed line, a
+/// primer scheme amplicon without left or right primer, or a scheme without amplicons.
+pub fn read_scheme<R: Read>(reader: R, bed: bool) -> Result<Vec<Region>, BoxError> {
+    let mut amplicons = Vec::new();
+    let mut first = true;
+    for (i, line) in input::decompress(reader)?.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        let skipped = line.trim().is_empty()
+            || line.starts_with('#')
+            || (bed && (line.starts_with("track") || line.starts_with("browser")));
+        if skipped {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let error = |message: &str| format!("Amplicon scheme line {}: {}", i + 1, message);
+        if fields.len() < 3 {
+            return Err(error("expected at least 3 tab-separated fields").into());
+        }
+        let header = std::mem::replace(&mut first, false) && !bed;
+        let (start, end) = match (fields[1].trim().parse(), fields[2].trim().parse()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ if header => continue,
+            _ => return Err(error("the start and end must be positions").into()),
+        };
+        let (name, start, end): (String, usize, usize) = if bed {
+            let name = fields
+                .get(3)
+                .map_or_else(|| format!("line{}", i + 1), |name| name.to_string());
+            (name, start + 1, end)
+        } else {
+            (fields[0].to_string(), start, end)
+        };
+        if start == 0 || end < start {
+            return Err(error("the interval is empty").into());
+        }
+        amplicons.push(Region::new(name, start, end));
+    }
+    if amplicons.is_empty() {
+        return Err("Amplicon scheme has no amplicons".into());
+    }
+    if bed
+        && amplicons
+            .iter()
+            .all(|primer| primer_side(&primer.name).is_some())
+    {
+        return pair_primers(&amplicons);
+    }
+    Ok(amplicons)
+}
+
+/// The name of the amplicon of the primer `name` and whether it is a left primer, if `name` ends
+/// in one of the `PRIMER_SIDES`, optionally followed by `_alt` and a number.
+fn primer_side(name: &str) -> Option<(&str, bool)> {
+    let name = match name.rfind("_alt") {
+        Some(i) if name[i + 4..].bytes().all(|b| b.is_ascii_digit()) => &name[..i],
+        _ => name,
+    };
+    PRIMER_SIDES.iter().enumerate().find_map(|(side, suffix)| {
+        name.strip_suffix(suffix)
+            .map(|amplicon| (amplicon, side == 0))
+    })
+}
+
+/// The amplicons of the primers of a BED primer scheme, in the order of their first primer.
+fn pair_primers(primers: &[Region]) -> Result<Vec<Region>, BoxError> {
+    // The start of the left primers and end of the right primers of every amplicon.
+    let mut amplicons: Vec<(&str, Option<RefPosition>, Option<RefPosition>)> = Vec::new();
+    for primer in primers {
+        let Some((name, left)) = primer_side(&primer.name) else {
+            continue;
+        };
+        let i = match amplicons
+            .iter()
+            .position(|(amplicon, ..)| *amplicon == name)
+        {
+            Some(i) => i,
+            None => {
+                amplicons.push((name, None, None));
+                amplicons.len() - 1
+            }
+        };
+        let (_, start, end) = &mut amplicons[i];
+        if left {
+            *start = Some(start.map_or(primer.start, |start| start.min(primer.start)));
+        } else {
+            *end = Some(end.map_or(primer.end, |end| end.max(primer.end)));
+        }
+    }
+    amplicons
+        .into_iter()
+        .map(|(name, start, end)| match (start, end) {
+            (Some(start), Some(end)) if start <= end => {
+                Ok(Region::new(name, start.get(), end.get()))
+            }
+            (None, _) => {
+                Err(format!("Amplicon scheme: amplicon {} has no left primer", name).into())
+            }
+            (_, None) => {
+                Err(format!("Amplicon scheme: amplicon {} has no right primer", name).into())
+            }
+            _ => Err(format!("Amplicon scheme: amplicon {} ends before it starts", name).into()),
+        })
+        .collect()
+}
+
+/// Returns the `amplicons` overlapped by `loc`, with the fraction of each it covers, best covered
+/// first and in the order of the scheme for equal fractions.
+pub fn locate(loc: &Locator, amplicons: &[Region]) -> Vec<AmpliconCoverage> {
+    let segments = loc.ref_segments();
+    let mut covered: Vec<AmpliconCoverage> = amplicons
+        .iter()
+        .filter_map(|amplicon| {
+            let bases: usize = segments
+                .iter()
+                .filter(|segment| amplicon.start <= segment.end && segment.start <= amplicon.end)
+                .map(|segment| {
+                    amplicon.end.min(segment.end).get() - amplicon.start.max(segment.start).get()
+                        + 1
+                })
+                .sum();
+            let length = amplicon.end.get() - amplicon.start.get() + 1;
+            (bases > 0).then(|| AmpliconCoverage {
+                amplicon: amplicon.name.clone(),
+                start: amplicon.start,
+                end: amplicon.end,
+                fraction: bases as f64 / length as f64,
+            })
+        })
+        .collect();
+    covered.sort_by(|a, b| b.fraction.total_cmp(&a.fraction));
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_scheme() {
+        let tsv = "name\tstart\tend\namp1\t1000\t1400\namp2\t1300\t1700\n";
+        let scheme = read_scheme(tsv.as_bytes(), false).unwrap();
+        assert_eq!(
+            scheme,
+            [
+                Region::new("amp1", 1000, 1400),
+                Region::new("amp2", 1300, 1700)
+            ]
+        );
+
+        let bed = "track name=primers\n\
+                   K03455\t100\t120\tHIV_1_LEFT\n\
+                   K03455\t95\t118\tHIV_1_LEFT_alt1\n\
+                   K03455\t480\t500\tHIV_1_RIGHT\n\
+                   K03455\t400\t420\tHIV_2_LEFT\n\
+                   K03455\t800\t820\tHIV_2_RIGHT\n";
+        let scheme = read_scheme(bed.as_bytes(), true).unwrap();
+        assert_eq!(
+            scheme,
+            [
+                Region::new("HIV_1", 96, 500),
+                Region::new("HIV_2", 401, 820)
+            ]
+        );
+        // Intervals without primer names are amplicons.
+        let scheme = read_scheme(&b"K03455\t99\t500\n"[..], true).unwrap();
+        assert_eq!(scheme, [Region::new("line1", 100, 500)]);
+
+        assert!(read_scheme(&b"K03455\t100\t120\tHIV_1_LEFT\n"[..], true).is_err());
+        assert!(read_scheme(&b"amp1\t1000\n"[..], false).is_err());
+        assert!(read_scheme(&b"# empty\n"[..], false).is_err());
+    }
+
+    #[test]
+    fn test_locate() {
+        let loc = Locator::new(
+            RefPosition::new(1000),
+            RefPosition::new(1399),
+            100.0,
+            false,
+            "A".repeat(400),
+            "A".repeat(400),
+        );
+        let scheme = [
+            Region::new("amp0", 500, 900),
+            Region::new("amp1", 1300, 1700),
+            Region::new("amp2", 1000, 1399),
+        ];
+        let covered: Vec<String> = locate(&loc, &scheme)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            covered,
+            ["amp2 1000-1399 (100.0%)", "amp1 1300-1700 (24.9%)"]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Detection of sequencing artifacts in queries.
+//!
+//! Long homopolymer runs and runs of ambiguous residues are common artifacts of nanopore and
+//! low-coverage consensus sequences, and routinely explain alignments full of indels or with a low
+//! identity. `detect` flags them as `ArtifactWarning`s, which are attached to the location of the
+//! query (see `Locator::warnings`):
+//!
+//! - `Homopolymer`: a nucleotide repeated at least `MIN_HOMOPOLYMER_LENGTH` times, longer than
+//!   any homopolymer of the HXB2 and SIVmm239 references.
+//! - `AmbiguousRun`: at least `MIN_AMBIGUOUS_RUN_LENGTH` consecutive ambiguous residues (IUPAC
+//!   ambiguity codes for `nt`, `X` for `aa`).
+//! - `Stop`: stops (`*`) in an `aa` query. Terminal stops are flagged too, although they are not
+//!   aligned.
+//!
+//! With `--mask-artifacts`, the alignment columns of the flagged residues, and the deletions
+//! next to a homopolymer of the deleted base, are left out of the percent identity
+//! (`masked_identity`).
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::artifacts::{self, ArtifactKind};
+//!
+//! let warnings = artifacts::detect("ATGCAAAAAAAAATGCNNNNATGC", "nt");
+//! assert_eq!(warnings.len(), 2);
+//! assert_eq!(warnings[0].kind, ArtifactKind::Homopolymer);
+//! assert_eq!(warnings[0].to_string(), "homopolymer Ax9 at query 5-13");
+//! assert_eq!(warnings[1].kind, ArtifactKind::AmbiguousRun);
+//! ```
+
+use crate::locator::IdentityMode;
+use crate::position::QueryPosition;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Minimum length of a homopolymer run to be flagged.
+pub const MIN_HOMOPOLYMER_LENGTH: usize = 8;
+
+/// Minimum length of a run of ambiguous residues to be flagged.
+pub const MIN_AMBIGUOUS_RUN_LENGTH: usize = 3;
+
+/// Kind of a sequencing artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// A long run of one nucleotide.
+    Homopolymer,
+    /// A run of ambiguous residues.
+    AmbiguousRun,
+    /// A run of stops in an amino acid query.
+    Stop,
+}
+
+/// A sequencing artifact found in a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArtifactWarning {
+    pub kind: ArtifactKind,
+    /// The repeated residue, or the first residue of an ambiguous run.
+    pub residue: char,
+    /// First position of the run in the normalized query.
+    pub start: QueryPosition,
+    /// Last position of the run in the normalized query (inclusive).
+    pub end: QueryPosition,
+    /// Whether the run was left out of `percent_identity`.
+    pub masked: bool,
+}
+
+impl ArtifactWarning {
+    fn contains(&self, index: usize) -> bool {
+        self.start.to_zero_based() <= index && index < self.end.get()
+    }
+}
+
+impl Display for ArtifactWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ArtifactKind::Homopolymer => "homopolymer",
+            ArtifactKind::AmbiguousRun => "ambiguous run",
+            ArtifactKind::Stop => "stop",
+        };
+        write!(
+            f,
+            "{} {}x{} at query {}-{}",
+            kind,
+            self.residue,
+            self.start.span_to(self.end),
+            self.start,
+            self.end
+        )
+    }
+}
+
+/// Whether `residue` is ambiguous for `type_query`.
+fn is_ambiguous(residue: u8, type_query: &str) -> bool {
+    match type_query {
+        "aa" => residue == b'X',
+        _ => !matches!(residue, b'A' | b'C' | b'G' | b'T'),
+    }
+}
+
+/// Returns the artifacts of the normalized `sequence` of `type_query`, in query order.
+pub fn detect(sequence: &str, type_query: &str) -> Vec<ArtifactWarning> {
+    let bytes = sequence.as_bytes();
+    let mut warnings = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let ambiguous = is_ambiguous(bytes[start], type_query);
+        let len = bytes[start..]
+            .iter()
+            .take_while(|&&b| {
+                if ambiguous {
+                    is_ambiguous(b, type_query)
+                } else {
+                    b == bytes[start]
+                }
+            })
+            .count();
+        let kind = if type_query == "aa" && bytes[start] == b'*' {
+            Some(ArtifactKind::Stop)
+        } else if ambiguous && len >= MIN_AMBIGUOUS_RUN_LENGTH {
+            Some(ArtifactKind::AmbiguousRun)
+        } else if !ambiguous && type_query == "nt" && len >= MIN_HOMOPOLYMER_LENGTH {
+            Some(ArtifactKind::Homopolymer)
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            warnings.push(ArtifactWarning {
+                kind,
+                residue: bytes[start] as char,
+                start: QueryPosition::from_zero_based(start),
+                end: QueryPosition::from_zero_based(start + len - 1),
+                masked: false,
+            });
+        }
+        start += len;
+    }
+    warnings
+}
+
+/// Returns the percent identity of an alignment with `mode` without the columns of the query
+/// residues in `warnings`, and the deletions of the repeated base next to a homopolymer. Returns
+/// `None` when every column is masked.
+pub fn masked_identity(
+    query_aligned_string: &str,
+    ref_aligned_string: &str,
+    warnings: &[ArtifactWarning],
+    mode: IdentityMode,
+) -> Option<f64> {
+    let run_at = |index: usize| warnings.iter().find(|warning| warning.contains(index));
+    let mut query_index: usize = 0;
+    let mut columns = Vec::new();
+    for (q, r) in query_aligned_string.bytes().zip(ref_aligned_string.bytes()) {
+        let masked = if q == b'-' {
+            let previous = query_index.checked_sub(1).and_then(run_at);
+            previous
+                .into_iter()
+                .chain(run_at(query_index))
+                .any(|run| run.kind == ArtifactKind::Homopolymer && run.residue as u8 == r)
+        } else {
+            query_index += 1;
+            run_at(query_index - 1).is_some()
+        };
+        if !masked {
+            columns.push((q, r));
+        }
+    }
+    mode.identity(&columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert!(detect("ATGCAAAAAAATGC", "nt").is_empty());
+        let warnings = detect("TTTTTTTTGCRYNAT", "nt");
+        assert_eq!(
+            warnings,
+            vec![
+                ArtifactWarning {
+                    kind: ArtifactKind::Homopolymer,
+                    residue: 'T',
+                    start: QueryPosition::new(1),
+                    end: QueryPosition::new(8),
+                    masked: false,
+                },
+                ArtifactWarning {
+                    kind: ArtifactKind::AmbiguousRun,
+                    residue: 'R',
+                    start: QueryPosition::new(11),
+                    end: QueryPosition::new(13),
+                    masked: false,
+                },
+            ]
+        );
+        assert!(detect("MGARRRRRRRRRK", "aa").is_empty());
+        let warnings = detect("MGA*RK**", "aa");
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].to_string(), "stop *x1 at query 4-4");
+        assert_eq!(warnings[1].to_string(), "stop *x2 at query 7-8");
+        assert_eq!(detect("MGAXXXK", "aa").len(), 1);
+    }
+
+    #[test]
+    fn test_masked_identity() {
+        // A homopolymer one base short of the reference, and a substitution outside of it.
+        let warnings = detect("GCAAAAAAAAGTCC", "nt");
+        assert_eq!(
+            masked_identity(
+                "GCAAAAAAAA-GTCC",
+                "GCAAAAAAAAAGTAC",
+                &warnings,
+                IdentityMode::Gapped
+            ),
+            Some(5.0 / 6.0 * 100.0)
+        );
+        assert_eq!(
+            masked_identity(
+                "AAAAAAAA",
+                "CCCCCCCC",
+                &detect("AAAAAAAA", "nt"),
+                IdentityMode::Gapped
+            ),
+            None
+        );
+    }
+}
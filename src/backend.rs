@@ -0,0 +1,222 @@
+//! The aligners behind the locations, pluggable so that other aligners, such as WFA or parasail
+//! bindings, can be used without forking the crate.
+//!
+//! Algorithm 1 aligns the whole query to an interval of the reference with
+//! `AlignmentBackend::semiglobal`, and algorithm 2 anchors the ends of long queries with
+//! `AlignmentBackend::approximate_matches` and `AlignmentBackend::match_alignment` before aligning
+//! them between the anchors. The locations are then read from the columns of
+//! `AlignmentBackend::path`. `BioBackend`, the aligners of the `bio` crate, is used by
+//! `Locator::build` and by default by `LocatorEngine`; another backend is passed to
+//! `LocatorEngine::with_backend`.
+//!
+//! The alignments are `bio::alignment::Alignment`s: a backend sets the start and end of the
+//! alignment on both sequences (`xstart`, `xend` on the query, `ystart`, `yend` on the reference),
+//! its `score`, the lengths of the sequences and its `operations`, from the start to the end, with
+//! `Ins` for query residues inserted relative to the reference and `Del` for reference residues
+//! deleted from the query.
+//!
+//! # Example
+//! ```rust
+//! use bio::alignment::Alignment;
+//! use virust_locator::BoxError;
+//! use virust_locator::backend::{AlignmentBackend, BioBackend};
+//! use virust_locator::config::Args;
+//! use virust_locator::locator::{LocatorEngine, Scoring};
+//!
+//! /// Counts the alignments of the default backend.
+//! #[derive(Debug, Default)]
+//! struct Counting(std::sync::atomic::AtomicUsize);
+//!
+//! impl AlignmentBackend for Counting {
+//!     fn semiglobal(
+//!         &self,
+//!         query: &[u8],
+//!         ref_seq: &[u8],
+//!         scoring: &Scoring,
+//!     ) -> Result<Alignment, BoxError> {
+//!         self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+//!         BioBackend.semiglobal(query, ref_seq, scoring)
+//!     }
+//!
+//!     fn approximate_matches(
+//!         &self,
+//!         pattern: &[u8],
+//!         text: &[u8],
+//!         max_dist: usize,
+//!     ) -> Vec<(usize, usize)> {
+//!         BioBackend.approximate_matches(pattern, text, max_dist)
+//!     }
+//!
+//!     fn match_alignment(
+//!         &self,
+//!         pattern: &[u8],
+//!         text: &[u8],
+//!         end: usize,
+//!         max_dist: usize,
+//!     ) -> Alignment {
+//!         BioBackend.match_alignment(pattern, text, end, max_dist)
+//!     }
+//! }
+//!
+//! let engine = LocatorEngine::new("HXB2", &Args::default())
+//!     .unwrap()
+//!     .with_backend(Counting::default());
+//! assert!(engine.locate("ATGCATGCATGC").unwrap().is_found());
+//! ```
+
+use crate::BoxError;
+use crate::hirschberg;
+use crate::locator::Scoring;
+use bio::alignment::pairwise::Aligner;
+use bio::alignment::{Alignment, AlignmentOperation};
+use bio::pattern_matching::myers::long;
+use std::cell::Cell;
+use std::fmt::Debug;
+
+/// An aligner locating the queries, see the module documentation.
+pub trait AlignmentBackend: Debug + Send + Sync {
+    /// Aligns the whole of `query` to an interval of `ref_seq` with the substitution scores and gap
+    /// penalties of `scoring`: a semi-global alignment, without penalties for the reference
+    /// residues before and after the interval.
+    fn semiglobal(
+        &self,
+        query: &[u8],
+        ref_seq: &[u8],
+        scoring: &Scoring,
+    ) -> Result<Alignment, BoxError>;
+
+    /// The approximate matches of `pattern` in `text` with at most `max_dist` edits: the 0-based
+    /// position in `text` of the last residue of every match, and its edit distance.
+    fn approximate_matches(
+        &self,
+        pattern: &[u8],
+        text: &[u8],
+        max_dist: usize,
+    ) -> Vec<(usize, usize)>;
+
+    /// The alignment of `pattern` to `text` of its match ending at `end`, one of the
+    /// `approximate_matches` with at most `max_dist` edits.
+    fn match_alignment(
+        &self,
+        pattern: &[u8],
+        text: &[u8],
+        end: usize,
+        max_dist: usize,
+    ) -> Alignment;
+
+    /// The columns of `aln`, from its start to its end: the 1-based positions of the query and
+    /// reference residues of every column, and its operation. `Alignment::path` by default.
+    fn path(&self, aln: &Alignment) -> Vec<(usize, usize, AlignmentOperation)> {
+        aln.path()
+    }
+}
+
+/// The aligners of the `bio` crate: `Aligner::semiglobal`, or `hirschberg::semiglobal` above the
+/// memory of the scoring (see `LocatorOptions::max_alignment_memory`), and the Myers bit-parallel
+/// algorithm for the approximate matches.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BioBackend;
+
+impl AlignmentBackend for BioBackend {
+    /// The aligner of the thread is reused by the next alignments with the same scoring, so that
+    /// its dynamic programming matrices are not reallocated for every query, unless they are larger
+    /// than `MAX_REUSED_CELLS`. Alignments whose traceback matrix would exceed the memory of the
+    /// scoring are computed in linear space with `hirschberg::semiglobal`, to the same score.
+    fn semiglobal(
+        &self,
+        query: &[u8],
+        ref_seq: &[u8],
+        scoring: &Scoring,
+    ) -> Result<Alignment, BoxError> {
+        let scoring = *scoring;
+        let cells = (query.len() + 1).saturating_mul(ref_seq.len() + 1);
+        if cells > scoring.max_cells() {
+            let score = |a, b| scoring.score(a, b);
+            return Ok(hirschberg::semiglobal(
+                query,
+                ref_seq,
+                score,
+                scoring.gap_open(),
+                scoring.gap_extend(),
+            ));
+        }
+        let reused = ALIGNER.take().filter(|(reused, _)| *reused == scoring);
+        let mut aligner = match reused {
+            Some((_, aligner)) => aligner,
+            None => Aligner::with_capacity(
+                query.len(),
+                ref_seq.len(),
+                scoring.gap_open(),
+                scoring.gap_extend(),
+                scoring,
+            ),
+        };
+        let aln = aligner.semiglobal(query, ref_seq);
+        if cells <= MAX_REUSED_CELLS {
+            ALIGNER.set(Some((scoring, aligner)));
+        }
+        Ok(aln)
+    }
+
+    fn approximate_matches(
+        &self,
+        pattern: &[u8],
+        text: &[u8],
+        max_dist: usize,
+    ) -> Vec<(usize, usize)> {
+        long::Myers::<u64>::new(pattern)
+            .find_all_end(text, max_dist)
+            .collect()
+    }
+
+    fn match_alignment(
+        &self,
+        pattern: &[u8],
+        text: &[u8],
+        end: usize,
+        max_dist: usize,
+    ) -> Alignment {
+        let mut myers = long::Myers::<u64>::new(pattern);
+        let mut lazy_matches = myers.find_all_lazy(text, max_dist);
+        lazy_matches.by_ref().for_each(drop);
+        let mut aln = Alignment::default();
+        lazy_matches.alignment_at(end, &mut aln);
+        aln
+    }
+}
+
+/// Largest dynamic programming matrix, in cells, kept by the aligner of a thread between
+/// alignments: about 32 MB of traceback, enough for queries of 1700 nucleotides on HXB2.
+const MAX_REUSED_CELLS: usize = 1 << 24;
+
+thread_local! {
+    /// The aligner of the thread, with its scoring, see `BioBackend::semiglobal`.
+    static ALIGNER: Cell<Option<(Scoring, Aligner<Scoring>)>> = const { Cell::new(None) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    #[test]
+    fn test_semiglobal() {
+        let search_string = b"AAATTAACCCCACTCTGTGTTGAATTAAATTGTACTAAGTATGAGGGTAATAGTACTACTACCACGAATAGTACTACTGCCACTACGAATAGTACTGCTGCCCCTAACGGGACGGAGACGGGAATGAAAAATTGCTCTTTCTATGTTAACACGGTCACAAACTATAAGGTGCAGAAGAAATATGCACTTTTCTATGATCTTGATATAGTACAAATAGAAGGTAGTAATACTAGCTATAGGATAACAAAGTGTAACACCTCAATCAGCACAGTACAATGCACACATGGTATTAAACCAGTAGTATCAACTCAATTATTGTTAAATGGCAGCTTAGCAGAAGAAAAGATAGTCATCAGATCTAGCAACTTCTCTAGCAACACTGAAAGCATAATAGTACAGCTGAAAAACCCTGTAGAAATTAACTGTACAAGACCCAACAACAATAGAAGACAGAGTATCCATATTGGACCAGGGAGAGCGTTTTTTACAACAGGAGAAATAATAGGAGATATAAGACAA";
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let aln = BioBackend
+            .semiglobal(search_string, ref_seq, &Scoring::default())
+            .unwrap();
+        assert_eq!(aln.ystart, 6584);
+        assert_eq!(aln.yend, 7208);
+    }
+
+    #[test]
+    fn test_approximate_matches() {
+        let text = b"TTTTACGTACGTTTTTTTTTACGAACGTTTTT";
+        let matches = BioBackend.approximate_matches(b"ACGTACGT", text, 1);
+        assert!(matches.contains(&(11, 0)));
+        assert!(matches.contains(&(27, 1)));
+        let aln = BioBackend.match_alignment(b"ACGTACGT", text, 11, 1);
+        assert_eq!((aln.ystart, aln.yend), (4, 12));
+    }
+}
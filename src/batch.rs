@@ -0,0 +1,885 @@
+//! Locating batches of records and writing their results.
+//!
+//! Every record is validated and located exactly like a `--query` of the CLI. A record that fails
+//! validation produces a result with an error instead of failing the whole batch, so one bad
+//! sequence in a file does not hide the results of the others.
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::input::Record;
+use crate::locator::{BUILD_ITER_CHUNK_SIZE, Coordinates, LocateOutcome, Locator, NotLocated};
+use crate::logging;
+use crate::parallel::*;
+use crate::reference::Gene;
+use crate::sequence;
+use crate::verify::{self, Disagreement};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::Write;
+use std::time::Instant;
+
+/// Location result for one record of a batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    /// The record identifier.
+    pub id: String,
+    /// Whether the record was located on the reference.
+    pub found: bool,
+    /// The location, when found.
+    #[serde(flatten)]
+    pub locator: Option<Locator>,
+    /// Why the record could not be located, when it failed validation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why the record could not be located, when it is valid but its alignment is degenerate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_located: Option<NotLocated>,
+    /// The type of query inferred from the sequence, with `--type-query auto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inferred_type: Option<&'static str>,
+    /// The disagreements between the two algorithms, with `--verify`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disagreements: Vec<Disagreement>,
+}
+
+impl BatchResult {
+    /// The result of the record `id` located with `outcome`.
+    pub fn from_outcome(id: String, outcome: LocateOutcome) -> Self {
+        let (locator, not_located) = match outcome {
+            LocateOutcome::Found(loc) => (Some(*loc), None),
+            LocateOutcome::NotFound(not_located) => (None, Some(not_located)),
+        };
+        BatchResult {
+            id,
+            found: locator.is_some(),
+            locator,
+            error: None,
+            not_located,
+            inferred_type: None,
+            disagreements: Vec::new(),
+        }
+    }
+
+    /// Validates and locates `record` with the options of `options`, and with the other algorithm
+    /// too if `options.verify` is set. The queries of `options` are ignored.
+    pub fn locate(record: Record, options: &Args) -> Self {
+        let started = Instant::now();
+        let inferred_type =
+            (options.common.type_query == "auto").then(|| sequence::infer_type(&record.sequence));
+        let sequence = match options.min_base_quality {
+            Some(min_quality) if options.type_of(&record.sequence) == "nt" => {
+                record.masked_sequence(min_quality)
+            }
+            _ => record.sequence,
+        };
+        let located = Args {
+            common: CommonArgs {
+                query: vec![sequence],
+                query_name: vec![record.id.clone()],
+                ..options.common.clone()
+            },
+            ..options.clone()
+        }
+        .validate()
+        .map_err(BoxError::from)
+        .and_then(|args| {
+            if options.verify {
+                verify::try_build_verified(&args, options.tolerance())
+            } else {
+                Locator::try_build(&args)
+                    .map(|located| located.into_iter().map(|o| (o, Vec::new())).collect())
+            }
+        })
+        .and_then(|mut located| {
+            located
+                .pop()
+                .ok_or_else(|| BoxError::from("No query to locate"))
+        });
+        logging::locate_event(
+            &record.id,
+            &options.common.reference,
+            options.common.algorithm,
+            started,
+            located
+                .as_ref()
+                .map(|(outcome, _)| outcome.is_found())
+                .map_err(|err| err as &dyn Display),
+        );
+
+        match located {
+            Ok((outcome, disagreements)) => BatchResult {
+                inferred_type,
+                disagreements,
+                ..BatchResult::from_outcome(record.id, outcome)
+            },
+            Err(err) => BatchResult {
+                id: record.id,
+                found: false,
+                locator: None,
+                error: Some(err.to_string()),
+                not_located: None,
+                inferred_type,
+                disagreements: Vec::new(),
+            },
+        }
+    }
+}
+
+/// Locates every record in parallel, keeping the input order.
+pub fn locate_records(records: Vec<Record>, options: &Args) -> Vec<BatchResult> {
+    records
+        .into_par_iter()
+        .map(|record| BatchResult::locate(record, options))
+        .collect()
+}
+
+/// Locates the records like `locate_records`, yielding their results in the input order as they
+/// are computed, `BUILD_ITER_CHUNK_SIZE` records at a time, like `Locator::build_iter`: only the
+/// results of the chunk being located are held in memory.
+pub fn locate_records_iter(
+    records: Vec<Record>,
+    options: &Args,
+) -> impl Iterator<Item = BatchResult> + '_ {
+    let mut records = records.into_iter();
+    std::iter::from_fn(move || {
+        let chunk: Vec<Record> = records.by_ref().take(BUILD_ITER_CHUNK_SIZE).collect();
+        (!chunk.is_empty()).then(|| locate_records(chunk, options))
+    })
+    .flatten()
+}
+
+/// Header line of the tab-separated result format.
+pub const TSV_HEADER: &str = "query_id\tref_start\tref_end\tpercent_identity\tindel\tquery_coverage\tref_span\tquery_aligned_string\tref_aligned_string\twarnings\terror";
+
+/// Header line of the tab-separated result format with 0-based, half-open positions.
+pub const TSV_HEADER_ZERO_BASED: &str = "query_id\tref_start_0based\tref_end_exclusive\tpercent_identity\tindel\tquery_coverage\tref_span\tquery_aligned_string\tref_aligned_string\twarnings\terror";
+
+/// Writes `results` as tab-separated values, one row per record after the `TSV_HEADER` line.
+/// `warnings` lists the type of query inferred with `--type-query auto`, the algorithm chosen with
+/// `--algorithm auto`, the segments of split alignments (see `--split-identity`), the sequencing
+/// artifacts of the query and the disagreements between the algorithms found by `--verify`,
+/// separated by `; `. Records that were not located have empty location columns and the reason in
+/// `error`: the validation error, or why the alignment is degenerate.
+pub fn write_tsv<W: Write>(writer: W, results: &[BatchResult]) -> Result<(), BoxError> {
+    write_tsv_with(writer, results, Coordinates::OneBased, None)
+}
+
+/// Writes `results` like `write_tsv`, with the reference positions in `coordinates`. The header
+/// line is `TSV_HEADER_ZERO_BASED` for 0-based positions, so they cannot be mistaken for 1-based
+/// ones. With `relative_to`, the columns `<gene>_start` and `<gene>_end`, with the positions
+/// relative to the gene (see `Gene::relative`), are inserted before `error`, so the other columns
+/// keep their place.
+pub fn write_tsv_with<W: Write>(
+    writer: W,
+    results: &[BatchResult],
+    coordinates: Coordinates,
+    relative_to: Option<&Gene>,
+) -> Result<(), BoxError> {
+    write_delimited(
+        writer,
+        results,
+        &DelimitedOptions {
+            coordinates,
+            relative_to,
+            ..Default::default()
+        },
+    )
+}
+
+/// Options of the delimited result format, see `write_delimited`.
+#[derive(Debug, Clone, Copy)]
+pub struct DelimitedOptions<'a> {
+    /// Convention of the reference positions.
+    pub coordinates: Coordinates,
+    /// Gene the positions are also reported relative to.
+    pub relative_to: Option<&'a Gene<'a>>,
+    /// Leave out the `query_aligned_string` and `ref_aligned_string` columns.
+    pub no_align_strings: bool,
+    /// The column delimiter, a tab by default.
+    pub delimiter: char,
+    /// Add a `strand` column, before `error`, with the orientation of the query aligned.
+    pub strand: bool,
+    /// Add a `regions` column, before `error`, with the genes overlapped by the location.
+    pub regions: bool,
+    /// Add an `other_hits` column, before `error`, with the other matches of the query.
+    pub other_hits: bool,
+    /// Add `nt_ref_start` and `nt_ref_end` columns, before `error`, with the nucleotide
+    /// positions of amino acid locations.
+    pub nt_coordinates: bool,
+    /// Add `query_start` and `query_end` columns, before `error`, with the query residues aligned
+    /// to the reference.
+    pub query_coordinates: bool,
+    /// Add a `cigar` column, before `error`, with the CIGAR string of the alignment.
+    pub cigar: bool,
+    /// Add `score` and `edit_distance` columns, before `error`, with the score of the alignment
+    /// and its number of differences.
+    pub score: bool,
+    /// Add an `identity_mode` column, before `error`, with the definition of `percent_identity`.
+    pub identity_mode: bool,
+    /// Add a `defects` column, before `error`, with the defects of the genes, see `--defects`.
+    pub defects: bool,
+    /// Add an `env_regions` column, before `error`, with the regions of gp120 overlapped by the
+    /// location.
+    pub env_regions: bool,
+    /// Add `ltr_features` and `ltr_ambiguous` columns, before `error`, with the features of the
+    /// LTRs overlapped by the location, see `--ltr-features`.
+    pub ltr_features: bool,
+    /// Add a `cis_elements` column, before `error`, with the packaging signal, major splice donor
+    /// and Rev response element overlapped by the location, see `--cis-elements`.
+    pub cis_elements: bool,
+    /// Add an `amplicons` column, before `error`, with the amplicons overlapped by the location,
+    /// see `--amplicon-scheme`.
+    pub amplicons: bool,
+    /// Add a `glycosylation_sites` column, before `error`, with the N-linked glycosylation sites
+    /// of the query, see `--glycosylation`.
+    pub glycosylation: bool,
+    /// Add an `insertions` column, before `error`, with the names of the insertions of the query,
+    /// see `--name-insertions`.
+    pub insertions: bool,
+    /// The rows written: those of the records of a batch, or of the queries of `--query`.
+    pub rows: Rows,
+    /// Write the header line.
+    pub header: bool,
+}
+
+/// The rows written by `write_delimited`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rows {
+    /// The rows of the records of a batch, with every column.
+    #[default]
+    Records,
+    /// The rows of the queries of `--query`: without the `query_coverage`, `ref_span`, `warnings`
+    /// and `error` columns, nor `query_id` unless the queries are `named`, and with `NOT_FOUND`
+    /// in the `ref_start` column of the queries not located.
+    Queries { named: bool },
+}
+
+impl Rows {
+    /// Whether the rows have the column `column` of the records.
+    fn have(self, column: &str) -> bool {
+        match (self, column) {
+            (Rows::Records, _) => true,
+            (Rows::Queries { named }, "query_id") => named,
+            (Rows::Queries { .. }, "query_coverage" | "ref_span" | "warnings" | "error") => false,
+            (Rows::Queries { .. }, _) => true,
+        }
+    }
+}
+
+/// The `ref_start` of the rows of the queries not located, see `Rows::Queries`.
+pub const NOT_FOUND: &str = "NOT_FOUND";
+
+impl Default for DelimitedOptions<'_> {
+    fn default() -> Self {
+        DelimitedOptions {
+            coordinates: Coordinates::OneBased,
+            relative_to: None,
+            no_align_strings: false,
+            delimiter: '\t',
+            strand: false,
+            regions: false,
+            other_hits: false,
+            nt_coordinates: false,
+            query_coordinates: false,
+            cigar: false,
+            score: false,
+            identity_mode: false,
+            defects: false,
+            env_regions: false,
+            ltr_features: false,
+            cis_elements: false,
+            amplicons: false,
+            glycosylation: false,
+            insertions: false,
+            rows: Rows::Records,
+            header: true,
+        }
+    }
+}
+
+/// Writes `results` like `write_tsv_with`, with the columns of `options` separated by
+/// `options.delimiter`. Fields containing the delimiter, a double quote or a line break are
+/// quoted (see `write_row`), so that they cannot shift the other columns.
+pub fn write_delimited<W: Write>(
+    mut writer: W,
+    results: &[BatchResult],
+    options: &DelimitedOptions,
+) -> Result<(), BoxError> {
+    let kept: Vec<bool> = record_columns(options)
+        .iter()
+        .map(|column| options.rows.have(column))
+        .collect();
+    if options.header {
+        write_row(&mut writer, &columns(options), options.delimiter)?;
+    }
+    for result in results {
+        let mut fields = record_fields(result, options);
+        if options.rows != Rows::Records && result.locator.is_none() {
+            // A NOT_FOUND row keeps the rows aligned with the queries.
+            fields[1..].fill(String::new());
+            fields[1] = NOT_FOUND.to_string();
+        }
+        let fields = fields.into_iter().zip(&kept).filter(|(_, kept)| **kept);
+        let fields: Vec<String> = fields.map(|(field, _)| field).collect();
+        write_row(&mut writer, &fields, options.delimiter)?;
+    }
+    Ok(())
+}
+
+/// The fields of the row of `result` with every column of the records, see `record_columns`.
+fn record_fields(result: &BatchResult, options: &DelimitedOptions) -> Vec<String> {
+    let mut fields = vec![result.id.clone()];
+    match &result.locator {
+        Some(loc) => {
+            let (ref_start, ref_end) = loc.interval(options.coordinates);
+            fields.extend([
+                ref_start.to_string(),
+                ref_end.to_string(),
+                loc.percent_identity.to_string(),
+                loc.indel.to_string(),
+                loc.query_coverage.to_string(),
+                loc.ref_span.to_string(),
+            ]);
+            if !options.no_align_strings {
+                fields.push(loc.query_aligned_as_given());
+                fields.push(loc.ref_aligned_string.clone());
+            }
+        }
+        None => {
+            let location_columns = if options.no_align_strings { 6 } else { 8 };
+            fields.resize(1 + location_columns, String::new());
+        }
+    }
+    fields.push(warnings(result));
+    if let Some(gene) = options.relative_to {
+        let relative = result.locator.as_ref().map(|loc| {
+            [
+                gene.relative(loc.ref_start).to_string(),
+                gene.relative(loc.ref_end).to_string(),
+            ]
+        });
+        fields.extend(relative.unwrap_or_default());
+    }
+    if options.strand {
+        let strand = result.locator.as_ref().map(|loc| loc.strand.to_string());
+        fields.push(strand.unwrap_or_default());
+    }
+    if options.regions {
+        let regions = result.locator.iter().flat_map(|loc| &loc.regions);
+        fields.push(
+            regions
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        );
+    }
+    if options.other_hits {
+        let hits = result.locator.as_ref();
+        fields.push(hits.map_or_else(String::new, |loc| other_hits(loc, options.coordinates)));
+    }
+    if options.nt_coordinates {
+        let nt_interval = result.locator.as_ref().and_then(|loc| {
+            let (start, end) = loc.nt_interval(options.coordinates)?;
+            Some([start.to_string(), end.to_string()])
+        });
+        fields.extend(nt_interval.unwrap_or_default());
+    }
+    if options.query_coordinates {
+        let query_interval = result.locator.as_ref().map(|loc| {
+            let (start, end) = loc.query_interval(options.coordinates);
+            [start, end].map(|p| p.to_string())
+        });
+        fields.extend(query_interval.unwrap_or_default());
+    }
+    if options.cigar {
+        fields.push(
+            result
+                .locator
+                .as_ref()
+                .map_or_else(String::new, Locator::cigar),
+        );
+    }
+    if options.score {
+        let score = result.locator.as_ref().map(|loc| {
+            let score = loc.score.map(|score| score.to_string());
+            [score.unwrap_or_default(), loc.edit_distance.to_string()]
+        });
+        fields.extend(score.unwrap_or_default());
+    }
+    if options.identity_mode {
+        let mode = result
+            .locator
+            .as_ref()
+            .map(|loc| loc.identity_mode.to_string());
+        fields.push(mode.unwrap_or_default());
+    }
+    if options.defects {
+        fields.push(result.locator.as_ref().map_or_else(String::new, defects));
+    }
+    if options.env_regions {
+        fields.push(
+            result
+                .locator
+                .as_ref()
+                .map_or_else(String::new, env_regions),
+        );
+    }
+    if options.ltr_features {
+        fields.extend(match &result.locator {
+            Some(loc) => [ltr_features(loc), loc.ltr_ambiguous.to_string()],
+            None => Default::default(),
+        });
+    }
+    if options.cis_elements {
+        fields.push(
+            result
+                .locator
+                .as_ref()
+                .map_or_else(String::new, cis_elements),
+        );
+    }
+    if options.amplicons {
+        fields.push(result.locator.as_ref().map_or_else(String::new, amplicons));
+    }
+    if options.glycosylation {
+        fields.push(
+            result
+                .locator
+                .as_ref()
+                .map_or_else(String::new, glycosylation_sites),
+        );
+    }
+    if options.insertions {
+        fields.push(result.locator.as_ref().map_or_else(String::new, insertions));
+    }
+    let error = match &result.locator {
+        Some(_) => String::new(),
+        None => result
+            .error
+            .clone()
+            .or_else(|| result.not_located.as_ref().map(ToString::to_string))
+            .unwrap_or_else(|| "Locator not found".to_string()),
+    };
+    fields.push(error);
+    fields
+}
+
+/// The names of the columns written by `write_delimited` with `options`, as in its header line.
+pub fn columns(options: &DelimitedOptions) -> Vec<String> {
+    let mut columns = record_columns(options);
+    columns.retain(|column| options.rows.have(column));
+    columns
+}
+
+/// The names of the columns of the rows of the records with `options`, whatever its `rows`.
+fn record_columns(options: &DelimitedOptions) -> Vec<String> {
+    let header = match options.coordinates {
+        Coordinates::OneBased => TSV_HEADER,
+        Coordinates::ZeroBased => TSV_HEADER_ZERO_BASED,
+    };
+    let relative_columns = options
+        .relative_to
+        .map(|gene| [format!("{}_start", gene.name), format!("{}_end", gene.name)]);
+    let mut columns = header
+        .split('\t')
+        .map(String::from)
+        .collect::<Vec<String>>();
+    if let Some(relative_columns) = relative_columns {
+        columns.splice(columns.len() - 1..columns.len() - 1, relative_columns);
+    }
+    if options.strand {
+        columns.insert(columns.len() - 1, "strand".to_string());
+    }
+    if options.regions {
+        columns.insert(columns.len() - 1, "regions".to_string());
+    }
+    if options.other_hits {
+        columns.insert(columns.len() - 1, "other_hits".to_string());
+    }
+    if options.nt_coordinates {
+        let nt_columns = ["nt_ref_start".to_string(), "nt_ref_end".to_string()];
+        columns.splice(columns.len() - 1..columns.len() - 1, nt_columns);
+    }
+    if options.query_coordinates {
+        let query_columns = ["query_start".to_string(), "query_end".to_string()];
+        columns.splice(columns.len() - 1..columns.len() - 1, query_columns);
+    }
+    if options.cigar {
+        columns.insert(columns.len() - 1, "cigar".to_string());
+    }
+    if options.score {
+        let score_columns = ["score".to_string(), "edit_distance".to_string()];
+        columns.splice(columns.len() - 1..columns.len() - 1, score_columns);
+    }
+    if options.identity_mode {
+        columns.insert(columns.len() - 1, "identity_mode".to_string());
+    }
+    if options.defects {
+        columns.insert(columns.len() - 1, "defects".to_string());
+    }
+    if options.env_regions {
+        columns.insert(columns.len() - 1, "env_regions".to_string());
+    }
+    if options.ltr_features {
+        let ltr_columns = ["ltr_features".to_string(), "ltr_ambiguous".to_string()];
+        columns.splice(columns.len() - 1..columns.len() - 1, ltr_columns);
+    }
+    if options.cis_elements {
+        columns.insert(columns.len() - 1, "cis_elements".to_string());
+    }
+    if options.amplicons {
+        columns.insert(columns.len() - 1, "amplicons".to_string());
+    }
+    if options.glycosylation {
+        columns.insert(columns.len() - 1, "glycosylation_sites".to_string());
+    }
+    if options.insertions {
+        columns.insert(columns.len() - 1, "insertions".to_string());
+    }
+    let is_align_string = |column: &str| column.ends_with("_aligned_string");
+    columns.retain(|column| !(options.no_align_strings && is_align_string(column)));
+    columns
+}
+
+/// Writes `fields` as one line separated by `delimiter`. Fields containing the delimiter, a
+/// double quote or a line break are enclosed in double quotes, with their double quotes doubled,
+/// as in RFC 4180.
+pub fn write_row<W: Write, S: AsRef<str>>(
+    writer: &mut W,
+    fields: &[S],
+    delimiter: char,
+) -> Result<(), BoxError> {
+    let line = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(&delimiter.to_string());
+    writeln!(writer, "{}", line)?;
+    Ok(())
+}
+
+/// The `other_hits` of `loc` in `coordinates`, as `start-end (identity%)` separated by `; `.
+pub fn other_hits(loc: &Locator, coordinates: Coordinates) -> String {
+    loc.other_hits
+        .iter()
+        .map(|hit| {
+            let (start, end) = hit.interval(coordinates);
+            format!("{}-{} ({}%)", start, end, hit.percent_identity)
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `defects` of `loc`, separated by `; `.
+pub fn defects(loc: &Locator) -> String {
+    loc.defects
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `env_regions` of `loc`, separated by `; `.
+pub fn env_regions(loc: &Locator) -> String {
+    loc.env_regions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `ltr_features` of `loc`, separated by `; `.
+pub fn ltr_features(loc: &Locator) -> String {
+    loc.ltr_features
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `cis_elements` of `loc`, separated by `; `.
+pub fn cis_elements(loc: &Locator) -> String {
+    loc.cis_elements
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `amplicons` of `loc`, separated by `; `.
+pub fn amplicons(loc: &Locator) -> String {
+    loc.amplicons
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `glycosylation_sites` of `loc`, separated by `; `.
+pub fn glycosylation_sites(loc: &Locator) -> String {
+    loc.glycosylation_sites
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The names of the `insertions` of `loc`, separated by `; `.
+pub fn insertions(loc: &Locator) -> String {
+    loc.insertions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// The `warnings` column of `result`.
+fn warnings(result: &BatchResult) -> String {
+    let artifacts = result.locator.iter().flat_map(|loc| &loc.warnings);
+    let inferred_type = result
+        .inferred_type
+        .map(|type_query| format!("inferred type {}", type_query));
+    let algorithm = result
+        .locator
+        .as_ref()
+        .and_then(|loc| loc.algorithm)
+        .map(|algorithm| format!("chose algorithm {}", algorithm));
+    let split = result.locator.as_ref().filter(|loc| loc.split).map(|loc| {
+        let segments: Vec<String> = loc
+            .segments
+            .iter()
+            .map(|segment| format!("{}-{}", segment.start, segment.end))
+            .collect();
+        format!("split alignment {}", segments.join(", "))
+    });
+    inferred_type
+        .into_iter()
+        .chain(algorithm)
+        .chain(split)
+        .chain(artifacts.map(ToString::to_string))
+        .chain(result.disagreements.iter().map(ToString::to_string))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, sequence: &str) -> Record {
+        Record {
+            id: id.to_string(),
+            sequence: sequence.to_string(),
+            quality: None,
+        }
+    }
+
+    #[test]
+    fn test_locate_records_keeps_going_on_invalid_record() {
+        let records = vec![record("good", "ATGCATGCATGC"), record("bad", "ATGCXYZ")];
+        let results = locate_records(records, &Args::default());
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].id, "good");
+        assert!(results[0].found);
+        assert_eq!(results[1].id, "bad");
+        assert!(!results[1].found);
+        assert!(
+            results[1]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("Invalid nucleotide sequence")
+        );
+    }
+
+    #[test]
+    fn test_locate_records_iter() {
+        let records: Vec<Record> = (0..BUILD_ITER_CHUNK_SIZE + 2)
+            .map(|i| record(&format!("q{}", i), "ATGCATGCATGC"))
+            .collect();
+        let results: Vec<BatchResult> = locate_records_iter(records, &Args::default()).collect();
+        assert_eq!(results.len(), BUILD_ITER_CHUNK_SIZE + 2);
+        assert_eq!(
+            results[BUILD_ITER_CHUNK_SIZE + 1].id,
+            format!("q{}", BUILD_ITER_CHUNK_SIZE + 1)
+        );
+        assert!(results.iter().all(|result| result.found));
+        assert_eq!(locate_records_iter(Vec::new(), &Args::default()).count(), 0);
+    }
+
+    #[test]
+    fn test_min_base_quality() {
+        // HXB2 790-849 read with the last 5 bases miscalled at a low quality.
+        let read = Record {
+            id: "read1".to_string(),
+            sequence: "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAAGAATC".to_string(),
+            quality: Some(format!("{}{}", "I".repeat(55), "#####")),
+        };
+        let args = Args {
+            min_base_quality: Some(20),
+            ..Default::default()
+        };
+        let result = BatchResult::locate(read.clone(), &args);
+        let loc = result.locator.unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (790, 849));
+        assert!(loc.query_aligned_string.ends_with("NNNNN"));
+        assert!(!loc.indel);
+
+        let unmasked = BatchResult::locate(read, &Args::default());
+        assert!(!unmasked.locator.unwrap().query_aligned_string.contains('N'));
+    }
+
+    #[test]
+    fn test_write_tsv_zero_based() {
+        let results = locate_records(vec![record("q1", "ATGCATGCATGC")], &Args::default());
+
+        let mut one_based = Vec::new();
+        write_tsv(&mut one_based, &results).unwrap();
+        let one_based = String::from_utf8(one_based).unwrap();
+        assert!(one_based.starts_with(TSV_HEADER));
+        assert!(one_based.contains("\nq1\t1373\t1384\t"));
+
+        let mut zero_based = Vec::new();
+        write_tsv_with(&mut zero_based, &results, Coordinates::ZeroBased, None).unwrap();
+        let zero_based = String::from_utf8(zero_based).unwrap();
+        assert!(zero_based.starts_with(TSV_HEADER_ZERO_BASED));
+        assert!(zero_based.contains("\nq1\t1372\t1384\t"));
+    }
+
+    #[test]
+    fn test_write_tsv_warnings() {
+        let args = Args {
+            mask_artifacts: true,
+            ..Default::default()
+        };
+        // HXB2 1001-1080 with 9 A inserted after 1040.
+        let query = format!(
+            "{}AAAAAAAAA{}",
+            "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAAC", "CCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC"
+        );
+        let results = locate_records(vec![record("q1", &query)], &args);
+        let loc = results[0].locator.as_ref().unwrap();
+        assert_eq!(loc.warnings.len(), 1);
+        assert!(loc.warnings[0].masked);
+        assert_eq!(loc.percent_identity, 100.0);
+
+        let mut tsv = Vec::new();
+        write_tsv(&mut tsv, &results).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("\thomopolymer Ax9 at query 41-49\t"));
+    }
+
+    #[test]
+    fn test_write_delimited() {
+        let results = locate_records(
+            vec![record("q1", "ATGCATGCATGC"), record("none", "NNNNNNNN")],
+            &Args::default(),
+        );
+        let options = DelimitedOptions {
+            no_align_strings: true,
+            delimiter: ',',
+            ..Default::default()
+        };
+        let mut csv = Vec::new();
+        write_delimited(&mut csv, &results, &options).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "query_id,ref_start,ref_end,percent_identity,indel,query_coverage,ref_span,warnings,error"
+        );
+        assert!(lines[1].starts_with("q1,1373,1384,75,"));
+        assert!(lines[1].ends_with(",12,,"));
+        // Why the query was not located contains commas and is quoted.
+        assert!(lines[2].starts_with("none,,,,,,,,\"Locator not found"));
+        assert!(lines[2].ends_with('"'));
+
+        let mut row = Vec::new();
+        write_row(&mut row, &["a\tb", "say \"hi\"", "c"], '\t').unwrap();
+        assert_eq!(
+            String::from_utf8(row).unwrap(),
+            "\"a\tb\"\t\"say \"\"hi\"\"\"\tc\n"
+        );
+    }
+
+    #[test]
+    fn test_write_delimited_queries() {
+        let results = locate_records(
+            vec![record("q1", "ATGCATGCATGC"), record("none", "NNNNNNNN")],
+            &Args::default(),
+        );
+        let mut options = DelimitedOptions {
+            no_align_strings: true,
+            strand: true,
+            rows: Rows::Queries { named: true },
+            ..Default::default()
+        };
+        let mut tsv = Vec::new();
+        write_delimited(&mut tsv, &results, &options).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "query_id\tref_start\tref_end\tpercent_identity\tindel\tstrand",
+                "q1\t1373\t1384\t75\tfalse\t+",
+                "none\tNOT_FOUND\t\t\t\t",
+            ]
+        );
+
+        options.rows = Rows::Queries { named: false };
+        options.header = false;
+        let mut tsv = Vec::new();
+        write_delimited(&mut tsv, &results[1..], &options).unwrap();
+        assert_eq!(String::from_utf8(tsv).unwrap(), "NOT_FOUND\t\t\t\t\n");
+    }
+
+    #[test]
+    fn test_write_regions() {
+        let gag = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+        let args = Args {
+            annotate: true,
+            ..Default::default()
+        };
+        let results = locate_records(vec![record("gag", gag)], &args);
+        let options = DelimitedOptions {
+            no_align_strings: true,
+            regions: true,
+            ..Default::default()
+        };
+        let mut tsv = Vec::new();
+        write_delimited(&mut tsv, &results, &options).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\twarnings\tregions\terror"));
+        assert!(lines[1].ends_with("\tgag 790-849 (partial)\t"));
+    }
+
+    #[test]
+    fn test_write_tsv_relative_to() {
+        let results = locate_records(
+            vec![record("q1", "ATGCATGCATGC"), record("bad", "ATGCXYZ")],
+            &Args::default(),
+        );
+        let gag = crate::reference::retrieve_gene("HXB2", "nt", "gag").unwrap();
+
+        let mut tsv = Vec::new();
+        write_tsv_with(&mut tsv, &results, Coordinates::OneBased, Some(gag)).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\twarnings\tgag_start\tgag_end\terror"));
+        assert!(lines[1].starts_with("q1\t1373\t1384\t"));
+        assert!(lines[1].ends_with("\t584\t595\t"));
+        for line in &lines {
+            assert_eq!(line.split('\t').count(), 13);
+        }
+    }
+}
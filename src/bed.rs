@@ -0,0 +1,139 @@
+//! BED output of the locations, for bedtools and the other interval tools.
+//!
+//! With `--format bed`, every located query is written as a BED6 line on the reference, named by
+//! its strain (e.g. `HXB2`), without header:
+//!
+//! - `chromStart`, `chromEnd`: the location as a 0-based, half-open interval. A location wrapping
+//!   around the end of the reference has one line per segment.
+//! - `name`: the query ID.
+//! - `score`: the percent identity of the location, as in the other formats rather than scaled to
+//!   the 0-1000 range of the UCSC browser.
+//! - `strand`: `-` for queries located on the reverse complement with `--both-strands`.
+//!
+//! Queries that are not located have no line.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::bed;
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGCATGCATGC".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let lines = bed::lines("q1", &loc, "HXB2").unwrap();
+//! assert_eq!(lines[0].join("\t"), "HXB2\t1372\t1384\tq1\t75\t+");
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::Formatter;
+use crate::locator::Locator;
+use crate::reference::resolve_reference;
+use std::io::Write;
+
+/// The BED6 lines of the query `id` located at `loc` on `reference`, one per segment. Tabs and
+/// line breaks in `id` are replaced by spaces, as BED fields cannot hold them.
+pub fn lines(id: &str, loc: &Locator, reference: &str) -> Result<Vec<Vec<String>>, BoxError> {
+    let chrom = resolve_reference(reference)?;
+    let name = id.replace(['\t', '\n', '\r'], " ");
+    Ok(loc
+        .ref_segments()
+        .iter()
+        .map(|segment| {
+            vec![
+                chrom.to_string(),
+                (segment.start.get() - 1).to_string(),
+                segment.end.get().to_string(),
+                name.clone(),
+                loc.percent_identity.to_string(),
+                loc.strand.to_string(),
+            ]
+        })
+        .collect())
+}
+
+/// Writes the BED lines of the query `id`, located at `loc` on `reference`, see `lines`. Nothing
+/// is written when `loc` is `None`.
+pub fn write_lines<W: Write>(
+    mut writer: W,
+    id: &str,
+    loc: Option<&Locator>,
+    reference: &str,
+) -> Result<(), BoxError> {
+    let Some(loc) = loc else {
+        return Ok(());
+    };
+    for line in lines(id, loc, reference)? {
+        writeln!(writer, "{}", line.join("\t"))?;
+    }
+    Ok(())
+}
+
+/// BED6 lines of the results, see `lines`.
+#[derive(Debug, Clone)]
+pub struct BedFormatter {
+    /// The reference the results are located on.
+    pub reference: String,
+}
+
+impl Formatter for BedFormatter {
+    fn write(&self, mut writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        for result in results {
+            write_lines(
+                &mut writer,
+                &result.id,
+                result.locator.as_ref(),
+                &self.reference,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::RefSegment;
+    use crate::position::RefPosition;
+
+    #[test]
+    fn test_lines() {
+        // A location wrapping around the end of HXB2, like a read across a 2-LTR junction.
+        let mut loc = Locator::new(
+            RefPosition::new(9710),
+            RefPosition::new(5),
+            100.0,
+            false,
+            "A".repeat(15),
+            "A".repeat(15),
+        );
+        loc.segments = vec![
+            RefSegment {
+                start: RefPosition::new(9710),
+                end: RefPosition::new(9719),
+            },
+            RefSegment {
+                start: RefPosition::new(1),
+                end: RefPosition::new(5),
+            },
+        ];
+        let lines: Vec<String> = lines("read\t1", &loc, "hxb2")
+            .unwrap()
+            .iter()
+            .map(|line| line.join("\t"))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "HXB2\t9709\t9719\tread 1\t100\t+",
+                "HXB2\t0\t5\tread 1\t100\t+"
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use clap::{ColorChoice, Parser};
+use virust_locator::config::get_styles;
+use virust_locator::consumer::RequestDefaults;
+use virust_locator::consumer::amqp::{self, AmqpOptions};
+use virust_locator::logging::{self, LogFormat};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "viRust-locator-amqp",
+    version = "0.1.0",
+    about = "\x1b[1;91mAMQP consumer for viRust-locator\x1b[0m",
+    color = ColorChoice::Always,
+    styles = get_styles(),
+)]
+struct AmqpArgs {
+    /// Broker URI
+    #[arg(long, default_value = "amqp://127.0.0.1:5672/%2f")]
+    uri: String,
+
+    /// Queue to consume requests from
+    #[arg(long)]
+    input_queue: String,
+
+    /// Queue to publish results to
+    #[arg(long)]
+    output_queue: String,
+
+    /// Number of messages processed concurrently
+    #[arg(long, default_value_t = 4)]
+    concurrency: u16,
+
+    #[command(flatten)]
+    defaults: RequestDefaults,
+
+    /// Format of the log records written to stderr; the level is set with RUST_LOG
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = AmqpArgs::parse();
+    logging::init(args.log_format);
+
+    eprintln!(
+        "viRust-locator consuming from AMQP queue {}, publishing to {}",
+        args.input_queue, args.output_queue
+    );
+    let options = AmqpOptions {
+        uri: args.uri,
+        input_queue: args.input_queue,
+        output_queue: args.output_queue,
+        concurrency: args.concurrency,
+    };
+    amqp::run(options, args.defaults.into())
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+}
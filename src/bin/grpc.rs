@@ -0,0 +1,55 @@
+use clap::{ColorChoice, Parser};
+use std::net::SocketAddr;
+use virust_locator::config::get_styles;
+use virust_locator::limits::LimitArgs;
+use virust_locator::logging::{self, LogFormat};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "viRust-locator-grpc",
+    version = "0.1.0",
+    about = "\x1b[1;91mgRPC service for viRust-locator\x1b[0m",
+    color = ColorChoice::Always,
+    styles = get_styles(),
+)]
+struct GrpcArgs {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:50051")]
+    addr: SocketAddr,
+
+    #[command(flatten)]
+    limits: LimitArgs,
+
+    /// Address to serve Prometheus metrics on (`GET /metrics`); disabled when not set
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Format of the log records written to stderr; the level is set with RUST_LOG
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = GrpcArgs::parse();
+    logging::init(args.log_format);
+
+    #[cfg(feature = "metrics")]
+    if let Some(metrics_addr) = args.metrics_addr {
+        eprintln!("Serving metrics on http://{}/metrics", metrics_addr);
+        tokio::spawn(async move {
+            if let Err(err) = virust_locator::metrics::serve(metrics_addr).await {
+                eprintln!("\x1b[1;91mError:\x1b[0m metrics endpoint failed: {}", err);
+            }
+        });
+    }
+
+    eprintln!("viRust-locator gRPC service listening on {}", args.addr);
+    virust_locator::grpc::serve(args.addr, args.limits.into())
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+}
@@ -0,0 +1,73 @@
+use clap::{ColorChoice, Parser};
+use virust_locator::config::get_styles;
+use virust_locator::consumer::RequestDefaults;
+use virust_locator::consumer::kafka::{self, KafkaOptions};
+use virust_locator::logging::{self, LogFormat};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "viRust-locator-kafka",
+    version = "0.1.0",
+    about = "\x1b[1;91mKafka consumer for viRust-locator\x1b[0m",
+    color = ColorChoice::Always,
+    styles = get_styles(),
+)]
+struct KafkaArgs {
+    /// Comma-separated list of bootstrap brokers
+    #[arg(long, default_value = "127.0.0.1:9092")]
+    brokers: String,
+
+    /// Consumer group id
+    #[arg(long, default_value = "virust-locator")]
+    group_id: String,
+
+    /// Topic to consume requests from
+    #[arg(long)]
+    input_topic: String,
+
+    /// Topic to publish results to
+    #[arg(long)]
+    output_topic: String,
+
+    /// Additional librdkafka client property, as key=value; may be repeated
+    #[arg(short = 'X', long = "property", value_parser = parse_property)]
+    properties: Vec<(String, String)>,
+
+    #[command(flatten)]
+    defaults: RequestDefaults,
+
+    /// Format of the log records written to stderr; the level is set with RUST_LOG
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+fn parse_property(property: &str) -> Result<(String, String), String> {
+    property
+        .split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .ok_or_else(|| format!("Expected key=value, found '{}'", property))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = KafkaArgs::parse();
+    logging::init(args.log_format);
+
+    eprintln!(
+        "viRust-locator consuming from Kafka topic {}, publishing to {}",
+        args.input_topic, args.output_topic
+    );
+    let options = KafkaOptions {
+        brokers: args.brokers,
+        group_id: args.group_id,
+        input_topic: args.input_topic,
+        output_topic: args.output_topic,
+        properties: args.properties,
+    };
+    kafka::run(options, args.defaults.into())
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+}
@@ -0,0 +1,41 @@
+use clap::{ColorChoice, Parser};
+use virust_locator::config::{ServeArgs, get_styles};
+use virust_locator::logging;
+use virust_locator::server::JobQueue;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "viRust-locator-server",
+    version = "0.1.0",
+    about = "\x1b[1;91mHTTP server for viRust-locator\x1b[0m",
+    color = ColorChoice::Always,
+    styles = get_styles(),
+)]
+struct ServerArgs {
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = ServerArgs::parse().serve;
+    logging::init(args.log_format);
+
+    let jobs = args.jobs_dir.map(|dir| {
+        JobQueue::open(dir).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        })
+    });
+
+    eprintln!(
+        "viRust-locator HTTP server listening on http://{}",
+        args.addr
+    );
+    virust_locator::server::serve(args.addr, args.limits.into(), jobs)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+}
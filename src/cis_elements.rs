@@ -0,0 +1,208 @@
+//! The cis-acting RNA elements of the genome located in the queries, and whether they are intact:
+//! the packaging signal (Ψ), the major splice donor (MSD) and the Rev response element (RRE), which
+//! lentiviral vectors keep for packaging and the nuclear export of their transcripts.
+//!
+//! With `--cis-elements`, the elements of `CIS_ELEMENTS` overlapped by a location on HXB2 are
+//! attached to it as `CisElement`s (see `Locator::cis_elements`), with the bases of the element
+//! substituted, deleted and inserted in the query. An element is intact when the location covers
+//! it entirely, with fewer than `MIN_ELEMENT_INDEL` bases deleted and inserted; the `GT` of the
+//! major splice donor must be conserved, as in the `intactness` module.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 730-760, the major splice donor, in the packaging signal.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["AGGGGCGGCGACTGGTGAGTACGCCAAAAAT".to_string()],
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         cis_elements: true,
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let elements: Vec<String> = loc.cis_elements.iter().map(ToString::to_string).collect();
+//! assert_eq!(elements, ["psi 730-760 (partial)", "MSD 744-745 (intact)"]);
+//! ```
+
+use crate::intactness::{self, MAJOR_SPLICE_DONOR, PACKAGING_SIGNAL};
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// The reference of `CIS_ELEMENTS`.
+pub const REFERENCE: &str = intactness::REFERENCE;
+
+/// The elements on the HXB2 genome, in order: their names and first and last bases. The major
+/// splice donor is its `GT`, and the RRE follows the Los Alamos HXB2 landmarks.
+pub static CIS_ELEMENTS: [(&str, usize, usize); 3] = [
+    ("psi", PACKAGING_SIGNAL.0, PACKAGING_SIGNAL.1),
+    ("MSD", MAJOR_SPLICE_DONOR, MAJOR_SPLICE_DONOR + 1),
+    ("RRE", 7710, 8061),
+];
+
+/// Minimum number of bases deleted, or inserted, in an element for it not to be intact, as for a
+/// deletion of the packaging signal in the `intactness` module.
+pub const MIN_ELEMENT_INDEL: usize = intactness::MIN_PACKAGING_SIGNAL_DELETION;
+
+/// An element overlapped by a location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CisElement {
+    /// The element, e.g. `RRE`.
+    pub element: String,
+    /// First covered position of the element on the reference.
+    pub ref_start: RefPosition,
+    /// Last covered position of the element on the reference (inclusive).
+    pub ref_end: RefPosition,
+    /// Whether the whole element is covered.
+    pub complete: bool,
+    /// The number of covered bases of the element substituted in the query.
+    pub substituted: usize,
+    /// The number of covered bases of the element deleted from the query.
+    pub deleted: usize,
+    /// The number of query bases inserted in the element.
+    pub inserted: usize,
+    /// Whether the element is intact, see the module documentation.
+    pub intact: bool,
+}
+
+impl Display for CisElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}-{} (", self.element, self.ref_start, self.ref_end)?;
+        if self.intact {
+            return write!(f, "intact)");
+        }
+        let mut changes = Vec::new();
+        if !self.complete {
+            changes.push("partial".to_string());
+        }
+        for (count, change) in [
+            (self.substituted, "substituted"),
+            (self.deleted, "deleted"),
+            (self.inserted, "inserted"),
+        ] {
+            if count > 0 {
+                changes.push(format!("{} {}", count, change));
+            }
+        }
+        write!(f, "{})", changes.join(", "))
+    }
+}
+
+/// Returns the elements of `CIS_ELEMENTS` overlapped by `loc` on HXB2, in their order. The
+/// elements deleted from the query entirely are reported too, not intact.
+pub fn locate(loc: &Locator) -> Vec<CisElement> {
+    let ref_positions = loc.ref_positions();
+    let columns: Vec<(u8, u8, Option<RefPosition>)> = loc
+        .query_aligned_string
+        .bytes()
+        .zip(loc.ref_aligned_string.bytes())
+        .zip(ref_positions)
+        .map(|((q, r), position)| (q, r, position))
+        .collect();
+    let mut located = Vec::new();
+    for &(name, start, end) in &CIS_ELEMENTS {
+        let (start, end) = (RefPosition::new(start), RefPosition::new(end));
+        let within = |position: RefPosition| start <= position && position <= end;
+        let mut covered: Option<(RefPosition, RefPosition)> = None;
+        let (mut substituted, mut deleted, mut inserted) = (0, 0, 0);
+        let mut last_ref = None;
+        for &(q, r, ref_position) in &columns {
+            match ref_position {
+                Some(position) if within(position) => {
+                    covered = Some((covered.map_or(position, |(first, _)| first), position));
+                    if q == b'-' {
+                        deleted += 1;
+                    } else if !q.eq_ignore_ascii_case(&r) {
+                        substituted += 1;
+                    }
+                }
+                Some(_) => {}
+                // Insertions are in the element when it continues after them.
+                None => inserted += usize::from(last_ref.is_some_and(|p| within(p) && p < end)),
+            }
+            last_ref = ref_position.or(last_ref);
+        }
+        let Some((ref_start, ref_end)) = covered else {
+            continue;
+        };
+        let complete = ref_start == start && ref_end == end;
+        let intact = complete
+            && if name == "MSD" {
+                substituted + deleted + inserted == 0
+            } else {
+                deleted < MIN_ELEMENT_INDEL && inserted < MIN_ELEMENT_INDEL
+            };
+        located.push(CisElement {
+            element: name.to_string(),
+            ref_start,
+            ref_end,
+            complete,
+            substituted,
+            deleted,
+            inserted,
+            intact,
+        });
+    }
+    located
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    fn hxb2(start: usize, end: usize) -> String {
+        let sequence = retrieve_reference_sequence(REFERENCE, "nt")
+            .unwrap()
+            .sequence;
+        String::from_utf8(sequence[start - 1..end].to_vec()).unwrap()
+    }
+
+    fn location(ref_start: usize, query: &str, reference: &str) -> Locator {
+        let ref_len = reference.bytes().filter(|&b| b != b'-').count();
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_start + ref_len - 1),
+            100.0,
+            query.contains('-') || reference.contains('-'),
+            query.to_string(),
+            reference.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_locate() {
+        // HXB2 650-900, with the MSD GT mutated to GC and 12 bases of the packaging signal
+        // deleted.
+        let reference = hxb2(650, 900);
+        let mut query = reference.clone().into_bytes();
+        query[745 - 650] = b'C';
+        query[780 - 650..792 - 650].fill(b'-');
+        let query = String::from_utf8(query).unwrap();
+        let elements = locate(&location(650, &query, &reference));
+        let elements: Vec<String> = elements.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            elements,
+            [
+                "psi 691-812 (1 substituted, 12 deleted)",
+                "MSD 744-745 (1 substituted)"
+            ]
+        );
+
+        // The RRE with 3 bases inserted, and the start of the location outside of it.
+        let reference = format!("{}---{}", hxb2(7700, 7800), hxb2(7801, 8100));
+        let query = format!("{}AAA{}", hxb2(7700, 7800), hxb2(7801, 8100));
+        let elements = locate(&location(7700, &query, &reference));
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].inserted, 3);
+        assert!(elements[0].intact);
+        assert_eq!(elements[0].to_string(), "RRE 7710-8061 (intact)");
+    }
+}
@@ -7,77 +7,1289 @@
 //! - `Args`: Represents the command-line arguments for the tool. It includes fields for the query
 //!   sequence, reference genome, query type, and algorithm choice.
 //!
+//! - `Cli`, `Command`: The command line of the binary, a subcommand or, without one, the `Args`
+//!   of `locate`. `IndexArgs`, `ConvertArgs` and `ServeArgs` are the options of `index`, `convert`
+//!   and `serve`.
+//!
+//! - `CommonArgs`: The options shared by `Args` and the `annotate`, `intactness`, `subtype` and
+//!   `primers` subcommands: the queries, the reference, the scoring and the output. The options of
+//!   the subcommands besides them are `AnnotationArgs`, `SubtypePanelArgs` and `PrimerArgs`. `Args`
+//!   flattens all of them, each option being defined once, in its `common`, `annotation`, `panel`
+//!   and `primer` fields. `AnnotateArgs`, `IntactnessArgs`, `SubtypeArgs` and `PrimersArgs`
+//!   flatten `CommonArgs` with the options of their subcommand, and convert into `Args`.
+//!
+//! # Subcommands
+//!
+//! - `locate`: Locates the query sequences, with the options below. It is the default without a
+//!   subcommand.
+//!
+//! - `annotate`: `locate` with `--annotate`, and only the annotation and tab-separated output
+//!   options.
+//!
+//! - `intactness`: `locate` with `--intactness`, and only the common options.
+//!
+//! - `subtype`: `locate` with `--subtype`, and `--subtype-panel`.
+//!
+//! - `primers`: `locate` with `--primers`, and `--max-primer-mismatches`.
+//!
+//! - `list-references`: Lists the reference sequences, with their lengths and aliases.
+//!
+//! - `index`: Builds the k-mer index of a reference for `--algorithm 3` and writes it to the
+//!   cache directory, see the `index_cache` module.
+//!
+//! - `convert`: Converts reference positions to positions relative to a gene or LTR, as
+//!   `--relative-to` numbers them, or back with `--from-gene`. With `--codons`, to the codons of
+//!   the genes and proteins covering them, or back (see the `coords` module).
+//!
+//! - `serve`: Runs the HTTP server of the `server` module, like `virust-locator-server`.
+//!
 //! # Functions
 //!
 //! - `get_styles`: Configures and returns custom styles for the CLI output, including styles for
 //!   usage, headers, literals, errors, and placeholders.
 //!
 //! - `Args::validate`: Validates the parsed arguments to ensure they meet the expected criteria,
-//!   such as valid query types (`nt` or `aa`), valid reference genomes (`HXB2` or `SIVmm239`),
-//!   and valid nucleotide or amino acid sequences.
+//!   such as valid query types (`nt` or `aa`), valid reference genomes (the strains of
+//!   `reference::REFS`), and valid nucleotide or amino acid sequences.
 //!
 //! # Command-Line Arguments
 //!
 //! - `--query` (`-q`): Specifies the query sequence. This can be a nucleotide or amino acid
 //!   sequence, depending on the `--type-query` argument.
 //!
+//! - `--query-name`: Names the queries of `--query`, in the same order. The names are reported in a
+//!   `query_id` column, as the record IDs of `--input`, in the `query_id` field of `Locator` and in
+//!   the messages about the queries, instead of their positions.
+//!
 //! - `--reference` (`-r`): Specifies the reference genome. The default value is `HXB2`. Valid
-//!   options are `HXB2` or `SIVmm239`.
+//!   options are the strains listed by `list-references` (see `reference::strains`), and their
+//!   aliases.
 //!
 //! - `--type-query` (`-t`): Specifies the type of the query sequence. The default value is `nt`
-//!   (nucleotide). Valid options are `nt` or `aa` (amino acid).
+//!   (nucleotide). Valid options are `nt`, `aa` (amino acid) or `auto`.
 //!
 //! - `--algorithm` (`-a`): Specifies the algorithm to use for the locator. The default value is `1`.
-//!   Valid options are `1` (accurate but slower) or `2` (fast but less accurate, suitable for smaller
-//!   query sequences).
+//!   Valid options are `1` (accurate but slower), `2` (fast but less accurate, suitable for smaller
+//!   query sequences), `3` (seeds and chains k-mers of the queries and aligns them to the chained
+//!   window of the reference only, the fastest for large batches of reads, see the `seed` module)
+//!   or `auto`, which chooses between algorithms 1 and 2 for every query from its length and a
+//!   quick estimate of its divergence from the reference (`locator::choose_algorithm`).
+//!
+//! - `--index-cache`: Directory of the k-mer indexes of the references for `--algorithm 3`, read
+//!   from it, or built and written to it once, see the `index_cache` module. Without it, the
+//!   indexes prebuilt by the `index` subcommand in `index_cache::default_dir` are read.
+//!
+//! - `--anchor-length`, `--anchor-max-dist` and `--min-anchored-length`: The length of the windows
+//!   at both ends of the queries anchored to the reference by algorithm 2 (100), their largest edit
+//!   distance to it (30), and the length of the shortest queries anchored (300); shorter queries
+//!   are aligned with algorithm 1. Divergent queries, such as SIV, may need a larger distance to be
+//!   anchored.
+//!
+//! - `--pipe`: Reads newline-delimited JSON requests from stdin and writes one JSON result per line
+//!   to stdout. The other options provide the defaults for each request. See the `pipe` module.
+//!
+//! - `--input` (`-i`): Reads the query sequences from FASTA or FASTQ files instead of `--query`, and
+//!   writes one tab-separated result row per record to stdout, or to `--output`. Both may be
+//!   object-store URLs, see the `storage` module.
+//!
+//! - `--output`: Writes the results of `--query`, `--input` or `--annotate-dr` to a file or object
+//!   instead of stdout, replaced atomically once they are complete: a failed run leaves no partial
+//!   file. `--output-format` is an alias of `--format`.
+//!
+//! - `--postgres`, `--postgres-schema`, `--postgres-batch-size`: Writes the results of `--input`
+//!   and their mutation lists to a PostgreSQL database. See the `sink` module.
+//!
+//! - `--format-plugin`, `--filter-plugin`: Formats and filters the results of `--input` with
+//!   plugins loaded from dynamic libraries. See the `plugin` module.
+//!
+//! - `--filter`, `--map`: Filters and transforms the results of `--input` with rhai expressions.
+//!   See the `script` module.
+//!
+//! - `--min-query-coverage`: Only writes the results of `--input` whose alignment covers at least
+//!   this fraction of the query, to drop heavily clipped or fragmentary alignments.
+//!
+//! - `--summary`, `--summary-file`: Summarizes the results of `--input` once they are located,
+//!   for the QC of a run: the records located, their mean and median percent identity, the number
+//!   located in every gene and a coverage histogram of the reference, written to stderr, or to a
+//!   file as JSON or tab-separated values. See the `summary` module.
+//!
+//! - `--depth-track`: Writes the number of located records of `--input` covering every position of
+//!   the reference to a file, as bedGraph or as tab-separated values like `samtools depth`, for the
+//!   coverage QC of amplicon runs. See the `depth` module.
+//!
+//! - `--min-identity`, `--min-aln-length`: Report the queries whose best alignment has a lower
+//!   percent identity, or fewer aligned columns, as not located, with the reason, instead of the
+//!   coordinates of a short or spurious alignment.
+//!
+//! - `--coords`, `--zero-based`: Report the reference and query intervals as 0-based, half-open
+//!   intervals (`0-half-open`, or `--zero-based`), as BED files and bedtools expect, instead of
+//!   the default 1-based, inclusive ones (`1-inclusive`), in the delimited output and the LANL
+//!   results table. The header of `--input` and `--workflow` results then names the columns
+//!   `ref_start_0based` and `ref_end_exclusive`. The formats defined with one convention (SAM,
+//!   VCF and GFF3 1-based, BED 0-based, and the JSON results) reject the other one rather than
+//!   silently ignore it.
+//!
+//! - `--relative-to`: Also reports the reference positions of the tab-separated output, and the
+//!   mutation positions written to PostgreSQL, relative to the start of a gene or LTR of the
+//!   reference, e.g. `pol` or `5LTR` (see `reference::GENES`). Positions before the gene are
+//!   negative.
+//!
+//! - `--gene`: Locates amino acid queries on the protein of a gene of the reference only, e.g.
+//!   `pol`, instead of the concatenated proteins, and reports their positions as residues of the
+//!   protein, as the LANL locator does (see `reference::protein_sequence`).
+//!
+//! - `--region`: Aligns the queries to a slice of the reference only: a gene or LTR of the
+//!   reference, e.g. `gag`, or an interval, e.g. `2253-3869`, 1-based and inclusive. Their
+//!   positions are still reported on the whole reference. This speeds up targeted assays, and
+//!   keeps their queries from being placed elsewhere, such as in the other LTR.
+//!
+//! - `--format`: Writes the results as tab-separated (`tsv`) or comma-separated (`csv`) values with
+//!   a header line naming the columns, for `--query` too, whose results otherwise have no header.
+//!   Fields are quoted as with `--delimiter`. `sam` writes SAM records instead, for samtools and
+//!   IGV, see the `sam` module, `lanl` the results table of the LANL HIV Sequence Locator, one row
+//!   per region, for the parsers written for it, see the `lanl` module, and `vcf` the
+//!   substitutions and left-normalized indels of the queries, one sample per query, for bcftools,
+//!   see the `vcf` module. `gff3` writes the locations, and the genes and LTRs they overlap, as
+//!   GFF3 features for genome browsers, see the `gff` module, and `bed` the locations as 0-based,
+//!   half-open BED intervals scored by their percent identity, for bedtools, see the `bed` module.
+//!   `pretty` writes the alignments in blocks of 60 columns, like BLAST, with the positions in the
+//!   margins, for reviewing them by eye, see the `pretty` module. `ndjson` writes one JSON object
+//!   per query or record and line, as soon as it is located, so that the results of large batches
+//!   are never all held in memory (see `Locator::build_iter` and `batch::locate_records_iter`).
+//!
+//! - `--no-align-strings`: Leaves the `query_aligned_string` and `ref_aligned_string` columns,
+//!   which can be several kilobases long, out of the tab-separated output.
+//!
+//! - `--delimiter`: Separates the columns of the tab-separated output with another character, e.g.
+//!   `,` for CSV. Fields containing the delimiter, a double quote or a line break are quoted as in
+//!   RFC 4180 (see `batch::write_row`).
+//!
+//! - `--both-strands`: Also aligns the reverse complement of nucleotide queries, such as reverse
+//!   reads, and reports the orientation aligned with the higher percent identity, in a `strand`
+//!   column (`+` or `-`) of the tab-separated output and the `strand` field of `Locator`.
+//!
+//! - `--annotate`: Reports the genes and LTRs of the reference overlapped by every location, with
+//!   the positions covered and whether the whole gene is (`complete`) or not (`partial`), like the
+//!   region table of the LANL locator. tat and rev are reported per exon, `spliced` when the
+//!   location joins the exons across the splice junction. See `sequence_locator::gene_regions`.
+//!
+//! - `--annotate-dr`: Reports, instead of the locations, the amino acids of the nucleotide queries
+//!   located on HXB2 at the known drug resistance positions of the capsid, protease, reverse
+//!   transcriptase and integrase, and the mutations from HXB2. See the `resistance` module.
+//!
+//! - `--intactness`: Reports, instead of the locations, whether near-full-length nucleotide queries
+//!   located on HXB2 are intact proviruses, or the reasons they are defective: an incomplete
+//!   genome, large deletions, a deleted packaging signal, a mutated major splice donor, G-to-A
+//!   hypermutation, and frameshifts and premature stops in gag, pol and env. See the `intactness`
+//!   module.
+//!
+//! - `--subtype`: Reports, instead of the locations, the subtype of the sequence of a panel closest
+//!   to each nucleotide query, with its percent identity, the closest other subtype and the
+//!   identity margin between them. The panel is `--subtype-panel`, a FASTA file of subtype
+//!   references or consensus sequences. See the `subtype` module.
+//!
+//! - `--primers`, `--max-primer-mismatches`: Reports, instead of the locations, the binding sites
+//!   of the nucleotide queries as primers or probes on the reference, on either strand, with at
+//!   most `--max-primer-mismatches` mismatched bases (3 by default), IUPAC codes matching the bases
+//!   they stand for, and the positions of the mismatches in the primer. See the `primers` module.
+//!
+//! - `--env-regions`: Reports the constant (C1 to C5) and variable (V1 to V5) regions of gp120
+//!   overlapped by every location on HXB2, with their positions on the reference and in the query
+//!   and the length of the query in each, in the `env_regions` field of `Locator` and an
+//!   `env_regions` column of the tab-separated output. See the `env_regions` module.
+//!
+//! - `--ltr-features`: Reports the U3, R and U5 regions, the TAR stem-loop and the
+//!   polyadenylation signal overlapped by every location in the LTRs of HXB2, in the
+//!   `ltr_features` field of `Locator` and an `ltr_features` column of the tab-separated output. A
+//!   location within an LTR, which could be placed in either, has the features of both and is
+//!   flagged in the `ltr_ambiguous` field and column. See the `ltr` module.
+//!
+//! - `--cis-elements`: Reports the packaging signal (Ψ), major splice donor and Rev response
+//!   element overlapped by every location on HXB2, with the bases of each substituted, deleted and
+//!   inserted and whether it is intact, in the `cis_elements` field of `Locator` and a
+//!   `cis_elements` column of the tab-separated output. See the `cis_elements` module.
+//!
+//! - `--amplicon-scheme`: Reports the amplicons of an amplicon or primer scheme, in BED or as
+//!   tab-separated values, overlapped by every location, with the fraction of each it covers, in
+//!   the `amplicons` field of `Locator` and an `amplicons` column of the tab-separated output. See
+//!   the `amplicons` module.
+//!
+//! - `--name-insertions`: Names the insertions of the queries on the genome and in the genes and
+//!   proteins they are inserted in, by the residue they follow and the inserted residues (e.g.
+//!   `RT:69insSS`), in the `insertions` field of `Locator` and an `insertions` column of the
+//!   tab-separated output. See the `insertions` module.
+//!
+//! - `--glycosylation`: Translates the queries in the reading frame of every gene they overlap and
+//!   reports their N-linked glycosylation sites (`N-X-S/T` sequons), numbered on the reference and
+//!   conserved, gained or lost relative to it, in the `glycosylation_sites` field of `Locator` and
+//!   a `glycosylation_sites` column of the tab-separated output. See the `glycosylation` module.
+//!
+//! - `--defects`: Translates nucleotide queries in the reading frame of every gene they overlap,
+//!   and flags the frameshifting indels and premature stop codons in the `defects` field of
+//!   `Locator` and a `defects` column of the tab-separated output. See the `defects` module.
+//!
+//! - `--matrix`: Scores the alignments of amino acid queries with a substitution matrix,
+//!   `blosum62` (the default), `pam40`, `pam120`, `pam200` or `pam250`, with a gap open penalty of
+//!   -11, or with `identity`, +1 per match and -1 per mismatch with a gap open penalty of -5, as
+//!   nucleotide queries are. See `locator::Matrix`.
+//!
+//! - `--match`, `--mismatch`, `--gap-open`, `--gap-extend`: Replace the default scores of the
+//!   alignments, +1 per match and -1 per mismatch for nucleotide queries (and amino acid queries
+//!   scored with `identity`), the gap open penalty of the matrix (-5 for nucleotide queries) and -1
+//!   per gap position, e.g. to tolerate the indels of divergent SIV queries. See
+//!   `locator::LocatorOptions`.
+//!
+//! - `--iupac-aware`: Scores the IUPAC ambiguity codes of nucleotide queries as matches against the
+//!   bases they can stand for, e.g. `R` against `A` or `G` and `N` against any base, and counts them
+//!   as identical in the percent identity, so that consensus sequences full of mixtures are not
+//!   reported with an artificially low identity. See `sequence::iupac_match`.
+//!
+//! - `--max-alignment-memory`: Caps the traceback matrix of an alignment, 1024 MiB by default.
+//!   Longer alignments, such as those of whole genomes wrapping around the end of the reference,
+//!   are computed in linear space instead, taking about twice as long, see the `hirschberg`
+//!   module.
+//!
+//! - `--threads`: Locates the queries on a pool of this many threads, instead of one per CPU, for
+//!   nodes shared by several processes. See `locator::with_threads`.
+//!
+//! - `--nt-coordinates`: Adds the `nt_ref_start` and `nt_ref_end` columns to the tab-separated
+//!   output of amino acid queries, with the nucleotide positions on the reference of the first
+//!   base of the codon of `ref_start` and the last base of the codon of `ref_end`, following the
+//!   exons of tat and rev (see `reference::codon`). They are always in the `nt_ref_start` and
+//!   `nt_ref_end` fields of `Locator`.
+//!
+//! - `--query-coordinates`: Adds the `query_start` and `query_end` columns to the tab-separated
+//!   output, with the first and last query residues aligned to the reference: the residues outside
+//!   of them, such as adapters or the ends of partially aligned reads, are clipped. They are always
+//!   in the `query_start` and `query_end` fields of `Locator`.
+//!
+//! - `--cigar`: Adds a `cigar` column to the tab-separated output, with the alignment as a CIGAR
+//!   string, see `Locator::cigar`.
+//!
+//! - `--score`: Adds the `score` and `edit_distance` columns to the tab-separated output, with the
+//!   score of the alignment with the scoring options and its number of substituted, inserted and
+//!   deleted residues, for cutoffs on the score rather than the percent identity. They are always
+//!   in the `score` and `edit_distance` fields of `Locator`.
+//!
+//! - `--max-hits`, `--min-hit-identity`: Also reports up to `--max-hits` - 1 other matches of
+//!   every query that overlap neither the best location nor each other, with at least
+//!   `--min-hit-identity` percent identity (80 by default), such as the other LTR of a query from
+//!   an LTR. They are listed in an `other_hits` column of the tab-separated output and the
+//!   `other_hits` field of `Locator`.
+//!
+//! - `--split-identity`: Aligns the queries whose best alignment has a lower percent identity
+//!   again as two segments, such as across a large deletion or a junction of distant parts of the
+//!   genome, and reports the split alignment when both segments reach it: `ref_start` and `ref_end`
+//!   are then those of the first and second segments, listed in the `segments` of `Locator`.
+//!
+//! - `--mask-artifacts`: Leaves the long homopolymers and ambiguous runs flagged in the warnings
+//!   of a result out of its percent identity. See the `artifacts` module.
+//!
+//! - `--identity-mode`: Defines the percent identity: `gapped` (the default) divides the identical
+//!   columns of the alignment by all its columns, so a deletion of 35 bases lowers it as much as 35
+//!   substitutions; `ungapped` by the columns without a gap; `blast` by the columns from the first
+//!   to the last aligned residues, leaving out the overhangs at the ends of the query. The mode is
+//!   recorded in the `identity_mode` field of `Locator`, and in an `identity_mode` column of the
+//!   tab-separated output when the option is given. See `locator::IdentityMode`.
+//!
+//! - `--soft-mask`: Treats lower-case residues of the queries as soft-masked, as repeat and
+//!   low-quality masking is conventionally encoded in FASTA files: they are not used to anchor
+//!   algorithm 2, but are aligned like the others.
+//!
+//! - `--min-base-quality`: Masks the bases of FASTQ records with a Phred quality below this as `N`
+//!   before alignment. `N` bases then score 0 against any reference base, instead of counting as
+//!   mismatches, so that low-quality read ends do not pull the alignment. See the `input` module.
+//!
+//! - `--strict-parse`: Rejects the `--input` files with CRLF line endings, blank lines, wrapped
+//!   FASTQ records or duplicate record IDs, which are otherwise accepted with a warning. See the
+//!   `input` module.
+//!
+//! - `--fail-fast`: Stops at the first `--query` that is not located. By default, a query that is
+//!   not located is reported on stderr and as a `NOT_FOUND` row, the remaining queries are located
+//!   and the exit code is 1.
+//!
+//! - `--verify`: Locates every query with both algorithms and reports the differences between
+//!   their positions or percent identities larger than `--verify-tolerance` positions or
+//!   `--verify-identity-tolerance` percentage points, both 0 by default. See the `verify` module.
+//!
+//! - `--log-format`: Writes log records to stderr as human-readable text (`text`, the default) or
+//!   as JSON objects (`json`). See the `logging` module.
+//!
+//! - `--workflow`, `--outdir` (`-o`): Locates the records of each input file and writes one result
+//!   file per input and a run manifest to the output directory, with exit codes that tell data
+//!   problems apart from tool failures. See the `workflow` module.
 //!
 //! # Validation Rules
 //!
-//! - The `type_query` must be either `nt` or `aa`.
-//! - The `algorithm` must be either `1` or `2`.
-//! - The `reference` must be either `HXB2` or `SIVmm239`.
-//! - For nucleotide sequences (`nt`):
-//!   - The sequence must conform to the IUPAC nucleotide alphabet.
-//!   - The sequence length must be greater than 3.
-//! - For amino acid sequences (`aa`):
-//!   - The sequence must conform to the IUPAC protein alphabet.
-//!   - The sequence length must be greater than 3.
+//! - The `type_query` must be either `nt`, `aa` or `auto`. With `auto`, the type of every query is
+//!   inferred from its sequence, see `sequence::infer_type`, and `relative_to` cannot be set.
+//! - The `algorithm` must be either `1`, `2`, `3` or `ALGORITHM_AUTO` (`auto`).
+//! - `verify` compares algorithms 1 and 2, and does not support algorithm 3.
+//! - The `reference` must be one of the strains of `reference::REFS`, or one of their aliases or
+//!   accessions (case-insensitive, see `reference::REFERENCE_ALIASES`), and is replaced by the
+//!   strain name.
+//! - The `min_query_coverage`, if set, must be between 0 and 1.
+//! - `summary` and `summary_file` require nucleotide or amino acid queries.
+//! - `depth_track` requires nucleotide or amino acid queries.
+//! - The `match_score` must be positive, and the `mismatch_score`, `gap_open` and `gap_extend` must
+//!   not be positive.
+//! - `format` `sam`, `lanl`, `vcf`, `gff3` and `bed` require nucleotide queries, and are not
+//!   supported by `workflow`.
+//! - `format` `ndjson` is not supported by `workflow`, nor by `verify` with `query`.
+//! - `format` `pretty` is not supported by `workflow`.
+//! - `coords`, or `zero_based`, must be the convention of `format` `sam`, `vcf`, `gff3`, `bed`,
+//!   `pretty` and `ndjson`, and `1-inclusive` with `relative_to`.
+//! - The `threads`, if set, must be at least 1.
+//! - The `max_hits`, if set, must be at least 1, and the `min_hit_identity` between 0 and 100.
+//! - The `split_identity`, if set, must be between 0 and 100.
+//! - The `min_identity`, if set, must be between 0 and 100.
+//! - The `verify_identity_tolerance` must not be negative.
+//! - The `relative_to` gene, if set, must be a gene of the reference for the type of query.
+//! - The `gene`, if set, requires amino acid queries and must be a gene of the reference.
+//! - The `region`, if set, requires nucleotide or amino acid queries, conflicts with `gene`, and
+//!   must be a gene of the reference or an interval within it.
+//! - `annotate_dr` requires nucleotide queries and the HXB2 reference.
+//! - `defects` requires nucleotide queries.
+//! - `env_regions` requires the HXB2 reference.
+//! - `ltr_features` requires nucleotide queries and the HXB2 reference.
+//! - `cis_elements` requires nucleotide queries and the HXB2 reference.
+//! - `amplicon_scheme` requires nucleotide queries.
+//! - `intactness` requires nucleotide queries and the HXB2 reference.
+//! - `subtype` requires nucleotide queries and `subtype_panel`, which requires `subtype`.
+//! - `primers` requires nucleotide queries.
+//! - Every query is normalized (whitespace and gaps removed, upper case, `U` read as `T` for `nt`)
+//!   and replaced by its normalized form, see the `sequence` module; with `--soft-mask`,
+//!   lower-case letters are kept in lower case. Then:
+//!   - For nucleotide sequences (`nt`):
+//!     - The sequence must conform to the IUPAC nucleotide alphabet.
+//!     - The sequence length must be greater than 3.
+//!   - For amino acid sequences (`aa`):
+//!     - The sequence must conform to the IUPAC protein alphabet.
+//!     - The sequence length must be greater than 3.
 //!
 //! # Errors
 //!
 //! The `Args::validate` function returns an error message if any of the validation rules are
 //! violated, such as invalid query types, invalid sequences, or unsupported reference genomes.
-use bio::alphabets;
+use crate::batch::{DelimitedOptions, Rows};
+use crate::cis_elements;
+use crate::env_regions;
+use crate::format::OutputFormat;
+use crate::intactness;
+#[cfg(feature = "server")]
+use crate::limits::LimitArgs;
+use crate::locator::{Coordinates, IdentityMode, LocatorOptions, Matrix};
+use crate::logging::LogFormat;
+use crate::ltr;
+use crate::position::RefPosition;
+use crate::primers;
+use crate::reference::{self, Gene};
+use crate::resistance;
+use crate::sequence::{self, SequenceRecord};
+use crate::verify::Tolerance;
+#[cfg(feature = "cli")]
 use clap::builder::styling::{AnsiColor, Color};
+#[cfg(feature = "cli")]
 use clap::builder::styling::{Style, Styles};
-use clap::{ColorChoice, Parser};
+#[cfg(feature = "cli")]
+use clap::{ColorChoice, Parser, Subcommand};
+#[cfg(feature = "cli")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
-#[derive(Parser, Debug)]
+/// The command line of the binary: a subcommand, or the options of `locate` without one, as
+/// before the subcommands.
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "viRust-locator",
     version = "0.1.0",
     about = "\x1b[1;91mSimple LANL's HIV locator tool implementation in Rust CLI\x1b[0m",
     color = ColorChoice::Always,
     styles = get_styles(),
+    args_conflicts_with_subcommands = true,
 )]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: Args,
+}
+
+#[cfg(feature = "cli")]
+impl Cli {
+    /// The subcommand, `locate` with the top-level options when there is none.
+    pub fn into_command(self) -> Command {
+        self.command
+            .unwrap_or_else(|| Command::Locate(Box::new(self.args)))
+    }
+}
+
+/// The subcommands of the binary.
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Locate query sequences on the reference (the default without a subcommand)
+    Locate(Box<Args>),
+
+    /// Locate query sequences and report the genes and LTRs they overlap, with their annotations
+    Annotate(Box<AnnotateArgs>),
+
+    /// Screen near-full-length proviral sequences for intactness, as `locate --intactness`
+    Intactness(Box<IntactnessArgs>),
+
+    /// Classify nucleotide queries against a panel of subtype references, as `locate --subtype`
+    Subtype(Box<SubtypeArgs>),
+
+    /// Map primers and probes on the reference, allowing mismatches, as `locate --primers`
+    Primers(Box<PrimersArgs>),
+
+    /// List the reference genomes, their sequence types, lengths and aliases
+    ListReferences,
+
+    /// Build the k-mer index of a reference for --algorithm 3 and write it to the cache directory
+    Index(IndexArgs),
+
+    /// Convert reference positions to positions relative to a gene or LTR, and back
+    Convert(ConvertArgs),
+
+    /// Serve the HTTP API (requires the `server` feature)
+    Serve(ServeArgs),
+}
+
+/// The options of the `annotate` subcommand.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnnotateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[command(flatten)]
+    pub annotation: AnnotationArgs,
+}
+
+#[cfg(feature = "cli")]
+impl From<AnnotateArgs> for Args {
+    fn from(args: AnnotateArgs) -> Self {
+        Args {
+            common: args.common,
+            annotate: true,
+            annotation: args.annotation,
+            ..Default::default()
+        }
+    }
+}
+
+/// The options of the `intactness` subcommand, which has none of its own.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct IntactnessArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+#[cfg(feature = "cli")]
+impl From<IntactnessArgs> for Args {
+    fn from(args: IntactnessArgs) -> Self {
+        Args {
+            common: args.common,
+            intactness: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// The options of the `subtype` subcommand, which requires `--subtype-panel`.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+#[command(mut_arg("subtype_panel", |arg| arg.required(true)))]
+pub struct SubtypeArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[command(flatten)]
+    pub panel: SubtypePanelArgs,
+}
+
+#[cfg(feature = "cli")]
+impl From<SubtypeArgs> for Args {
+    fn from(args: SubtypeArgs) -> Self {
+        Args {
+            common: args.common,
+            subtype: true,
+            panel: args.panel,
+            ..Default::default()
+        }
+    }
+}
+
+/// The options of the `primers` subcommand.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct PrimersArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[command(flatten)]
+    pub primer: PrimerArgs,
+}
+
+#[cfg(feature = "cli")]
+impl From<PrimersArgs> for Args {
+    fn from(args: PrimersArgs) -> Self {
+        Args {
+            common: args.common,
+            primers: true,
+            primer: args.primer,
+            ..Default::default()
+        }
+    }
+}
+
+/// The options of the `index` subcommand.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct IndexArgs {
+    /// Reference genome, one of the strains of list-references or one of their aliases
+    #[arg(short, long, default_value = "HXB2")]
+    pub reference: String,
+
+    /// Types of the sequences to index: nt (nucleotides) or aa (amino acids)
+    #[arg(short, long, num_args = 1.., default_values = ["nt", "aa"], value_parser = ["nt", "aa"])]
+    pub type_query: Vec<String>,
+
+    /// Directory to write the indexes to, by default the cache directory of the user, e.g.
+    /// ~/.cache/virust-locator
+    #[arg(long, value_name = "DIR")]
+    pub index_cache: Option<PathBuf>,
+}
+
+/// The options of the `convert` subcommand.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConvertArgs {
+    /// Positions to convert: reference positions, or positions relative to the gene with
+    /// --from-gene
+    #[arg(required = true, allow_negative_numbers = true)]
+    pub positions: Vec<i64>,
+
+    /// Reference genome, one of the strains of list-references or one of their aliases
+    #[arg(short, long, default_value = "HXB2")]
+    pub reference: String,
+
+    /// Type of the positions: nt (nucleotides) or aa (amino acids)
+    #[arg(short, long, default_value = "nt", value_parser = ["nt", "aa"])]
+    pub type_query: String,
+
+    /// Gene or LTR of the reference the positions are relative to, e.g. pol or 5LTR; with
+    /// --codons, only reports the codons of this gene or protein, e.g. RT
+    #[arg(short, long, required_unless_present = "codons")]
+    pub gene: Option<String>,
+
+    /// Convert positions relative to the gene, or codons of the gene with --codons, to reference
+    /// positions
+    #[arg(long, requires = "gene")]
+    pub from_gene: bool,
+
+    /// Number nucleotide positions as codons of the genes and proteins covering them, see the
+    /// `coords` module
+    #[arg(long)]
+    pub codons: bool,
+}
+
+/// The options of the `serve` subcommand, shared with the `virust-locator-server` binary.
+#[cfg(feature = "cli")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    pub addr: SocketAddr,
+
+    /// Directory where batch jobs are persisted; the /jobs routes are disabled when not set
+    #[arg(long)]
+    pub jobs_dir: Option<PathBuf>,
+
+    #[cfg(feature = "server")]
+    #[command(flatten)]
+    pub limits: LimitArgs,
+
+    /// Format of the log records written to stderr; the level is set with RUST_LOG
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
 pub struct Args {
+    #[cfg_attr(feature = "cli", command(flatten))]
+    pub common: CommonArgs,
+
+    /// Directory to read the k-mer indexes of --algorithm 3 from, or to write them to once built;
+    /// without it, the indexes prebuilt by the index subcommand are read
+    #[cfg_attr(feature = "cli", arg(long, value_name = "DIR"))]
+    pub index_cache: Option<PathBuf>,
+
+    /// Read newline-delimited JSON requests from stdin and write one JSON result per line to stdout
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        conflicts_with_all = ["query", "input", "output", "format", "no_align_strings", "delimiter", "relative_to"]
+    ))]
+    pub pipe: bool,
+
+    /// Workflow mode: locate the records of each --input file, writing one result file per input
+    /// and a run manifest to --outdir
+    #[cfg_attr(feature = "cli", arg(long, requires = "input", requires = "outdir", conflicts_with_all = ["query", "pipe", "output"]))]
+    pub workflow: bool,
+
+    /// Output directory or object-store URL prefix for --workflow
+    #[cfg_attr(feature = "cli", arg(short, long, requires = "workflow"))]
+    pub outdir: Option<PathBuf>,
+
+    /// PostgreSQL connection string to write the results of --input to (requires the `postgres`
+    /// feature)
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub postgres: Option<String>,
+
+    /// Schema of the PostgreSQL result tables
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value = "public", requires = "postgres")
+    )]
+    pub postgres_schema: String,
+
+    /// Number of results written per PostgreSQL transaction
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value_t = 500, requires = "postgres")
+    )]
+    pub postgres_batch_size: usize,
+
+    /// Dynamic library formatting the results of --input, instead of tab-separated values
+    /// (requires the `plugins` feature)
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            requires = "input",
+            conflicts_with_all = ["workflow", "format", "no_align_strings", "delimiter", "relative_to"]
+        )
+    )]
+    pub format_plugin: Option<PathBuf>,
+
+    /// Dynamic library filtering the results of --input; may be repeated, a result is kept if
+    /// every filter keeps it (requires the `plugins` feature)
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub filter_plugin: Vec<PathBuf>,
+
+    /// Expression a result of --input must satisfy to be written, e.g. 'percent_identity > 90.0 &&
+    /// !indel'; may be repeated (requires the `scripting` feature)
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub filter: Vec<String>,
+
+    /// Script transforming each result of --input before it is written, e.g. 'ref_start -= 789';
+    /// may be repeated, applied in order (requires the `scripting` feature)
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub map: Vec<String>,
+
+    /// Only write the results of --input whose alignment covers at least this fraction of the
+    /// query, from 0 to 1; records that were not located are dropped too
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub min_query_coverage: Option<f64>,
+
+    /// Write a summary of the results of --input to stderr once they are located: the records
+    /// located, their mean and median percent identity, the number located in every gene, and the
+    /// mean depth of every 100 positions of the reference
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, requires = "input", conflicts_with = "workflow")
+    )]
+    pub summary: bool,
+
+    /// File or object-store URL to write the summary of --summary to instead of stderr, as JSON,
+    /// or as tab-separated values if its name ends in .tsv
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "FILE",
+            requires = "input",
+            conflicts_with = "workflow"
+        )
+    )]
+    pub summary_file: Option<PathBuf>,
+
+    /// File or object-store URL to write the depth of every position of the reference to, the
+    /// number of located records of --input covering it: as bedGraph if its name ends in
+    /// .bedgraph or .bg, otherwise as tab-separated reference, position and depth
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "FILE",
+            requires = "input",
+            conflicts_with = "workflow"
+        )
+    )]
+    pub depth_track: Option<PathBuf>,
+
+    /// Report reference positions as 0-based, half-open intervals (BED convention) instead of
+    /// 1-based, inclusive ones, in the tab-separated output; the same as --coords 0-half-open
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "format_plugin", "relative_to"]))]
+    pub zero_based: bool,
+
+    /// Convention of the reference and query intervals of every output format: 1-based and
+    /// inclusive like the LANL locator, or 0-based and half-open like BED [default: 1-inclusive]
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_enum,
+        value_name = "CONVENTION",
+        conflicts_with_all = ["pipe", "format_plugin", "zero_based"]
+    ))]
+    pub coords: Option<Coordinates>,
+
+    /// Locate amino acid queries on the protein of this gene only, e.g. pol, and report their
+    /// positions as residues of the protein
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "GENE", conflicts_with = "relative_to")
+    )]
+    pub gene: Option<String>,
+
+    /// Align the queries to this gene or LTR of the reference only, e.g. gag, or to this interval,
+    /// e.g. 2253-3869, still reporting their positions on the whole reference
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "REGION", conflicts_with = "gene")
+    )]
+    pub region: Option<String>,
+
+    #[cfg_attr(feature = "cli", command(flatten))]
+    pub annotation: AnnotationArgs,
+
+    /// Also align the reverse complement of nucleotide queries and report the orientation that
+    /// aligns better
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub both_strands: bool,
+
+    /// Report the genes and LTRs of the reference overlapped by the location, and whether they are
+    /// covered completely or partially
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub annotate: bool,
+
+    /// Report the amino acids of the queries at the known drug resistance positions of CA, PR, RT
+    /// and IN and the mutations from HXB2, instead of the locations
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "workflow", "verify", "format_plugin"]))]
+    pub annotate_dr: bool,
+
+    /// Report whether near-full-length queries are intact proviruses, or why they are defective,
+    /// instead of the locations
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "workflow", "verify", "format_plugin", "annotate_dr"]))]
+    pub intactness: bool,
+
+    /// Report the subtype of the sequence of --subtype-panel closest to each nucleotide query, with
+    /// the identity margin to the closest other subtype, instead of the locations
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "workflow", "verify", "format_plugin", "annotate_dr", "intactness"]))]
+    pub subtype: bool,
+
+    #[cfg_attr(feature = "cli", command(flatten))]
+    pub panel: SubtypePanelArgs,
+
+    /// Report the binding sites of the queries as primers or probes on the reference, on either
+    /// strand, with their mismatches, instead of the locations
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "workflow", "verify", "format_plugin", "annotate_dr", "intactness", "subtype"]))]
+    pub primers: bool,
+
+    #[cfg_attr(feature = "cli", command(flatten))]
+    pub primer: PrimerArgs,
+
+    /// Score the IUPAC ambiguity codes of nucleotide queries (R, Y, N...) as matches against the
+    /// bases they can stand for, and count them as identical in the percent identity, instead of as
+    /// mismatches
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub iupac_aware: bool,
+
+    /// Largest alignment matrix, in MiB; longer alignments are computed in linear space, more
+    /// slowly; 0 computes every alignment in linear space
+    #[cfg_attr(feature = "cli", arg(long, value_name = "MIB", default_value_t = 1024))]
+    pub max_alignment_memory: usize,
+
+    /// Length of the windows at both ends of the queries anchored to the reference by algorithm 2
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", default_value_t = 100))]
+    pub anchor_length: usize,
+
+    /// Largest edit distance of an anchor of algorithm 2 to the reference; divergent queries, such
+    /// as SIV, may need more to be anchored rather than aligned with algorithm 1
+    #[cfg_attr(feature = "cli", arg(long, value_name = "EDITS", default_value_t = 30))]
+    pub anchor_max_dist: usize,
+
+    /// Length of the shortest queries located with algorithm 2, or chosen for it by --algorithm
+    /// auto; shorter queries are aligned with algorithm 1
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", default_value_t = 300))]
+    pub min_anchored_length: usize,
+
+    /// Also report the nucleotide positions of the codons of amino acid locations, in the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub nt_coordinates: bool,
+
+    /// Also report the first and last query residues aligned to the reference, in the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub query_coordinates: bool,
+
+    /// Also report the CIGAR string of the alignments, in the tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub cigar: bool,
+
+    /// Also report the score of the alignments and their edit distance to the reference, in the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub score: bool,
+
+    /// Report up to this many non-overlapping matches of every query, the best one first
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N"))]
+    pub max_hits: Option<usize>,
+
+    /// Minimum percent identity of the matches reported besides the best one with --max-hits
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "PERCENT", default_value_t = 80.0)
+    )]
+    pub min_hit_identity: f64,
+
+    /// Align the queries whose best alignment has a lower percent identity as two segments, such
+    /// as across a large deletion or a junction, and report the split alignment when both segments
+    /// reach it
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PERCENT"))]
+    pub split_identity: Option<f64>,
+
+    /// Report the queries whose best alignment has a lower percent identity as not located, with
+    /// the reason
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PERCENT"))]
+    pub min_identity: Option<f64>,
+
+    /// Report the queries whose best alignment is shorter, in columns, as not located, with the
+    /// reason
+    #[cfg_attr(feature = "cli", arg(long, value_name = "LENGTH"))]
+    pub min_aln_length: Option<usize>,
+
+    /// Leave the long homopolymers and runs of ambiguous residues flagged in the warnings out of
+    /// the percent identity
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub mask_artifacts: bool,
+
+    /// Definition of the percent identity: gapped counts every gap position as a difference,
+    /// ungapped only the substitutions, and blast leaves out the overhangs at the ends, as BLAST
+    /// does; recorded in an identity_mode column of the tab-separated output [default: gapped]
+    #[cfg_attr(feature = "cli", arg(long, value_enum, value_name = "MODE"))]
+    pub identity_mode: Option<IdentityMode>,
+
+    /// Treat lower-case residues of the queries as soft-masked: they are not used to anchor
+    /// algorithm 2, but are aligned like the others
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub soft_mask: bool,
+
+    /// Mask the bases of FASTQ records with a Phred quality below this as N; N bases then score
+    /// neither as matches nor as mismatches in the alignment
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PHRED", requires = "input"))]
+    pub min_base_quality: Option<u8>,
+
+    /// Stop at the first query of --query that is not located instead of reporting a NOT_FOUND
+    /// row and locating the remaining queries
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["input", "pipe"]))]
+    pub fail_fast: bool,
+
+    /// Also locate every query with the other algorithm and report where the two disagree
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["pipe", "format_plugin"]))]
+    pub verify: bool,
+
+    /// Largest difference between the positions found by the two algorithms not reported by
+    /// --verify
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "POSITIONS",
+            default_value_t = 0,
+            requires = "verify"
+        )
+    )]
+    pub verify_tolerance: usize,
+
+    /// Largest difference between the percent identities found by the two algorithms not
+    /// reported by --verify, in percentage points
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "PERCENT",
+            default_value_t = 0.0,
+            requires = "verify"
+        )
+    )]
+    pub verify_identity_tolerance: f64,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            common: CommonArgs::default(),
+            index_cache: None,
+            pipe: false,
+            workflow: false,
+            outdir: None,
+            postgres: None,
+            postgres_schema: "public".to_string(),
+            postgres_batch_size: 500,
+            format_plugin: None,
+            filter_plugin: Vec::new(),
+            filter: Vec::new(),
+            map: Vec::new(),
+            min_query_coverage: None,
+            summary: false,
+            summary_file: None,
+            depth_track: None,
+            zero_based: false,
+            coords: None,
+            gene: None,
+            region: None,
+            both_strands: false,
+            annotate: false,
+            annotate_dr: false,
+            annotation: AnnotationArgs::default(),
+            intactness: false,
+            subtype: false,
+            panel: SubtypePanelArgs::default(),
+            primers: false,
+            primer: PrimerArgs::default(),
+            iupac_aware: false,
+            max_alignment_memory: 1024,
+            anchor_length: 100,
+            anchor_max_dist: 30,
+            min_anchored_length: 300,
+            nt_coordinates: false,
+            query_coordinates: false,
+            cigar: false,
+            score: false,
+            max_hits: None,
+            min_hit_identity: 80.0,
+            split_identity: None,
+            min_identity: None,
+            min_aln_length: None,
+            mask_artifacts: false,
+            identity_mode: None,
+            soft_mask: false,
+            min_base_quality: None,
+            fail_fast: false,
+            verify: false,
+            verify_tolerance: 0,
+            verify_identity_tolerance: 0.0,
+        }
+    }
+}
+
+/// The options shared by `Args` and the subcommands locating queries: the queries, the reference,
+/// the scoring of the alignments and the output.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct CommonArgs {
     /// Query sequence
-    #[arg(short, long, use_value_delimiter = true, value_delimiter = ' ', num_args = 1..)]
+    #[cfg_attr(feature = "cli", arg(short, long, use_value_delimiter = true, value_delimiter = ' ', num_args = 1..))]
     pub query: Vec<String>,
 
-    /// Reference genome, either HXB2 or SIVmm239
-    #[arg(short, long, default_value = "HXB2")]
+    /// Names of the queries, one per --query in the same order, reported in a query_id column
+    /// instead of their positions
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "NAME",
+        requires = "query",
+        use_value_delimiter = true,
+        value_delimiter = ' ',
+        num_args = 1..
+    ))]
+    pub query_name: Vec<String>,
+
+    /// FASTA or FASTQ query files, optionally gzip- or bgzip-compressed, as paths or object-store
+    /// URLs (s3://, gs://, az://, https://)
+    #[cfg_attr(feature = "cli", arg(short, long, num_args = 1.., conflicts_with = "query"))]
+    pub input: Vec<PathBuf>,
+
+    /// Fail on CRLF line endings, blank lines, wrapped FASTQ records and duplicate record IDs in
+    /// the --input files instead of accepting them with a warning
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub strict_parse: bool,
+
+    /// Reference genome, one of the strains of list-references; aliases and accessions such as
+    /// hxb2, K03455 or SIVmac239 are accepted
+    #[cfg_attr(feature = "cli", arg(short, long, default_value = "HXB2"))]
     pub reference: String,
 
-    /// Type of query, either nt or aa
-    #[arg(short, long, default_value = "nt")]
+    /// Type of query, either nt or aa, or auto to infer it for every sequence
+    #[cfg_attr(feature = "cli", arg(short, long, default_value = "nt"))]
     pub type_query: String,
 
-    /// algorithm for locator, 1 is accurate but slower, 2 is fast but less accurate, suitable for smaller query sequences
-    #[arg(short, long, default_value_t = 1)]
+    /// algorithm for locator, 1 is accurate but slower, 2 is fast but less accurate, suitable for
+    /// smaller query sequences, 3 seeds and chains k-mers for large batches of reads, or auto to
+    /// choose 1 or 2 for every query
+    #[cfg_attr(feature = "cli", arg(short, long, default_value_t = 1, value_parser = parse_algorithm))]
     pub algorithm: u8,
+
+    /// Substitution matrix scoring the alignments of amino acid queries
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value_t = Matrix::default()))]
+    pub matrix: Matrix,
+
+    /// Score of a match, for nucleotide queries and amino acid queries scored with the identity
+    /// matrix
+    #[cfg_attr(
+        feature = "cli",
+        arg(long = "match", value_name = "SCORE", default_value_t = 1)
+    )]
+    pub match_score: i32,
+
+    /// Score of a mismatch, for nucleotide queries and amino acid queries scored with the identity
+    /// matrix
+    #[cfg_attr(feature = "cli", arg(
+        long = "mismatch",
+        value_name = "SCORE",
+        default_value_t = -1,
+        allow_negative_numbers = true
+    ))]
+    pub mismatch_score: i32,
+
+    /// Penalty for opening a gap [default: -5, or -11 with the substitution matrices]
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "PENALTY", allow_negative_numbers = true)
+    )]
+    pub gap_open: Option<i32>,
+
+    /// Penalty for every position of a gap
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "PENALTY",
+        default_value_t = -1,
+        allow_negative_numbers = true
+    ))]
+    pub gap_extend: i32,
+
+    /// Number of threads locating the queries [default: one per CPU]
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N"))]
+    pub threads: Option<usize>,
+
+    /// File or object-store URL to write the results to, instead of stdout. Files are written to
+    /// a temporary file renamed over them once the results are complete
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub output: Option<PathBuf>,
+
+    /// Format of the log records written to stderr; the level is set with RUST_LOG
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value_t = LogFormat::Text))]
+    pub log_format: LogFormat,
 }
 
+impl Default for CommonArgs {
+    fn default() -> Self {
+        CommonArgs {
+            query: Vec::new(),
+            query_name: Vec::new(),
+            input: Vec::new(),
+            strict_parse: false,
+            reference: "HXB2".to_string(),
+            type_query: "nt".to_string(),
+            algorithm: 1,
+            matrix: Matrix::default(),
+            match_score: 1,
+            mismatch_score: -1,
+            gap_open: None,
+            gap_extend: -1,
+            threads: None,
+            output: None,
+            log_format: LogFormat::Text,
+        }
+    }
+}
+
+/// The options of `annotate` besides `CommonArgs`: the annotations of the locations and the
+/// tab-separated output they are reported in.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct AnnotationArgs {
+    /// Write the results as tab- or comma-separated values with a header line, also for --query;
+    /// fields are quoted as needed. sam writes SAM records of nucleotide queries, lanl the LANL
+    /// results table, vcf their substitutions and indels, gff3 their locations and the regions
+    /// they overlap as GFF3 features, bed their 0-based, half-open intervals, pretty the
+    /// alignments in blocks for reading, ndjson one JSON object per line as the queries are located
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            visible_alias = "output-format",
+            value_enum,
+            conflicts_with = "delimiter"
+        )
+    )]
+    pub format: Option<OutputFormat>,
+
+    /// Leave the query_aligned_string and ref_aligned_string columns out of the tab-separated
+    /// output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub no_align_strings: bool,
+
+    /// Column delimiter of the tab-separated output: a single character, or `tab`; fields
+    /// containing it are quoted
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_name = "CHAR",
+        default_value = "tab",
+        value_parser = parse_delimiter
+    ))]
+    pub delimiter: char,
+
+    /// Also report positions relative to the start of this gene or LTR of the reference, e.g. pol
+    /// or 5LTR, in the tab-separated output and the mutation lists
+    #[cfg_attr(feature = "cli", arg(long, value_name = "GENE"))]
+    pub relative_to: Option<String>,
+
+    /// Flag the frameshifts and premature stop codons in the genes overlapped by nucleotide
+    /// queries, in a defects column of the tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub defects: bool,
+
+    /// Report the constant and variable regions of gp120 (C1-C5, V1-V5) overlapped by the
+    /// locations on HXB2, with their query positions and lengths, in an env_regions column of the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub env_regions: bool,
+
+    /// Report the U3, R and U5 regions, TAR and the polyadenylation signal overlapped by the
+    /// locations in the LTRs of HXB2, in ltr_features and ltr_ambiguous columns of the
+    /// tab-separated output, with the features of both LTRs when either could hold the location
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub ltr_features: bool,
+
+    /// Report the packaging signal (psi), major splice donor and Rev response element overlapped by
+    /// the locations on HXB2, and whether they are intact, in a cis_elements column of the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub cis_elements: bool,
+
+    /// Amplicon or primer scheme (BED, or tab-separated name, start and end), reporting the
+    /// amplicons overlapped by the locations and the fraction of each covered in an amplicons
+    /// column of the tab-separated output
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE"))]
+    pub amplicon_scheme: Option<PathBuf>,
+
+    /// Report the N-linked glycosylation sites (N-X-S/T sequons) of the queries in the genes they
+    /// overlap, numbered on the reference and marked gained or lost relative to it, in a
+    /// glycosylation_sites column of the tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub glycosylation: bool,
+
+    /// Name the insertions of the queries relative to the reference, on the genome and in the genes
+    /// and proteins they are inserted in (e.g. RT:69insSS), in an insertions column of the
+    /// tab-separated output
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub name_insertions: bool,
+}
+
+impl Default for AnnotationArgs {
+    fn default() -> Self {
+        AnnotationArgs {
+            format: None,
+            no_align_strings: false,
+            delimiter: '\t',
+            relative_to: None,
+            defects: false,
+            env_regions: false,
+            ltr_features: false,
+            cis_elements: false,
+            amplicon_scheme: None,
+            glycosylation: false,
+            name_insertions: false,
+        }
+    }
+}
+
+/// The panel of `subtype`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct SubtypePanelArgs {
+    /// FASTA file of the subtype references or consensus sequences of --subtype, their IDs starting
+    /// with the subtype (e.g. B.FR.83.HXB2); required by --subtype
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FASTA"))]
+    pub subtype_panel: Option<PathBuf>,
+}
+
+/// The mismatches allowed by `primers`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct PrimerArgs {
+    /// Maximum number of bases of a primer not matching the reference at its binding sites, with
+    /// --primers
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "N", default_value_t = primers::DEFAULT_MAX_MISMATCHES)
+    )]
+    pub max_primer_mismatches: usize,
+}
+
+impl Default for PrimerArgs {
+    fn default() -> Self {
+        PrimerArgs {
+            max_primer_mismatches: primers::DEFAULT_MAX_MISMATCHES,
+        }
+    }
+}
+
+/// The `algorithm` of `--algorithm auto`: the algorithm of every query is chosen with
+/// `locator::choose_algorithm`.
+pub const ALGORITHM_AUTO: u8 = 0;
+
+/// Parses `--algorithm`: a number, or `auto` for `ALGORITHM_AUTO`.
+#[cfg(feature = "cli")]
+fn parse_algorithm(value: &str) -> Result<u8, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(ALGORITHM_AUTO);
+    }
+    value
+        .parse()
+        .map_err(|_| format!("invalid algorithm '{}', expected 1, 2, 3 or auto", value))
+}
+
+/// Parses `--delimiter`: a single character other than a letter, a digit, a double quote or a
+/// line break, or `tab`.
+#[cfg(feature = "cli")]
+fn parse_delimiter(value: &str) -> Result<char, String> {
+    if value.eq_ignore_ascii_case("tab") || value == "\\t" {
+        return Ok('\t');
+    }
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_alphanumeric() && !matches!(c, '"' | '\n' | '\r') => Ok(c),
+        _ => Err(format!(
+            "invalid delimiter '{}', expected a single character other than a letter, a digit, \
+             a double quote or a line break, or tab",
+            value
+        )),
+    }
+}
+
+#[cfg(feature = "cli")]
 pub fn get_styles() -> Styles {
     Styles::styled()
         .usage(
@@ -113,49 +1325,403 @@ pub fn get_styles() -> Styles {
 }
 
 impl Args {
-    pub fn validate(self) -> Result<Args, String> {
-        if self.type_query != "nt" && self.type_query != "aa" {
-            return Err("Type of query must be either 'nt' or 'aa'".to_string());
+    /// Convention of the positions in the output: `coords`, or 0-based with `zero_based`.
+    pub fn coordinates(&self) -> Coordinates {
+        match self.coords {
+            Some(coordinates) => coordinates,
+            None if self.zero_based => Coordinates::ZeroBased,
+            None => Coordinates::OneBased,
         }
-        if self.algorithm != 1 && self.algorithm != 2 {
-            return Err("Algorithm must be either 1 or 2".to_string());
+    }
+
+    /// Options of the tab-separated output: `coordinates`, `relative_gene`, `--no-align-strings`
+    /// and the delimiter of `--format` or `--delimiter`.
+    pub fn delimited_options(&self) -> DelimitedOptions<'static> {
+        DelimitedOptions {
+            coordinates: self.coordinates(),
+            relative_to: self.relative_gene(),
+            no_align_strings: self.annotation.no_align_strings,
+            delimiter: self
+                .annotation
+                .format
+                .map_or(self.annotation.delimiter, OutputFormat::delimiter),
+            strand: self.both_strands,
+            regions: self.annotate,
+            other_hits: self.max_hits.is_some(),
+            nt_coordinates: self.nt_coordinates,
+            query_coordinates: self.query_coordinates,
+            cigar: self.cigar,
+            score: self.score,
+            identity_mode: self.identity_mode.is_some(),
+            defects: self.annotation.defects,
+            env_regions: self.annotation.env_regions,
+            ltr_features: self.annotation.ltr_features,
+            cis_elements: self.annotation.cis_elements,
+            amplicons: self.annotation.amplicon_scheme.is_some(),
+            glycosylation: self.annotation.glycosylation,
+            insertions: self.annotation.name_insertions,
+            rows: Rows::Records,
+            header: true,
         }
-        if self.reference != "HXB2" && self.reference != "SIVmm239" {
-            return Err("Reference genome must be either 'HXB2' or 'SIVmm239'".to_string());
+    }
+
+    /// The scores of the alignments: `--matrix`, `--match`, `--mismatch`, `--gap-open`,
+    /// `--gap-extend` and `--iupac-aware`, `--max-alignment-memory` and `--threads`, and the
+    /// anchors of algorithm 2: `--anchor-length`, `--anchor-max-dist` and `--min-anchored-length`.
+    pub fn locator_options(&self) -> LocatorOptions {
+        LocatorOptions {
+            matrix: self.common.matrix,
+            match_score: self.common.match_score,
+            mismatch_score: self.common.mismatch_score,
+            gap_open: self.common.gap_open,
+            gap_extend: self.common.gap_extend,
+            max_alignment_memory: self.max_alignment_memory.saturating_mul(1 << 20),
+            threads: self.common.threads,
+            iupac_aware: self.iupac_aware,
+            anchor_length: self.anchor_length,
+            anchor_max_dist: self.anchor_max_dist,
+            min_anchored_length: self.min_anchored_length,
+        }
+    }
+
+    /// The `--relative-to` gene, once the arguments are validated.
+    pub fn relative_gene(&self) -> Option<&'static Gene<'static>> {
+        let name = self.annotation.relative_to.as_deref()?;
+        reference::retrieve_gene(&self.common.reference, &self.common.type_query, name).ok()
+    }
+
+    /// The first and last positions of the `--region` on the reference: those of the gene or LTR
+    /// it names, or of its `start-end` interval. Errors on an unknown gene, or an interval that is
+    /// empty or outside of the reference.
+    pub fn region_interval(&self) -> Result<Option<(RefPosition, RefPosition)>, String> {
+        let Some(region) = self.region.as_deref() else {
+            return Ok(None);
+        };
+        let interval = region
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)));
+        let (start, end): (usize, usize) = match interval {
+            Some(interval) => interval,
+            None => {
+                let gene = reference::retrieve_gene(
+                    &self.common.reference,
+                    &self.common.type_query,
+                    region,
+                )
+                .map_err(|err| err.to_string())?;
+                (gene.start.get(), gene.end.get())
+            }
+        };
+        let length =
+            reference::retrieve_reference_sequence(&self.common.reference, &self.common.type_query)
+                .map_err(|err| err.to_string())?
+                .sequence
+                .len();
+        if start == 0 || end < start || end > length {
+            return Err(format!(
+                "--region {} must be within the reference, 1-{}",
+                region, length
+            ));
+        }
+        Ok(Some((RefPosition::new(start), RefPosition::new(end))))
+    }
+
+    /// The identifiers of the queries of `--query`: their `--query-name`, or their 1-based
+    /// positions.
+    pub fn query_ids(&self) -> Vec<String> {
+        if self.common.query_name.is_empty() {
+            (1..=self.common.query.len())
+                .map(|i| i.to_string())
+                .collect()
+        } else {
+            self.common.query_name.clone()
         }
-        if self.query.is_empty() {
+    }
+
+    /// The type of `query`: `type_query`, or the type inferred from the sequence (see
+    /// `sequence::infer_type`) if it is `auto`.
+    pub fn type_of<'a>(&'a self, query: &str) -> &'a str {
+        match self.common.type_query.as_str() {
+            "auto" => sequence::infer_type(query),
+            type_query => type_query,
+        }
+    }
+
+    /// The tolerance of `--verify`.
+    pub fn tolerance(&self) -> Tolerance {
+        Tolerance {
+            positions: self.verify_tolerance,
+            identity: self.verify_identity_tolerance,
+        }
+    }
+
+    pub fn validate(self) -> Result<Args, String> {
+        let mut args = self.validate_options()?;
+        // The space-delimited values of --query split the header of a pasted FASTA record.
+        args.common.query = sequence::rejoin_fasta_headers(args.common.query);
+        if args.common.query.is_empty() && args.common.input.is_empty() && !args.pipe {
             return Err(
                 "Query sequence cannot be empty, run `virust-locator -h` for more information"
                     .to_string(),
             );
         }
-        if self.type_query == "nt" && !self.query.is_empty() {
-            let alphabet = alphabets::dna::iupac_alphabet();
-            for q in &self.query {
-                if alphabet.is_word(q.as_bytes()) {
-                    if q.len() <= 3 {
-                        return Err("Nucleotide sequence length too short".to_string());
-                    } else {
-                        return Ok(self);
-                    }
-                } else {
-                    return Err("Invalid nucleotide sequence: ".to_string() + q);
-                }
+        if !args.common.query_name.is_empty()
+            && args.common.query_name.len() != args.common.query.len()
+        {
+            return Err(format!(
+                "--query-name must name every query ({} names for {} queries)",
+                args.common.query_name.len(),
+                args.common.query.len()
+            ));
+        }
+        args.common.query = args
+            .common
+            .query
+            .iter()
+            .enumerate()
+            .map(|(i, q)| args.validate_query(i + 1, q))
+            .collect::<Result<Vec<String>, String>>()?;
+        Ok(args)
+    }
+
+    /// The part of `Args::validate` checking the options, whatever the queries: the reference is
+    /// resolved to its strain, and the queries are left as they are.
+    pub fn validate_options(mut self) -> Result<Args, String> {
+        if !matches!(self.common.type_query.as_str(), "nt" | "aa" | "auto") {
+            return Err("Type of query must be either 'nt', 'aa' or 'auto'".to_string());
+        }
+        if !matches!(self.common.algorithm, 1 | 2 | 3 | ALGORITHM_AUTO) {
+            return Err("Algorithm must be either 1, 2, 3 or auto".to_string());
+        }
+        if self.verify && self.common.algorithm == 3 {
+            return Err("--verify compares algorithms 1 and 2, not algorithm 3".to_string());
+        }
+        self.common.reference = reference::resolve_reference(&self.common.reference)
+            .map_err(|err| err.to_string())?
+            .to_string();
+        if let Some(coverage) = self.min_query_coverage
+            && !(0.0..=1.0).contains(&coverage)
+        {
+            return Err("Minimum query coverage must be between 0 and 1".to_string());
+        }
+        if (self.summary || self.summary_file.is_some()) && self.common.type_query == "auto" {
+            return Err("--summary requires --type-query nt or aa".to_string());
+        }
+        if self.depth_track.is_some() && self.common.type_query == "auto" {
+            return Err("--depth-track requires --type-query nt or aa".to_string());
+        }
+        if self.common.match_score <= 0 {
+            return Err("Match score must be positive".to_string());
+        }
+        if self.common.mismatch_score > 0
+            || self.common.gap_extend > 0
+            || self.common.gap_open.is_some_and(|p| p > 0)
+        {
+            return Err("Mismatch score and gap penalties must not be positive".to_string());
+        }
+        if self.common.threads == Some(0) {
+            return Err("Number of threads must be at least 1".to_string());
+        }
+        if self.anchor_length == 0 {
+            return Err("Anchor length must be at least 1".to_string());
+        }
+        if self.anchor_max_dist >= self.anchor_length {
+            return Err("Anchor edit distance must be less than the anchor length".to_string());
+        }
+        if self.min_anchored_length < 2 * self.anchor_length {
+            return Err(
+                "Minimum anchored length must be at least twice the anchor length".to_string(),
+            );
+        }
+        if self.max_hits == Some(0) {
+            return Err("Maximum number of hits must be at least 1".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.min_hit_identity) {
+            return Err("Minimum hit identity must be between 0 and 100".to_string());
+        }
+        if let Some(identity) = self.split_identity
+            && !(0.0..=100.0).contains(&identity)
+        {
+            return Err("Split identity must be between 0 and 100".to_string());
+        }
+        if let Some(identity) = self.min_identity
+            && !(0.0..=100.0).contains(&identity)
+        {
+            return Err("Minimum identity must be between 0 and 100".to_string());
+        }
+        if self.verify_identity_tolerance < 0.0 {
+            return Err("Verify identity tolerance must not be negative".to_string());
+        }
+        if self.both_strands && self.common.type_query == "aa" {
+            return Err("--both-strands requires nucleotide queries".to_string());
+        }
+        if self.iupac_aware && self.common.type_query == "aa" {
+            return Err("--iupac-aware requires nucleotide queries".to_string());
+        }
+        if self.annotate_dr && self.common.type_query != "nt" {
+            return Err("--annotate-dr requires --type-query nt".to_string());
+        }
+        if self.annotation.defects && self.common.type_query != "nt" {
+            return Err("--defects requires --type-query nt".to_string());
+        }
+        if self.annotate_dr && !resistance::supports(&self.common.reference) {
+            return Err(format!(
+                "--annotate-dr requires the {} reference",
+                resistance::REFERENCE
+            ));
+        }
+        if self.annotation.env_regions && self.common.reference != env_regions::REFERENCE {
+            return Err(format!(
+                "--env-regions requires the {} reference",
+                env_regions::REFERENCE
+            ));
+        }
+        if self.annotation.ltr_features && self.common.type_query != "nt" {
+            return Err("--ltr-features requires --type-query nt".to_string());
+        }
+        if self.annotation.ltr_features && self.common.reference != ltr::REFERENCE {
+            return Err(format!(
+                "--ltr-features requires the {} reference",
+                ltr::REFERENCE
+            ));
+        }
+        if self.annotation.cis_elements && self.common.type_query != "nt" {
+            return Err("--cis-elements requires --type-query nt".to_string());
+        }
+        if self.annotation.cis_elements && self.common.reference != cis_elements::REFERENCE {
+            return Err(format!(
+                "--cis-elements requires the {} reference",
+                cis_elements::REFERENCE
+            ));
+        }
+        if self.annotation.amplicon_scheme.is_some() && self.common.type_query != "nt" {
+            return Err("--amplicon-scheme requires --type-query nt".to_string());
+        }
+        if self.intactness && self.common.type_query != "nt" {
+            return Err("--intactness requires --type-query nt".to_string());
+        }
+        if self.intactness && self.common.reference != intactness::REFERENCE {
+            return Err(format!(
+                "--intactness requires the {} reference",
+                intactness::REFERENCE
+            ));
+        }
+        if self.subtype && self.common.type_query != "nt" {
+            return Err("--subtype requires --type-query nt".to_string());
+        }
+        if self.panel.subtype_panel.is_some() && !self.subtype {
+            return Err("--subtype-panel requires --subtype".to_string());
+        }
+        if self.subtype && self.panel.subtype_panel.is_none() {
+            return Err("--subtype requires --subtype-panel".to_string());
+        }
+        if self.primers && self.common.type_query != "nt" {
+            return Err("--primers requires --type-query nt".to_string());
+        }
+        if let Some(
+            format @ (OutputFormat::Sam
+            | OutputFormat::Lanl
+            | OutputFormat::Vcf
+            | OutputFormat::Gff3
+            | OutputFormat::Bed),
+        ) = self.annotation.format
+            && (self.common.type_query == "aa" || self.workflow)
+        {
+            let name = match format {
+                OutputFormat::Sam => "sam",
+                OutputFormat::Lanl => "lanl",
+                OutputFormat::Vcf => "vcf",
+                OutputFormat::Gff3 => "gff3",
+                _ => "bed",
+            };
+            return Err(format!(
+                "--format {} requires nucleotide queries, without --workflow",
+                name
+            ));
+        }
+        if self.annotation.format == Some(OutputFormat::Ndjson)
+            && (self.workflow || self.verify && self.common.input.is_empty())
+        {
+            return Err(
+                "--format ndjson is not supported by --workflow, nor by --verify with --query"
+                    .to_string(),
+            );
+        }
+        if self.annotation.format == Some(OutputFormat::Pretty) && self.workflow {
+            return Err("--format pretty is not supported by --workflow".to_string());
+        }
+        // The file formats that define their convention cannot follow --coords.
+        let fixed = match self.annotation.format {
+            Some(OutputFormat::Sam) => Some(("sam", Coordinates::OneBased)),
+            Some(OutputFormat::Vcf) => Some(("vcf", Coordinates::OneBased)),
+            Some(OutputFormat::Gff3) => Some(("gff3", Coordinates::OneBased)),
+            Some(OutputFormat::Bed) => Some(("bed", Coordinates::ZeroBased)),
+            Some(OutputFormat::Pretty) => Some(("pretty", Coordinates::OneBased)),
+            Some(OutputFormat::Ndjson) => Some(("ndjson", Coordinates::OneBased)),
+            _ => None,
+        };
+        if let Some((name, fixed)) = fixed
+            && (self.coords.is_some() || self.zero_based)
+            && self.coordinates() != fixed
+        {
+            return Err(format!(
+                "--format {} always reports {} coordinates, not {}",
+                name,
+                fixed,
+                self.coordinates()
+            ));
+        }
+        if self.annotation.relative_to.is_some() && self.coordinates() == Coordinates::ZeroBased {
+            return Err("--relative-to requires 1-inclusive coordinates".to_string());
+        }
+        if self.nt_coordinates && self.common.type_query == "nt" {
+            return Err("--nt-coordinates requires amino acid queries".to_string());
+        }
+        if let Some(name) = &self.gene {
+            if self.common.type_query != "aa" {
+                return Err("--gene requires --type-query aa".to_string());
             }
-        } else if self.type_query == "aa" && !self.query.is_empty() {
-            let alphabet = alphabets::protein::iupac_alphabet();
-            for q in &self.query {
-                if alphabet.is_word(q.as_bytes()) {
-                    if q.len() <= 3 {
-                        return Err("Nucleotide sequence length too short".to_string());
-                    } else {
-                        return Ok(self);
-                    }
-                } else {
-                    return Err("Invalid amino acid sequence: ".to_string() + q);
-                }
+            reference::retrieve_gene(&self.common.reference, &self.common.type_query, name)
+                .map_err(|err| err.to_string())?;
+        }
+        if self.region.is_some() {
+            if self.common.type_query == "auto" {
+                return Err("--region requires --type-query nt or aa".to_string());
+            }
+            if self.gene.is_some() {
+                return Err("--region conflicts with --gene".to_string());
+            }
+            self.region_interval()?;
+        }
+        if let Some(name) = &self.annotation.relative_to {
+            if self.common.type_query == "auto" {
+                return Err("--relative-to requires --type-query nt or aa".to_string());
             }
+            reference::retrieve_gene(&self.common.reference, &self.common.type_query, name)
+                .map_err(|err| err.to_string())?;
         }
         Ok(self)
     }
+
+    /// Checks the query `q`, the `index`-th one (from 1), with `SequenceRecord::new` and returns it
+    /// normalized, like `Args::validate` does.
+    pub fn validate_query(&self, index: usize, q: &str) -> Result<String, String> {
+        let q = sequence::strip_fasta_header(q);
+        let type_query = self.type_of(q);
+        SequenceRecord::new(index.to_string(), q, type_query)
+            .map(|record| {
+                let sequence = if self.soft_mask {
+                    sequence::normalize_soft_masked(q, type_query)
+                } else {
+                    record.into_parts().1
+                };
+                // RNA queries keep their U, read as T again when they are located.
+                if type_query == "nt" && sequence::is_rna(q) {
+                    sequence::to_rna(&sequence)
+                } else {
+                    sequence
+                }
+            })
+            .map_err(|err| err.to_string())
+    }
 }
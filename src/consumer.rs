@@ -0,0 +1,88 @@
+//! Message-queue consumer mode, available with the `amqp` and `kafka` features.
+//!
+//! The consumers read sequence messages from an input queue or topic, locate them, and publish
+//! the results to an output queue or topic. Message payloads use the JSON request and result
+//! objects of the pipe protocol (see the `pipe` module), one per message:
+//!
+//! ```json
+//! {"id": "sample-42", "seq": "ATGCATGCATGC", "reference": "HXB2"}
+//! ```
+//!
+//! A message that is not a valid request produces a result with an `error` instead of stopping
+//! the consumer. Input messages are acknowledged (AMQP) or their offsets committed (Kafka) only
+//! after their result has been published, so every message is processed at least once.
+
+use crate::config::{Args, CommonArgs};
+use crate::pipe::{self, PipeResponse};
+use serde_json::Value;
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+
+/// Command-line options for the request defaults of the consumers.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RequestDefaults {
+    /// Reference genome for messages that do not set `reference`, one of the strains of
+    /// list-references
+    #[arg(short, long, default_value = "HXB2")]
+    pub reference: String,
+
+    /// Type of query for messages that do not set `type_query`, either nt, aa or auto
+    #[arg(short, long, default_value = "nt")]
+    pub type_query: String,
+
+    /// Algorithm for messages that do not set `algorithm`, 1, 2 or 3
+    #[arg(short, long, default_value_t = 1)]
+    pub algorithm: u8,
+}
+
+impl From<RequestDefaults> for Args {
+    fn from(defaults: RequestDefaults) -> Self {
+        Args {
+            common: CommonArgs {
+                reference: defaults.reference,
+                type_query: defaults.type_query,
+                algorithm: defaults.algorithm,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Locates the request carried by a message payload and returns the JSON result payload.
+pub fn process_message(payload: &[u8], defaults: &Args) -> Vec<u8> {
+    let response = match std::str::from_utf8(payload) {
+        Ok(line) => pipe::process_line(line, defaults),
+        Err(err) => PipeResponse::error(Value::Null, format!("Invalid request: {}", err)),
+    };
+    serde_json::to_vec(&response).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_message() {
+        let result = process_message(
+            br#"{"id": 7, "seq": "ATGCATGCATGC", "reference": "SIVmm239"}"#,
+            &Args::default(),
+        );
+        let value: Value = serde_json::from_slice(&result).unwrap();
+        assert_eq!(value["id"], 7);
+        assert_eq!(value["found"], true);
+
+        let result = process_message(&[0xff, 0xfe], &Args::default());
+        let value: Value = serde_json::from_slice(&result).unwrap();
+        assert_eq!(value["id"], Value::Null);
+        assert!(
+            value["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid request")
+        );
+    }
+}
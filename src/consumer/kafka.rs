@@ -0,0 +1,76 @@
+//! Kafka consumer.
+
+use super::process_message;
+use crate::BoxError;
+use crate::config::Args;
+use rdkafka::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use std::sync::Arc;
+
+/// Options of the Kafka consumer.
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    /// Comma-separated list of bootstrap brokers.
+    pub brokers: String,
+    /// Consumer group id.
+    pub group_id: String,
+    /// Topic the requests are consumed from.
+    pub input_topic: String,
+    /// Topic the results are published to.
+    pub output_topic: String,
+    /// Additional librdkafka client properties, e.g. `security.protocol` or `sasl.mechanisms`,
+    /// applied to both the consumer and the producer.
+    pub properties: Vec<(String, String)>,
+}
+
+impl KafkaOptions {
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", &self.brokers);
+        for (key, value) in &self.properties {
+            config.set(key, value);
+        }
+        config
+    }
+}
+
+/// Consumes requests from `options.input_topic` and publishes the results to
+/// `options.output_topic` until an error occurs.
+///
+/// Messages are processed one at a time, and results keep the key of their request, so they land
+/// in the matching partition of the output topic. The offset of a request is committed once its
+/// result has been delivered. To scale out, run several consumers with the same group id.
+pub async fn run(options: KafkaOptions, defaults: Args) -> Result<(), BoxError> {
+    let consumer: StreamConsumer = options
+        .client_config()
+        .set("group.id", &options.group_id)
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[&options.input_topic])?;
+    let producer: FutureProducer = options.client_config().create()?;
+
+    let defaults = Arc::new(defaults);
+    loop {
+        let message = consumer.recv().await?;
+        let payload = message.payload().unwrap_or_default().to_vec();
+        let task_defaults = defaults.clone();
+        let result =
+            tokio::task::spawn_blocking(move || process_message(&payload, &task_defaults)).await?;
+
+        let mut record = FutureRecord::to(&options.output_topic).payload(&result);
+        if let Some(key) = message.key() {
+            record = record.key(key);
+        }
+        producer
+            .send(record, Timeout::Never)
+            .await
+            .map_err(|(err, _)| err)?;
+
+        consumer.store_offset_from_message(&message)?;
+    }
+}
@@ -0,0 +1,208 @@
+//! Conversion between the nucleotide positions of a reference and the codons of its genes, without
+//! aligning a query: "what is RT codon 215 in HXB2 coordinates", and back.
+//!
+//! The codons of a gene are numbered from 1 at its start, following its coding segments (see
+//! `reference::coding_segments`), so the codons of the spliced tat and rev continue in their second
+//! exon. On HXB2, the codons of the proteins cleaved from the gag and pol polyproteins listed in
+//! `resistance::PROTEINS` (CA, PR, RT and IN) are numbered within the protein, as in the drug
+//! resistance literature. The LTRs have no codons.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::coords;
+//! use virust_locator::position::RefPosition;
+//!
+//! let bases = coords::gene_codon_to_hxb2("RT", 215).unwrap();
+//! assert_eq!(bases.map(RefPosition::get), [3192, 3193, 3194]);
+//!
+//! let codons = coords::hxb2_to_gene(RefPosition::new(3193));
+//! assert_eq!(codons[0].to_string(), "pol 370:2");
+//! assert_eq!(codons[1].to_string(), "RT 215:2");
+//! ```
+
+use crate::BoxError;
+use crate::position::RefPosition;
+use crate::reference::{self, Gene};
+use crate::resistance::{self, Protein};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// A base of a codon of a gene or protein.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GeneCodon {
+    /// The gene, e.g. `pol`, or the protein, e.g. `RT`.
+    pub gene: &'static str,
+    /// The 1-based codon in the gene or protein.
+    pub codon: usize,
+    /// The base in the codon, 1 to 3.
+    pub codon_position: usize,
+}
+
+impl Display for GeneCodon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}:{}", self.gene, self.codon, self.codon_position)
+    }
+}
+
+/// The genes of `reference` with codons: its nucleotide genes, without the LTRs.
+pub(crate) fn coding_genes(reference: &str) -> impl Iterator<Item = &'static Gene<'static>> + '_ {
+    reference::genes(reference, "nt").filter(|gene| !gene.name.ends_with("LTR"))
+}
+
+/// The proteins of `gene` numbered within the polyprotein, on HXB2 only.
+fn proteins(reference: &str, gene: &'static str) -> impl Iterator<Item = &'static Protein> {
+    let supported = resistance::supports(reference);
+    resistance::PROTEINS
+        .iter()
+        .filter(move |protein| supported && protein.gene == gene)
+}
+
+/// The 0-based offset of `position` in the coding segments of `gene`, if they contain it.
+pub(crate) fn coding_offset(gene: &Gene, position: RefPosition) -> Option<usize> {
+    let mut offset = 0;
+    for (start, end) in reference::coding_segments(gene) {
+        if start <= position && position <= end {
+            return Some(offset + start.span_to(position) - 1);
+        }
+        offset += start.span_to(end);
+    }
+    None
+}
+
+/// The codons of the genes and proteins of `reference` that `position` is a base of: the genes in
+/// the order of the reference, each followed by its proteins. Empty for positions outside of the
+/// genes, such as the LTRs or the intron of tat and rev.
+pub fn reference_to_genes(reference: &str, position: RefPosition) -> Vec<GeneCodon> {
+    let mut codons = Vec::new();
+    for gene in coding_genes(reference) {
+        let Some(offset) = coding_offset(gene, position) else {
+            continue;
+        };
+        let codon = GeneCodon {
+            gene: gene.name,
+            codon: offset / 3 + 1,
+            codon_position: offset % 3 + 1,
+        };
+        codons.push(codon);
+        codons.extend(
+            proteins(reference, gene.name)
+                .filter(|protein| {
+                    protein.offset < codon.codon && codon.codon <= protein.offset + protein.length
+                })
+                .map(|protein| GeneCodon {
+                    gene: protein.name,
+                    codon: codon.codon - protein.offset,
+                    ..codon
+                }),
+        );
+    }
+    codons
+}
+
+/// The positions of the three bases of `codon` of `gene` on `reference`. `gene` is a gene of
+/// `reference::GENES` or, on HXB2, a protein of `resistance::PROTEINS`, case-insensitive. The bases
+/// of a codon split by a splice junction are in different exons.
+pub fn gene_codon_to_reference(
+    reference: &str,
+    gene: &str,
+    codon: usize,
+) -> Result<[RefPosition; 3], BoxError> {
+    let protein = resistance::PROTEINS
+        .iter()
+        .find(|protein| protein.name.eq_ignore_ascii_case(gene) && resistance::supports(reference));
+    let (gene, name, offset, length) = match protein {
+        Some(protein) => (
+            reference::retrieve_gene(reference, "nt", protein.gene)?,
+            protein.name,
+            protein.offset,
+            protein.length,
+        ),
+        None => {
+            let gene = reference::retrieve_gene(reference, "nt", gene)?;
+            if gene.name.ends_with("LTR") {
+                return Err(format!("{} has no codons", gene.name).into());
+            }
+            let coding: usize = reference::coding_segments(gene)
+                .iter()
+                .map(|&(start, end)| start.span_to(end))
+                .sum();
+            (gene, gene.name, 0, coding / 3)
+        }
+    };
+    if codon == 0 || codon > length {
+        return Err(format!("Codon {} is outside {} (1-{})", codon, name, length).into());
+    }
+    let segments = reference::coding_segments(gene);
+    let base = |mut offset: usize| {
+        for &(start, end) in &segments {
+            let len = start.span_to(end);
+            if offset < len {
+                return RefPosition::new(start.get() + offset);
+            }
+            offset -= len;
+        }
+        unreachable!("the codons of {} are within its coding segments", name)
+    };
+    let first = 3 * (offset + codon - 1);
+    Ok([base(first), base(first + 1), base(first + 2)])
+}
+
+/// `reference_to_genes` on HXB2.
+pub fn hxb2_to_gene(position: RefPosition) -> Vec<GeneCodon> {
+    reference_to_genes(resistance::REFERENCE, position)
+}
+
+/// `gene_codon_to_reference` on HXB2.
+pub fn gene_codon_to_hxb2(gene: &str, codon: usize) -> Result<[RefPosition; 3], BoxError> {
+    gene_codon_to_reference(resistance::REFERENCE, gene, codon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hxb2_to_gene() {
+        // The first base of gag, the start of PR and the base skipped by the frames of vpr.
+        let codons = hxb2_to_gene(RefPosition::new(790));
+        assert_eq!(
+            codons.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["gag 1:1"]
+        );
+        let codons = hxb2_to_gene(RefPosition::new(2253));
+        let codons = codons.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(codons, ["gag 488:3", "pol 57:1", "PR 1:1"]);
+        assert!(hxb2_to_gene(RefPosition::new(5775)).is_empty());
+        assert!(hxb2_to_gene(RefPosition::new(100)).is_empty());
+
+        // The second exon of tat.
+        let codons = hxb2_to_gene(RefPosition::new(8380));
+        assert!(codons.contains(&GeneCodon {
+            gene: "tat",
+            codon: 73,
+            codon_position: 1,
+        }));
+    }
+
+    #[test]
+    fn test_gene_codon_to_hxb2() {
+        for (gene, codon) in [("RT", 184), ("pr", 30), ("IN", 148), ("CA", 66), ("env", 1)] {
+            let bases = gene_codon_to_hxb2(gene, codon).unwrap();
+            let codons = hxb2_to_gene(bases[2]);
+            let found = codons
+                .iter()
+                .find(|c| c.gene.eq_ignore_ascii_case(gene))
+                .unwrap();
+            assert_eq!((found.codon, found.codon_position), (codon, 3));
+        }
+        // A codon of tat split by the splice junction.
+        let bases = gene_codon_to_hxb2("tat", 72).unwrap();
+        assert_eq!(bases.map(RefPosition::get), [6044, 6045, 8379]);
+
+        assert!(gene_codon_to_hxb2("RT", 561).is_err());
+        assert!(gene_codon_to_hxb2("RT", 0).is_err());
+        assert!(gene_codon_to_hxb2("5LTR", 1).is_err());
+        assert!(gene_codon_to_reference("SIVmm239", "RT", 1).is_err());
+        assert!(gene_codon_to_reference("SIVmm239", "vpx", 1).is_ok());
+    }
+}
@@ -0,0 +1,280 @@
+//! Detection of the defects of the genes of nucleotide queries, the first step of proviral
+//! intactness screening.
+//!
+//! With `--defects`, the query of every location is read in the frame of each gene of the
+//! reference it overlaps (its coding segments, see `reference::coding_segments`, without the LTRs)
+//! and `detect` flags as `Defect`s, attached to the location (see `Locator::defects`):
+//!
+//! - `Frameshift`: an insertion or deletion within the coding segments of the gene whose length
+//!   is not a multiple of 3.
+//! - `PrematureStop`: a stop codon in the query translated from the first codon of the gene it
+//!   covers, before the last codon of the gene. Only the first one of every gene is flagged: the
+//!   translation is not read past it. Stops aligned to a stop of the reference, such as codon 124
+//!   of nef in HXB2, are not flagged.
+//!
+//! Codons are numbered from 1 at the start of the gene, following its coding segments, as in the
+//! `coords` module. Codons with ambiguous bases are stops only when every resolution is a stop, see
+//! `sequence::translate_codon`.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::defects::DefectKind;
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 790-849 with 805 and 830-832 deleted: the first deletion shifts the frame of gag.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         defects: true,
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! assert_eq!(loc.defects.len(), 2);
+//! assert_eq!(loc.defects[0].kind, DefectKind::Frameshift);
+//! assert_eq!(loc.defects[0].to_string(), "gag frameshift at 805 (codon 6)");
+//! assert_eq!(loc.defects[1].to_string(), "gag premature stop at 812 (codon 8)");
+//! ```
+
+use crate::coords::{coding_genes, coding_offset};
+use crate::locator::{Locator, VariantKind};
+use crate::position::{QueryPosition, RefPosition};
+use crate::reference::{self, Gene};
+use crate::sequence::translate_codon;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// Kind of a gene defect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefectKind {
+    /// An indel shifting the reading frame of the gene.
+    Frameshift,
+    /// A stop codon before the end of the gene.
+    PrematureStop,
+}
+
+/// A defect of a gene in a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Defect {
+    pub kind: DefectKind,
+    /// The gene, e.g. `gag`.
+    pub gene: &'static str,
+    /// The codon of the gene of `ref_position`, from 1.
+    pub codon: usize,
+    /// For a frameshift, the position of the indel as in `Variant::ref_position`; for a premature
+    /// stop, the reference position aligned to the first base of the stop codon.
+    pub ref_position: RefPosition,
+    /// For a frameshift, the position of the indel as in `Variant::query_position`; for a
+    /// premature stop, the first base of the stop codon.
+    pub query_position: QueryPosition,
+}
+
+impl Display for Defect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            DefectKind::Frameshift => "frameshift",
+            DefectKind::PrematureStop => "premature stop",
+        };
+        write!(
+            f,
+            "{} {} at {} (codon {})",
+            self.gene, kind, self.ref_position, self.codon
+        )
+    }
+}
+
+/// A base of the query read in the frame of a gene.
+struct CodingBase {
+    base: u8,
+    /// The reference base it is aligned to, `-` for inserted bases.
+    ref_base: u8,
+    /// The offset in the coding segments of the reference base it is aligned to, or of the one
+    /// before it for inserted bases.
+    offset: usize,
+    inserted: bool,
+    ref_position: RefPosition,
+    query_position: QueryPosition,
+}
+
+/// Returns the defects of the nucleotide query of `loc`, located on `reference`, in the order of
+/// the genes of the reference, and in query order within a gene.
+pub fn detect(loc: &Locator, reference: &str) -> Vec<Defect> {
+    let variants = loc.variants();
+    let ref_positions = loc.ref_positions();
+    let mut defects = Vec::new();
+    for gene in coding_genes(reference) {
+        let length: usize = reference::coding_segments(gene)
+            .iter()
+            .map(|&(start, end)| start.span_to(end))
+            .sum();
+        let between_bases = |position: RefPosition| {
+            coding_offset(gene, position).is_some() && coding_offset(gene, position + 1).is_some()
+        };
+        let mut gene_defects = Vec::new();
+        for variant in &variants {
+            let shifted = match variant.kind {
+                VariantKind::Substitution => continue,
+                VariantKind::Insertion if !between_bases(variant.ref_position) => continue,
+                VariantKind::Insertion => variant.query_bases.len(),
+                VariantKind::Deletion => (0..variant.ref_bases.len())
+                    .filter(|&i| coding_offset(gene, variant.ref_position + i).is_some())
+                    .count(),
+            };
+            if shifted % 3 == 0 {
+                continue;
+            }
+            let first = (0..variant.ref_bases.len().max(1))
+                .find_map(|i| coding_offset(gene, variant.ref_position + i));
+            if let Some(offset) = first {
+                gene_defects.push(Defect {
+                    kind: DefectKind::Frameshift,
+                    gene: gene.name,
+                    codon: offset / 3 + 1,
+                    ref_position: variant.ref_position,
+                    query_position: variant.query_position,
+                });
+            }
+        }
+        let bases = coding_bases(loc, &ref_positions, gene, between_bases);
+        gene_defects.extend(premature_stop(&bases, gene.name, length / 3));
+        gene_defects.sort_by_key(|defect| defect.query_position);
+        defects.extend(gene_defects);
+    }
+    defects
+}
+
+/// The bases of the query of `loc` aligned to the coding segments of `gene`, and those inserted
+/// between two of its bases, in query order. Overhangs are left out.
+fn coding_bases(
+    loc: &Locator,
+    ref_positions: &[Option<RefPosition>],
+    gene: &Gene,
+    between_bases: impl Fn(RefPosition) -> bool,
+) -> Vec<CodingBase> {
+    let (Some(first), Some(last)) = (
+        ref_positions.iter().position(Option::is_some),
+        ref_positions.iter().rposition(Option::is_some),
+    ) else {
+        return Vec::new();
+    };
+    let mut bases = Vec::new();
+    let mut query_residues = 0;
+    let mut last_ref = None;
+    let columns = loc
+        .query_aligned_string
+        .bytes()
+        .zip(loc.ref_aligned_string.bytes())
+        .zip(ref_positions);
+    for (i, ((q, r), &ref_position)) in columns.enumerate() {
+        if q != b'-' {
+            query_residues += 1;
+        }
+        let inserted = ref_position.is_none();
+        let position = match ref_position.or(last_ref) {
+            Some(position) if q != b'-' && first <= i && i <= last => position,
+            _ => {
+                last_ref = ref_position.or(last_ref);
+                continue;
+            }
+        };
+        last_ref = Some(position);
+        if inserted && !between_bases(position) {
+            continue;
+        }
+        if let Some(offset) = coding_offset(gene, position) {
+            bases.push(CodingBase {
+                base: q,
+                ref_base: r,
+                offset,
+                inserted,
+                ref_position: position,
+                query_position: QueryPosition::new(query_residues),
+            });
+        }
+    }
+    bases
+}
+
+/// The first stop codon of `bases` before codon `codons` of `gene`, and not aligned in frame to a
+/// stop codon of the reference, reading from the first base aligned to the first base of a codon.
+fn premature_stop(bases: &[CodingBase], gene: &'static str, codons: usize) -> Option<Defect> {
+    let start = bases
+        .iter()
+        .position(|base| !base.inserted && base.offset % 3 == 0)?;
+    bases[start..].chunks_exact(3).find_map(|codon| {
+        let first = &codon[0];
+        let bases: String = codon.iter().map(|base| base.base as char).collect();
+        let ref_bases: String = codon.iter().map(|base| base.ref_base as char).collect();
+        let number = first.offset / 3 + 1;
+        let reference_stop = first.offset % 3 == 0 && translate_codon(&ref_bases) == "*";
+        (number < codons && !reference_stop && translate_codon(&bases) == "*").then_some(Defect {
+            kind: DefectKind::PrematureStop,
+            gene,
+            codon: number,
+            ref_position: first.ref_position,
+            query_position: first.query_position,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    /// A location of `query` aligned to HXB2 from `ref_start`, with `reference` the aligned
+    /// reference bases.
+    fn location(ref_start: usize, query: &str, reference: &str) -> Locator {
+        let ref_end = ref_start + reference.bytes().filter(|&b| b != b'-').count() - 1;
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_end),
+            100.0,
+            query.contains('-') || reference.contains('-'),
+            query.to_string(),
+            reference.to_string(),
+        )
+    }
+
+    fn hxb2(start: usize, end: usize) -> String {
+        let sequence = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        String::from_utf8(sequence[start - 1..end].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        // The start of gag, intact.
+        let gag = hxb2(790, 849);
+        assert!(detect(&location(790, &gag, &gag), "HXB2").is_empty());
+
+        // Codon 5 (802-804, GCG) to TAG.
+        let stop = format!("{}TAG{}", &gag[..12], &gag[15..]);
+        assert_eq!(
+            detect(&location(790, &stop, &gag), "HXB2"),
+            vec![Defect {
+                kind: DefectKind::PrematureStop,
+                gene: "gag",
+                codon: 5,
+                ref_position: RefPosition::new(802),
+                query_position: QueryPosition::new(13),
+            }]
+        );
+
+        // A base inserted after 795, and 3 deleted after 810, in frame.
+        let query = format!("{}A{}---{}", &gag[..6], &gag[6..21], &gag[24..]);
+        let reference = format!("{}-{}", &gag[..6], &gag[6..]);
+        let defects = detect(&location(790, &query, &reference), "HXB2");
+        assert_eq!(defects.len(), 1);
+        assert_eq!(defects[0].to_string(), "gag frameshift at 795 (codon 2)");
+
+        // The stop codons of nef in HXB2, at codon 124 and at its end, are not premature.
+        let nef = hxb2(9160, 9417);
+        assert!(detect(&location(9160, &nef, &nef), "HXB2").is_empty());
+    }
+}
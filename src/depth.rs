@@ -0,0 +1,184 @@
+//! Per-base depth of the reference covered by the located queries, for the coverage QC of
+//! amplicon sequencing runs.
+//!
+//! With `--depth-track`, the locations of the records of `--input` are accumulated in a
+//! `DepthTrack`, the number of locations covering every position of the reference, and the track
+//! written once they are all located, in either format:
+//!
+//! - bedGraph, if the name of the file ends in `.bedgraph` or `.bg`: one line per run of positions
+//!   with the same depth, as a 0-based, half-open interval on the reference named by its strain
+//!   (e.g. `HXB2`), as in the BED output. Positions without coverage have lines of depth 0, so the
+//!   track covers the whole reference.
+//! - Tab-separated values otherwise: the strain, the 1-based position and its depth for every
+//!   position, without header, as `samtools depth -a` writes them.
+//!
+//! A location wrapping around the end of the reference, or split, covers its `segments`. The
+//! residues deleted from the query are covered: the depth is that of the reference spanned.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::depth::{self, DepthTrack};
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 790-849, in gag.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let mut track = DepthTrack::new("HXB2", "nt").unwrap();
+//! track.add(&loc);
+//! let mut out = Vec::new();
+//! depth::write_bedgraph(&mut out, "HXB2", &track.depths()).unwrap();
+//! let out = String::from_utf8(out).unwrap();
+//! assert!(out.starts_with("HXB2\t0\t789\t0\nHXB2\t789\t849\t1\nHXB2\t849\t9719\t0\n"));
+//! ```
+
+use crate::BoxError;
+use crate::locator::Locator;
+use crate::reference::retrieve_reference_sequence;
+use std::io::Write;
+use std::path::Path;
+
+/// The number of locations covering every position of a reference, accumulated one location at a
+/// time.
+#[derive(Debug, Clone)]
+pub struct DepthTrack {
+    /// The change of depth at every position of the reference, and after its end.
+    changes: Vec<isize>,
+}
+
+impl DepthTrack {
+    /// An empty track of the reference of `reference` for queries of type `type_query`.
+    pub fn new(reference: &str, type_query: &str) -> Result<Self, BoxError> {
+        let length = retrieve_reference_sequence(reference, type_query)?
+            .sequence
+            .len();
+        Ok(DepthTrack {
+            changes: vec![0; length + 1],
+        })
+    }
+
+    /// Adds the positions covered by `loc`: its `segments`, or `ref_start` to `ref_end`. The
+    /// positions past the end of the reference, which the locations of amino acid queries on a
+    /// protein (see `--gene`) do not have, are ignored.
+    pub fn add(&mut self, loc: &Locator) {
+        let length = self.changes.len() - 1;
+        for segment in loc.ref_segments() {
+            let end = segment.end.get().min(length);
+            if segment.start.get() <= end {
+                self.changes[segment.start.to_zero_based()] += 1;
+                self.changes[end] -= 1;
+            }
+        }
+    }
+
+    /// The depth of every position of the reference, from its start.
+    pub fn depths(&self) -> Vec<usize> {
+        let mut depth = 0;
+        self.changes[..self.changes.len() - 1]
+            .iter()
+            .map(|change| {
+                depth += change;
+                depth as usize
+            })
+            .collect()
+    }
+}
+
+/// Whether the track of `--depth-track` written to `path` is in bedGraph, see the module
+/// documentation.
+pub fn is_bedgraph(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    name.ends_with(".bedgraph") || name.ends_with(".bg")
+}
+
+/// Writes the `depths` of the positions of the reference `chrom` as bedGraph, one line per run of
+/// positions with the same depth.
+pub fn write_bedgraph<W: Write>(
+    mut writer: W,
+    chrom: &str,
+    depths: &[usize],
+) -> Result<(), BoxError> {
+    let mut start = 0;
+    for run in depths.chunk_by(|a, b| a == b) {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            chrom,
+            start,
+            start + run.len(),
+            run[0]
+        )?;
+        start += run.len();
+    }
+    Ok(())
+}
+
+/// Writes the `depths` of the positions of the reference `chrom` as tab-separated values, one line
+/// per position.
+pub fn write_tsv<W: Write>(mut writer: W, chrom: &str, depths: &[usize]) -> Result<(), BoxError> {
+    for (i, depth) in depths.iter().enumerate() {
+        writeln!(writer, "{}\t{}\t{}", chrom, i + 1, depth)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::RefSegment;
+    use crate::position::RefPosition;
+
+    fn location(ref_start: usize, ref_end: usize) -> Locator {
+        let len = ref_end - ref_start + 1;
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_end),
+            100.0,
+            false,
+            "A".repeat(len),
+            "A".repeat(len),
+        )
+    }
+
+    #[test]
+    fn test_depths() {
+        let mut track = DepthTrack::new("HXB2", "nt").unwrap();
+        track.add(&location(1, 10));
+        track.add(&location(6, 20));
+        // A location wrapping around the end of HXB2.
+        let mut wrapped = location(9710, 9719);
+        wrapped.segments = vec![
+            RefSegment {
+                start: RefPosition::new(9710),
+                end: RefPosition::new(9719),
+            },
+            RefSegment {
+                start: RefPosition::new(1),
+                end: RefPosition::new(2),
+            },
+        ];
+        track.add(&wrapped);
+        let depths = track.depths();
+        assert_eq!(depths.len(), 9719);
+        assert_eq!(&depths[..7], [2, 2, 1, 1, 1, 2, 2]);
+        assert_eq!(depths[10], 1);
+        assert_eq!(depths[20], 0);
+        assert_eq!(depths[9718], 1);
+
+        let mut out = Vec::new();
+        write_bedgraph(&mut out, "HXB2", &depths[..25]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HXB2\t0\t2\t2\nHXB2\t2\t5\t1\nHXB2\t5\t10\t2\nHXB2\t10\t20\t1\nHXB2\t20\t25\t0\n"
+        );
+        let mut out = Vec::new();
+        write_tsv(&mut out, "HXB2", &depths[..2]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "HXB2\t1\t2\nHXB2\t2\t2\n");
+    }
+}
@@ -0,0 +1,234 @@
+//! The constant (C1 to C5) and variable (V1 to V5) regions of the gp120 of env, located in the
+//! queries.
+//!
+//! With `--env-regions`, the regions of `ENV_REGIONS` overlapped by a location on HXB2 are attached
+//! to it as `EnvRegion`s (see `Locator::env_regions`), with their positions on the reference and in
+//! the query, and the length of the query in each of them: the variable loops vary widely in
+//! length between viruses, through insertions and deletions.
+//!
+//! Nucleotide queries have the regions on the HXB2 genome, and amino acid queries on the env
+//! protein, on the concatenated protein sequence or numbered within env with `--gene env`.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 7080-7250, with 6 bases inserted in V3 after 7160.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec![
+//!             "GTACAGCTGAACACATCTGTAGAAATTAATTGTACAAGACCCAACAACAATACAAGAAAAAGAATCCGTATCCAGAGAGGAAATAA\
+//!              TCCAGGGAGAGCATTTGTTACAATAGGAAAAATAGGAAATATGAGACAAGCACATTGTAACATTAGTAGAGCAAAATGGAATAACA\
+//!              CTTTA"
+//!                 .to_string(),
+//!         ],
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         env_regions: true,
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let regions: Vec<String> = loc.env_regions.iter().map(ToString::to_string).collect();
+//! assert_eq!(
+//!     regions,
+//!     [
+//!         "C2 7080-7109 (query 1-30, length 30, partial)",
+//!         "V3 7110-7217 (query 31-144, length 114)",
+//!         "C3 7218-7250 (query 145-177, length 33, partial)",
+//!     ]
+//! );
+//! ```
+
+use crate::locator::Locator;
+use crate::position::{QueryPosition, RefPosition};
+use crate::reference::{self, Gene};
+use crate::sequence_locator::Region;
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// The reference of `ENV_REGIONS`.
+pub const REFERENCE: &str = "HXB2";
+
+/// The regions of gp120 on the HXB2 genome, in order: their names and first and last bases, as in
+/// the Los Alamos HXB2 landmarks.
+pub static ENV_REGIONS: [(&str, usize, usize); 10] = [
+    ("C1", 6225, 6614),
+    ("V1", 6615, 6692),
+    ("V2", 6693, 6812),
+    ("C2", 6813, 7109),
+    ("V3", 7110, 7217),
+    ("C3", 7218, 7376),
+    ("V4", 7377, 7478),
+    ("C4", 7479, 7601),
+    ("V5", 7602, 7634),
+    ("C5", 7635, 7757),
+];
+
+/// A region of gp120 overlapped by a location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvRegion {
+    /// The region, e.g. `V3`.
+    pub region: String,
+    /// First covered position of the region on the reference.
+    pub ref_start: RefPosition,
+    /// Last covered position of the region on the reference (inclusive).
+    pub ref_end: RefPosition,
+    /// First query residue in the region.
+    pub query_start: QueryPosition,
+    /// Last query residue in the region (inclusive).
+    pub query_end: QueryPosition,
+    /// The number of query residues in the region, those inserted in it included: the loop
+    /// length, in nucleotides or amino acids.
+    pub length: usize,
+    /// Whether the whole region is covered.
+    pub complete: bool,
+}
+
+impl Display for EnvRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}-{} (query {}-{}, length {}",
+            self.region,
+            self.ref_start,
+            self.ref_end,
+            self.query_start,
+            self.query_end,
+            self.length
+        )?;
+        if !self.complete {
+            write!(f, ", partial")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The regions of `ENV_REGIONS` on the reference of `type_query`: on the genome for `nt`, and for
+/// `aa` on the concatenated protein sequence, or on the protein of `gene`. Empty for the proteins of
+/// the other genes.
+pub fn regions(type_query: &str, gene: Option<&Gene>) -> Vec<Region> {
+    let offset = match (type_query, gene) {
+        ("nt", _) => {
+            return ENV_REGIONS
+                .iter()
+                .map(|&(name, start, end)| Region::new(name, start, end))
+                .collect();
+        }
+        (_, Some(gene)) if gene.name != "env" => return Vec::new(),
+        (_, Some(_)) => 0,
+        (_, None) => match reference::retrieve_gene(REFERENCE, "aa", "env") {
+            Ok(env) => env.start.to_zero_based(),
+            Err(_) => return Vec::new(),
+        },
+    };
+    let codon = |base: usize| offset + (base - ENV_REGIONS[0].1) / 3 + 1;
+    ENV_REGIONS
+        .iter()
+        .map(|&(name, start, end)| Region::new(name, codon(start), codon(end)))
+        .collect()
+}
+
+/// Returns the `regions` overlapped by `loc`, in their order, see `regions`. The regions deleted
+/// from the query entirely are left out.
+pub fn locate(loc: &Locator, regions: &[Region]) -> Vec<EnvRegion> {
+    let ref_positions = loc.ref_positions();
+    let (Some(first), Some(last)) = (
+        ref_positions.iter().position(Option::is_some),
+        ref_positions.iter().rposition(Option::is_some),
+    ) else {
+        return Vec::new();
+    };
+    let mut located = Vec::new();
+    for region in regions {
+        let within = |position: RefPosition| region.start <= position && position <= region.end;
+        let mut covered: Option<(RefPosition, RefPosition)> = None;
+        let mut residues: Option<(QueryPosition, QueryPosition)> = None;
+        let mut length = 0;
+        let mut query_residues = 0;
+        let mut last_ref = None;
+        let columns = loc.query_aligned_string.bytes().zip(&ref_positions);
+        for (i, (q, &ref_position)) in columns.enumerate() {
+            query_residues += usize::from(q != b'-');
+            // Insertions are in the region when it continues after them.
+            let inside = match ref_position {
+                Some(position) => within(position),
+                None => last_ref.is_some_and(|p| within(p) && p < region.end),
+            };
+            last_ref = ref_position.or(last_ref);
+            if !inside || i < first || i > last {
+                continue;
+            }
+            if let Some(position) = ref_position {
+                covered = Some((covered.map_or(position, |(start, _)| start), position));
+            }
+            if q != b'-' {
+                let position = QueryPosition::new(query_residues);
+                residues = Some((residues.map_or(position, |(start, _)| start), position));
+                length += 1;
+            }
+        }
+        if let (Some((ref_start, ref_end)), Some((query_start, query_end))) = (covered, residues) {
+            located.push(EnvRegion {
+                region: region.name.clone(),
+                ref_start,
+                ref_end,
+                query_start,
+                query_end,
+                length,
+                complete: ref_start == region.start && ref_end == region.end,
+            });
+        }
+    }
+    located
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AnnotationArgs, Args, CommonArgs};
+
+    #[test]
+    fn test_regions() {
+        let nt = regions("nt", None);
+        assert_eq!(nt.len(), 10);
+        assert_eq!(nt[4], Region::new("V3", 7110, 7217));
+        // V3 is residues 296-331 of env.
+        let env = reference::retrieve_gene(REFERENCE, "aa", "env").unwrap();
+        assert_eq!(regions("aa", Some(env))[4], Region::new("V3", 296, 331));
+        assert_eq!(
+            regions("aa", None)[4],
+            Region::new("V3", 2090 + 296, 2090 + 331)
+        );
+        let gag = reference::retrieve_gene(REFERENCE, "aa", "gag").unwrap();
+        assert!(regions("aa", Some(gag)).is_empty());
+    }
+
+    #[test]
+    fn test_locate_aa() {
+        // Env 290-340, with residues 310-312 of V3 (296-331) deleted.
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["TSVEINCTRPNNNTRKRIRIPGRAFVTIGKIGNMRQAHCNISRAKWNN".to_string()],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            annotation: AnnotationArgs {
+                env_regions: true,
+                ..Default::default()
+            },
+            gene: Some("env".to_string()),
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let v3 = &loc.env_regions[1];
+        assert_eq!(v3.region, "V3");
+        assert_eq!((v3.ref_start.get(), v3.ref_end.get()), (296, 331));
+        assert_eq!((v3.query_start.get(), v3.query_end.get()), (7, 39));
+        assert_eq!(v3.length, 33);
+        assert!(v3.complete);
+    }
+}
@@ -0,0 +1,211 @@
+//! C bindings of the locator, available with the `ffi` feature, to locate queries from C, C++,
+//! Java (JNI) or Julia pipelines.
+//!
+//! The crate is built as a `cdylib` (`libvirust_locator.so`, `.dylib` or `virust_locator.dll`)
+//! with `cargo build --release --lib --features ffi`, exporting:
+//!
+//! ```c
+//! #include <stdbool.h>
+//! #include <stddef.h>
+//! #include <stdint.h>
+//!
+//! typedef struct {
+//!     // 0 when the query was located, 1 when it was not, -1 on error.
+//!     int32_t status;
+//!     // The 1-based location on the reference, when located.
+//!     size_t ref_start;
+//!     size_t ref_end;
+//!     double percent_identity;
+//!     bool indel;
+//!     // The aligned strings, when located; NULL otherwise.
+//!     char *query_aligned_string;
+//!     char *ref_aligned_string;
+//!     // Why the query was not located, or the error; NULL when located.
+//!     char *message;
+//! } VirustLocation;
+//!
+//! // Locates the query on the reference ("HXB2" or "SIVmm239", or one of their aliases) as a
+//! // query of type type_query ("nt", "aa" or "auto"), with the default options of the CLI. The
+//! // strings are NUL-terminated UTF-8. Never returns NULL.
+//! VirustLocation *virust_locate(const char *query, const char *reference, const char *type_query);
+//!
+//! // Releases a location returned by virust_locate, and its strings. Accepts NULL.
+//! void virust_location_free(VirustLocation *location);
+//! ```
+//!
+//! The layout of `VirustLocation` is stable: fields are only ever appended to it.
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::locator::{LocateOutcome, Locator};
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Status of a located query.
+pub const VIRUST_FOUND: i32 = 0;
+/// Status of a query that could not be located.
+pub const VIRUST_NOT_FOUND: i32 = 1;
+/// Status of an invalid query or options.
+pub const VIRUST_ERROR: i32 = -1;
+
+/// The result of `virust_locate`, see the module documentation.
+#[repr(C)]
+#[derive(Debug)]
+pub struct VirustLocation {
+    pub status: i32,
+    pub ref_start: usize,
+    pub ref_end: usize,
+    pub percent_identity: f64,
+    pub indel: bool,
+    pub query_aligned_string: *mut c_char,
+    pub ref_aligned_string: *mut c_char,
+    pub message: *mut c_char,
+}
+
+impl VirustLocation {
+    /// A location without a query located, with the `status` and its `message`.
+    fn failed(status: i32, message: &str) -> VirustLocation {
+        VirustLocation {
+            status,
+            ref_start: 0,
+            ref_end: 0,
+            percent_identity: 0.0,
+            indel: false,
+            query_aligned_string: ptr::null_mut(),
+            ref_aligned_string: ptr::null_mut(),
+            message: c_string(message),
+        }
+    }
+}
+
+impl From<LocateOutcome> for VirustLocation {
+    fn from(outcome: LocateOutcome) -> VirustLocation {
+        match outcome {
+            LocateOutcome::Found(loc) => VirustLocation {
+                status: VIRUST_FOUND,
+                ref_start: loc.ref_start.get(),
+                ref_end: loc.ref_end.get(),
+                percent_identity: loc.percent_identity,
+                indel: loc.indel,
+                query_aligned_string: c_string(&loc.query_aligned_string),
+                ref_aligned_string: c_string(&loc.ref_aligned_string),
+                message: ptr::null_mut(),
+            },
+            LocateOutcome::NotFound(not_located) => {
+                VirustLocation::failed(VIRUST_NOT_FOUND, &not_located.to_string())
+            }
+        }
+    }
+}
+
+/// A copy of `s` allocated for C, released by `virust_location_free`. The sequences and messages
+/// have no NUL bytes; one would truncate the string.
+fn c_string(s: &str) -> *mut c_char {
+    let s = s.split('\0').next().unwrap_or_default();
+    CString::new(s)
+        .expect("the NUL bytes are removed")
+        .into_raw()
+}
+
+/// The UTF-8 string at `s`, named `name` in the errors.
+///
+/// # Safety
+/// `s` is NULL or a NUL-terminated string, valid for the duration of the call.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, BoxError> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", name).into());
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|err| format!("{} is not UTF-8: {}", name, err).into())
+}
+
+/// Locates `query` on `reference` as a query of type `type_query`, see the module documentation.
+/// The result is released with `virust_location_free`.
+///
+/// # Safety
+/// The arguments are NULL or NUL-terminated strings, valid for the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn virust_locate(
+    query: *const c_char,
+    reference: *const c_char,
+    type_query: *const c_char,
+) -> *mut VirustLocation {
+    let locate = || -> Result<LocateOutcome, BoxError> {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![unsafe { str_arg(query, "query") }?.to_string()],
+                reference: unsafe { str_arg(reference, "reference") }?.to_string(),
+                type_query: unsafe { str_arg(type_query, "type_query") }?.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .validate()?;
+        Ok(Locator::try_build(&args)?.remove(0))
+    };
+    // Panics must not unwind into the caller.
+    let location = match panic::catch_unwind(AssertUnwindSafe(locate)) {
+        Ok(Ok(outcome)) => VirustLocation::from(outcome),
+        Ok(Err(err)) => VirustLocation::failed(VIRUST_ERROR, &err.to_string()),
+        Err(_) => VirustLocation::failed(VIRUST_ERROR, "the locator panicked"),
+    };
+    Box::into_raw(Box::new(location))
+}
+
+/// Releases a location returned by `virust_locate`, and its strings.
+///
+/// # Safety
+/// `location` is NULL or a location returned by `virust_locate`, not released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn virust_location_free(location: *mut VirustLocation) {
+    if location.is_null() {
+        return;
+    }
+    let location = unsafe { Box::from_raw(location) };
+    for s in [
+        location.query_aligned_string,
+        location.ref_aligned_string,
+        location.message,
+    ] {
+        if !s.is_null() {
+            drop(unsafe { CString::from_raw(s) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virust_locate() {
+        let reference = CString::new("HXB2").unwrap();
+        let nt = CString::new("nt").unwrap();
+
+        let query = CString::new("ATGCATGCATGC").unwrap();
+        let location = unsafe { virust_locate(query.as_ptr(), reference.as_ptr(), nt.as_ptr()) };
+        let found = unsafe { &*location };
+        assert_eq!(found.status, VIRUST_FOUND);
+        assert_eq!((found.ref_start, found.ref_end), (1373, 1384));
+        let aligned = unsafe { CStr::from_ptr(found.query_aligned_string) };
+        assert_eq!(aligned.to_str().unwrap(), "ATGCATGCATGC");
+        assert!(found.message.is_null());
+        unsafe { virust_location_free(location) };
+
+        let query = CString::new("ATGCXYZ").unwrap();
+        let location = unsafe { virust_locate(query.as_ptr(), reference.as_ptr(), nt.as_ptr()) };
+        let error = unsafe { &*location };
+        assert_eq!(error.status, VIRUST_ERROR);
+        assert!(error.query_aligned_string.is_null());
+        assert!(!error.message.is_null());
+        unsafe { virust_location_free(location) };
+
+        let location = unsafe { virust_locate(ptr::null(), reference.as_ptr(), nt.as_ptr()) };
+        let message = unsafe { CStr::from_ptr((*location).message) };
+        assert_eq!(message.to_str().unwrap(), "query is NULL");
+        unsafe { virust_location_free(location) };
+        unsafe { virust_location_free(ptr::null_mut()) };
+    }
+}
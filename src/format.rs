@@ -0,0 +1,328 @@
+//! Output formatters, filters and transformations for batches of results.
+//!
+//! `Formatter`, `ResultFilter` and `ResultMapper` are the extension points for output formats,
+//! filters and transformations: the built-in formats implement `Formatter`, with the `plugins`
+//! feature formatters and filters can be loaded from dynamic libraries (see the `plugin` module),
+//! and with the `scripting` feature filters and transformations can be written as expressions
+//! (see the `script` module).
+
+use crate::BoxError;
+use crate::batch::{self, BatchResult, DelimitedOptions, Rows};
+use crate::locator::Coordinates;
+use crate::reference::Gene;
+use std::io::Write;
+
+/// Writes a batch of results in an output format.
+pub trait Formatter {
+    /// Writes `results`, in order, to `writer`.
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError>;
+}
+
+/// Decides which results are kept in the output.
+pub trait ResultFilter {
+    /// Returns `true` if `result` is kept.
+    fn keep(&self, result: &BatchResult) -> Result<bool, BoxError>;
+}
+
+/// Transforms results before they are written.
+pub trait ResultMapper {
+    /// Returns the transformed `result`.
+    fn map(&self, result: BatchResult) -> Result<BatchResult, BoxError>;
+}
+
+/// Format of the results, selected with `--format`: delimited values, SAM records, the LANL
+/// results table, the VCF of the variants, GFF3 features, BED intervals, JSON lines or the
+/// alignments for reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OutputFormat {
+    /// Tab-separated values.
+    #[default]
+    Tsv,
+    /// Comma-separated values.
+    Csv,
+    /// SAM records, see the `sam` module.
+    Sam,
+    /// The results table of the LANL HIV Sequence Locator, see the `lanl` module.
+    Lanl,
+    /// The substitutions and indels of the queries as VCF, see the `vcf` module.
+    Vcf,
+    /// The locations and the regions they overlap as GFF3 features, see the `gff` module.
+    Gff3,
+    /// The locations as BED intervals, see the `bed` module.
+    Bed,
+    /// The alignments in blocks, like BLAST, for reviewing them by eye, see the `pretty` module.
+    Pretty,
+    /// One JSON object per result and line, written as the queries are located, see
+    /// `NdjsonFormatter`.
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// The column delimiter of the format.
+    pub fn delimiter(self) -> char {
+        match self {
+            OutputFormat::Tsv
+            | OutputFormat::Sam
+            | OutputFormat::Lanl
+            | OutputFormat::Vcf
+            | OutputFormat::Gff3
+            | OutputFormat::Bed
+            | OutputFormat::Pretty
+            | OutputFormat::Ndjson => '\t',
+            OutputFormat::Csv => ',',
+        }
+    }
+}
+
+/// Tab-separated values, or values separated by another `delimiter`, see
+/// `batch::write_delimited`.
+#[derive(Debug, Clone, Copy)]
+pub struct TsvFormatter {
+    /// Convention of the reference positions.
+    pub coordinates: Coordinates,
+    /// Gene the positions are also reported relative to.
+    pub relative_to: Option<&'static Gene<'static>>,
+    /// Leave out the aligned strings.
+    pub no_align_strings: bool,
+    /// The column delimiter.
+    pub delimiter: char,
+    /// Add the `strand` column.
+    pub strand: bool,
+    /// Add the `regions` column.
+    pub regions: bool,
+    /// Add the `other_hits` column.
+    pub other_hits: bool,
+    /// Add the `nt_ref_start` and `nt_ref_end` columns.
+    pub nt_coordinates: bool,
+    /// Add the `query_start` and `query_end` columns.
+    pub query_coordinates: bool,
+    /// Add the `cigar` column.
+    pub cigar: bool,
+    /// Add the `score` and `edit_distance` columns.
+    pub score: bool,
+    /// Add the `identity_mode` column.
+    pub identity_mode: bool,
+    /// Add the `defects` column.
+    pub defects: bool,
+    /// Add the `env_regions` column.
+    pub env_regions: bool,
+    /// Add the `ltr_features` and `ltr_ambiguous` columns.
+    pub ltr_features: bool,
+    /// Add the `cis_elements` column.
+    pub cis_elements: bool,
+    /// Add the `amplicons` column.
+    pub amplicons: bool,
+    /// Add the `glycosylation_sites` column.
+    pub glycosylation: bool,
+    /// Add the `insertions` column.
+    pub insertions: bool,
+}
+
+impl Default for TsvFormatter {
+    fn default() -> Self {
+        TsvFormatter {
+            coordinates: Coordinates::OneBased,
+            relative_to: None,
+            no_align_strings: false,
+            delimiter: '\t',
+            strand: false,
+            regions: false,
+            other_hits: false,
+            nt_coordinates: false,
+            query_coordinates: false,
+            cigar: false,
+            score: false,
+            identity_mode: false,
+            defects: false,
+            env_regions: false,
+            ltr_features: false,
+            cis_elements: false,
+            amplicons: false,
+            glycosylation: false,
+            insertions: false,
+        }
+    }
+}
+
+impl From<DelimitedOptions<'static>> for TsvFormatter {
+    fn from(options: DelimitedOptions<'static>) -> Self {
+        TsvFormatter {
+            coordinates: options.coordinates,
+            relative_to: options.relative_to,
+            no_align_strings: options.no_align_strings,
+            delimiter: options.delimiter,
+            strand: options.strand,
+            regions: options.regions,
+            other_hits: options.other_hits,
+            nt_coordinates: options.nt_coordinates,
+            query_coordinates: options.query_coordinates,
+            cigar: options.cigar,
+            score: options.score,
+            identity_mode: options.identity_mode,
+            defects: options.defects,
+            env_regions: options.env_regions,
+            ltr_features: options.ltr_features,
+            cis_elements: options.cis_elements,
+            amplicons: options.amplicons,
+            glycosylation: options.glycosylation,
+            insertions: options.insertions,
+        }
+    }
+}
+
+impl Formatter for TsvFormatter {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        let options = DelimitedOptions {
+            coordinates: self.coordinates,
+            relative_to: self.relative_to,
+            no_align_strings: self.no_align_strings,
+            delimiter: self.delimiter,
+            strand: self.strand,
+            regions: self.regions,
+            other_hits: self.other_hits,
+            nt_coordinates: self.nt_coordinates,
+            query_coordinates: self.query_coordinates,
+            cigar: self.cigar,
+            score: self.score,
+            identity_mode: self.identity_mode,
+            defects: self.defects,
+            env_regions: self.env_regions,
+            ltr_features: self.ltr_features,
+            cis_elements: self.cis_elements,
+            amplicons: self.amplicons,
+            glycosylation: self.glycosylation,
+            insertions: self.insertions,
+            rows: Rows::Records,
+            header: true,
+        };
+        batch::write_delimited(writer, results, &options)
+    }
+}
+
+/// A pretty-printed JSON array of results.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        serde_json::to_writer_pretty(&mut *writer, results)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+/// Newline-delimited JSON: one compact JSON object per result and line, in the shape of the
+/// objects of `JsonFormatter`. The writer is flushed after every call, so that the results
+/// written one chunk at a time reach the reader as they are located.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NdjsonFormatter;
+
+impl Formatter for NdjsonFormatter {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        for result in results {
+            serde_json::to_writer(&mut *writer, result)?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Keeps the located results covering at least a fraction of their query, see
+/// `Locator::query_coverage`.
+#[derive(Debug, Clone, Copy)]
+pub struct MinQueryCoverage(pub f64);
+
+impl ResultFilter for MinQueryCoverage {
+    fn keep(&self, result: &BatchResult) -> Result<bool, BoxError> {
+        Ok(result
+            .locator
+            .as_ref()
+            .is_some_and(|loc| loc.query_coverage >= self.0))
+    }
+}
+
+/// Keeps the results accepted by every filter, in order.
+pub fn apply_filters(
+    results: Vec<BatchResult>,
+    filters: &[Box<dyn ResultFilter>],
+) -> Result<Vec<BatchResult>, BoxError> {
+    let mut kept = Vec::with_capacity(results.len());
+    'results: for result in results {
+        for filter in filters {
+            if !filter.keep(&result)? {
+                continue 'results;
+            }
+        }
+        kept.push(result);
+    }
+    Ok(kept)
+}
+
+/// Applies every mapper, in order, to every result.
+pub fn apply_mappers(
+    results: Vec<BatchResult>,
+    mappers: &[Box<dyn ResultMapper>],
+) -> Result<Vec<BatchResult>, BoxError> {
+    results
+        .into_iter()
+        .map(|result| {
+            mappers
+                .iter()
+                .try_fold(result, |result, mapper| mapper.map(result))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Args;
+    use crate::input::Record;
+
+    struct FoundOnly;
+
+    impl ResultFilter for FoundOnly {
+        fn keep(&self, result: &BatchResult) -> Result<bool, BoxError> {
+            Ok(result.found)
+        }
+    }
+
+    #[test]
+    fn test_filters_and_formatters() {
+        let records = vec![
+            Record {
+                id: "good".to_string(),
+                sequence: "ATGCATGCATGC".to_string(),
+                quality: None,
+            },
+            Record {
+                id: "bad".to_string(),
+                sequence: "ATGCXYZ".to_string(),
+                quality: None,
+            },
+        ];
+        let results = batch::locate_records(records, &Args::default());
+        let filters: Vec<Box<dyn ResultFilter>> = vec![Box::new(FoundOnly)];
+        let kept = apply_filters(results, &filters).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert!(MinQueryCoverage(1.0).keep(&kept[0]).unwrap());
+
+        let mut tsv = Vec::new();
+        TsvFormatter::default().write(&mut tsv, &kept).unwrap();
+        assert_eq!(String::from_utf8(tsv).unwrap().lines().count(), 2);
+
+        let mut json = Vec::new();
+        JsonFormatter.write(&mut json, &kept).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value[0]["id"], "good");
+
+        let mut ndjson = Vec::new();
+        NdjsonFormatter.write(&mut ndjson, &kept).unwrap();
+        let ndjson = String::from_utf8(ndjson).unwrap();
+        assert_eq!(ndjson.lines().count(), 1);
+        let line: serde_json::Value = serde_json::from_str(ndjson.trim_end()).unwrap();
+        assert_eq!(line, value[0]);
+    }
+}
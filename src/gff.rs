@@ -0,0 +1,217 @@
+//! GFF3 output of the locations, for genome browsers showing them as a track on the reference.
+//!
+//! With `--format gff3`, every located query is written as a `match` feature on the reference,
+//! named by its strain (e.g. `HXB2`), after the `##gff-version 3` and `##sequence-region` lines,
+//! with one `match_part` child feature per gene or LTR of the reference its location overlaps:
+//!
+//! - `start`, `end`: the location, or the part of the region covered, 1-based and inclusive. A
+//!   location wrapping around the end of the reference has one line per segment, sharing its `ID`.
+//! - `score`: the percent identity of the whole location.
+//! - `strand`: `-` for queries located on the reverse complement with `--both-strands`.
+//! - `ID`, `Name`, `Parent`: the query ID, and `<query ID>.<region>` and the region for the child
+//!   features; the ID of an exon of tat or rev is `<query ID>.<region>.exon<n>`.
+//! - `Target`: the query ID and the query residues aligned to the feature, 1-based.
+//!
+//! Queries that are not located have no feature. Attribute values are escaped as GFF3 requires.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::gff;
+//! use virust_locator::locator::Locator;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let features = gff::features("q1", &loc, "HXB2").unwrap();
+//! assert_eq!(
+//!     features[1].join("\t"),
+//!     "HXB2\tvirust-locator\tmatch_part\t790\t849\t100\t+\t.\tID=q1.gag;Parent=q1;Name=gag;Target=q1 1 60"
+//! );
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::Formatter;
+use crate::lanl;
+use crate::locator::Locator;
+use crate::reference::{resolve_reference, retrieve_reference_sequence};
+use crate::sequence_locator;
+use std::io::Write;
+
+/// The source column of the features.
+const SOURCE: &str = "virust-locator";
+
+/// Writes the header of the GFF3 output on `reference`: the `##gff-version` and
+/// `##sequence-region` lines.
+pub fn write_header<W: Write>(mut writer: W, reference: &str) -> Result<(), BoxError> {
+    let name = resolve_reference(reference)?;
+    let length = retrieve_reference_sequence(name, "nt")?.sequence.len();
+    writeln!(writer, "##gff-version 3")?;
+    writeln!(writer, "##sequence-region {} 1 {}", name, length)?;
+    Ok(())
+}
+
+/// Escapes the characters of `value` reserved in the attributes column.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' | '=' | '&' | ',' | '%' | '\t' | '\n' | '\r' => {
+                escaped.push_str(&format!("%{:02X}", c as u32))
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The features of the query `id` located at `loc` on `reference`: its `match` lines, one per
+/// segment, then a `match_part` line per region overlap.
+pub fn features(id: &str, loc: &Locator, reference: &str) -> Result<Vec<Vec<String>>, BoxError> {
+    let seqid = resolve_reference(reference)?;
+    let line = |kind: &str, start: usize, end: usize, attributes: String| {
+        vec![
+            seqid.to_string(),
+            SOURCE.to_string(),
+            kind.to_string(),
+            start.to_string(),
+            end.to_string(),
+            loc.percent_identity.to_string(),
+            loc.strand.to_string(),
+            ".".to_string(),
+            attributes,
+        ]
+    };
+    // The target ID is separated from the positions by spaces, so its own are escaped too.
+    let target_id = escape(id).replace(' ', "%20");
+    let target = |start: usize, end: usize| format!("Target={} {} {}", target_id, start, end);
+    let mut features: Vec<Vec<String>> = loc
+        .ref_segments()
+        .iter()
+        .map(|segment| {
+            let attributes = format!(
+                "ID={};Name={};{}",
+                escape(id),
+                escape(id),
+                target(loc.query_start.get(), loc.query_end.get())
+            );
+            line("match", segment.start.get(), segment.end.get(), attributes)
+        })
+        .collect();
+    let regions = sequence_locator::gene_regions(reference, "nt");
+    for overlap in sequence_locator::overlaps(loc, &regions) {
+        // The exons of a spliced gene share its name, not their ID.
+        let exon = overlap
+            .exon
+            .map_or(String::new(), |exon| format!(".exon{}", exon));
+        let mut attributes = format!(
+            "ID={}.{}{};Parent={};Name={}",
+            escape(id),
+            escape(&overlap.region),
+            exon,
+            escape(id),
+            escape(&overlap.region)
+        );
+        if let Some((start, end)) = lanl::query_interval(loc, &overlap) {
+            attributes.push(';');
+            attributes.push_str(&target(start, end));
+        }
+        features.push(line(
+            "match_part",
+            overlap.start.get(),
+            overlap.end.get(),
+            attributes,
+        ));
+    }
+    Ok(features)
+}
+
+/// Writes the features of the query `id`, located at `loc` on `reference`, see `features`.
+/// Nothing is written when `loc` is `None`.
+pub fn write_features<W: Write>(
+    mut writer: W,
+    id: &str,
+    loc: Option<&Locator>,
+    reference: &str,
+) -> Result<(), BoxError> {
+    let Some(loc) = loc else {
+        return Ok(());
+    };
+    for feature in features(id, loc, reference)? {
+        writeln!(writer, "{}", feature.join("\t"))?;
+    }
+    Ok(())
+}
+
+/// GFF3 features of the results, see `features`.
+#[derive(Debug, Clone)]
+pub struct Gff3Formatter {
+    /// The reference the results are located on.
+    pub reference: String,
+}
+
+impl Formatter for Gff3Formatter {
+    fn write(&self, mut writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        write_header(&mut writer, &self.reference)?;
+        for result in results {
+            write_features(
+                &mut writer,
+                &result.id,
+                result.locator.as_ref(),
+                &self.reference,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, CommonArgs};
+
+    #[test]
+    fn test_features() {
+        // HXB2 2075-2175: the end of gag and the start of pol, from 2085.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "GACAGGCTAATTTTTTAGGGAAGATCTGGCCTTCCTACAAGGGAAGGCCAGGGAATTTTCTTCAGAGCAGACCAGAGCCAACAGCCCCACCAGAAGAGAGC".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let features: Vec<String> = features("read 1;a", &loc, "HXB2")
+            .unwrap()
+            .iter()
+            .map(|feature| feature.join("\t"))
+            .collect();
+        assert_eq!(
+            features,
+            [
+                "HXB2\tvirust-locator\tmatch\t2075\t2175\t100\t+\t.\tID=read 1%3Ba;Name=read 1%3Ba;Target=read%201%3Ba 1 101",
+                "HXB2\tvirust-locator\tmatch_part\t2075\t2175\t100\t+\t.\tID=read 1%3Ba.gag;Parent=read 1%3Ba;Name=gag;Target=read%201%3Ba 1 101",
+                "HXB2\tvirust-locator\tmatch_part\t2085\t2175\t100\t+\t.\tID=read 1%3Ba.pol;Parent=read 1%3Ba;Name=pol;Target=read%201%3Ba 11 101",
+            ]
+        );
+
+        let mut out = Vec::new();
+        Gff3Formatter {
+            reference: "HXB2".to_string(),
+        }
+        .write(&mut out, &[])
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "##gff-version 3\n##sequence-region HXB2 1 9719\n"
+        );
+    }
+}
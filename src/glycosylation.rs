@@ -0,0 +1,365 @@
+//! Detection of the N-linked glycosylation sites of the queries, the `N-X-S` and `N-X-T` sequons
+//! (`X` any amino acid but proline), compared with those of the reference.
+//!
+//! With `--glycosylation`, the query of every location is translated in the reading frame of each
+//! gene of the reference it overlaps (nucleotide queries, see the `defects` module), or read as is
+//! (amino acid queries), and its sequons are attached to the location as `GlycosylationSite`s (see
+//! `Locator::glycosylation_sites`), numbered on the reference: the codon of the `N` in its gene, as
+//! in the `coords` module, or in its protein for amino acid queries. A site is:
+//!
+//! - `Conserved` when the reference has a sequon at the same position,
+//! - `Gained` when it does not, including the sequons of residues inserted relative to the
+//!   reference, numbered after the reference residue before them with a letter, e.g. `186a`,
+//! - `Lost` for a sequon of the reference whose three residues are covered by the location, and
+//!   that the query does not have.
+//!
+//! Codons with ambiguous bases translated to several amino acids, and codons with a frameshifting
+//! indel, are `X`, which are part of no sequon.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::glycosylation::SiteStatus;
+//! use virust_locator::locator::Locator;
+//!
+//! // Env 290-340, with T303N losing the site at 301 (NNN), and K305S gaining one at 303 (NRS).
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["TSVEINCTRPNNNNRSRIRIQRGPGRAFVTIGKIGNMRQAHCNISRAKWNN".to_string()],
+//!         type_query: "aa".to_string(),
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         glycosylation: true,
+//!         ..Default::default()
+//!     },
+//!     gene: Some("env".to_string()),
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let sites: Vec<String> = loc.glycosylation_sites.iter().map(ToString::to_string).collect();
+//! assert_eq!(
+//!     sites,
+//!     ["env N295 (NCT)", "env N301 (NNT, lost)", "env N303 (NRS, gained)", "env N332 (NIS)"]
+//! );
+//! ```
+
+use crate::coords::{coding_genes, coding_offset};
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::reference::{self, Gene, retrieve_reference_sequence};
+use crate::sequence::translate_codon;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// Whether a glycosylation site of the query is also one of the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteStatus {
+    /// The reference and the query have the site.
+    Conserved,
+    /// The query has the site, the reference does not.
+    Gained,
+    /// The reference has the site, the query does not.
+    Lost,
+}
+
+/// An N-linked glycosylation site of a query or of the reference.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GlycosylationSite {
+    /// The gene, e.g. `env`.
+    pub gene: &'static str,
+    /// The codon of the gene (or the residue of its protein) of the `N` on the reference, or of the
+    /// reference residue before it when it is inserted.
+    pub position: usize,
+    /// 0 for an `N` aligned to the reference, `k` for the `k`-th residue inserted after `position`.
+    pub insertion: usize,
+    /// The three residues of the sequon: those of the reference for a lost site.
+    pub sequon: String,
+    pub status: SiteStatus,
+}
+
+impl Display for GlycosylationSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} N{}", self.gene, self.position)?;
+        if self.insertion > 0 {
+            // a to z, then aa, ab...: insertions longer than 26 residues are rare.
+            let mut letters = String::new();
+            let mut k = self.insertion;
+            while k > 0 {
+                letters.insert(0, (b'a' + ((k - 1) % 26) as u8) as char);
+                k = (k - 1) / 26;
+            }
+            write!(f, "{}", letters)?;
+        }
+        match self.status {
+            SiteStatus::Conserved => write!(f, " ({})", self.sequon),
+            SiteStatus::Gained => write!(f, " ({}, gained)", self.sequon),
+            SiteStatus::Lost => write!(f, " ({}, lost)", self.sequon),
+        }
+    }
+}
+
+/// A residue of the query read in a gene: the codon of the reference residue it is aligned to, or
+/// of the one before it and its rank when it is inserted.
+#[derive(Debug, Clone, Copy)]
+struct Residue {
+    codon: usize,
+    insertion: usize,
+    amino_acid: u8,
+}
+
+/// The residues of the query of a location in a gene, and the codons of the gene covered.
+#[derive(Debug, Default)]
+struct Translation {
+    residues: Vec<Residue>,
+    covered: Vec<usize>,
+}
+
+/// Whether the residues `a`, `b` and `c` form a sequon.
+fn is_sequon(a: u8, b: u8, c: u8) -> bool {
+    a == b'N' && !matches!(b, b'P' | b'X' | b'*') && matches!(c, b'S' | b'T')
+}
+
+/// Returns the glycosylation sites of the location `loc` on `reference` with their status, in the
+/// order of the genes of the reference and of their codons. Amino acid locations are on the
+/// protein of `gene` when it is set, as with `--gene`.
+pub fn sites(
+    loc: &Locator,
+    reference: &str,
+    type_query: &str,
+    gene: Option<&Gene<'static>>,
+) -> Vec<GlycosylationSite> {
+    let Ok(proteins) = retrieve_reference_sequence(reference, "aa") else {
+        return Vec::new();
+    };
+    let mut sites = Vec::new();
+    for (name, translation) in translations(loc, reference, type_query, gene) {
+        let Ok(protein) = reference::retrieve_gene(reference, "aa", name) else {
+            continue;
+        };
+        let ref_protein = &proteins.sequence[protein.start.to_zero_based()..protein.end.get()];
+        let ref_residue = |codon: usize| ref_protein.get(codon - 1).copied().unwrap_or(b'*');
+        let is_ref_site = |codon: usize| {
+            is_sequon(
+                ref_residue(codon),
+                ref_residue(codon + 1),
+                ref_residue(codon + 2),
+            )
+        };
+        let residues = &translation.residues;
+        let mut gene_sites = BTreeMap::new();
+        for (i, window) in residues.windows(3).enumerate() {
+            if !is_sequon(
+                window[0].amino_acid,
+                window[1].amino_acid,
+                window[2].amino_acid,
+            ) {
+                continue;
+            }
+            let residue = residues[i];
+            let status = if residue.insertion == 0 && is_ref_site(residue.codon) {
+                SiteStatus::Conserved
+            } else {
+                SiteStatus::Gained
+            };
+            gene_sites.insert(
+                (residue.codon, residue.insertion),
+                GlycosylationSite {
+                    gene: name,
+                    position: residue.codon,
+                    insertion: residue.insertion,
+                    sequon: window.iter().map(|r| r.amino_acid as char).collect(),
+                    status,
+                },
+            );
+        }
+        let covered = |codon: usize| translation.covered.binary_search(&codon).is_ok();
+        for &codon in &translation.covered {
+            if is_ref_site(codon)
+                && covered(codon + 1)
+                && covered(codon + 2)
+                && !gene_sites.contains_key(&(codon, 0))
+            {
+                gene_sites.insert(
+                    (codon, 0),
+                    GlycosylationSite {
+                        gene: name,
+                        position: codon,
+                        insertion: 0,
+                        sequon: (codon..codon + 3).map(|c| ref_residue(c) as char).collect(),
+                        status: SiteStatus::Lost,
+                    },
+                );
+            }
+        }
+        sites.extend(gene_sites.into_values());
+    }
+    sites
+}
+
+/// The residues of the query of `loc` in every gene it overlaps, see `sites`.
+fn translations(
+    loc: &Locator,
+    reference: &str,
+    type_query: &str,
+    gene: Option<&Gene<'static>>,
+) -> Vec<(&'static str, Translation)> {
+    let ref_positions = loc.ref_positions();
+    let (Some(first), Some(last)) = (
+        ref_positions.iter().position(Option::is_some),
+        ref_positions.iter().rposition(Option::is_some),
+    ) else {
+        return Vec::new();
+    };
+    // The columns of the alignment, without the overhangs: the query residue and the reference
+    // position it is aligned to, or the one before it for inserted residues.
+    let mut columns = Vec::new();
+    let mut last_ref = None;
+    let aligned = loc.query_aligned_string.bytes().zip(&ref_positions);
+    for (i, (q, &ref_position)) in aligned.enumerate() {
+        last_ref = ref_position.or(last_ref);
+        if let Some(position) = last_ref
+            && (first..=last).contains(&i)
+        {
+            columns.push((q, position, ref_position.is_none()));
+        }
+    }
+    if type_query == "aa" {
+        let proteins: Vec<(&'static str, usize, RefPosition, RefPosition)> = match gene {
+            Some(gene) => {
+                let length = gene.start.span_to(gene.end);
+                vec![(gene.name, 0, RefPosition::new(1), RefPosition::new(length))]
+            }
+            None => reference::genes(reference, "aa")
+                .map(|gene| (gene.name, gene.start.to_zero_based(), gene.start, gene.end))
+                .collect(),
+        };
+        return proteins
+            .into_iter()
+            .filter_map(|(name, offset, start, end)| {
+                let mut translation = Translation::default();
+                let mut insertion = 0;
+                for &(q, position, inserted) in &columns {
+                    if position < start || position > end {
+                        continue;
+                    }
+                    let codon = position.get() - offset;
+                    insertion = if inserted { insertion + 1 } else { 0 };
+                    if !inserted {
+                        translation.covered.push(codon);
+                    }
+                    if q != b'-' {
+                        translation.residues.push(Residue {
+                            codon,
+                            insertion,
+                            amino_acid: q,
+                        });
+                    }
+                }
+                (!translation.covered.is_empty()).then_some((name, translation))
+            })
+            .collect();
+    }
+    coding_genes(reference)
+        .filter_map(|gene| {
+            // The bases of every codon covered, and of the insertions after them.
+            let mut codons: BTreeMap<usize, (usize, Vec<u8>)> = BTreeMap::new();
+            for &(q, position, inserted) in &columns {
+                let Some(offset) = coding_offset(gene, position) else {
+                    continue;
+                };
+                // Insertions after the last base of the gene are not in it.
+                if inserted && coding_offset(gene, position + 1).is_none() {
+                    continue;
+                }
+                let (ref_bases, bases) = codons.entry(offset / 3 + 1).or_default();
+                *ref_bases += usize::from(!inserted);
+                bases.extend((q != b'-').then_some(q));
+            }
+            let mut translation = Translation::default();
+            for (codon, (ref_bases, bases)) in codons {
+                if ref_bases < 3 {
+                    continue;
+                }
+                translation.covered.push(codon);
+                let amino_acids: Vec<u8> = match bases.len() % 3 {
+                    0 => bases
+                        .chunks(3)
+                        .map(|codon| {
+                            let amino_acids =
+                                translate_codon(std::str::from_utf8(codon).unwrap_or_default());
+                            match amino_acids.as_bytes() {
+                                &[amino_acid] => amino_acid,
+                                _ => b'X',
+                            }
+                        })
+                        .collect(),
+                    _ => vec![b'X'],
+                };
+                translation
+                    .residues
+                    .extend(
+                        amino_acids
+                            .into_iter()
+                            .enumerate()
+                            .map(|(insertion, amino_acid)| Residue {
+                                codon,
+                                insertion,
+                                amino_acid,
+                            }),
+                    );
+            }
+            (!translation.covered.is_empty()).then_some((gene.name, translation))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sites_nt() {
+        // HXB2 7080-7250, with NGT inserted in V3 after 7160, the last base of codon 312 of env.
+        let reference = "GTACAGCTGAACACATCTGTAGAAATTAATTGTACAAGACCCAACAACAATACAAGAAAAAGAATCCGTATCCAGAGAGGACCAGGGAGAGCATTTGTTACAATAGGAAAAATAGGAAATATGAGACAAGCACATTGTAACATTAGTAGAGCAAAATGGAATAACACTTTA";
+        let query = format!("{}AACGGTACT{}", &reference[..81], &reference[81..]);
+        let ref_aligned = format!("{}---------{}", &reference[..81], &reference[81..]);
+        let loc = Locator::new(
+            RefPosition::new(7080),
+            RefPosition::new(7250),
+            95.0,
+            true,
+            query,
+            ref_aligned,
+        );
+        let sites: Vec<String> = sites(&loc, "HXB2", "nt", None)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            sites,
+            [
+                "env N289 (NTS)",
+                "env N295 (NCT)",
+                "env N301 (NNT)",
+                "env N312a (NGT, gained)",
+                "env N332 (NIS)",
+                "env N339 (NNT)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let site = GlycosylationSite {
+            gene: "env",
+            position: 186,
+            insertion: 28,
+            sequon: "NGT".to_string(),
+            status: SiteStatus::Gained,
+        };
+        assert_eq!(site.to_string(), "env N186ab (NGT, gained)");
+    }
+}
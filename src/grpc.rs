@@ -0,0 +1,348 @@
+//! gRPC interface for the locator, available with the `grpc` feature.
+//!
+//! The service is defined in `proto/locator.proto` (package `virust.locator.v1`) and exposes two
+//! methods:
+//!
+//! - `Locate`: locates a single query sequence. Invalid input is reported through the gRPC status.
+//! - `LocateStream`: bidirectional streaming for batch location. One `LocateResponse` is sent for
+//!   every `LocateRequest`, in the order received. Per-query problems are reported in the `error`
+//!   field of the response so that one bad sequence does not terminate the whole stream.
+//!
+//! Alignments are CPU-bound, so every query is run on tokio's blocking thread pool. Requests are
+//! subject to the `ServerLimits` the service is created with: oversized messages are rejected by
+//! the transport, streams are closed with `RESOURCE_EXHAUSTED` once they exceed the maximum number
+//! of sequences, clients over their rate limit get `RESOURCE_EXHAUSTED` for the affected queries,
+//! and alignments running past the timeout are reported as `DEADLINE_EXCEEDED`. A timed-out
+//! alignment cannot be interrupted and keeps its worker thread until it finishes.
+//!
+//! # Example
+//! ```rust,no_run
+//! # async fn run() -> Result<(), virust_locator::BoxError> {
+//! use virust_locator::limits::ServerLimits;
+//!
+//! let addr = "127.0.0.1:50051".parse()?;
+//! virust_locator::grpc::serve(addr, ServerLimits::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::limits::{RateLimiter, ServerLimits};
+use crate::locator::{LocateOutcome, Locator};
+use crate::logging;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Code generated from `proto/locator.proto`.
+pub mod proto {
+    tonic::include_proto!("virust.locator.v1");
+}
+
+use proto::locator_server::{Locator as LocatorRpc, LocatorServer};
+use proto::{LocateRequest, LocateResponse, RefSegment};
+
+/// Implementation of the `virust.locator.v1.Locator` gRPC service.
+#[derive(Debug, Default, Clone)]
+pub struct LocatorService {
+    limits: ServerLimits,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl LocatorService {
+    /// Creates a service enforcing the given limits.
+    pub fn new(limits: ServerLimits) -> Self {
+        LocatorService {
+            limits,
+            limiter: limits.rate_limiter().map(Arc::new),
+        }
+    }
+
+    /// Wraps the service in the tonic server type, ready to be added to a `Server` router. The
+    /// maximum request size of the service limits is applied to incoming messages.
+    pub fn into_server(self) -> LocatorServer<LocatorService> {
+        let max_request_size = self.limits.max_request_size;
+        LocatorServer::new(self).max_decoding_message_size(max_request_size)
+    }
+}
+
+/// Returns the IP address used as the rate-limiting key for a request.
+fn client_ip<T>(request: &Request<T>) -> IpAddr {
+    request
+        .remote_addr()
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Fails with `RESOURCE_EXHAUSTED` if `client` is over its rate limit.
+fn check_rate(limiter: Option<&RateLimiter>, client: IpAddr) -> Result<(), Status> {
+    match limiter {
+        Some(limiter) if !limiter.check(client) => {
+            #[cfg(feature = "metrics")]
+            crate::metrics::QUERIES
+                .with_label_values(&["rejected"])
+                .inc();
+            Err(Status::resource_exhausted("Rate limit exceeded"))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl LocateRequest {
+    /// Converts the request into validated `Args`, applying the same defaults as the CLI.
+    fn to_args(&self) -> Result<Args, String> {
+        Args {
+            common: CommonArgs {
+                query: vec![self.sequence.clone()],
+                reference: if self.reference.is_empty() {
+                    "HXB2".to_string()
+                } else {
+                    self.reference.clone()
+                },
+                type_query: if self.type_query.is_empty() {
+                    "nt".to_string()
+                } else {
+                    self.type_query.clone()
+                },
+                algorithm: match self.algorithm {
+                    0 => 1,
+                    a => u8::try_from(a).unwrap_or(u8::MAX),
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .validate()
+    }
+}
+
+impl LocateResponse {
+    fn from_locator(id: String, loc: Locator) -> Self {
+        LocateResponse {
+            id,
+            found: true,
+            ref_start: loc.ref_start.get() as u64,
+            ref_end: loc.ref_end.get() as u64,
+            percent_identity: loc.percent_identity,
+            indel: loc.indel,
+            query_aligned_string: loc.query_aligned_string,
+            ref_aligned_string: loc.ref_aligned_string,
+            error: String::new(),
+            not_located: String::new(),
+            warnings: loc.warnings.iter().map(ToString::to_string).collect(),
+            query_coverage: loc.query_coverage,
+            ref_span: loc.ref_span as u64,
+            segments: loc
+                .segments
+                .iter()
+                .map(|segment| RefSegment {
+                    start: segment.start.get() as u64,
+                    end: segment.end.get() as u64,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Locates the query of a single request. Blocking; call from a blocking-capable thread.
+fn locate_one(request: LocateRequest) -> Result<LocateResponse, Status> {
+    let started = Instant::now();
+    let args = request.to_args().map_err(|err| {
+        logging::locate_event(
+            &request.id,
+            &request.reference,
+            u8::try_from(request.algorithm).unwrap_or(u8::MAX),
+            started,
+            Err(&err),
+        );
+        Status::invalid_argument(err)
+    })?;
+    #[cfg(feature = "metrics")]
+    let _timer = crate::metrics::QUERY_DURATION
+        .with_label_values(&[args.common.algorithm.to_string()])
+        .start_timer();
+    let loc = Locator::try_build(&args)
+        .map_err(|err| Status::internal(err.to_string()))?
+        .pop()
+        .ok_or_else(|| Status::internal("No query to locate"))?;
+    logging::locate_event(
+        &request.id,
+        &args.common.reference,
+        args.common.algorithm,
+        started,
+        Ok(loc.is_found()),
+    );
+
+    Ok(match loc {
+        LocateOutcome::Found(loc) => LocateResponse::from_locator(request.id, *loc),
+        LocateOutcome::NotFound(not_located) => LocateResponse {
+            id: request.id,
+            found: false,
+            not_located: not_located.to_string(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Runs `locate_one` on the blocking thread pool, giving up after `timeout` if one is set.
+async fn locate_blocking(
+    request: LocateRequest,
+    timeout: Option<Duration>,
+) -> Result<LocateResponse, Status> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::QUEUE_DEPTH.inc();
+    let task = tokio::task::spawn_blocking(move || {
+        #[cfg(feature = "metrics")]
+        crate::metrics::QUEUE_DEPTH.dec();
+        locate_one(request)
+    });
+    let joined = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, task)
+            .await
+            .map_err(|_| Status::deadline_exceeded("Alignment timed out")),
+        None => Ok(task.await),
+    };
+    let result = joined.and_then(|joined| {
+        joined
+            .map_err(|err| Status::internal(err.to_string()))
+            .and_then(|result| result)
+    });
+
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = match &result {
+            Ok(response) if response.found => "found",
+            Ok(_) => "not_found",
+            Err(_) => "error",
+        };
+        crate::metrics::QUERIES.with_label_values(&[outcome]).inc();
+    }
+
+    result
+}
+
+#[tonic::async_trait]
+impl LocatorRpc for LocatorService {
+    async fn locate(
+        &self,
+        request: Request<LocateRequest>,
+    ) -> Result<Response<LocateResponse>, Status> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::REQUESTS
+            .with_label_values(&["Locate"])
+            .inc();
+        check_rate(self.limiter.as_deref(), client_ip(&request))?;
+        locate_blocking(request.into_inner(), self.limits.alignment_timeout)
+            .await
+            .map(Response::new)
+    }
+
+    type LocateStreamStream = ReceiverStream<Result<LocateResponse, Status>>;
+
+    async fn locate_stream(
+        &self,
+        request: Request<Streaming<LocateRequest>>,
+    ) -> Result<Response<Self::LocateStreamStream>, Status> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::REQUESTS
+            .with_label_values(&["LocateStream"])
+            .inc();
+        let client = client_ip(&request);
+        let limits = self.limits;
+        let limiter = self.limiter.clone();
+        let mut inbound = request.into_inner();
+        let (tx, rx) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            let mut received = 0;
+            loop {
+                let request = match inbound.message().await {
+                    Ok(Some(request)) => request,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                };
+                received += 1;
+                if received > limits.max_sequences {
+                    let message = format!(
+                        "Too many sequences in one stream, the maximum is {}",
+                        limits.max_sequences
+                    );
+                    let _ = tx.send(Err(Status::resource_exhausted(message))).await;
+                    break;
+                }
+                let id = request.id.clone();
+                let response = match check_rate(limiter.as_deref(), client) {
+                    Ok(()) => locate_blocking(request, limits.alignment_timeout).await,
+                    Err(status) => Err(status),
+                }
+                .unwrap_or_else(|status| LocateResponse {
+                    id,
+                    error: status.message().to_string(),
+                    ..Default::default()
+                });
+                if tx.send(Ok(response)).await.is_err() {
+                    // The client went away.
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serves the locator gRPC service on `addr`, enforcing `limits`, until the process is terminated.
+pub async fn serve(addr: SocketAddr, limits: ServerLimits) -> Result<(), BoxError> {
+    tonic::transport::Server::builder()
+        .add_service(LocatorService::new(limits).into_server())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(sequence: &str) -> LocateRequest {
+        LocateRequest {
+            id: "q1".to_string(),
+            sequence: sequence.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_locate_one_defaults() {
+        let response = locate_one(request("ATGCATGCATGC")).unwrap();
+        assert_eq!(response.id, "q1");
+        assert!(response.found);
+        assert!(response.ref_start > 0);
+        assert!(response.ref_end >= response.ref_start);
+        assert!(response.error.is_empty());
+    }
+
+    #[test]
+    fn test_check_rate() {
+        let limiter = RateLimiter::new(1, 1);
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(check_rate(None, client).is_ok());
+        assert!(check_rate(Some(&limiter), client).is_ok());
+        let status = check_rate(Some(&limiter), client).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[test]
+    fn test_locate_one_invalid_sequence() {
+        let status = locate_one(request("ATGCXYZ")).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().contains("Invalid nucleotide sequence"));
+    }
+}
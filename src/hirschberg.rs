@@ -0,0 +1,290 @@
+//! Semi-global alignment in linear space, for queries whose dynamic programming matrix would not
+//! fit in memory.
+//!
+//! The aligner of `bio` keeps a traceback matrix of `(m + 1) * (n + 1)` cells to align a query of
+//! `m` residues to a reference of `n`: about 190 MB for a whole genome on HXB2, and more on the
+//! copies of the reference joined for the queries wrapping around its end. `semiglobal` finds an
+//! alignment of the same score with the divide and conquer of Hirschberg, extended to affine gap
+//! penalties by Myers and Miller (1988), keeping only a few rows of the matrix: the query is split
+//! in two halves, the reference position where an optimal alignment crosses the middle of the
+//! query is found from the scores of the first half forwards and of the second half backwards,
+//! and the two halves are aligned on either side of it. It computes about twice as many cells as
+//! the full matrix.
+//!
+//! The alignment is global on the query and local on the reference, as `Aligner::semiglobal`: the
+//! reference interval is found first from the last row of the scores forwards, for its end, and
+//! of the query backwards from that end, for its start. Between alignments of equal score, the
+//! two aligners may choose differently.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::hirschberg;
+//!
+//! let score = |a: u8, b: u8| if a == b { 1 } else { -1 };
+//! let aln = hirschberg::semiglobal(b"ACGTTGCA", b"TTTACGTAGCATTT", score, -5, -1);
+//! assert_eq!((aln.ystart, aln.yend, aln.score), (3, 11, 6));
+//! ```
+
+use bio::alignment::{Alignment, AlignmentMode, AlignmentOperation};
+
+/// Score of the states no alignment reaches, low enough not to overflow when penalties are added.
+const NEG: i32 = i32::MIN / 2;
+
+/// The penalties of the gaps, and the score of aligning a query residue to a reference residue.
+struct Scores<F> {
+    score: F,
+    gap_open: i32,
+    gap_extend: i32,
+}
+
+impl<F: Fn(u8, u8) -> i32> Scores<F> {
+    /// The penalty of a gap of `len` positions, 0 for none.
+    fn gap(&self, len: usize) -> i32 {
+        match len {
+            0 => 0,
+            len => self.gap_open + len as i32 * self.gap_extend,
+        }
+    }
+
+    /// The last row of the scores of aligning `a` to the prefixes of `b`: the best scores ending at
+    /// each column, and the best ending with a gap in `b` (query residues inserted). A gap in `b`
+    /// at the start of `a` is opened with `open`, 0 when it continues a gap before `a`. With
+    /// `free_start`, `a` can start anywhere in `b`.
+    fn last_row(&self, a: &[u8], b: &[u8], open: i32, free_start: bool) -> (Vec<i32>, Vec<i32>) {
+        let mut cc: Vec<i32> = (0..=b.len())
+            .map(|j| if free_start { 0 } else { self.gap(j) })
+            .collect();
+        let mut dd = vec![NEG; b.len() + 1];
+        for (i, &x) in a.iter().enumerate() {
+            let mut diagonal = cc[0];
+            let mut c = open + (i + 1) as i32 * self.gap_extend;
+            cc[0] = c;
+            dd[0] = c;
+            let mut e = NEG;
+            for (j, &y) in b.iter().enumerate() {
+                e = e.max(c + self.gap_open) + self.gap_extend;
+                dd[j + 1] = dd[j + 1].max(cc[j + 1] + self.gap_open) + self.gap_extend;
+                c = (diagonal + (self.score)(x, y)).max(e).max(dd[j + 1]);
+                diagonal = cc[j + 1];
+                cc[j + 1] = c;
+            }
+        }
+        (cc, dd)
+    }
+
+    /// Appends to `ops` an optimal global alignment of `a` and `b`. A gap in `b` at the start or
+    /// the end of `a` is opened with `open_start` or `open_end`, 0 when it continues a gap outside.
+    fn align(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        open_start: i32,
+        open_end: i32,
+        ops: &mut Vec<AlignmentOperation>,
+    ) {
+        let (m, n) = (a.len(), b.len());
+        if n == 0 {
+            ops.extend(std::iter::repeat_n(AlignmentOperation::Ins, m));
+            return;
+        }
+        if m == 0 {
+            ops.extend(std::iter::repeat_n(AlignmentOperation::Del, n));
+            return;
+        }
+        if m == 1 {
+            self.align_residue(a[0], b, open_start, open_end, ops);
+            return;
+        }
+
+        let middle = m / 2;
+        let (column, in_gap) = self.middle_crossing(a, b, middle, open_start, open_end);
+        if in_gap {
+            self.align(&a[..middle - 1], &b[..column], open_start, 0, ops);
+            ops.extend([AlignmentOperation::Ins, AlignmentOperation::Ins]);
+            self.align(&a[middle + 1..], &b[column..], 0, open_end, ops);
+        } else {
+            self.align(&a[..middle], &b[..column], open_start, self.gap_open, ops);
+            self.align(&a[middle..], &b[column..], self.gap_open, open_end, ops);
+        }
+    }
+
+    /// The column where an optimal alignment of `a` and `b` crosses the row `middle` of `a`, and
+    /// whether it crosses it within a gap in `b`, which is opened once for both halves. The rows
+    /// of the scores are dropped before the halves are aligned, so the memory stays linear.
+    fn middle_crossing(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        middle: usize,
+        open_start: i32,
+        open_end: i32,
+    ) -> (usize, bool) {
+        let (cc, dd) = self.last_row(&a[..middle], b, open_start, false);
+        let reversed_a: Vec<u8> = a[middle..].iter().rev().copied().collect();
+        let reversed_b: Vec<u8> = b.iter().rev().copied().collect();
+        let (mut rr, mut ss) = self.last_row(&reversed_a, &reversed_b, open_end, false);
+        rr.reverse();
+        ss.reverse();
+        let mut best = (NEG, 0, false);
+        for j in 0..=b.len() {
+            let through = cc[j] + rr[j];
+            if through > best.0 {
+                best = (through, j, false);
+            }
+            let gap = dd[j] + ss[j] - self.gap_open;
+            if gap > best.0 {
+                best = (gap, j, true);
+            }
+        }
+        (best.1, best.2)
+    }
+
+    /// Appends to `ops` an optimal global alignment of the residue `x` and `b`: `x` aligned to one
+    /// residue of `b` between two gaps, or inserted before or after a gap over the whole of `b`.
+    fn align_residue(
+        &self,
+        x: u8,
+        b: &[u8],
+        open_start: i32,
+        open_end: i32,
+        ops: &mut Vec<AlignmentOperation>,
+    ) {
+        let n = b.len();
+        let inserted = open_start.max(open_end) + self.gap_extend + self.gap(n);
+        let aligned = (0..n)
+            .map(|j| (self.gap(j) + (self.score)(x, b[j]) + self.gap(n - j - 1), j))
+            .max_by_key(|&(score, j)| (score, std::cmp::Reverse(j)));
+        match aligned {
+            Some((score, j)) if score >= inserted => {
+                ops.extend(std::iter::repeat_n(AlignmentOperation::Del, j));
+                ops.push(match x == b[j] {
+                    true => AlignmentOperation::Match,
+                    false => AlignmentOperation::Subst,
+                });
+                ops.extend(std::iter::repeat_n(AlignmentOperation::Del, n - j - 1));
+            }
+            _ if open_start >= open_end => {
+                ops.push(AlignmentOperation::Ins);
+                ops.extend(std::iter::repeat_n(AlignmentOperation::Del, n));
+            }
+            _ => {
+                ops.extend(std::iter::repeat_n(AlignmentOperation::Del, n));
+                ops.push(AlignmentOperation::Ins);
+            }
+        }
+    }
+}
+
+/// Aligns the whole of `query` to an interval of `ref_seq`, like `Aligner::semiglobal` with the
+/// same `score`, `gap_open` and `gap_extend`, in memory linear in the lengths of the sequences.
+pub fn semiglobal<F: Fn(u8, u8) -> i32>(
+    query: &[u8],
+    ref_seq: &[u8],
+    score: F,
+    gap_open: i32,
+    gap_extend: i32,
+) -> Alignment {
+    let scores = Scores {
+        score,
+        gap_open,
+        gap_extend,
+    };
+    // The end of the interval, then its start from the query aligned backwards from the end.
+    let (cc, _) = scores.last_row(query, ref_seq, gap_open, true);
+    let (yend, &best) = cc
+        .iter()
+        .enumerate()
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))
+        .expect("the scores have a column per reference position and one before them");
+    let reversed_query: Vec<u8> = query.iter().rev().copied().collect();
+    let reversed_ref: Vec<u8> = ref_seq[..yend].iter().rev().copied().collect();
+    let (cc, _) = scores.last_row(&reversed_query, &reversed_ref, gap_open, false);
+    let (length, _) = cc
+        .iter()
+        .enumerate()
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))
+        .expect("the scores have a column per reference position and one before them");
+    let ystart = yend - length;
+
+    let mut operations = Vec::with_capacity(query.len() + length);
+    scores.align(
+        query,
+        &ref_seq[ystart..yend],
+        gap_open,
+        gap_open,
+        &mut operations,
+    );
+    Alignment {
+        score: best,
+        ystart,
+        xstart: 0,
+        yend,
+        xend: query.len(),
+        ylen: ref_seq.len(),
+        xlen: query.len(),
+        operations,
+        mode: AlignmentMode::Semiglobal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bio::alignment::pairwise::Aligner;
+
+    /// The score of `operations` aligning `query` from its start to `ref_seq` from `ystart`.
+    fn path_score(aln: &Alignment, query: &[u8], ref_seq: &[u8], open: i32, extend: i32) -> i32 {
+        let (mut x, mut y, mut score) = (0, aln.ystart, 0);
+        let mut previous = None;
+        for &op in &aln.operations {
+            match op {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    score += if query[x] == ref_seq[y] { 1 } else { -1 };
+                    x += 1;
+                    y += 1;
+                }
+                AlignmentOperation::Ins => {
+                    score += extend + if previous == Some(op) { 0 } else { open };
+                    x += 1;
+                }
+                AlignmentOperation::Del => {
+                    score += extend + if previous == Some(op) { 0 } else { open };
+                    y += 1;
+                }
+                _ => unreachable!(),
+            }
+            previous = Some(op);
+        }
+        assert_eq!((x, y), (query.len(), aln.yend));
+        score
+    }
+
+    #[test]
+    fn test_semiglobal() {
+        let score = |a: u8, b: u8| if a == b { 1 } else { -1 };
+        let reference = crate::reference::retrieve_reference_sequence("HXB2", "nt")
+            .unwrap()
+            .sequence;
+        let ref_seq = &reference[..3000];
+        let mut queries: Vec<Vec<u8>> = vec![
+            ref_seq[790..1200].to_vec(),
+            b"ACGTACGTTTGACCA".to_vec(),
+            b"G".to_vec(),
+        ];
+        // Substitutions, a deletion and an insertion.
+        let mut edited = ref_seq[1500..2300].to_vec();
+        edited[100] = b'T';
+        edited.drain(300..340);
+        edited.splice(500..500, b"GGGGGGGGGGCCCCCCCC".iter().copied());
+        queries.push(edited);
+        for (open, extend) in [(-5, -1), (-11, -2), (0, -1)] {
+            let mut aligner = Aligner::new(open, extend, score);
+            for query in &queries {
+                let expected = aligner.semiglobal(query, ref_seq);
+                let aln = semiglobal(query, ref_seq, score, open, extend);
+                assert_eq!(aln.score, expected.score);
+                assert_eq!(path_score(&aln, query, ref_seq, open, extend), aln.score);
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! On-disk cache of the k-mer indexes of algorithm 3, so that repeated invocations read the index
+//! of the reference instead of building it.
+//!
+//! The index of a reference and query type is the file `<strain>.<type>.k<k>.idx` of the cache
+//! directory, e.g. `HXB2.nt.k11.idx`, written by `KmerIndex::write`. It records the length and
+//! CRC-32 of the reference sequence it indexes, and an index of another sequence or length of
+//! k-mers is never used.
+//!
+//! The directory is `--index-cache`. When it is set, a missing, stale or unreadable index is built
+//! and written to it, atomically (see `storage::create`); without it, the index is read from
+//! `default_dir` if `virust-locator index` prebuilt it there, and otherwise built in memory only.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::index_cache;
+//!
+//! let dir = std::env::temp_dir().join(format!("virust-locator-doc-index-{}", std::process::id()));
+//! let path = index_cache::build(&dir, "HXB2", "nt").unwrap();
+//! assert!(path.ends_with("HXB2.nt.k11.idx"));
+//! let index = index_cache::load(Some(&dir), "HXB2", "nt").unwrap();
+//! assert_eq!(index.k, 11);
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::BoxError;
+use crate::reference::{resolve_reference, retrieve_reference_sequence};
+use crate::seed::{self, KmerIndex};
+use crate::storage;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// The directory of the indexes prebuilt by `virust-locator index` without `--index-cache`:
+/// `virust-locator` in `$XDG_CACHE_HOME`, or in `$HOME/.cache`. `None` without either variable.
+pub fn default_dir() -> Option<PathBuf> {
+    let cache = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("virust-locator"))
+}
+
+/// The file of the index of `reference`, resolved to its strain, for `type_query` in `dir`.
+pub fn path(dir: &Path, reference: &str, type_query: &str) -> Result<PathBuf, BoxError> {
+    let strain = resolve_reference(reference)?;
+    Ok(dir.join(format!(
+        "{}.{}.k{}.idx",
+        strain,
+        type_query,
+        seed::kmer_length(type_query)
+    )))
+}
+
+/// Builds the index of `reference` for `type_query` and writes it to `dir`, created if needed.
+/// Returns the path of the index.
+pub fn build(dir: &Path, reference: &str, type_query: &str) -> Result<PathBuf, BoxError> {
+    let ref_seq = retrieve_reference_sequence(reference, type_query)?.sequence;
+    save(
+        dir,
+        reference,
+        type_query,
+        &KmerIndex::new(ref_seq, type_query),
+        ref_seq,
+    )
+}
+
+/// Writes `index`, of `ref_seq`, as the index of `reference` for `type_query` in `dir`.
+fn save(
+    dir: &Path,
+    reference: &str,
+    type_query: &str,
+    index: &KmerIndex,
+    ref_seq: &[u8],
+) -> Result<PathBuf, BoxError> {
+    let path = path(dir, reference, type_query)?;
+    storage::create_dir_all(dir)?;
+    let mut output = storage::create(&path)?;
+    index.write(BufWriter::new(&mut output), ref_seq)?;
+    output.finish()?;
+    Ok(path)
+}
+
+/// The index of `reference` for `type_query`, read from `dir`, or from `default_dir` without
+/// `dir`, when an index of the sequence is found there; otherwise built, and written to `dir` if
+/// set.
+pub fn load(dir: Option<&Path>, reference: &str, type_query: &str) -> Result<KmerIndex, BoxError> {
+    let ref_seq = retrieve_reference_sequence(reference, type_query)?.sequence;
+    let Some(cache) = dir.map(Path::to_path_buf).or_else(default_dir) else {
+        return Ok(KmerIndex::new(ref_seq, type_query));
+    };
+    let cached = storage::open(&path(&cache, reference, type_query)?)
+        .and_then(|file| KmerIndex::read(BufReader::new(file), ref_seq, type_query));
+    if let Ok(index) = cached {
+        return Ok(index);
+    }
+    let index = KmerIndex::new(ref_seq, type_query);
+    if let Some(dir) = dir {
+        save(dir, reference, type_query, &index, ref_seq)?;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("virust-locator-index-{}", std::process::id()));
+        let path = path(&dir, "hxb2", "aa").unwrap();
+        assert_eq!(path, dir.join("HXB2.aa.k4.idx"));
+        assert!(!path.exists());
+
+        let ref_seq = retrieve_reference_sequence("HXB2", "aa").unwrap().sequence;
+        let index = load(Some(&dir), "HXB2", "aa").unwrap();
+        assert_eq!(index, KmerIndex::new(ref_seq, "aa"));
+        assert!(path.exists());
+        assert_eq!(load(Some(&dir), "HXB2", "aa").unwrap(), index);
+
+        // A stale index is replaced.
+        std::fs::write(&path, b"VLKMER").unwrap();
+        assert_eq!(load(Some(&dir), "HXB2", "aa").unwrap(), index);
+        let file = std::fs::File::open(&path).unwrap();
+        assert_eq!(KmerIndex::read(file, ref_seq, "aa").unwrap(), index);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
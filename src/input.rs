@@ -0,0 +1,434 @@
+//! Reading query sequences from FASTA and FASTQ data.
+//!
+//! The format is detected from the first record marker (`>` for FASTA, `@` for FASTQ) and
+//! gzip-compressed data, including the multi-member files written by bgzip, is decompressed
+//! transparently, from its magic bytes rather than the file extension, so callers only need to
+//! hand over a reader.
+//!
+//! FASTQ quality strings are kept with the records: with `--min-base-quality`, the bases below a
+//! Phred quality are masked as `N` before alignment (see `Record::masked_sequence`), and score
+//! neither as matches nor as mismatches.
+//!
+//! Files from collaborators and instruments are rarely pristine: by default, CRLF line endings,
+//! blank lines, wrapped FASTQ records and duplicate record IDs are accepted with a warning logged
+//! to stderr. `read_records_with` in strict mode (`--strict-parse`) rejects them instead.
+
+use crate::BoxError;
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+
+/// A query sequence read from an input file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The record identifier, taken from the header line.
+    pub id: String,
+    /// The sequence, as found in the input.
+    pub sequence: String,
+    /// The quality string of FASTQ records, one Phred+33 score per base of `sequence`.
+    pub quality: Option<String>,
+}
+
+/// Offset of the Phred scores of FASTQ quality strings (Sanger and Illumina 1.8+ encoding).
+pub const PHRED_OFFSET: u8 = 33;
+
+impl Record {
+    /// Returns the sequence with the bases whose Phred quality is below `min_quality` replaced by
+    /// `N`. The sequence of a record without quality string is returned as it is.
+    pub fn masked_sequence(&self, min_quality: u8) -> String {
+        let Some(quality) = &self.quality else {
+            return self.sequence.clone();
+        };
+        self.sequence
+            .chars()
+            .zip(quality.bytes())
+            .map(|(base, score)| {
+                if score.saturating_sub(PHRED_OFFSET) < min_quality {
+                    'N'
+                } else {
+                    base
+                }
+            })
+            .collect()
+    }
+}
+
+/// Supported input formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Fasta,
+    Fastq,
+}
+
+/// Returns `true` if `buf` starts with the gzip magic bytes.
+fn is_gzip(buf: &[u8]) -> bool {
+    buf.starts_with(&[0x1f, 0x8b])
+}
+
+/// Wraps `reader` in a gzip decoder if its content is gzip-compressed. Multi-member archives,
+/// such as bgzip output, are decoded in full.
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn BufRead + 'a>, BoxError> {
+    let mut reader = BufReader::new(reader);
+    if is_gzip(reader.fill_buf()?) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Detects the format from the first non-whitespace byte of `reader` without consuming it.
+/// Returns `None` for empty input.
+fn detect_format<R: BufRead + ?Sized>(reader: &mut R) -> Result<Option<InputFormat>, BoxError> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(pos) => {
+                let format = match buf[pos] {
+                    b'>' => InputFormat::Fasta,
+                    b'@' => InputFormat::Fastq,
+                    other => {
+                        return Err(Box::from(format!(
+                            "Unrecognized input format: expected FASTA ('>') or FASTQ ('@'), found '{}'",
+                            other as char
+                        )));
+                    }
+                };
+                reader.consume(pos);
+                return Ok(Some(format));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Reads all records from FASTA or FASTQ data, which may be gzip-compressed, tolerating the
+/// irregularities of real-world files, see `read_records_with`.
+pub fn read_records<R: Read>(reader: R) -> Result<Vec<Record>, BoxError> {
+    read_records_with(reader, false)
+}
+
+/// Reads all records from FASTA or FASTQ data, which may be gzip-compressed.
+///
+/// FASTA sequences may be wrapped over several lines. Unless `strict`, CRLF line endings, blank
+/// lines, FASTQ sequence and quality wrapped over several lines, and duplicate record IDs are
+/// accepted and logged as warnings; with `strict`, they are errors.
+pub fn read_records_with<R: Read>(reader: R, strict: bool) -> Result<Vec<Record>, BoxError> {
+    let mut reader = decompress(reader)?;
+    let format = match detect_format(&mut reader)? {
+        None => return Ok(Vec::new()),
+        Some(format) => format,
+    };
+    let mut lines = Lines::new(reader, strict);
+    let mut records = Vec::new();
+    let mut ids = HashMap::new();
+    while let Some((line_number, header)) = lines.next_line()? {
+        let (marker, format_name) = match format {
+            InputFormat::Fasta => ('>', "FASTA"),
+            InputFormat::Fastq => ('@', "FASTQ"),
+        };
+        let Some(header) = header.strip_prefix(marker) else {
+            return Err(Box::from(format!(
+                "Invalid {} record at line {}: expected a header starting with '{}'",
+                format_name, line_number, marker
+            )));
+        };
+        let id = header
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let (sequence, quality) = match format {
+            InputFormat::Fasta => (lines.fasta_sequence()?, None),
+            InputFormat::Fastq => {
+                let (sequence, quality) = lines.fastq_sequence(line_number)?;
+                (sequence, Some(quality))
+            }
+        };
+        if let Some(first) = ids.insert(id.clone(), line_number) {
+            let message = format!(
+                "Duplicate record ID '{}' at line {}, first seen at line {}",
+                id, line_number, first
+            );
+            if strict {
+                return Err(Box::from(message));
+            }
+            tracing::warn!("{}", message);
+        }
+        records.push(Record {
+            id,
+            sequence,
+            quality,
+        });
+    }
+    lines.warn_irregularities();
+    Ok(records)
+}
+
+/// The lines of FASTA or FASTQ data, without their line endings, skipping or rejecting the blank
+/// lines and CRLF line endings.
+struct Lines<R> {
+    reader: R,
+    strict: bool,
+    line_number: usize,
+    peeked: Option<(usize, String)>,
+    crlf_lines: usize,
+    blank_lines: usize,
+    wrapped_records: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    fn new(reader: R, strict: bool) -> Self {
+        Lines {
+            reader,
+            strict,
+            line_number: 0,
+            peeked: None,
+            crlf_lines: 0,
+            blank_lines: 0,
+            wrapped_records: 0,
+        }
+    }
+
+    /// Returns the next non-blank line and its 1-based number.
+    fn next_line(&mut self) -> Result<Option<(usize, String)>, BoxError> {
+        if let Some(line) = self.peeked.take() {
+            return Ok(Some(line));
+        }
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            if line.ends_with('\n') {
+                line.pop();
+            }
+            if line.ends_with('\r') {
+                if self.strict {
+                    return Err(self.strict_error("CRLF line ending"));
+                }
+                line.pop();
+                self.crlf_lines += 1;
+            }
+            if line.trim().is_empty() {
+                if self.strict {
+                    return Err(self.strict_error("blank line"));
+                }
+                self.blank_lines += 1;
+                continue;
+            }
+            return Ok(Some((self.line_number, line)));
+        }
+    }
+
+    /// Returns the next non-blank line if it satisfies `accept`, leaving it to be read again
+    /// otherwise.
+    fn next_line_if(
+        &mut self,
+        accept: impl Fn(&str) -> bool,
+    ) -> Result<Option<(usize, String)>, BoxError> {
+        match self.next_line()? {
+            Some(line) if accept(&line.1) => Ok(Some(line)),
+            other => {
+                self.peeked = other;
+                Ok(None)
+            }
+        }
+    }
+
+    fn strict_error(&self, irregularity: &str) -> BoxError {
+        Box::from(format!(
+            "Invalid input at line {}: {} (not allowed with --strict-parse)",
+            self.line_number, irregularity
+        ))
+    }
+
+    /// Reads the sequence lines of a FASTA record, up to the next header.
+    fn fasta_sequence(&mut self) -> Result<String, BoxError> {
+        let mut sequence = String::new();
+        while let Some((_, line)) = self.next_line_if(|line| !line.starts_with('>'))? {
+            sequence.push_str(line.trim());
+        }
+        Ok(sequence)
+    }
+
+    /// Reads the sequence, separator and quality lines of the FASTQ record whose header is at
+    /// line `header`, and returns the sequence and the quality. The sequence ends at the `+`
+    /// separator and the quality once it is as long as the sequence.
+    fn fastq_sequence(&mut self, header: usize) -> Result<(String, String), BoxError> {
+        let truncated = || format!("Invalid FASTQ record at line {}: truncated record", header);
+        let mut sequence = String::new();
+        let mut sequence_lines = 0;
+        loop {
+            let (_, line) = self.next_line()?.ok_or_else(truncated)?;
+            if line.starts_with('+') {
+                break;
+            }
+            sequence.push_str(line.trim());
+            sequence_lines += 1;
+        }
+        let mut quality = String::new();
+        let mut quality_lines = 0;
+        while quality.len() < sequence.len() {
+            let (_, line) = self.next_line()?.ok_or_else(truncated)?;
+            quality.push_str(line.trim());
+            quality_lines += 1;
+        }
+        if quality.len() != sequence.len() {
+            return Err(Box::from(format!(
+                "Invalid FASTQ record at line {}: {} quality scores for {} bases",
+                header,
+                quality.len(),
+                sequence.len()
+            )));
+        }
+        if sequence_lines > 1 || quality_lines > 1 {
+            if self.strict {
+                return Err(Box::from(format!(
+                    "Invalid FASTQ record at line {}: sequence or quality wrapped over several \
+                     lines (not allowed with --strict-parse)",
+                    header
+                )));
+            }
+            self.wrapped_records += 1;
+        }
+        Ok((sequence, quality))
+    }
+
+    /// Logs a warning for every kind of irregularity that was tolerated.
+    fn warn_irregularities(&self) {
+        if self.crlf_lines > 0 {
+            tracing::warn!("{} lines with CRLF line endings", self.crlf_lines);
+        }
+        if self.blank_lines > 0 {
+            tracing::warn!("{} blank lines skipped", self.blank_lines);
+        }
+        if self.wrapped_records > 0 {
+            tracing::warn!(
+                "{} FASTQ records wrapped over several lines",
+                self.wrapped_records
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+    use std::io::Write;
+
+    #[test]
+    fn test_read_fasta() {
+        let data = b"\n>q1 first query\nATGCATGC\nATGC\n>q2\nGCATGCAT\n";
+        let records = read_records(&data[..]).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    id: "q1".to_string(),
+                    sequence: "ATGCATGCATGC".to_string(),
+                    quality: None,
+                },
+                Record {
+                    id: "q2".to_string(),
+                    sequence: "GCATGCAT".to_string(),
+                    quality: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_gzipped_fastq() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b"@read1\nATGCATGCATGC\n+\nIIIIIIIIIIII\n")
+            .unwrap();
+        let data = encoder.finish().unwrap();
+
+        let records = read_records(&data[..]).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "read1");
+        assert_eq!(records[0].sequence, "ATGCATGCATGC");
+        assert_eq!(records[0].quality.as_deref(), Some("IIIIIIIIIIII"));
+    }
+
+    /// Compresses `data` as a BGZF block: a gzip member with the `BC` extra field holding the
+    /// block size minus 1.
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzBuilder::new()
+            .extra(vec![b'B', b'C', 2, 0, 0, 0])
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let mut block = encoder.finish().unwrap();
+        let size = (block.len() - 1) as u16;
+        block[16..18].copy_from_slice(&size.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_read_bgzipped_fasta() {
+        // Two blocks, the second starting mid-record, then the empty end-of-file block.
+        let mut data = bgzf_block(b">q1\nATGCAT");
+        data.extend(bgzf_block(b"GCATGC\n>q2\nGCATGCAT\n"));
+        data.extend(bgzf_block(b""));
+
+        let records = read_records(&data[..]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].sequence, "ATGCATGCATGC");
+        assert_eq!(records[1].id, "q2");
+    }
+
+    #[test]
+    fn test_masked_sequence() {
+        let records = read_records(&b"@read1\nATGCATGC\n+\nII#I5+II\n"[..]).unwrap();
+        assert_eq!(records[0].masked_sequence(20), "ATNCANGC");
+        assert_eq!(records[0].masked_sequence(0), "ATGCATGC");
+        let fasta = read_records(&b">q1\nATGCATGC\n"[..]).unwrap();
+        assert_eq!(fasta[0].masked_sequence(20), "ATGCATGC");
+    }
+
+    #[test]
+    fn test_read_irregular_records() {
+        let fasta = b">q1\r\nATGC\r\n\r\nATGC\r\n>q1 again\r\nGCAT\r\n";
+        let records = read_records(&fasta[..]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].sequence, "ATGCATGC");
+        assert_eq!(records[1].id, "q1");
+        let err = read_records_with(&fasta[..], true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid input at line 1: CRLF line ending (not allowed with --strict-parse)"
+        );
+        let err = read_records_with(&b">q1\nATGC\n>q1\nGCAT\n"[..], true).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Duplicate record ID 'q1' at line 3")
+        );
+
+        let fastq = b"@r1\nATGC\nATGC\n+\nIIII\nIIII\n\n@r2\nGCAT\n+r2\n@III\n";
+        let records = read_records(&fastq[..]).unwrap();
+        assert_eq!(records[0].sequence, "ATGCATGC");
+        assert_eq!(records[1].sequence, "GCAT");
+        assert!(read_records_with(&fastq[..], true).is_err());
+        let err = read_records(&b"@r1\nATGC\n+\nIIIIII\n"[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid FASTQ record at line 1: 6 quality scores for 4 bases"
+        );
+    }
+
+    #[test]
+    fn test_read_unknown_format() {
+        let err = read_records(&b"ATGCATGC\n"[..]).unwrap_err();
+        assert!(err.to_string().contains("Unrecognized input format"));
+    }
+}
@@ -0,0 +1,264 @@
+//! Names of the insertions of the queries relative to the reference, in the nomenclature of the
+//! drug resistance literature, e.g. `RT:69insSS`.
+//!
+//! With `--name-insertions`, every insertion of a location (see `Locator::variants`) is named, and
+//! the names are attached to it as `InsertionName`s (see `Locator::insertions`):
+//!
+//! - For nucleotide queries, on the genome first, by the reference base before the insertion and
+//!   the inserted bases, e.g. `2755insTAGTAG`; then in every gene, and on HXB2 every protein of
+//!   `resistance::PROTEINS`, it is inserted in, by the codon it follows and the inserted amino
+//!   acids, e.g. `pol:224insSS` and `RT:69insSS`, as numbered in the `coords` module.
+//! - For amino acid queries, by the residue it follows in the protein of `--gene`, or of the gene
+//!   of the concatenated protein sequence it is in, then on HXB2 in the proteins of
+//!   `resistance::PROTEINS`, e.g. `pol:224insSS` and `RT:69insSS`.
+//!
+//! The inserted residues are numbered after the residue before them with letters: the `SS` of
+//! `RT:69insSS` are `69a` and `69b`. An insertion within a codon is read with the bases of the
+//! codon, whose first amino acid is that of the codon and the others are inserted. An insertion
+//! whose length is not a multiple of 3 shifts the frame of the gene, and is named `fs`, e.g.
+//! `gag:6insfs`.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 2700-2800, with AGTAGT inserted after codon 69 of RT (2754-2756, ACT), aligned as TAGTAG
+//! // inserted after 2755, within the codon.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec![
+//!             "GGGCCTGAAAATCCATACAATACTCCAGTATTTGCCATAAAGAAAAAAGACAGTACTAGTAGTAAATGGAGAAAATTAGTAGATTT\
+//!              CAGAGAACTTAATAAGAGAAC"
+//!                 .to_string(),
+//!         ],
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         name_insertions: true,
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let names: Vec<String> = loc.insertions.iter().map(ToString::to_string).collect();
+//! assert_eq!(names, ["2755insTAGTAG", "pol:224insSS", "RT:69insSS"]);
+//! ```
+
+use crate::coords::{self, GeneCodon};
+use crate::locator::{Locator, Variant, VariantKind};
+use crate::position::RefPosition;
+use crate::reference::{self, Gene};
+use crate::resistance;
+use crate::sequence::translate_codon;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// The name of an insertion of a query, on the genome or in a gene or protein.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InsertionName {
+    /// The gene or protein, e.g. `RT`, or `None` on the nucleotide genome.
+    pub gene: Option<&'static str>,
+    /// The reference residue the insertion follows: a base of the genome, a codon of the gene or a
+    /// residue of the protein.
+    pub position: usize,
+    /// The inserted bases on the genome, or amino acids in a gene; empty for a frameshift.
+    pub inserted: String,
+    /// Whether the insertion shifts the frame of the gene.
+    pub frameshift: bool,
+}
+
+impl Display for InsertionName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(gene) = self.gene {
+            write!(f, "{}:", gene)?;
+        }
+        match self.frameshift {
+            true => write!(f, "{}insfs", self.position),
+            false => write!(f, "{}ins{}", self.position, self.inserted),
+        }
+    }
+}
+
+/// Translates `bases`, a whole number of codons, with `X` for the ambiguous codons.
+fn translate(bases: &[u8]) -> String {
+    bases
+        .chunks(3)
+        .map(|codon| {
+            let amino_acids = translate_codon(&String::from_utf8_lossy(codon));
+            match amino_acids.len() {
+                1 => amino_acids,
+                _ => "X".to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the names of the insertions of the location `loc` on `reference`, in the order of the
+/// alignment, each on the genome then in its genes for nucleotide queries. Amino acid locations
+/// are on the protein of `gene` when it is set, as with `--gene`.
+pub fn name(
+    loc: &Locator,
+    reference: &str,
+    type_query: &str,
+    gene: Option<&Gene<'static>>,
+) -> Vec<InsertionName> {
+    let insertions = loc
+        .variants()
+        .into_iter()
+        .filter(|variant| variant.kind == VariantKind::Insertion);
+    if type_query == "aa" {
+        return insertions
+            .flat_map(|variant| name_residues(&variant, reference, gene))
+            .collect();
+    }
+    // The query bases aligned to the reference bases, to read the codons with insertions.
+    let aligned: BTreeMap<RefPosition, u8> = loc
+        .ref_positions()
+        .into_iter()
+        .zip(loc.query_aligned_string.bytes())
+        .filter_map(|(position, q)| Some((position?, q)))
+        .collect();
+    let mut names = Vec::new();
+    for variant in insertions {
+        names.push(InsertionName {
+            gene: None,
+            position: variant.ref_position.get(),
+            inserted: variant.query_bases.clone(),
+            frameshift: false,
+        });
+        // The genes with bases on both sides of the insertion.
+        let after = coords::reference_to_genes(reference, variant.ref_position + 1);
+        let codons = coords::reference_to_genes(reference, variant.ref_position)
+            .into_iter()
+            .filter(|codon| after.iter().any(|next| next.gene == codon.gene));
+        for codon in codons {
+            names.push(name_codon(&variant, codon, reference, &aligned));
+        }
+    }
+    names
+}
+
+/// The names of the insertion `variant` of an amino acid query, see `name`.
+fn name_residues(
+    variant: &Variant,
+    reference: &str,
+    gene: Option<&Gene<'static>>,
+) -> Vec<InsertionName> {
+    let found = match gene {
+        Some(gene) => Some((gene.name, variant.ref_position.get())),
+        None => reference::genes(reference, "aa")
+            .find(|gene| gene.start <= variant.ref_position && variant.ref_position <= gene.end)
+            .map(|gene| (gene.name, gene.start.span_to(variant.ref_position))),
+    };
+    let Some((gene, position)) = found else {
+        return Vec::new();
+    };
+    let proteins = resistance::PROTEINS.iter().filter(|protein| {
+        resistance::supports(reference)
+            && protein.gene == gene
+            && protein.offset < position
+            && position <= protein.offset + protein.length
+    });
+    [(gene, position)]
+        .into_iter()
+        .chain(proteins.map(|protein| (protein.name, position - protein.offset)))
+        .map(|(gene, position)| InsertionName {
+            gene: Some(gene),
+            position,
+            inserted: variant.query_bases.clone(),
+            frameshift: false,
+        })
+        .collect()
+}
+
+/// The name of the insertion `variant` in the gene or protein of `codon`, the codon of the base
+/// before it, reading the query bases `aligned` to the other bases of the codon.
+fn name_codon(
+    variant: &Variant,
+    codon: GeneCodon,
+    reference: &str,
+    aligned: &BTreeMap<RefPosition, u8>,
+) -> InsertionName {
+    let mut name = InsertionName {
+        gene: Some(codon.gene),
+        position: codon.codon,
+        inserted: String::new(),
+        frameshift: !variant.query_bases.len().is_multiple_of(3),
+    };
+    if name.frameshift {
+        return name;
+    }
+    let Ok(positions) = coords::gene_codon_to_reference(reference, codon.gene, codon.codon) else {
+        return name;
+    };
+    let query_base = |position: &RefPosition| aligned.get(position).copied().unwrap_or(b'-');
+    let (before, after) = positions.split_at(codon.codon_position);
+    let bases: Vec<u8> = (before.iter().map(query_base))
+        .chain(variant.query_bases.bytes())
+        .chain(after.iter().map(query_base))
+        .filter(|&base| base != b'-')
+        .collect();
+    name.inserted = match bases.len() % 3 {
+        0 => translate(&bases).chars().skip(1).collect(),
+        // The codon has a deletion too: the inserted amino acids cannot be told.
+        _ => "X".repeat(variant.query_bases.len() / 3),
+    };
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AnnotationArgs, Args, CommonArgs};
+    use crate::position::QueryPosition;
+
+    #[test]
+    fn test_name_codon() {
+        // The TTT codon of gag at 799-801 (codon 4), with AAA inserted after its first base.
+        let variant = Variant {
+            kind: VariantKind::Insertion,
+            ref_position: RefPosition::new(799),
+            query_position: QueryPosition::new(11),
+            ref_bases: String::new(),
+            query_bases: "AAA".to_string(),
+        };
+        let codon = coords::hxb2_to_gene(RefPosition::new(799))[0];
+        let aligned: BTreeMap<RefPosition, u8> = [(799, b'G'), (800, b'C'), (801, b'G')]
+            .into_iter()
+            .map(|(position, base)| (RefPosition::new(position), base))
+            .collect();
+        // GAA ACG: E, then T inserted.
+        let name = name_codon(&variant, codon, "HXB2", &aligned);
+        assert_eq!(name.to_string(), "gag:4insT");
+
+        let shifted = Variant {
+            query_bases: "AA".to_string(),
+            ..variant
+        };
+        let name = name_codon(&shifted, codon, "HXB2", &aligned);
+        assert!(name.frameshift);
+        assert_eq!(name.to_string(), "gag:4insfs");
+    }
+
+    #[test]
+    fn test_name_aa() {
+        // RT 58-85 on the concatenated proteins, with SS inserted after 69.
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["TPVFAIKKKDSTSSKWRKLVDFRELNKRTQ".to_string()],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            annotation: AnnotationArgs {
+                name_insertions: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let names: Vec<String> = loc.insertions.iter().map(ToString::to_string).collect();
+        assert_eq!(names, ["pol:224insSS", "RT:69insSS"]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Proviral intactness screening of near-full-length nucleotide queries located on HXB2, as the
+//! HIVSeqinR and Proviral Sequence Annotation and Intactness pipelines do.
+//!
+//! `screen` classifies the location of a query as intact, or defective with the `Reason`s:
+//!
+//! - `Incomplete`: the location does not cover the genome from the start of the packaging signal
+//!   (`PACKAGING_SIGNAL`) to the end of nef.
+//! - `LargeDeletion`: a deletion of at least `MIN_LARGE_DELETION` bases, or as many bases of the
+//!   reference skipped between the segments of a split alignment (see `Locator::split`).
+//! - `PackagingSignalDeletion`: a deletion of at least `MIN_PACKAGING_SIGNAL_DELETION` bases of the
+//!   packaging signal (Ψ).
+//! - `SpliceDonorMutation`: the `GT` of the major splice donor (MSD, `MAJOR_SPLICE_DONOR`) mutated
+//!   or deleted.
+//! - `Hypermutated`: G-to-A mutations enriched in the APOBEC3G/F context, see `hypermutation`.
+//! - `Gene`: a frameshift or a premature stop codon in one of the `ESSENTIAL_GENES`, see the
+//!   `defects` module.
+//!
+//! The `intactness` subcommand (or `--intactness`) writes them as tab-separated values, one row
+//! per query.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::intactness::{self, Reason};
+//! use virust_locator::locator::Locator;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let intactness = intactness::screen(&loc);
+//! assert!(!intactness.intact);
+//! assert!(matches!(intactness.reasons[0], Reason::Incomplete { .. }));
+//! ```
+
+use crate::BoxError;
+use crate::defects::{self, Defect};
+use crate::locator::{Locator, VariantKind};
+use crate::position::RefPosition;
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::Write;
+
+/// The reference of the positions below: queries are screened on HXB2 only.
+pub const REFERENCE: &str = "HXB2";
+
+/// The packaging signal (Ψ), from the first to the fourth stem-loop, on HXB2.
+pub const PACKAGING_SIGNAL: (usize, usize) = (691, 812);
+
+/// The `G` of the `GT` of the major splice donor, on HXB2.
+pub const MAJOR_SPLICE_DONOR: usize = 744;
+
+/// The last base of nef, the end of the genome screened.
+pub const GENOME_END: usize = 9417;
+
+/// Minimum length of a deletion to be a large deletion.
+pub const MIN_LARGE_DELETION: usize = 100;
+
+/// Minimum length of a deletion of the packaging signal to be a defect.
+pub const MIN_PACKAGING_SIGNAL_DELETION: usize = 10;
+
+/// The genes whose frameshifts and premature stops make a provirus defective.
+pub const ESSENTIAL_GENES: [&str; 3] = ["gag", "pol", "env"];
+
+/// Maximum p-value of the Fisher exact test of `hypermutation` for a hypermutated query.
+pub const MAX_HYPERMUTATION_P_VALUE: f64 = 0.05;
+
+/// Why a query is defective.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Reason {
+    /// The location does not cover the genome screened.
+    Incomplete {
+        ref_start: RefPosition,
+        ref_end: RefPosition,
+    },
+    /// A deletion of at least `MIN_LARGE_DELETION` bases from `ref_position`.
+    LargeDeletion {
+        ref_position: RefPosition,
+        length: usize,
+    },
+    /// A deletion of at least `MIN_PACKAGING_SIGNAL_DELETION` bases of the packaging signal.
+    PackagingSignalDeletion {
+        ref_position: RefPosition,
+        length: usize,
+    },
+    /// The query bases aligned to the `GT` of the major splice donor, `-` for deleted ones.
+    SpliceDonorMutation { observed: String },
+    /// G-to-A hypermutation, with the p-value of `hypermutation`.
+    Hypermutated { p_value: f64 },
+    /// A frameshift or a premature stop in an essential gene.
+    Gene(Defect),
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Incomplete { ref_start, ref_end } => {
+                write!(f, "incomplete genome {}-{}", ref_start, ref_end)
+            }
+            Reason::LargeDeletion {
+                ref_position,
+                length,
+            } => write!(f, "large deletion of {} bases at {}", length, ref_position),
+            Reason::PackagingSignalDeletion {
+                ref_position,
+                length,
+            } => write!(
+                f,
+                "packaging signal deletion of {} bases at {}",
+                length, ref_position
+            ),
+            Reason::SpliceDonorMutation { observed } => {
+                write!(f, "major splice donor GT mutated to {}", observed)
+            }
+            Reason::Hypermutated { p_value } => write!(f, "hypermutated (p = {:.2e})", p_value),
+            Reason::Gene(defect) => write!(f, "{}", defect),
+        }
+    }
+}
+
+/// The intactness of a query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Intactness {
+    /// Whether the query has none of the `reasons`.
+    pub intact: bool,
+    /// Why the query is defective, in the order of the `Reason` variants.
+    pub reasons: Vec<Reason>,
+}
+
+/// The G-to-A mutations of a query in the APOBEC3G/F context, `GRD` on the query (`R` is `A` or
+/// `G`, `D` is not `C`), and in the other contexts, as counted by Los Alamos Hypermut 2.0.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Hypermutation {
+    /// The reference `G`s followed by an APOBEC context on the query, mutated to `A`.
+    pub apobec_mutations: usize,
+    /// The reference `G`s followed by an APOBEC context on the query.
+    pub apobec_sites: usize,
+    /// The reference `G`s followed by another context on the query, mutated to `A`.
+    pub control_mutations: usize,
+    /// The reference `G`s followed by another context on the query.
+    pub control_sites: usize,
+    /// The one-sided p-value of the Fisher exact test of more mutations in the APOBEC context.
+    pub p_value: f64,
+}
+
+/// Counts the G-to-A mutations of the location `loc` in and out of the APOBEC context, see
+/// `Hypermutation`. The reference `G`s aligned to a gap, or followed by a gap or an ambiguous base
+/// on the query, are not counted.
+pub fn hypermutation(loc: &Locator) -> Hypermutation {
+    let query: Vec<u8> = loc.query_aligned_string.bytes().collect();
+    let mut counts = [[0; 2]; 2];
+    for (i, r) in loc.ref_aligned_string.bytes().enumerate() {
+        if r != b'G' || !matches!(query[i], b'G' | b'A') {
+            continue;
+        }
+        let context = query.get(i + 1..=i + 2);
+        let apobec = match context {
+            Some(&[next, after]) if b"ACGT".contains(&next) && b"ACGT".contains(&after) => {
+                matches!(next, b'A' | b'G') && after != b'C'
+            }
+            _ => continue,
+        };
+        counts[usize::from(!apobec)][usize::from(query[i] == b'A')] += 1;
+    }
+    let [
+        [apobec_kept, apobec_mutations],
+        [control_kept, control_mutations],
+    ] = counts;
+    Hypermutation {
+        apobec_mutations,
+        apobec_sites: apobec_kept + apobec_mutations,
+        control_mutations,
+        control_sites: control_kept + control_mutations,
+        p_value: fisher_greater(
+            apobec_mutations,
+            apobec_kept,
+            control_mutations,
+            control_kept,
+        ),
+    }
+}
+
+/// The one-sided p-value of the Fisher exact test of the 2x2 table `[[a, b], [c, d]]`: the
+/// probability of `a` or more with the same margins.
+fn fisher_greater(a: usize, b: usize, c: usize, d: usize) -> f64 {
+    let n = a + b + c + d;
+    let mut ln_factorial = vec![0.0; n + 1];
+    for k in 1..=n {
+        ln_factorial[k] = ln_factorial[k - 1] + (k as f64).ln();
+    }
+    let (row, column) = (a + b, a + c);
+    let ln_choose = |n: usize, k: usize| ln_factorial[n] - ln_factorial[k] - ln_factorial[n - k];
+    let p: f64 = (a..=row.min(column))
+        .map(|x| (ln_choose(row, x) + ln_choose(n - row, column - x) - ln_choose(n, column)).exp())
+        .sum();
+    p.min(1.0)
+}
+
+/// Screens the location `loc` of a nucleotide query on HXB2 for the defects of the module
+/// documentation.
+pub fn screen(loc: &Locator) -> Intactness {
+    let mut reasons = Vec::new();
+    let (psi_start, psi_end) = PACKAGING_SIGNAL;
+    // A split alignment in order skips the reference between its segments: a deletion.
+    let skipped = match &loc.segments[..] {
+        [first, second] if loc.split && second.start > first.end => {
+            Some((first.end + 1, second.start.get() - first.end.get() - 1))
+        }
+        _ => None,
+    };
+    let rearranged = loc.split && skipped.is_none();
+    if loc.wraps()
+        || rearranged
+        || loc.ref_start.get() > psi_start
+        || loc.ref_end.get() < GENOME_END
+    {
+        reasons.push(Reason::Incomplete {
+            ref_start: loc.ref_start,
+            ref_end: loc.ref_end,
+        });
+    }
+    let variants = loc.variants();
+    let deletions = variants
+        .iter()
+        .filter(|variant| variant.kind == VariantKind::Deletion);
+    for deletion in deletions.clone() {
+        let length = deletion.ref_bases.len();
+        if length >= MIN_LARGE_DELETION {
+            reasons.push(Reason::LargeDeletion {
+                ref_position: deletion.ref_position,
+                length,
+            });
+        }
+    }
+    if let Some((ref_position, length)) = skipped
+        && length >= MIN_LARGE_DELETION
+    {
+        reasons.push(Reason::LargeDeletion {
+            ref_position,
+            length,
+        });
+    }
+    for deletion in deletions {
+        let first = deletion.ref_position.get();
+        let last = first + deletion.ref_bases.len() - 1;
+        let deleted = last.min(psi_end).saturating_sub(first.max(psi_start)) + 1;
+        if first <= psi_end && last >= psi_start && deleted >= MIN_PACKAGING_SIGNAL_DELETION {
+            reasons.push(Reason::PackagingSignalDeletion {
+                ref_position: deletion.ref_position,
+                length: deleted,
+            });
+        }
+    }
+    let splice_donor = [MAJOR_SPLICE_DONOR, MAJOR_SPLICE_DONOR + 1].map(RefPosition::new);
+    let observed: String = loc
+        .ref_positions()
+        .iter()
+        .zip(loc.query_aligned_string.chars())
+        .filter(|(position, _)| position.is_some_and(|p| splice_donor.contains(&p)))
+        .map(|(_, base)| base)
+        .collect();
+    if observed.len() == 2 && observed != "GT" {
+        reasons.push(Reason::SpliceDonorMutation { observed });
+    }
+    let hypermutation = hypermutation(loc);
+    if hypermutation.p_value <= MAX_HYPERMUTATION_P_VALUE {
+        reasons.push(Reason::Hypermutated {
+            p_value: hypermutation.p_value,
+        });
+    }
+    reasons.extend(
+        defects::detect(loc, REFERENCE)
+            .into_iter()
+            .filter(|defect| ESSENTIAL_GENES.contains(&defect.gene))
+            .map(Reason::Gene),
+    );
+    Intactness {
+        intact: reasons.is_empty(),
+        reasons,
+    }
+}
+
+/// Header line of the tab-separated output of `intactness`.
+pub const TSV_HEADER: &str = "query_id\tverdict\treasons";
+
+/// Writes the intactness of the query `query_id` as a tab-separated row.
+pub fn write_tsv<W: Write>(
+    mut writer: W,
+    query_id: &str,
+    intactness: &Intactness,
+) -> Result<(), BoxError> {
+    let verdict = if intactness.intact {
+        "intact"
+    } else {
+        "defective"
+    };
+    let reasons: Vec<String> = intactness.reasons.iter().map(ToString::to_string).collect();
+    writeln!(writer, "{}\t{}\t{}", query_id, verdict, reasons.join("; "))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    fn hxb2() -> String {
+        let sequence = retrieve_reference_sequence(REFERENCE, "nt")
+            .unwrap()
+            .sequence;
+        String::from_utf8(sequence.to_vec()).unwrap()
+    }
+
+    fn location(query: &str, reference: &str) -> Locator {
+        Locator::new(
+            RefPosition::new(1),
+            RefPosition::new(reference.bytes().filter(|&b| b != b'-').count()),
+            100.0,
+            query.contains('-'),
+            query.to_string(),
+            reference.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_fisher_greater() {
+        assert!((fisher_greater(3, 1, 1, 3) - 0.2428571).abs() < 1e-6);
+        assert_eq!(fisher_greater(0, 5, 0, 5), 1.0);
+    }
+
+    #[test]
+    fn test_screen() {
+        let genome = hxb2();
+        assert_eq!(
+            screen(&location(&genome, &genome)),
+            Intactness {
+                intact: true,
+                reasons: Vec::new(),
+            }
+        );
+
+        // The MSD GT to GC, 200 bases of pol deleted, and every G of env in the GRD context to A.
+        let mut query = genome.clone().into_bytes();
+        query[MAJOR_SPLICE_DONOR] = b'C';
+        query[3000..3200].fill(b'-');
+        for i in 6224..8795 {
+            if query[i] == b'G' && matches!(query[i + 1], b'A' | b'G') && query[i + 2] != b'C' {
+                query[i] = b'A';
+            }
+        }
+        let query = String::from_utf8(query).unwrap();
+        let reasons: Vec<String> = screen(&location(&query, &genome))
+            .reasons
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(reasons[0], "large deletion of 200 bases at 3001");
+        assert_eq!(reasons[1], "major splice donor GT mutated to GC");
+        assert!(reasons[2].starts_with("hypermutated"));
+        assert!(
+            reasons[3..]
+                .iter()
+                .any(|reason| reason.starts_with("env premature stop"))
+        );
+    }
+}
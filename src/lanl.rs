@@ -0,0 +1,229 @@
+//! The layout of the results table of the LANL HIV Sequence Locator, for the parsers written for it.
+//!
+//! With `--format lanl`, every query is written as one row per gene or LTR of the reference its
+//! location overlaps, like the region breakdown of the LANL results page, after a header naming the
+//! columns after the reference (e.g. `HXB2 start`):
+//!
+//! - `Query`: the query ID.
+//! - `Region`: the gene or LTR, as in `reference::GENES`; empty when the location overlaps none.
+//! - `HXB2 start`, `HXB2 end`: the part of the region covered, 1-based and inclusive.
+//! - `Query start`, `Query end`: the query residues aligned to that part, 1-based; those of the
+//!   reverse complement for queries located on the minus strand with `--both-strands`.
+//! - `Protein start`, `Protein end`: the codons of the gene covered, see `coords`; empty for the
+//!   LTRs and the bases outside of the coding segments, such as the intron of tat and rev.
+//! - `Percent identity`: the percent identity of the whole location.
+//!
+//! Queries that are not located have a single row with `NOT_FOUND` as their region.
+//!
+//! With `--coords 0-half-open`, the three intervals are 0-based and half-open, and their columns
+//! are named e.g. `HXB2 start (0-based)` and `HXB2 end (exclusive)`, see `rows_with`.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::lanl;
+//! use virust_locator::locator::Locator;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let rows = lanl::rows("q1", Some(&loc), "HXB2");
+//! assert_eq!(rows[0].join("\t"), "q1\tgag\t790\t849\t1\t60\t1\t20\t100");
+//! ```
+
+use crate::BoxError;
+use crate::batch::{self, BatchResult};
+use crate::coords;
+use crate::format::Formatter;
+use crate::locator::{Coordinates, Locator};
+use crate::position::RefPosition;
+use crate::reference::resolve_reference;
+use crate::sequence_locator::{self, RegionOverlap};
+use std::io::Write;
+
+/// The region of the row of a query that is not located.
+const NOT_FOUND: &str = "NOT_FOUND";
+
+/// The columns of the table on `reference`.
+pub fn columns(reference: &str) -> Result<Vec<String>, BoxError> {
+    columns_with(reference, Coordinates::OneBased)
+}
+
+/// The columns of the table on `reference` with the intervals in `coordinates`.
+pub fn columns_with(reference: &str, coordinates: Coordinates) -> Result<Vec<String>, BoxError> {
+    let name = resolve_reference(reference)?;
+    let (start, end) = match coordinates {
+        Coordinates::OneBased => ("start", "end"),
+        Coordinates::ZeroBased => ("start (0-based)", "end (exclusive)"),
+    };
+    Ok(vec![
+        "Query".to_string(),
+        "Region".to_string(),
+        format!("{} {}", name, start),
+        format!("{} {}", name, end),
+        format!("Query {}", start),
+        format!("Query {}", end),
+        format!("Protein {}", start),
+        format!("Protein {}", end),
+        "Percent identity".to_string(),
+    ])
+}
+
+/// The first and last query residues of `loc` aligned to the reference positions of `overlap`.
+pub(crate) fn query_interval(loc: &Locator, overlap: &RegionOverlap) -> Option<(usize, usize)> {
+    let mut residue = 0;
+    let mut interval: Option<(usize, usize)> = None;
+    for (q, position) in loc.query_aligned_string.bytes().zip(loc.ref_positions()) {
+        if q == b'-' {
+            continue;
+        }
+        residue += 1;
+        if position.is_some_and(|p| overlap.start <= p && p <= overlap.end) {
+            interval = Some((interval.map_or(residue, |(start, _)| start), residue));
+        }
+    }
+    interval
+}
+
+/// The codon of `gene` that `position` is a base of, on `reference`.
+fn codon(reference: &str, gene: &str, position: RefPosition) -> Option<usize> {
+    coords::reference_to_genes(reference, position)
+        .into_iter()
+        .find(|codon| codon.gene == gene)
+        .map(|codon| codon.codon)
+}
+
+/// The rows of the query `id`, located at `loc` on `reference`, or its `NOT_FOUND` row when `loc`
+/// is `None`.
+pub fn rows(id: &str, loc: Option<&Locator>, reference: &str) -> Vec<Vec<String>> {
+    rows_with(id, loc, reference, Coordinates::OneBased)
+}
+
+/// The rows of the query `id` like `rows`, with the intervals in `coordinates`.
+pub fn rows_with(
+    id: &str,
+    loc: Option<&Locator>,
+    reference: &str,
+    coordinates: Coordinates,
+) -> Vec<Vec<String>> {
+    let interval = |start: usize, end: usize| {
+        let (start, end) = coordinates.interval(start, end);
+        [start.to_string(), end.to_string()]
+    };
+    let Some(loc) = loc else {
+        let mut row = vec![String::new(); 9];
+        row[0] = id.to_string();
+        row[1] = NOT_FOUND.to_string();
+        return vec![row];
+    };
+    let regions = sequence_locator::gene_regions(reference, "nt");
+    let overlaps = sequence_locator::overlaps(loc, &regions);
+    if overlaps.is_empty() {
+        let query_len = loc
+            .query_aligned_string
+            .bytes()
+            .filter(|&q| q != b'-')
+            .count();
+        let mut row = vec![id.to_string(), String::new()];
+        row.extend(interval(loc.ref_start.get(), loc.ref_end.get()));
+        row.extend(interval(1, query_len));
+        row.extend([
+            String::new(),
+            String::new(),
+            loc.percent_identity.to_string(),
+        ]);
+        return vec![row];
+    }
+    overlaps
+        .iter()
+        .map(|overlap| {
+            let query = query_interval(loc, overlap)
+                .map(|(start, end)| interval(start, end))
+                .unwrap_or_default();
+            // The first and last codons are numbered separately: either may be outside of the
+            // coding segments.
+            let protein_start = codon(reference, &overlap.region, overlap.start)
+                .map(|codon| coordinates.interval(codon, codon).0.to_string())
+                .unwrap_or_default();
+            let protein_end = codon(reference, &overlap.region, overlap.end)
+                .map(|codon| codon.to_string())
+                .unwrap_or_default();
+            let mut row = vec![id.to_string(), overlap.region.clone()];
+            row.extend(interval(overlap.start.get(), overlap.end.get()));
+            row.extend(query);
+            row.extend([protein_start, protein_end, loc.percent_identity.to_string()]);
+            row
+        })
+        .collect()
+}
+
+/// The LANL results table of the results, see `rows_with`.
+#[derive(Debug, Clone)]
+pub struct LanlFormatter {
+    /// The reference the results are located on.
+    pub reference: String,
+    /// Convention of the intervals.
+    pub coordinates: Coordinates,
+}
+
+impl Formatter for LanlFormatter {
+    fn write(&self, mut writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        let columns = columns_with(&self.reference, self.coordinates)?;
+        batch::write_row(&mut writer, &columns, '\t')?;
+        for result in results {
+            let loc = result.locator.as_ref();
+            for row in rows_with(&result.id, loc, &self.reference, self.coordinates) {
+                batch::write_row(&mut writer, &row, '\t')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, CommonArgs};
+
+    #[test]
+    fn test_rows() {
+        // HXB2 2075-2175: the end of gag and the start of pol, from 2085, in another frame.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "GACAGGCTAATTTTTTAGGGAAGATCTGGCCTTCCTACAAGGGAAGGCCAGGGAATTTTCTTCAGAGCAGACCAGAGCCAACAGCCCCACCAGAAGAGAGC".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let rows = rows("q1", Some(&loc), "HXB2");
+        let rows: Vec<String> = rows.iter().map(|row| row.join("\t")).collect();
+        assert_eq!(
+            rows,
+            [
+                "q1\tgag\t2075\t2175\t1\t101\t429\t462\t100",
+                "q1\tpol\t2085\t2175\t11\t101\t1\t31\t100",
+            ]
+        );
+
+        let rows = rows_with("q1", Some(&loc), "HXB2", Coordinates::ZeroBased);
+        assert_eq!(
+            rows[1].join("\t"),
+            "q1\tpol\t2084\t2175\t10\t101\t0\t31\t100"
+        );
+        assert_eq!(
+            columns_with("HXB2", Coordinates::ZeroBased).unwrap()[2..4],
+            ["HXB2 start (0-based)", "HXB2 end (exclusive)"]
+        );
+
+        let rows = super::rows("q2", None, "HXB2");
+        assert_eq!(rows, [["q2", NOT_FOUND, "", "", "", "", "", "", ""]]);
+    }
+}
@@ -2,10 +2,68 @@
 //! resembling the LANL HIV-locator tool.
 
 use std::error::Error;
+pub mod amplicons;
+pub mod artifacts;
+pub mod backend;
+pub mod batch;
+pub mod bed;
+pub mod cis_elements;
 pub mod config;
+#[cfg(any(feature = "amqp", feature = "kafka"))]
+pub mod consumer;
+pub mod coords;
+pub mod defects;
+pub mod depth;
+pub mod env_regions;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod format;
+pub mod gff;
+pub mod glycosylation;
+pub mod hirschberg;
+pub mod index_cache;
+pub mod input;
+pub mod insertions;
+pub mod intactness;
+pub mod lanl;
+#[cfg(any(feature = "grpc", feature = "router"))]
+pub mod limits;
 pub mod locator;
+pub mod logging;
+pub mod ltr;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod parallel;
+pub mod pipe;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod position;
 pub mod prelude;
+pub mod pretty;
+pub mod primers;
 pub mod reference;
+pub mod resistance;
+pub mod sam;
+pub mod sequence;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod seed;
+pub mod sequence_locator;
+#[cfg(feature = "router")]
+pub mod server;
+pub mod sink;
+pub mod storage;
+pub mod subtype;
+pub mod summary;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod vcf;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workflow;
 
-///! This crate provides a set of utilities for working with the [OpenTelemetry](https://opentelemetry.io/) ecosystem.
+/// The error of the fallible functions of the crate, which can be sent across threads.
 pub type BoxError = Box<dyn Error + Send + Sync>;
@@ -0,0 +1,172 @@
+//! Request limits for the server modes.
+//!
+//! `ServerLimits` groups the knobs that keep a single client from monopolizing a server: the
+//! maximum request size, the maximum number of sequences per request, a per-client rate limit and
+//! an alignment timeout. `RateLimiter` is a token bucket keyed by client IP address, where each
+//! query consumes one token. `LimitArgs` provides the matching command-line options for the server
+//! binaries.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits applied to incoming requests in server mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerLimits {
+    /// Maximum size of a single request message, in bytes.
+    pub max_request_size: usize,
+    /// Maximum number of sequences accepted in one request or stream.
+    pub max_sequences: usize,
+    /// Sustained number of queries per second allowed per client. `None` disables rate limiting.
+    pub rate_limit: Option<u32>,
+    /// Number of queries a client may submit in a burst before the rate limit applies.
+    pub rate_burst: u32,
+    /// Maximum time spent aligning a single query, or a whole upload for batch endpoints. `None`
+    /// disables the timeout.
+    pub alignment_timeout: Option<Duration>,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        ServerLimits {
+            max_request_size: 4 * 1024 * 1024,
+            max_sequences: 10_000,
+            rate_limit: None,
+            rate_burst: 100,
+            alignment_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl ServerLimits {
+    /// Returns a rate limiter for these limits, or `None` when rate limiting is disabled.
+    pub fn rate_limiter(&self) -> Option<RateLimiter> {
+        self.rate_limit
+            .map(|rate| RateLimiter::new(rate, self.rate_burst))
+    }
+}
+
+/// Command-line options for `ServerLimits`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct LimitArgs {
+    /// Maximum size of a single request, in bytes
+    #[arg(long, default_value_t = ServerLimits::default().max_request_size)]
+    pub max_request_size: usize,
+
+    /// Maximum number of sequences accepted in one request or request stream
+    #[arg(long, default_value_t = ServerLimits::default().max_sequences)]
+    pub max_sequences: usize,
+
+    /// Queries per second allowed per client; rate limiting is disabled when not set
+    #[arg(long)]
+    pub rate_limit: Option<u32>,
+
+    /// Number of queries a client may submit in a burst before the rate limit applies
+    #[arg(long, default_value_t = ServerLimits::default().rate_burst)]
+    pub rate_burst: u32,
+
+    /// Maximum alignment time per query (per upload for batch endpoints), in seconds; 0 disables
+    /// the timeout
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+}
+
+impl From<LimitArgs> for ServerLimits {
+    fn from(args: LimitArgs) -> Self {
+        ServerLimits {
+            max_request_size: args.max_request_size,
+            max_sequences: args.max_sequences,
+            rate_limit: args.rate_limit,
+            rate_burst: args.rate_burst,
+            alignment_timeout: (args.timeout > 0).then(|| Duration::from_secs(args.timeout)),
+        }
+    }
+}
+
+/// Per-client token bucket rate limiter.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Number of tracked clients above which idle buckets are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate_per_second` queries per second per client, with bursts of
+    /// up to `burst` queries.
+    pub fn new(rate_per_second: u32, burst: u32) -> Self {
+        RateLimiter {
+            rate: rate_per_second as f64,
+            burst: burst.max(1) as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes one token from the bucket of `client`. Returns `false` if the client is over its limit.
+    pub fn check(&self, client: IpAddr) -> bool {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.retain(|_, b| {
+                b.tokens + now.saturating_duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_rate_limiter_burst_and_refill() {
+        let limiter = RateLimiter::new(2, 3);
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let start = Instant::now();
+
+        assert!(limiter.check_at(client, start));
+        assert!(limiter.check_at(client, start));
+        assert!(limiter.check_at(client, start));
+        assert!(!limiter.check_at(client, start));
+
+        // Other clients have their own bucket.
+        assert!(limiter.check_at(other, start));
+
+        // Two tokens per second are added back.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_at(client, later));
+        assert!(!limiter.check_at(client, later));
+    }
+}
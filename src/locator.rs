@@ -5,14 +5,34 @@
 //! methods.
 
 use crate::BoxError;
-use crate::config::Args;
-use crate::reference::retrieve_reference_sequence;
+use crate::amplicons::{self, AmpliconCoverage};
+use crate::artifacts::{self, ArtifactWarning};
+use crate::backend::{AlignmentBackend, BioBackend};
+use crate::cis_elements::{self, CisElement};
+use crate::config::{ALGORITHM_AUTO, Args, CommonArgs};
+use crate::defects::{self, Defect};
+use crate::env_regions::{self, EnvRegion};
+use crate::glycosylation::{self, GlycosylationSite};
+use crate::index_cache;
+use crate::insertions::{self, InsertionName};
+use crate::ltr::{self, LtrFeature};
+use crate::parallel::*;
+use crate::position::{QueryPosition, RefPosition};
+use crate::reference::{self, Gene, retrieve_reference_sequence};
+use crate::seed::{self, KmerIndex};
+use crate::sequence;
+use crate::sequence_locator::{self, RegionOverlap};
 use bio::alignment::Alignment;
 use bio::alignment::AlignmentOperation;
 use bio::alignment::pairwise::*;
-use bio::pattern_matching::myers::long;
-use rayon::prelude::*;
+use bio::scores;
+#[cfg(feature = "parallel")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 
 /// The `Locator` struct and its associated methods are used to locate and align a query sequence
 /// against a reference sequence. It provides functionality to calculate alignment details such as
@@ -26,14 +46,16 @@ use std::fmt::Display;
 /// - `Locator::new`: Constructs a new `Locator` instance with the given alignment details.
 /// - `Locator::build`: Builds a `Locator` instance by aligning a query sequence against a reference
 ///   sequence using the specified algorithm.
-/// - `get_aln`: Performs a semi-global alignment between a query and reference sequence using a
-///   scoring function and gap penalties.
-/// - `pattern_match`: Uses the Myers bit-parallel algorithm to find approximate matches of a
-///   pattern in a text with a maximum allowed distance.
+/// - `pattern_match`: Uses the approximate matches of the alignment backend (see the `backend`
+///   module) to find the unique match of a pattern in a text with a maximum allowed distance.
 /// - `from_path`: Converts an alignment path into aligned strings, calculates percent identity,
 ///   and determines the presence of indels.
-/// - `algorithm1`: Implements a specific alignment algorithm to align a query sequence against a
-///   reference sequence.
+/// - `algorithm1`: Aligns a query sequence against a reference sequence with the semi-global
+///   alignment of the backend.
+/// - `algorithm2`: Anchors the ends of a long query to the reference with `pattern_match`, checking
+///   that the anchors are unique and colinear, and aligns it to the reference between them.
+/// - `algorithm3`: Chains the k-mers of a query found in a `KmerIndex` of the reference and aligns
+///   it to the window of the reference around the chain.
 ///
 /// # Modules
 /// - `test`: Contains unit tests for the `Locator` struct and its associated methods.
@@ -45,9 +67,11 @@ use std::fmt::Display;
 ///
 /// # Usage
 /// The `Locator` struct is designed to be used in bioinformatics applications where sequence
-/// alignment is required. It supports two algorithms for alignment:
+/// alignment is required. It supports three algorithms for alignment:
 /// - Algorithm 1: A semi-global alignment approach. Slow and more accurate.
 /// - Algorithm 2: A combination of pattern matching and refinement. Faster but less accurate.
+/// - Algorithm 3: Seeding and chaining of k-mers, then a semi-global alignment to the chained
+///   window only. The fastest, for large batches of reads.
 ///
 /// The `Locator::build` method determines which algorithm to use based on the query length and
 /// user-specified parameters.
@@ -55,37 +79,431 @@ use std::fmt::Display;
 /// # Example
 /// ```rust
 /// use virust_locator::locator::Locator;
-/// use virust_locator::config::Args;
+/// use virust_locator::config::{Args, CommonArgs};
 /// let args = Args {
-///     query: vec!["ATTAACAGAGATTTGTGAAGAAATGGAAAAGGAAGGAAAAATTACAAAAATTGGGCCTGAAAATCCATATAACACTCCAATATTTGCCATAAAAAAGAAGGACAGTACTAAGTGGAGAAAATTAGTAGATTTCAGAGAGCTCAATAAAAGAACTCAAGACTTTTGGGAGGTTCAATTAGGAATACCACACCCAGCAGGGTTAAAAAAGAAAAAATCAGTGACAGTACTGGATGTGGGGGATGCATATTTTTCTGTTCCTTTAGATG".to_string()],
-///     reference: "HXB2".to_string(),
-///     type_query: "nt".to_string(),
-///     algorithm: 1,
+///     common: CommonArgs {
+///         query: vec!["ATTAACAGAGATTTGTGAAGAAATGGAAAAGGAAGGAAAAATTACAAAAATTGGGCCTGAAAATCCATATAACACTCCAATATTTGCCATAAAAAAGAAGGACAGTACTAAGTGGAGAAAATTAGTAGATTTCAGAGAGCTCAATAAAAGAACTCAAGACTTTTGGGAGGTTCAATTAGGAATACCACACCCAGCAGGGTTAAAAAAGAAAAAATCAGTGACAGTACTGGATGTGGGGGATGCATATTTTTCTGTTCCTTTAGATG".to_string()],
+///         reference: "HXB2".to_string(),
+///         type_query: "nt".to_string(),
+///         algorithm: 1,
+///         ..Default::default()
+///     },
+///     ..Default::default()
 /// };
 ///
 /// let locator = Locator::build(&args).unwrap().pop().unwrap().unwrap();
 /// println!("{}", locator);
 /// ```
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Locator {
+    /// The identifier of the query: the ID of its FASTA or FASTQ record, or its `--query-name`;
+    /// `None` for the queries without a name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_id: Option<String>,
     /// The starting position of the reference sequence (1-based index).
-    pub ref_start: usize, // starting from 1 on reference
+    pub ref_start: RefPosition,
     /// The ending position of the reference sequence (inclusive).
-    pub ref_end: usize, // inclusive
+    pub ref_end: RefPosition,
     /// The percent identity of the alignment.
     pub percent_identity: f64,
+    /// The score of the alignment with the scoring of the options, see `Scoring`, set by
+    /// `Locator::build`: its aligned columns, and the gaps of the residues overhanging the ends
+    /// of the reference. `None` for the locations built otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<i32>,
+    /// The number of substituted, inserted and deleted residues of the alignment, the residues
+    /// overhanging the ends of the reference left out.
+    pub edit_distance: usize,
+    /// The definition of `percent_identity`, see `IdentityMode`.
+    pub identity_mode: IdentityMode,
     /// Indicates whether there are indels (insertions or deletions) in the alignment.
     pub indel: bool,
     /// The aligned string of the query sequence. Gaps are represented by '-'.
     pub query_aligned_string: String,
     /// The aligned string of the reference sequence. Gaps are represented by '-'.
     pub ref_aligned_string: String,
+    /// The fraction of the query residues aligned to a reference residue, from 0 to 1. Residues
+    /// inserted relative to the reference, including overhangs clipped at the ends of the
+    /// reference, are not covered.
+    pub query_coverage: f64,
+    /// The first query residue aligned to a reference residue (1-based). The residues before it
+    /// overhang the start of the location and are clipped, e.g. an adapter.
+    pub query_start: QueryPosition,
+    /// The last query residue aligned to a reference residue (inclusive). The residues after it
+    /// overhang the end of the location and are clipped.
+    pub query_end: QueryPosition,
+    /// The length of the reference covered by the alignment, `ref_end - ref_start + 1`, or the
+    /// total length of the `segments` when the location wraps around.
+    pub ref_span: usize,
+    /// The stretches of the reference covered, in query order, when the location wraps around
+    /// the end of the reference (see `Locator::build`) or is `split`; empty otherwise.
+    /// `ref_start` is then the start of the first segment and `ref_end` the end of the last one,
+    /// so `ref_end` may be lower than `ref_start`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<RefSegment>,
+    /// Whether the location is a split alignment, with `--split-identity`: the query aligned as
+    /// two `segments` apart on the reference, such as across a large deletion, see `split`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub split: bool,
+    /// The sequencing artifacts of the query, such as long homopolymers, see the `artifacts`
+    /// module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ArtifactWarning>,
+    /// The frameshifts and premature stops in the genes overlapped by a nucleotide location, with
+    /// `--defects`, see the `defects` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub defects: Vec<Defect>,
+    /// The algorithm chosen for the query with `--algorithm auto`, see `choose_algorithm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<u8>,
+    /// The orientation of the query aligned: `Minus` when its reverse complement aligned better,
+    /// with `--both-strands`. The aligned strings and the query positions of the `warnings` are
+    /// then those of the reverse complement.
+    pub strand: Strand,
+    /// Whether the query was given as RNA, with `U` bases and no `T`. It is aligned with its `U`
+    /// read as `T`, and `query_aligned_string` has `T`; `query_aligned_as_given` has `U`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rna: bool,
+    /// The genes and LTRs of the reference overlapped by the location, with `--annotate`, see
+    /// `sequence_locator::gene_regions`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<RegionOverlap>,
+    /// The constant and variable regions of gp120 overlapped by the location, with
+    /// `--env-regions`, see the `env_regions` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env_regions: Vec<EnvRegion>,
+    /// The features of the LTRs overlapped by the location, with `--ltr-features`, see the `ltr`
+    /// module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ltr_features: Vec<LtrFeature>,
+    /// Whether the location is within an LTR and could be placed in either, with
+    /// `--ltr-features`: `ltr_features` then lists the features in both LTRs.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ltr_ambiguous: bool,
+    /// The packaging signal, major splice donor and Rev response element overlapped by the
+    /// location, and whether they are intact, with `--cis-elements`, see the `cis_elements` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cis_elements: Vec<CisElement>,
+    /// The amplicons of `--amplicon-scheme` overlapped by the location, with the fraction of each
+    /// it covers, see the `amplicons` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub amplicons: Vec<AmpliconCoverage>,
+    /// The N-linked glycosylation sites of the query in the genes overlapped by the location,
+    /// conserved, gained or lost relative to the reference, with `--glycosylation`, see the
+    /// `glycosylation` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub glycosylation_sites: Vec<GlycosylationSite>,
+    /// The names of the insertions of the query, on the genome and in the genes and proteins they
+    /// are inserted in, with `--name-insertions`, see the `insertions` module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub insertions: Vec<InsertionName>,
+    /// The other matches of the query, with `--max-hits`: best first, overlapping neither this
+    /// location nor each other, with at least `--min-hit-identity` percent identity.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_hits: Vec<Locator>,
+    /// For amino acid queries, the nucleotide position of the first base of the codon of
+    /// `ref_start` on the reference, see `reference::codon`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nt_ref_start: Option<RefPosition>,
+    /// For amino acid queries, the nucleotide position of the last base of the codon of `ref_end`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nt_ref_end: Option<RefPosition>,
+}
+
+/// Orientation of a query relative to the reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Strand {
+    /// The query as given.
+    #[default]
+    #[serde(rename = "+")]
+    Plus,
+    /// The reverse complement of the query.
+    #[serde(rename = "-")]
+    Minus,
+}
+
+impl Display for Strand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strand::Plus => write!(f, "+"),
+            Strand::Minus => write!(f, "-"),
+        }
+    }
+}
+
+/// A stretch of the reference, 1-based and inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RefSegment {
+    pub start: RefPosition,
+    pub end: RefPosition,
+}
+
+/// Kind of a `Variant`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantKind {
+    /// A query residue aligned to a different reference residue.
+    Substitution,
+    /// Query residues aligned to a gap in the reference.
+    Insertion,
+    /// Reference residues aligned to a gap in the query.
+    Deletion,
+}
+
+/// A difference between the query and the reference in the alignment of a location, see
+/// `Locator::variants`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Variant {
+    pub kind: VariantKind,
+    /// The position of the first reference residue of the variant, or for an insertion, of the
+    /// reference residue before it.
+    pub ref_position: RefPosition,
+    /// The position of the first query residue of the variant, or for a deletion, of the query
+    /// residue before it.
+    pub query_position: QueryPosition,
+    /// The reference residues of the variant, empty for an insertion.
+    pub ref_bases: String,
+    /// The query residues of the variant, empty for a deletion.
+    pub query_bases: String,
+}
+
+/// Why a query could not be located, see `NotLocated`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotLocatedReason {
+    /// The query has no residue to align.
+    EmptyQuery,
+    /// The best alignment aligns no query residue to a reference residue, so it has no interval.
+    NoAlignedResidues,
+    /// The best alignment has no identical residue, so its interval is meaningless.
+    NoMatches,
+    /// The percent identity of the best alignment is below `--min-identity`.
+    BelowMinIdentity,
+    /// The best alignment is shorter than `--min-aln-length`, see `Locator::aligned_length`.
+    BelowMinAlnLength,
+}
+
+/// The diagnostics of a query that could not be located: why, the best alignment found when it
+/// has an interval on the reference, its score, and whether the query looks related to the
+/// reference at all.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NotLocated {
+    pub reason: NotLocatedReason,
+    /// The best alignment found, for `NoMatches` and the alignments filtered out by
+    /// `--min-identity` and `--min-aln-length`; its `ref_start` and `ref_end` are the best
+    /// candidate interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best: Option<Box<Locator>>,
+    /// The score of the best alignment (for nucleotide queries +1 per match, -1 per mismatch, -5 to
+    /// open a gap and -1 per gap position; for amino acid queries, see `Matrix`), if the query was
+    /// aligned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_score: Option<i32>,
+    /// The k-mers the query shares with the reference, see `Screening`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screening: Option<Screening>,
+}
+
+impl NotLocated {
+    fn new(reason: NotLocatedReason) -> Self {
+        NotLocated {
+            reason,
+            best: None,
+            best_score: None,
+            screening: None,
+        }
+    }
+}
+
+/// Whether a query shares enough k-mers with the reference to be related to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreeningVerdict {
+    /// The query shares k-mers with the reference.
+    Related,
+    /// The query shares no k-mer with the reference: it is unlikely to be HIV or SIV, or is of the
+    /// wrong type of query.
+    Unrelated,
+    /// The query is shorter than a k-mer.
+    TooShort,
+}
+
+/// The k-mers of a query found in the reference: 8-mers for `nt`, 3-mers for `aa`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Screening {
+    pub verdict: ScreeningVerdict,
+    /// The length of the k-mers.
+    pub k: usize,
+    /// The number of k-mers of the query found in the reference.
+    pub shared_kmers: usize,
+    /// The number of k-mers of the query.
+    pub query_kmers: usize,
+}
+
+impl Screening {
+    /// Screens `query` against `ref_seq` with the k-mer length of `type_query`.
+    pub fn screen(query: &[u8], ref_seq: &[u8], type_query: &str) -> Self {
+        let k = if type_query == "aa" { 3 } else { 8 };
+        if query.len() < k {
+            return Screening {
+                verdict: ScreeningVerdict::TooShort,
+                k,
+                shared_kmers: 0,
+                query_kmers: 0,
+            };
+        }
+        let ref_kmers: HashSet<&[u8]> = ref_seq.windows(k).collect();
+        let query_kmers = query.len() - k + 1;
+        let shared_kmers = query
+            .windows(k)
+            .filter(|kmer| ref_kmers.contains(kmer))
+            .count();
+        Screening {
+            verdict: if shared_kmers > 0 {
+                ScreeningVerdict::Related
+            } else {
+                ScreeningVerdict::Unrelated
+            },
+            k,
+            shared_kmers,
+            query_kmers,
+        }
+    }
+}
+
+/// The outcome of locating one query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocateOutcome {
+    /// The query was located, boxed as it is much larger than the diagnostics.
+    Found(Box<Locator>),
+    /// The query could not be located, with the diagnostics.
+    NotFound(NotLocated),
+}
+
+impl LocateOutcome {
+    /// Whether the query was located.
+    pub fn is_found(&self) -> bool {
+        matches!(self, LocateOutcome::Found(_))
+    }
+
+    /// The location, if the query was located.
+    pub fn locator(&self) -> Option<&Locator> {
+        match self {
+            LocateOutcome::Found(loc) => Some(loc),
+            LocateOutcome::NotFound(_) => None,
+        }
+    }
+
+    /// Converts the outcome into the location, or the diagnostics as the error.
+    pub fn into_result(self) -> Result<Locator, NotLocated> {
+        match self {
+            LocateOutcome::Found(loc) => Ok(*loc),
+            LocateOutcome::NotFound(not_located) => Err(not_located),
+        }
+    }
+}
+
+/// The outcome of one query of `Locator::build_iter`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LocatorRecord {
+    /// The index of the query in `args.query`.
+    pub index: usize,
+    /// The outcome of locating the query.
+    pub outcome: LocateOutcome,
+}
+
+/// Number of queries `Locator::build_iter` locates at a time, in parallel.
+pub const BUILD_ITER_CHUNK_SIZE: usize = 256;
+
+impl From<Result<Locator, NotLocated>> for LocateOutcome {
+    fn from(result: Result<Locator, NotLocated>) -> Self {
+        match result {
+            Ok(loc) => LocateOutcome::Found(Box::new(loc)),
+            Err(not_located) => LocateOutcome::NotFound(not_located),
+        }
+    }
+}
+
+impl Display for NotLocated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Locator not found: ")?;
+        match self.reason {
+            NotLocatedReason::EmptyQuery => write!(f, "the query is empty"),
+            NotLocatedReason::NoAlignedResidues => {
+                write!(f, "no query residue aligns to the reference")
+            }
+            NotLocatedReason::NoMatches => write!(f, "no query residue matches the reference"),
+            NotLocatedReason::BelowMinIdentity => {
+                write!(f, "the percent identity is below --min-identity")
+            }
+            NotLocatedReason::BelowMinAlnLength => {
+                write!(f, "the alignment is shorter than --min-aln-length")
+            }
+        }?;
+        match (&self.best, self.best_score) {
+            (Some(best), Some(score)) => write!(
+                f,
+                " (best candidate {}-{}, score {})",
+                best.ref_start, best.ref_end, score
+            )?,
+            (None, Some(score)) => write!(f, " (best score {})", score)?,
+            _ => {}
+        }
+        if let Some(screening) = &self.screening
+            && screening.verdict == ScreeningVerdict::Unrelated
+        {
+            write!(
+                f,
+                "; the query shares no {}-mer with the reference",
+                screening.k
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NotLocated {}
+
+/// Convention of the positions in the output, see `--coords`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Coordinates {
+    /// 1-based, inclusive intervals, like the LANL HIV Locator: `ref_start` is the first position
+    /// and `ref_end` the last one.
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "1-inclusive"))]
+    OneBased,
+    /// 0-based, half-open intervals, like BED: `ref_start` is the number of reference positions
+    /// before the location and `ref_end` the position after its last one.
+    #[cfg_attr(feature = "cli", value(name = "0-half-open"))]
+    ZeroBased,
+}
+
+impl Coordinates {
+    /// Returns the 1-based, inclusive interval `start`-`end` in this convention.
+    pub fn interval(self, start: usize, end: usize) -> (usize, usize) {
+        match self {
+            Coordinates::OneBased => (start, end),
+            Coordinates::ZeroBased => (start - 1, end),
+        }
+    }
+}
+
+impl Display for Coordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Coordinates::OneBased => write!(f, "1-inclusive"),
+            Coordinates::ZeroBased => write!(f, "0-half-open"),
+        }
+    }
 }
 
 /// Implements the `Display` trait for the `Locator` struct to provide a formatted string
 /// representation of the alignment details.
 /// The output format includes the reference start and end positions, percent identity,
 /// indel presence, aligned query string, and aligned reference string, separated by tabs.
+/// The coverage fields are left out, so the output keeps the columns of the LANL HIV Locator.
 impl Display for Locator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -102,124 +520,1581 @@ impl Display for Locator {
 }
 
 impl Locator {
-    /// Constructs a new `Locator` instance with the given alignment details.
+    /// Constructs a new `Locator` instance with the given alignment details. `query_coverage`,
+    /// `query_start`, `query_end`, `edit_distance` and `ref_span` are computed from the aligned
+    /// strings and the positions.
     pub fn new(
-        ref_start: usize,
-        ref_end: usize,
+        ref_start: RefPosition,
+        ref_end: RefPosition,
         percent_identity: f64,
         indel: bool,
         query_aligned_string: String,
         ref_aligned_string: String,
     ) -> Self {
+        let (query_start, query_end) = query_interval(&query_aligned_string, &ref_aligned_string);
         Locator {
+            query_id: None,
             ref_start,
             ref_end,
             percent_identity,
+            score: None,
+            edit_distance: edit_distance(&query_aligned_string, &ref_aligned_string),
+            identity_mode: IdentityMode::Gapped,
             indel,
+            query_coverage: query_coverage(&query_aligned_string, &ref_aligned_string),
+            query_start,
+            query_end,
+            ref_span: ref_start.span_to(ref_end),
             query_aligned_string,
             ref_aligned_string,
+            segments: Vec::new(),
+            split: false,
+            warnings: Vec::new(),
+            defects: Vec::new(),
+            algorithm: None,
+            strand: Strand::Plus,
+            rna: false,
+            regions: Vec::new(),
+            env_regions: Vec::new(),
+            ltr_features: Vec::new(),
+            ltr_ambiguous: false,
+            cis_elements: Vec::new(),
+            amplicons: Vec::new(),
+            glycosylation_sites: Vec::new(),
+            insertions: Vec::new(),
+            other_hits: Vec::new(),
+            nt_ref_start: None,
+            nt_ref_end: None,
+        }
+    }
+
+    /// The aligned query string as the query was given: with `U` in place of `T` if the query is
+    /// RNA, see `rna`.
+    pub fn query_aligned_as_given(&self) -> String {
+        if self.rna {
+            sequence::to_rna(&self.query_aligned_string)
+        } else {
+            self.query_aligned_string.clone()
+        }
+    }
+
+    /// Whether the location wraps around the end of the reference.
+    pub fn wraps(&self) -> bool {
+        !self.segments.is_empty() && !self.split
+    }
+
+    /// Returns the stretches of the reference covered, in query order: the `segments` when the
+    /// location wraps around or is split, otherwise `ref_start` to `ref_end`.
+    pub fn ref_segments(&self) -> Vec<RefSegment> {
+        if !self.segments.is_empty() {
+            self.segments.clone()
+        } else {
+            vec![RefSegment {
+                start: self.ref_start,
+                end: self.ref_end,
+            }]
+        }
+    }
+
+    /// Returns the reference position of every alignment column, or `None` for the columns where
+    /// the reference has a gap. Positions follow the `segments` when the location wraps around or
+    /// is split.
+    pub fn ref_positions(&self) -> Vec<Option<RefPosition>> {
+        let segments = self.ref_segments();
+        let mut segments = segments.iter();
+        let mut segment = segments.next();
+        let mut pos = self.ref_start;
+        let mut positions = Vec::with_capacity(self.ref_aligned_string.len());
+        for r in self.ref_aligned_string.bytes() {
+            if r == b'-' {
+                positions.push(None);
+                continue;
+            }
+            while let Some(current) = segment
+                && pos > current.end
+            {
+                segment = segments.next();
+                match segment {
+                    Some(next) => pos = next.start,
+                    None => break,
+                }
+            }
+            positions.push(Some(pos));
+            pos += 1;
+        }
+        positions
+    }
+
+    /// Sets `nt_ref_start` and `nt_ref_end` of an amino acid location to the nucleotide positions
+    /// of the codons of `ref_start` and `ref_end` on `reference`, or on the protein of `gene`.
+    fn back_map(mut self, reference: &str, type_query: &str, gene: Option<&Gene>) -> Self {
+        if type_query != "aa" {
+            return self;
+        }
+        let offset = gene.map_or(0, |gene| gene.start.to_zero_based());
+        let on_reference = |position: RefPosition| RefPosition::new(position.get() + offset);
+        self.nt_ref_start =
+            reference::codon(reference, on_reference(self.ref_start)).map(|(_, first, _)| first);
+        self.nt_ref_end =
+            reference::codon(reference, on_reference(self.ref_end)).map(|(_, _, last)| last);
+        self
+    }
+
+    /// Moves the location, its `segments` and its `other_hits` `by` positions along the
+    /// reference: from a slice of the reference starting after `by` residues, see `--region`, to
+    /// the whole reference.
+    fn offset(mut self, by: usize) -> Self {
+        if by == 0 {
+            return self;
         }
+        let shift = |position: RefPosition| RefPosition::new(position.get() + by);
+        self.ref_start = shift(self.ref_start);
+        self.ref_end = shift(self.ref_end);
+        for segment in &mut self.segments {
+            segment.start = shift(segment.start);
+            segment.end = shift(segment.end);
+        }
+        self.other_hits = self
+            .other_hits
+            .into_iter()
+            .map(|hit| hit.offset(by))
+            .collect();
+        self
+    }
+
+    /// Returns `nt_ref_start` and `nt_ref_end` in `coordinates`, like `interval`, if they are set.
+    pub fn nt_interval(&self, coordinates: Coordinates) -> Option<(usize, usize)> {
+        let (start, end) = (self.nt_ref_start?, self.nt_ref_end?);
+        Some(coordinates.interval(start.get(), end.get()))
+    }
+
+    /// Returns the alignment as a CIGAR string: `M` for aligned residues, matching or not, `I` and
+    /// `D` for insertions and deletions relative to the reference, and `S` for the insertions at
+    /// the ends of the alignment, the query residues overhanging the reference, which are
+    /// soft-clipped. The reference positions of `M` and `D` start at `ref_start`; for a location
+    /// wrapping around the end of the reference, they continue from the start of the reference.
+    pub fn cigar(&self) -> String {
+        let columns: Vec<(u8, u8)> = self
+            .query_aligned_string
+            .bytes()
+            .zip(self.ref_aligned_string.bytes())
+            .collect();
+        let first = columns.iter().position(|&(_, r)| r != b'-');
+        let last = columns.iter().rposition(|&(_, r)| r != b'-');
+        let mut cigar = Vec::new();
+        for (i, &(q, r)) in columns.iter().enumerate() {
+            let clipped = first.is_none_or(|first| i < first) || last.is_none_or(|last| i > last);
+            push_cigar_op(&mut cigar, cigar_op(q, r, clipped));
+        }
+        cigar_string(&cigar)
+    }
+
+    /// Returns the substitutions, insertions and deletions of the query relative to the reference,
+    /// in the order of the alignment: one variant per substituted residue, and one per run of
+    /// inserted or deleted residues. The query residues overhanging the ends of the reference,
+    /// soft-clipped in the `cigar`, are not variants. The reference positions follow the
+    /// `segments` when the location wraps around, and the query positions are those of the
+    /// reverse complement on the minus `strand`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use virust_locator::config::{Args, CommonArgs};
+    /// use virust_locator::locator::{Locator, VariantKind};
+    ///
+    /// // HXB2 790-849 with 3 bases deleted after 829.
+    /// let args = Args {
+    ///     common: CommonArgs {
+    ///         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string()],
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// };
+    /// let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+    /// let variants = loc.variants();
+    /// assert_eq!(variants.len(), 1);
+    /// assert_eq!(variants[0].kind, VariantKind::Deletion);
+    /// assert_eq!(variants[0].ref_position.get(), 830);
+    /// assert_eq!(variants[0].query_position.get(), 40);
+    /// assert_eq!(variants[0].ref_bases.len(), 3);
+    /// ```
+    pub fn variants(&self) -> Vec<Variant> {
+        let ref_positions = self.ref_positions();
+        let (Some(first), Some(last)) = (
+            ref_positions.iter().position(Option::is_some),
+            ref_positions.iter().rposition(Option::is_some),
+        ) else {
+            return Vec::new();
+        };
+        let mut variants: Vec<Variant> = Vec::new();
+        let mut previous = None;
+        let mut query_residues = 0;
+        let mut last_ref = ref_positions[first];
+        let columns = self
+            .query_aligned_string
+            .chars()
+            .zip(self.ref_aligned_string.chars())
+            .zip(&ref_positions);
+        for (i, ((q, r), &ref_position)) in columns.enumerate() {
+            let kind = match (q, r) {
+                _ if i < first || i > last => None,
+                ('-', _) => Some(VariantKind::Deletion),
+                (_, '-') => Some(VariantKind::Insertion),
+                _ if q != r => Some(VariantKind::Substitution),
+                _ => None,
+            };
+            match (kind, variants.last_mut()) {
+                (None, _) => {}
+                (Some(kind), Some(variant))
+                    if kind != VariantKind::Substitution && previous == Some(kind) =>
+                {
+                    variant.ref_bases.extend((r != '-').then_some(r));
+                    variant.query_bases.extend((q != '-').then_some(q));
+                }
+                (Some(kind), _) => variants.push(Variant {
+                    kind,
+                    ref_position: ref_position.or(last_ref).unwrap_or(self.ref_start),
+                    // The alignments never start with a deletion, so a query residue precedes it.
+                    query_position: QueryPosition::new(match kind {
+                        VariantKind::Deletion => query_residues.max(1),
+                        _ => query_residues + 1,
+                    }),
+                    ref_bases: (r != '-').then_some(r).into_iter().collect(),
+                    query_bases: (q != '-').then_some(q).into_iter().collect(),
+                }),
+            }
+            previous = kind;
+            query_residues += usize::from(q != '-');
+            last_ref = ref_position.or(last_ref);
+        }
+        variants
+    }
+
+    /// Attaches the artifacts of the normalized `query` as `warnings`, and computes
+    /// `percent_identity` with `mode`, leaving the artifacts out of it if `mask` is set. The
+    /// percent identities of the `other_hits` are computed with `mode` too.
+    fn flag_artifacts(
+        mut self,
+        query: &str,
+        type_query: &str,
+        mask: bool,
+        mode: IdentityMode,
+        iupac_aware: bool,
+    ) -> Self {
+        self.warnings = artifacts::detect(query, type_query);
+        if mask
+            && let Some(identity) = artifacts::masked_identity(
+                &self.identity_query(iupac_aware),
+                &self.ref_aligned_string,
+                &self.warnings,
+                mode,
+            )
+        {
+            self.percent_identity = identity;
+            for warning in &mut self.warnings {
+                warning.masked = true;
+            }
+        } else if mode != IdentityMode::Gapped || iupac_aware {
+            self.percent_identity = self.identity(mode, iupac_aware);
+        }
+        self.identity_mode = mode;
+        for hit in &mut self.other_hits {
+            if mode != IdentityMode::Gapped || iupac_aware {
+                hit.percent_identity = hit.identity(mode, iupac_aware);
+            }
+            hit.identity_mode = mode;
+        }
+        self
+    }
+
+    /// Returns the number of columns of the alignment between its first and last pair of aligned
+    /// residues: the residues overhanging the ends of the reference are left out.
+    pub fn aligned_length(&self) -> usize {
+        let columns: Vec<(u8, u8)> = self
+            .query_aligned_string
+            .bytes()
+            .zip(self.ref_aligned_string.bytes())
+            .collect();
+        let aligned = |&(q, r): &(u8, u8)| q != b'-' && r != b'-';
+        match (
+            columns.iter().position(aligned),
+            columns.iter().rposition(aligned),
+        ) {
+            (Some(first), Some(last)) => last - first + 1,
+            _ => 0,
+        }
+    }
+
+    /// Sets the `score` of the location and of its `other_hits` with `scoring`.
+    fn score_with(mut self, scoring: &Scoring) -> Self {
+        self.score =
+            Some(scoring.score_alignment(&self.query_aligned_string, &self.ref_aligned_string));
+        for hit in &mut self.other_hits {
+            hit.score =
+                Some(scoring.score_alignment(&hit.query_aligned_string, &hit.ref_aligned_string));
+        }
+        self
+    }
+
+    /// The aligned query string as counted by the percent identity: with `iupac_aware`, the
+    /// ambiguity codes that can stand for the reference base aligned to them are replaced by it,
+    /// so that they count as identical (see `sequence::iupac_match`).
+    fn identity_query(&self, iupac_aware: bool) -> String {
+        if !iupac_aware {
+            return self.query_aligned_string.clone();
+        }
+        self.query_aligned_string
+            .bytes()
+            .zip(self.ref_aligned_string.bytes())
+            .map(|(q, r)| {
+                if sequence::iupac_match(q, r) {
+                    r as char
+                } else {
+                    q as char
+                }
+            })
+            .collect()
+    }
+
+    /// The percent identity of the aligned strings with `mode`, 0 without any column counted,
+    /// counting the ambiguity codes of the query as identical with `iupac_aware`.
+    fn identity(&self, mode: IdentityMode, iupac_aware: bool) -> f64 {
+        let columns: Vec<(u8, u8)> = self
+            .identity_query(iupac_aware)
+            .bytes()
+            .zip(self.ref_aligned_string.bytes())
+            .collect();
+        mode.identity(&columns).unwrap_or(0.0)
+    }
+
+    /// Returns `ref_start` and `ref_end` in `coordinates`. `Locator` positions are always 1-based
+    /// and inclusive; `Coordinates::ZeroBased` gives the BED-style interval `(ref_start - 1,
+    /// ref_end)`.
+    pub fn interval(&self, coordinates: Coordinates) -> (usize, usize) {
+        coordinates.interval(self.ref_start.get(), self.ref_end.get())
+    }
+
+    /// Returns `query_start` and `query_end` in `coordinates`, like `interval`.
+    pub fn query_interval(&self, coordinates: Coordinates) -> (usize, usize) {
+        coordinates.interval(self.query_start.get(), self.query_end.get())
     }
 
     /// Builds a `Locator` instance by aligning a query sequence against a reference sequence using
     /// the specified algorithm.
     /// The method retrieves the reference sequence, performs alignment, and returns a vector of
     /// `Locator` instances.
-    /// If the query length is less than 300 or the specified algorithm is 1, it uses the
-    /// `algorithm1` method for alignment.
-    /// If the query length is greater than or equal to 300, it uses a combination of pattern
-    /// matching and refinement.
+    /// If the query length is less than `args.min_anchored_length` (300 by default) or the
+    /// specified algorithm is 1, it uses the `algorithm1` method for alignment.
+    /// Otherwise, it uses a combination of pattern matching and refinement, see `algorithm2`.
     /// The method returns a `Result` containing a vector of `Option<Locator>` instances.
+    /// Queries longer than the reference, such as concatemers or proviruses with flanking
+    /// sequence, and queries whose end anchors before their start with algorithm 2, such as reads
+    /// across a 2-LTR circle junction, are aligned to copies of the reference joined end to start.
+    /// If the alignment crosses the end of the reference, the location wraps around and reports
+    /// its `segments`; copies at either end aligned with less than 50% identity are reported as
+    /// unaligned overhang instead.
+    /// The queries are normalized like in `Args::validate` (see the `sequence` module), but not
+    /// validated. With the `auto` type of query, the type of every query is inferred with
+    /// `sequence::infer_type`.
+    /// A query whose best alignment is degenerate is `None`, see `Locator::try_build` for the
+    /// diagnostics.
+    /// The sequencing artifacts of every query are attached as `warnings`, and left out of the
+    /// percent identity with `args.mask_artifacts`.
+    /// With `args.soft_mask`, lower-case residues of the queries are soft-masked: the anchors of
+    /// algorithm 2 are the first and last windows of residues without any of them, but they are
+    /// aligned like the others.
+    /// With the `ALGORITHM_AUTO` algorithm, the algorithm of every query is chosen with
+    /// `choose_algorithm` and recorded in its `algorithm`.
+    /// With algorithm 3, queries of any length are aligned to the window of the reference around
+    /// their chain of seeds, see `algorithm3`; the k-mer index of the reference is built once for
+    /// all the queries, or read from `args.index_cache` (see the `index_cache` module).
+    /// With `args.both_strands`, nucleotide queries are also located as their reverse complement,
+    /// and the orientation aligned with the higher percent identity is kept, see `Strand`.
+    /// With `args.gene`, amino acid queries are located on the protein of that gene only, and
+    /// their positions are residues of the protein, numbered from 1.
+    /// With `args.region`, the queries are aligned to that gene or interval of the reference only
+    /// (see `Args::region_interval`), and their positions are still those of the whole reference.
+    /// With `args.split_identity`, the queries aligned with a lower percent identity are aligned
+    /// again as two segments, and the split alignment is kept when both reach it, see `split`.
     pub fn build(args: &Args) -> Result<Vec<Option<Locator>>, BoxError> {
-        let query_vec = args
+        Ok(Locator::try_build(args)?
+            .into_iter()
+            .map(|outcome| outcome.into_result().ok())
+            .collect())
+    }
+
+    /// Builds the locations of the queries of `args` like `Locator::try_build`, yielding them in
+    /// the order of the queries as they are computed, `BUILD_ITER_CHUNK_SIZE` queries at a time,
+    /// instead of collecting them all: only the aligned strings of the chunk being located are held
+    /// in memory. The iteration stops after the first error.
+    pub fn build_iter(args: &Args) -> impl Iterator<Item = Result<LocatorRecord, BoxError>> + '_ {
+        let template = Args {
+            common: CommonArgs {
+                query: Vec::new(),
+                query_name: Vec::new(),
+                ..args.common.clone()
+            },
+            ..args.clone()
+        };
+        args.common
+            .query
+            .chunks(BUILD_ITER_CHUNK_SIZE)
+            .enumerate()
+            .flat_map(move |(chunk_index, chunk)| {
+                let start = chunk_index * BUILD_ITER_CHUNK_SIZE;
+                let names = args.common.query_name.iter().skip(start).take(chunk.len());
+                let outcomes = Locator::try_build(&Args {
+                    common: CommonArgs {
+                        query: chunk.to_vec(),
+                        query_name: names.cloned().collect(),
+                        ..template.common.clone()
+                    },
+                    ..template.clone()
+                });
+                match outcomes {
+                    Ok(outcomes) => outcomes
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, outcome)| {
+                            Ok(LocatorRecord {
+                                index: chunk_index * BUILD_ITER_CHUNK_SIZE + i,
+                                outcome,
+                            })
+                        })
+                        .collect(),
+                    Err(err) => vec![Err(err)],
+                }
+            })
+            .scan(false, |failed, record| {
+                if *failed {
+                    return None;
+                }
+                *failed = record.is_err();
+                Some(record)
+            })
+    }
+
+    /// Builds the locations of the queries of `args` like `Locator::build`, returning the
+    /// diagnostics of every query that could not be located: empty queries, alignments that
+    /// align no query residue to the reference, and alignments without a single identical residue
+    /// are not located. The diagnostics include the `Screening` of the query. The locations are
+    /// named after the `--query-name` of their query.
+    pub fn try_build(args: &Args) -> Result<Vec<LocateOutcome>, BoxError> {
+        let scheme = load_scheme(args)?;
+        let mut located = with_threads(args.locator_options().threads, || {
+            Locator::locate_on(args, &[], &scheme, &BioBackend)
+        })??;
+        for (outcome, name) in located.iter_mut().zip(&args.common.query_name) {
+            if let LocateOutcome::Found(loc) = outcome {
+                loc.query_id = Some(name.clone());
+            }
+        }
+        Ok(located)
+    }
+
+    /// `Locator::try_build` on the `references` already loaded, aligning with `backend`, see
+    /// `LocatorEngine`. The references of the other query types are loaded for the call. The
+    /// amplicons of `--amplicon-scheme` are the `scheme` already loaded.
+    fn locate_on(
+        args: &Args,
+        references: &[LoadedReference],
+        scheme: &[sequence_locator::Region],
+        backend: &dyn AlignmentBackend,
+    ) -> Result<Vec<LocateOutcome>, BoxError> {
+        if args.common.type_query == "auto" {
+            // Every query is located on the reference of its own type.
+            let template = Args {
+                common: CommonArgs {
+                    query: Vec::new(),
+                    ..args.common.clone()
+                },
+                ..args.clone()
+            };
+            return args
+                .common
+                .query
+                .par_iter()
+                .map(|query| {
+                    let mut located = Locator::locate_on(
+                        &Args {
+                            common: CommonArgs {
+                                query: vec![query.clone()],
+                                type_query: sequence::infer_type(query).to_string(),
+                                ..template.common.clone()
+                            },
+                            ..template.clone()
+                        },
+                        references,
+                        scheme,
+                        backend,
+                    )?;
+                    Ok(located.remove(0))
+                })
+                .collect();
+        }
+        if args.both_strands && args.common.type_query == "nt" {
+            let plus = Locator::locate_on(
+                &Args {
+                    both_strands: false,
+                    ..args.clone()
+                },
+                references,
+                scheme,
+                backend,
+            )?;
+            let minus = Locator::locate_on(
+                &Args {
+                    common: CommonArgs {
+                        query: args
+                            .common
+                            .query
+                            .iter()
+                            .map(|query| sequence::reverse_complement(query))
+                            .collect(),
+                        ..args.common.clone()
+                    },
+                    both_strands: false,
+                    ..args.clone()
+                },
+                references,
+                scheme,
+                backend,
+            )?;
+            return Ok(plus.into_iter().zip(minus).map(best_strand).collect());
+        }
+        let queries = args
+            .common
             .query
             .iter()
-            .map(|x| x.as_bytes())
+            .map(|x| sequence::normalize(x, &args.common.type_query))
+            .collect::<Vec<String>>();
+        // Terminal stops of amino acid queries are not aligned; they are still flagged as warnings.
+        let query_vec = queries
+            .iter()
+            .map(|x| match args.common.type_query.as_str() {
+                "aa" => x.trim_end_matches(sequence::STOP).as_bytes(),
+                _ => x.as_bytes(),
+            })
             .collect::<Vec<&[u8]>>();
+        let soft_masks = args
+            .common
+            .query
+            .iter()
+            .map(|x| {
+                args.soft_mask
+                    .then(|| sequence::soft_mask(x, &args.common.type_query))
+            })
+            .collect::<Vec<Option<Vec<bool>>>>();
 
-        let ref_seq = retrieve_reference_sequence(&args.reference, &args.type_query)?.sequence;
+        let loaded;
+        let reference = match references
+            .iter()
+            .find(|reference| reference.type_query == args.common.type_query)
+        {
+            Some(reference) => reference,
+            None => {
+                loaded = LoadedReference::load(&args.common.reference, &args.common.type_query)?;
+                &loaded
+            }
+        };
+        // With a gene, amino acid queries are located on its protein only, numbered from 1.
+        let protein = match &args.gene {
+            Some(name) => Some(reference::retrieve_gene(
+                &args.common.reference,
+                &args.common.type_query,
+                name,
+            )?),
+            None => None,
+        };
+        // With a region, queries are aligned to its slice of the reference, and their locations
+        // moved back to the whole reference.
+        let region = args.region_interval()?;
+        let ref_seq = match (protein, region) {
+            (Some(gene), _) => &reference.sequence[gene.start.to_zero_based()..gene.end.get()],
+            (None, Some((start, end))) => &reference.sequence[start.to_zero_based()..end.get()],
+            (None, None) => reference.sequence,
+        };
+        let offset = region.map_or(0, |(start, _)| start.to_zero_based());
+        let protein_regions;
+        let genes = match (args.annotate, protein) {
+            (false, _) => &[][..],
+            (true, Some(gene)) => {
+                protein_regions = [sequence_locator::Region::new(gene.name, 1, ref_seq.len())];
+                &protein_regions[..]
+            }
+            (true, None) => &reference.genes[..],
+        };
+        let gp120 = if args.annotation.env_regions {
+            env_regions::regions(&args.common.type_query, protein)
+        } else {
+            Vec::new()
+        };
 
-        let algorithm = args.algorithm;
+        let options = args.locator_options();
+        let algorithms = query_vec
+            .par_iter()
+            .map(|query| match args.common.algorithm {
+                ALGORITHM_AUTO => {
+                    choose_algorithm(query, ref_seq, &args.common.type_query, &options)
+                }
+                algorithm => algorithm,
+            })
+            .collect::<Vec<u8>>();
 
-        let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
+        let scoring = Scoring::new(
+            &options,
+            &args.common.type_query,
+            // The bases masked for their low quality are neutral.
+            args.min_base_quality.is_some(),
+        );
+        let sliced = protein.is_some() || region.is_some();
+        let slice_index;
+        let index = match (algorithms.contains(&3), sliced) {
+            (false, _) => None,
+            (true, true) => {
+                slice_index = KmerIndex::new(ref_seq, &args.common.type_query);
+                Some(&slice_index)
+            }
+            (true, false) => Some(reference.index(args.index_cache.as_deref())?),
+        };
 
         let result_vec = query_vec
             .par_iter()
-            .map(|query| {
-                if query.len() < 300 || algorithm == 1 {
-                    algorithm1(query, &ref_seq, score)
+            .zip(&soft_masks)
+            .zip(&algorithms)
+            .map(|((query, soft_mask), &algorithm)| {
+                if query.len() > ref_seq.len() {
+                    // A protein or a region is not circular: the query overhangs its ends.
+                    if sliced {
+                        return algorithm1(query, ref_seq, scoring, backend);
+                    }
+                    return circular(query, ref_seq, scoring, backend);
+                }
+                if let Some(index) = index
+                    && algorithm == 3
+                {
+                    algorithm3(query, ref_seq, index, scoring, backend)
+                } else if query.len() < options.min_anchored_length || algorithm == 1 {
+                    algorithm1(query, ref_seq, scoring, backend)
                 } else {
-                    let s1 = &query[..100];
-                    let s2 = &query[query.len() - 100..];
+                    let soft_mask = soft_mask.as_deref();
+                    match algorithm2(query, ref_seq, soft_mask, &options, scoring, backend)? {
+                        // Nor is a query whose end anchors before its start wrapped around it.
+                        Ok(loc) if sliced && loc.wraps() => {
+                            algorithm1(query, ref_seq, scoring, backend)
+                        }
+                        located => Ok(located),
+                    }
+                }
+            })
+            .collect::<Result<Vec<Result<Locator, NotLocated>>, BoxError>>()?;
+        let result_vec = match args.split_identity {
+            Some(min_identity) => result_vec
+                .into_par_iter()
+                .zip(&query_vec)
+                .map(|(located, query)| match located {
+                    Ok(loc) if loc.percent_identity < min_identity && !loc.wraps() => {
+                        let split = split(query, ref_seq, min_identity, scoring, backend)?;
+                        Ok(Ok(split.unwrap_or(loc)))
+                    }
+                    located => Ok(located),
+                })
+                .collect::<Result<Vec<Result<Locator, NotLocated>>, BoxError>>()?,
+            None => result_vec,
+        };
+        let result_vec = match args.max_hits {
+            Some(max_hits) if max_hits > 1 => result_vec
+                .into_par_iter()
+                .zip(&query_vec)
+                .map(|(located, query)| {
+                    let Ok(mut loc) = located else {
+                        return Ok(located);
+                    };
+                    loc.other_hits = other_hits(
+                        query,
+                        ref_seq,
+                        &loc,
+                        max_hits - 1,
+                        args.min_hit_identity,
+                        scoring,
+                        backend,
+                    )?;
+                    Ok(Ok(loc))
+                })
+                .collect::<Result<Vec<Result<Locator, NotLocated>>, BoxError>>()?,
+            _ => result_vec,
+        };
+        Ok(result_vec
+            .into_iter()
+            .zip(queries.iter().zip(&args.common.query))
+            .zip(algorithms)
+            .map(|((located, (query, given)), algorithm)| match located {
+                Ok(loc) => {
+                    let loc = loc.offset(offset);
+                    let (ltr_features, ltr_ambiguous) = if args.annotation.ltr_features {
+                        ltr::locate(&loc)
+                    } else {
+                        (Vec::new(), false)
+                    };
+                    LocateOutcome::Found(Box::new(Locator {
+                        algorithm: (args.common.algorithm == ALGORITHM_AUTO).then_some(algorithm),
+                        rna: args.common.type_query == "nt" && sequence::is_rna(given),
+                        regions: sequence_locator::overlaps(&loc, genes),
+                        env_regions: env_regions::locate(&loc, &gp120),
+                        ltr_features,
+                        ltr_ambiguous,
+                        cis_elements: if args.annotation.cis_elements {
+                            cis_elements::locate(&loc)
+                        } else {
+                            Vec::new()
+                        },
+                        amplicons: amplicons::locate(&loc, scheme),
+                        glycosylation_sites: if args.annotation.glycosylation {
+                            glycosylation::sites(
+                                &loc,
+                                &args.common.reference,
+                                &args.common.type_query,
+                                protein,
+                            )
+                        } else {
+                            Vec::new()
+                        },
+                        insertions: if args.annotation.name_insertions {
+                            insertions::name(
+                                &loc,
+                                &args.common.reference,
+                                &args.common.type_query,
+                                protein,
+                            )
+                        } else {
+                            Vec::new()
+                        },
+                        defects: if args.annotation.defects {
+                            defects::detect(&loc, &args.common.reference)
+                        } else {
+                            Vec::new()
+                        },
+                        ..loc
+                            .score_with(&scoring)
+                            .flag_artifacts(
+                                query,
+                                &args.common.type_query,
+                                args.mask_artifacts,
+                                args.identity_mode.unwrap_or_default(),
+                                args.iupac_aware,
+                            )
+                            .back_map(&args.common.reference, &args.common.type_query, protein)
+                    }))
+                }
+                Err(mut not_located) => {
+                    not_located.best = not_located.best.map(|best| Box::new(best.offset(offset)));
+                    not_located.screening = Some(Screening::screen(
+                        query.as_bytes(),
+                        ref_seq,
+                        &args.common.type_query,
+                    ));
+                    LocateOutcome::NotFound(not_located)
+                }
+            })
+            .map(|outcome| filter_location(outcome, args))
+            .collect())
+    }
+}
 
-                    let aln1 = pattern_match(s1, &ref_seq, 30);
+/// The `outcome`, or why its location is not located when it is below `--min-identity` or
+/// `--min-aln-length`, with the location as the best alignment.
+fn filter_location(outcome: LocateOutcome, args: &Args) -> LocateOutcome {
+    let LocateOutcome::Found(loc) = outcome else {
+        return outcome;
+    };
+    let reason = if args
+        .min_identity
+        .is_some_and(|identity| loc.percent_identity < identity)
+    {
+        NotLocatedReason::BelowMinIdentity
+    } else if args
+        .min_aln_length
+        .is_some_and(|length| loc.aligned_length() < length)
+    {
+        NotLocatedReason::BelowMinAlnLength
+    } else {
+        return LocateOutcome::Found(loc);
+    };
+    LocateOutcome::NotFound(NotLocated {
+        best_score: loc.score,
+        best: Some(loc),
+        ..NotLocated::new(reason)
+    })
+}
 
-                    if aln1.is_none() {
-                        return algorithm1(query, &ref_seq, score);
-                    }
-                    let pos_start = aln1.unwrap().ystart as usize;
+/// A reference sequence of one query type and the regions of its genes, loaded once for all the
+/// queries located on it, with the k-mer index of algorithm 3 loaded the first time it is needed.
+#[derive(Debug, Clone)]
+struct LoadedReference {
+    reference: String,
+    type_query: String,
+    sequence: &'static [u8],
+    genes: Vec<sequence_locator::Region>,
+    index: OnceLock<Arc<KmerIndex>>,
+}
+
+impl LoadedReference {
+    fn load(reference: &str, type_query: &str) -> Result<Self, BoxError> {
+        Ok(LoadedReference {
+            reference: reference.to_string(),
+            type_query: type_query.to_string(),
+            sequence: retrieve_reference_sequence(reference, type_query)?.sequence,
+            genes: sequence_locator::gene_regions(reference, type_query),
+            index: OnceLock::new(),
+        })
+    }
 
-                    let aln2 = pattern_match(s2, &ref_seq, 30);
+    /// The k-mer index of the sequence, see `KmerIndex`, read from or written to the `cache`
+    /// directory by `index_cache::load`.
+    fn index(&self, cache: Option<&Path>) -> Result<&KmerIndex, BoxError> {
+        if let Some(index) = self.index.get() {
+            return Ok(index);
+        }
+        let index = index_cache::load(cache, &self.reference, &self.type_query)?;
+        Ok(self.index.get_or_init(|| Arc::new(index)))
+    }
+}
 
-                    if aln2.is_none() {
-                        return algorithm1(query, &ref_seq, score);
-                    }
-                    let pos_end = aln2.unwrap().yend as usize;
+/// Locates queries one at a time on a reference loaded once, for services locating every request
+/// as it arrives: `Locator::build` looks the reference up again for every call, while the engine
+/// keeps it, with its gene regions, for all the queries. The aligners of the default backend are
+/// reused across the calls on the same thread (see `BioBackend`), so their matrices are not
+/// reallocated for every query; `LocatorEngine::with_backend` replaces them.
+///
+/// # Example
+/// ```rust
+/// use virust_locator::config::Args;
+/// use virust_locator::locator::LocatorEngine;
+///
+/// let engine = LocatorEngine::new("HXB2", &Args::default()).unwrap();
+/// for query in ["ATGCATGCATGC", "TGGAAGGGCTAATTCACTCC"] {
+///     let outcome = engine.locate(query).unwrap();
+///     assert!(outcome.is_found());
+/// }
+/// assert!(engine.locate("ATGCXYZ").is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocatorEngine {
+    args: Args,
+    references: Vec<LoadedReference>,
+    /// The amplicons of `--amplicon-scheme`, read once for all the calls.
+    scheme: Vec<sequence_locator::Region>,
+    backend: Arc<dyn AlignmentBackend>,
+    /// The pool of `LocatorOptions::threads`, built once for all the calls.
+    #[cfg(feature = "parallel")]
+    pool: Option<Arc<ThreadPool>>,
+}
 
-                    let refined_ref = &ref_seq[pos_start..pos_end];
+impl LocatorEngine {
+    /// Loads `reference` to locate queries with the options of `options`: its query type,
+    /// algorithm, scores and the other options of `Locator::try_build`. Its queries are ignored.
+    /// Returns an error if the reference is unknown or the options are invalid (see
+    /// `Args::validate`).
+    pub fn new(reference: &str, options: &Args) -> Result<Self, BoxError> {
+        let args = Args {
+            common: CommonArgs {
+                query: Vec::new(),
+                reference: reference.to_string(),
+                ..options.common.clone()
+            },
+            ..options.clone()
+        }
+        .validate_options()?;
+        let types = match args.common.type_query.as_str() {
+            "auto" => vec!["nt", "aa"],
+            type_query => vec![type_query],
+        };
+        let references = types
+            .iter()
+            .map(|type_query| LoadedReference::load(&args.common.reference, type_query))
+            .collect::<Result<Vec<LoadedReference>, BoxError>>()?;
+        #[cfg(feature = "parallel")]
+        let pool = match args.locator_options().threads {
+            Some(threads) => Some(Arc::new(
+                ThreadPoolBuilder::new().num_threads(threads).build()?,
+            )),
+            None => None,
+        };
+        Ok(LocatorEngine {
+            scheme: load_scheme(&args)?,
+            args,
+            references,
+            backend: Arc::new(BioBackend),
+            #[cfg(feature = "parallel")]
+            pool,
+        })
+    }
 
-                    let mut loc = algorithm1(query, refined_ref, score)?.unwrap();
-                    loc.ref_start = pos_start + 1;
-                    loc.ref_end = pos_end;
-                    Ok(Some(loc))
-                }
+    /// The engine aligning the queries with `backend` instead of `BioBackend`, see the `backend`
+    /// module.
+    pub fn with_backend(self, backend: impl AlignmentBackend + 'static) -> Self {
+        LocatorEngine {
+            backend: Arc::new(backend),
+            ..self
+        }
+    }
+
+    /// The strain of the reference, e.g. `HXB2` for any of its aliases.
+    pub fn reference(&self) -> &str {
+        &self.args.common.reference
+    }
+
+    /// Locates `query` like `Locator::try_build`. Returns an error if the query is invalid.
+    pub fn locate(&self, query: &str) -> Result<LocateOutcome, BoxError> {
+        let query = self.args.validate_query(1, query)?;
+        let args = Args {
+            common: CommonArgs {
+                query: vec![query],
+                ..self.args.common.clone()
+            },
+            ..self.args.clone()
+        };
+        let locate =
+            || Locator::locate_on(&args, &self.references, &self.scheme, self.backend.as_ref());
+        #[cfg(feature = "parallel")]
+        let mut located = match &self.pool {
+            Some(pool) => pool.install(locate)?,
+            None => locate()?,
+        };
+        #[cfg(not(feature = "parallel"))]
+        let mut located = locate()?;
+        Ok(located.remove(0))
+    }
+}
+
+/// The amplicons of the `--amplicon-scheme` of `args`, none without one.
+fn load_scheme(args: &Args) -> Result<Vec<sequence_locator::Region>, BoxError> {
+    match &args.annotation.amplicon_scheme {
+        Some(path) => amplicons::load(path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The locations computed on the blocking thread pool of the Tokio runtime, with the `async`
+/// feature, for async services: the alignments are CPU-bound and would stall the tasks of the
+/// runtime's worker threads if they ran there.
+#[cfg(feature = "async")]
+impl Locator {
+    /// `Locator::build` on the blocking thread pool. Must be called from within a Tokio runtime.
+    pub async fn build_async(args: Args) -> Result<Vec<Option<Locator>>, BoxError> {
+        tokio::task::spawn_blocking(move || Locator::build(&args)).await?
+    }
+
+    /// `Locator::try_build` on the blocking thread pool. Must be called from within a Tokio
+    /// runtime.
+    pub async fn try_build_async(args: Args) -> Result<Vec<LocateOutcome>, BoxError> {
+        tokio::task::spawn_blocking(move || Locator::try_build(&args)).await?
+    }
+}
+
+#[cfg(feature = "async")]
+impl LocatorEngine {
+    /// `LocatorEngine::locate` on the blocking thread pool of the Tokio runtime, with the `async`
+    /// feature. The engine is cloned for the call: its reference is shared, not reloaded. Must be
+    /// called from within a Tokio runtime.
+    ///
+    /// # Example
+    /// ```rust
+    /// use virust_locator::config::Args;
+    /// use virust_locator::locator::LocatorEngine;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    /// let engine = LocatorEngine::new("HXB2", &Args::default()).unwrap();
+    /// let outcome = engine.locate_async("ATGCATGCATGC").await.unwrap();
+    /// assert!(outcome.is_found());
+    /// # });
+    /// ```
+    pub async fn locate_async(&self, query: &str) -> Result<LocateOutcome, BoxError> {
+        let engine = self.clone();
+        let query = query.to_string();
+        tokio::task::spawn_blocking(move || engine.locate(&query)).await?
+    }
+}
+
+/// The CIGAR operation of the alignment column of the query residue `q` and reference residue `r`:
+/// `S` if the column is `clipped` (see `Locator::cigar`).
+pub(crate) fn cigar_op(q: u8, r: u8, clipped: bool) -> char {
+    match (q, r) {
+        _ if clipped => 'S',
+        (b'-', _) => 'D',
+        (_, b'-') => 'I',
+        _ => 'M',
+    }
+}
+
+/// Appends the operation `op` to `cigar`, extending the last operation if it is the same.
+pub(crate) fn push_cigar_op(cigar: &mut Vec<(usize, char)>, op: char) {
+    match cigar.last_mut() {
+        Some((length, last_op)) if *last_op == op => *length += 1,
+        _ => cigar.push((1, op)),
+    }
+}
+
+/// The CIGAR string of the operations `cigar`.
+pub(crate) fn cigar_string(cigar: &[(usize, char)]) -> String {
+    cigar
+        .iter()
+        .map(|(length, op)| format!("{}{}", length, op))
+        .collect()
+}
+
+/// Returns the outcome of the orientation of a query aligned with the higher percent identity,
+/// the `plus` one on a tie, marking the `minus` one with `Strand::Minus`.
+fn best_strand((plus, minus): (LocateOutcome, LocateOutcome)) -> LocateOutcome {
+    match (plus, minus) {
+        (LocateOutcome::Found(plus), LocateOutcome::Found(minus))
+            if plus.percent_identity >= minus.percent_identity =>
+        {
+            LocateOutcome::Found(plus)
+        }
+        (plus @ LocateOutcome::Found(_), LocateOutcome::NotFound(_)) => plus,
+        (plus @ LocateOutcome::NotFound(_), LocateOutcome::NotFound(_)) => plus,
+        (_, LocateOutcome::Found(minus)) => LocateOutcome::Found(Box::new(Locator {
+            strand: Strand::Minus,
+            ..*minus
+        })),
+    }
+}
+
+/// Substitution scores of the alignments of amino acid queries, see `--matrix`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Matrix {
+    /// +1 per match and -1 per mismatch, like nucleotide queries.
+    Identity,
+    /// BLOSUM62, for divergent proteins.
+    #[default]
+    Blosum62,
+    /// PAM40, for closely related proteins.
+    Pam40,
+    /// PAM120.
+    Pam120,
+    /// PAM200.
+    Pam200,
+    /// PAM250, for distant proteins.
+    Pam250,
+}
+
+/// Score of the residues outside of the substitution matrices, such as the masked reference
+/// positions of `other_hits`: the lowest score of BLOSUM62.
+const OUTSIDE_MATRIX_SCORE: i32 = -4;
+
+/// Returns the score of `a` and `b` in `matrix`, or `OUTSIDE_MATRIX_SCORE` if either has no row
+/// in the substitution matrices, which cover upper-case letters and stops.
+fn matrix_score(matrix: fn(u8, u8) -> i32, a: u8, b: u8) -> i32 {
+    let in_matrix = |residue: u8| residue.is_ascii_uppercase() || residue == sequence::STOP as u8;
+    if in_matrix(a) && in_matrix(b) {
+        matrix(a, b)
+    } else {
+        OUTSIDE_MATRIX_SCORE
+    }
+}
+
+impl Matrix {
+    /// The score of aligning the query residue `a` to the reference residue `b`.
+    pub fn score(self) -> fn(u8, u8) -> i32 {
+        match self {
+            Matrix::Identity => |a, b| if a == b { 1 } else { -1 },
+            Matrix::Blosum62 => |a, b| matrix_score(scores::blosum62, a, b),
+            Matrix::Pam40 => |a, b| matrix_score(scores::pam40, a, b),
+            Matrix::Pam120 => |a, b| matrix_score(scores::pam120, a, b),
+            Matrix::Pam200 => |a, b| matrix_score(scores::pam200, a, b),
+            Matrix::Pam250 => |a, b| matrix_score(scores::pam250, a, b),
+        }
+    }
+
+    /// The penalty for opening a gap with the matrix: -5 with `Identity`, as for nucleotide
+    /// queries, and -11, the BLAST default for BLOSUM62, with the substitution matrices.
+    pub fn gap_open(self) -> i32 {
+        match self {
+            Matrix::Identity => -5,
+            _ => -11,
+        }
+    }
+}
+
+/// The definition of the percent identity of the alignments, see `--identity-mode`. The columns
+/// of an alignment pair a query residue with a reference residue, or either with a gap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityMode {
+    /// The identical columns over all the columns: every position of a gap counts as a
+    /// difference, as do the query residues overhanging the ends of the reference.
+    #[default]
+    Gapped,
+    /// The identical columns over the columns without a gap: only substitutions count as
+    /// differences, so a deletion does not lower the identity.
+    Ungapped,
+    /// The identical columns over the columns from the first to the last column without a gap,
+    /// like the percent identity of BLAST: the overhangs at the ends are left out, but the
+    /// positions of the gaps within the alignment count as differences.
+    Blast,
+}
+
+impl IdentityMode {
+    /// The percent identity of the alignment `columns`, pairs of a query and a reference residue
+    /// with `-` for the gaps, or `None` when no column is counted.
+    pub fn identity(self, columns: &[(u8, u8)]) -> Option<f64> {
+        let aligned = |&(q, r): &(u8, u8)| q != b'-' && r != b'-';
+        let columns = match self {
+            IdentityMode::Gapped | IdentityMode::Ungapped => columns,
+            IdentityMode::Blast => {
+                let first = columns.iter().position(aligned).unwrap_or(columns.len());
+                let end = columns
+                    .iter()
+                    .rposition(aligned)
+                    .map_or(first, |last| last + 1);
+                &columns[first..end]
+            }
+        };
+        let counted = match self {
+            IdentityMode::Ungapped => columns.iter().filter(|column| aligned(column)).count(),
+            _ => columns.len(),
+        };
+        let matches = columns.iter().filter(|(q, r)| q == r).count();
+        (counted > 0).then(|| matches as f64 / counted as f64 * 100.0)
+    }
+}
+
+impl Display for IdentityMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdentityMode::Gapped => write!(f, "gapped"),
+            IdentityMode::Ungapped => write!(f, "ungapped"),
+            IdentityMode::Blast => write!(f, "blast"),
+        }
+    }
+}
+
+/// The scores of the alignments, see `--matrix`, `--match`, `--mismatch`, `--gap-open`,
+/// `--gap-extend` and `--iupac-aware`, the memory and threads available to them, see
+/// `--max-alignment-memory` and `--threads`, and the anchors of algorithm 2, see
+/// `--anchor-length`, `--anchor-max-dist` and `--min-anchored-length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocatorOptions {
+    /// The substitution scores of amino acid queries.
+    pub matrix: Matrix,
+    /// The score of a match, for nucleotide queries and amino acid queries scored with
+    /// `Matrix::Identity`.
+    pub match_score: i32,
+    /// The score of a mismatch, for nucleotide queries and amino acid queries scored with
+    /// `Matrix::Identity`.
+    pub mismatch_score: i32,
+    /// The penalty for opening a gap, or `None` for the default of the matrix (see
+    /// `Matrix::gap_open`).
+    pub gap_open: Option<i32>,
+    /// The penalty for every position of a gap.
+    pub gap_extend: i32,
+    /// The largest traceback matrix of an alignment, in bytes, estimated as `TRACEBACK_CELL_BYTES`
+    /// per cell. Larger alignments, of long queries or of queries wrapping around the end of the
+    /// reference, are computed in linear space with `hirschberg::semiglobal` instead; 0 computes
+    /// them all in linear space.
+    pub max_alignment_memory: usize,
+    /// The number of threads of the pool locating the queries, see `with_threads`, or `None` for
+    /// the pool of the caller, by default the global pool of rayon with one thread per CPU.
+    pub threads: Option<usize>,
+    /// Whether the IUPAC ambiguity codes of nucleotide queries score as matches against the bases
+    /// they can stand for (see `sequence::iupac_match`) instead of as mismatches.
+    pub iupac_aware: bool,
+    /// The length of the windows at both ends of the query anchored to the reference by algorithm
+    /// 2.
+    pub anchor_length: usize,
+    /// The largest edit distance of an anchor of algorithm 2 to the reference. Divergent queries,
+    /// such as SIV, may need more than the default to be anchored rather than aligned with
+    /// algorithm 1.
+    pub anchor_max_dist: usize,
+    /// The length of the shortest queries located with algorithm 2, and chosen for it by
+    /// `--algorithm auto`; shorter queries are aligned with algorithm 1. At least twice
+    /// `anchor_length`, so that both anchors fit in the query.
+    pub min_anchored_length: usize,
+}
+
+/// BLOSUM62 for amino acid queries, +1 per match and -1 per mismatch for nucleotide queries, the
+/// gap open penalty of the matrix and -1 per gap position, with traceback matrices up to
+/// `DEFAULT_MAX_ALIGNMENT_MEMORY`, and anchors of `DEFAULT_ANCHOR_LENGTH` residues within
+/// `DEFAULT_ANCHOR_MAX_DIST` edits for queries of `DEFAULT_MIN_ANCHORED_LENGTH` residues or more.
+impl Default for LocatorOptions {
+    fn default() -> Self {
+        LocatorOptions {
+            matrix: Matrix::default(),
+            match_score: 1,
+            mismatch_score: -1,
+            gap_open: None,
+            gap_extend: -1,
+            max_alignment_memory: DEFAULT_MAX_ALIGNMENT_MEMORY,
+            threads: None,
+            iupac_aware: false,
+            anchor_length: DEFAULT_ANCHOR_LENGTH,
+            anchor_max_dist: DEFAULT_ANCHOR_MAX_DIST,
+            min_anchored_length: DEFAULT_MIN_ANCHORED_LENGTH,
+        }
+    }
+}
+
+/// Default length of the anchors of algorithm 2.
+pub const DEFAULT_ANCHOR_LENGTH: usize = 100;
+
+/// Default largest edit distance of an anchor of algorithm 2 to the reference.
+pub const DEFAULT_ANCHOR_MAX_DIST: usize = 30;
+
+/// Default length of the shortest queries located with algorithm 2.
+pub const DEFAULT_MIN_ANCHORED_LENGTH: usize = 300;
+
+/// Default largest traceback matrix of an alignment, 1 GiB: whole genomes are aligned with the
+/// full matrix, but not the copies of the reference joined for queries wrapping around its end.
+pub const DEFAULT_MAX_ALIGNMENT_MEMORY: usize = 1 << 30;
+
+/// Bytes per cell of the traceback matrix of the aligner of `bio`.
+pub const TRACEBACK_CELL_BYTES: usize = 2;
+
+/// Runs `f` on a rayon pool of `threads` threads, so that the parallel iterators it runs, such as
+/// those of `Locator::try_build`, use at most that many threads instead of one per CPU. `f` runs
+/// on the current pool when `threads` is `None` or the current pool already has that many
+/// threads, so nested calls do not build pools of their own. Returns an error if the threads
+/// cannot be spawned. Without the `parallel` feature, `f` runs on the current thread.
+pub fn with_threads<R: Send>(
+    threads: Option<usize>,
+    f: impl FnOnce() -> R + Send,
+) -> Result<R, BoxError> {
+    match threads {
+        #[cfg(feature = "parallel")]
+        Some(threads) if threads != rayon::current_num_threads() => {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+            Ok(pool.install(f))
+        }
+        _ => Ok(f()),
+    }
+}
+
+/// The scoring of an alignment: substitution scores and gap penalties, and the largest traceback
+/// matrix of the aligner, built from the `LocatorOptions` for the type of the queries and passed
+/// to the `AlignmentBackend`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scoring {
+    matrix: Matrix,
+    match_score: i32,
+    mismatch_score: i32,
+    /// Whether `N` query bases score 0 against any reference base.
+    neutral_n: bool,
+    /// Whether the ambiguity codes of the query score as matches against the bases they can stand
+    /// for.
+    iupac_aware: bool,
+    gap_open: i32,
+    gap_extend: i32,
+    /// Cells of the largest traceback matrix, see `LocatorOptions::max_alignment_memory`.
+    max_cells: usize,
+}
+
+impl Scoring {
+    /// The scoring of `options` for queries of `type_query`: nucleotide queries are scored with
+    /// `Matrix::Identity`, and `neutral_n` and `options.iupac_aware` apply to them only.
+    pub fn new(options: &LocatorOptions, type_query: &str, neutral_n: bool) -> Self {
+        let matrix = match type_query {
+            "aa" => options.matrix,
+            _ => Matrix::Identity,
+        };
+        Scoring {
+            matrix,
+            match_score: options.match_score,
+            mismatch_score: options.mismatch_score,
+            neutral_n: neutral_n && type_query != "aa",
+            iupac_aware: options.iupac_aware && type_query != "aa",
+            gap_open: options.gap_open.unwrap_or(matrix.gap_open()),
+            gap_extend: options.gap_extend,
+            max_cells: options.max_alignment_memory / TRACEBACK_CELL_BYTES,
+        }
+    }
+
+    /// The score of aligning the query residue `a` to the reference residue `b`.
+    pub fn score(&self, a: u8, b: u8) -> i32 {
+        match self.matrix {
+            _ if self.neutral_n && a == b'N' => 0,
+            Matrix::Identity if a == b => self.match_score,
+            Matrix::Identity if self.iupac_aware && sequence::iupac_match(a, b) => self.match_score,
+            Matrix::Identity => self.mismatch_score,
+            matrix => matrix.score()(a, b),
+        }
+    }
+
+    /// The penalty for opening a gap.
+    pub fn gap_open(&self) -> i32 {
+        self.gap_open
+    }
+
+    /// The penalty for every position of a gap.
+    pub fn gap_extend(&self) -> i32 {
+        self.gap_extend
+    }
+
+    /// Cells of the largest traceback matrix of an alignment, see
+    /// `LocatorOptions::max_alignment_memory`.
+    pub fn max_cells(&self) -> usize {
+        self.max_cells
+    }
+
+    /// The score of the alignment of the aligned strings: the score of every aligned pair of
+    /// residues, and `gap_open` and `gap_extend` per position of every run of gaps in either
+    /// string.
+    pub fn score_alignment(&self, query_aligned_string: &str, ref_aligned_string: &str) -> i32 {
+        self.column_scores(query_aligned_string, ref_aligned_string)
+            .iter()
+            .sum()
+    }
+
+    /// The score of every column of the alignment of the aligned strings, as `score_alignment`
+    /// sums them: the first position of a run of gaps scores `gap_open` too.
+    fn column_scores(&self, query_aligned_string: &str, ref_aligned_string: &str) -> Vec<i32> {
+        let mut previous = (false, false);
+        query_aligned_string
+            .bytes()
+            .zip(ref_aligned_string.bytes())
+            .map(|(q, r)| {
+                let gaps = (q == b'-', r == b'-');
+                let score = match gaps {
+                    (false, false) => self.score(q, r),
+                    _ if gaps == previous => self.gap_extend,
+                    _ => self.gap_open + self.gap_extend,
+                };
+                previous = gaps;
+                score
             })
-            .collect::<Result<Vec<Option<Locator>>, BoxError>>()?;
-        return Ok(result_vec);
-    }
-}
-
-/// Performs a semi-global alignment between a query and reference sequence using a scoring
-/// function and gap penalties.
-/// The function takes the query sequence, reference sequence, scoring function, gap open penalty,
-/// and gap extend penalty as input and returns an `Alignment` object.
-/// The alignment is performed using the `bio::alignment::pairwise` module, which provides
-/// efficient algorithms for sequence alignment.
-/// The function returns a `Result` containing the `Alignment` object or an error if the alignment
-/// fails.
-/// The `score` function is used to calculate the score for matching or mismatching characters.
-/// The `gap_open` and `gap_extend` parameters specify the penalties for opening and extending gaps
-/// in the alignment.
-fn get_aln(
+            .collect()
+    }
+}
+
+impl MatchFunc for Scoring {
+    fn score(&self, a: u8, b: u8) -> i32 {
+        Scoring::score(self, a, b)
+    }
+}
+
+/// +1 per match, -1 per mismatch, -5 to open a gap and -1 per gap position.
+impl Default for Scoring {
+    fn default() -> Self {
+        Scoring::new(&LocatorOptions::default(), "nt", false)
+    }
+}
+
+/// Largest divergence from the reference, estimated from the k-mers shared with it, of the
+/// queries located with algorithm 2 by `--algorithm auto`.
+const MAX_AUTO_DIVERGENCE: f64 = 0.15;
+
+/// Chooses the algorithm of a query for `--algorithm auto`: algorithm 2 for queries of
+/// `options.min_anchored_length` residues or more whose divergence from the reference is at most
+/// 15%, where its anchors are reliable, and algorithm 1 otherwise. The divergence is estimated
+/// from the fraction `f` of the k-mers of the query found in the reference (see `Screening`) as
+/// `1 - f^(1/k)`, the divergence at which that fraction of k-mers is expected to be free of
+/// substitutions.
+pub fn choose_algorithm(
     query: &[u8],
     ref_seq: &[u8],
-    score: fn(u8, u8) -> i32,
-    gap_open: i32,
-    gap_extend: i32,
-) -> Result<Alignment, BoxError> {
-    let mut aligner =
-        Aligner::with_capacity(query.len(), ref_seq.len(), gap_open, gap_extend, &score);
+    type_query: &str,
+    options: &LocatorOptions,
+) -> u8 {
+    if query.len() < options.min_anchored_length {
+        return 1;
+    }
+    let screening = Screening::screen(query, ref_seq, type_query);
+    let shared = screening.shared_kmers as f64 / screening.query_kmers as f64;
+    let divergence = 1.0 - shared.powf(1.0 / screening.k as f64);
+    if divergence <= MAX_AUTO_DIVERGENCE {
+        2
+    } else {
+        1
+    }
+}
+
+/// Returns the offsets of the first and last windows of `anchor_length` residues of a query of
+/// `len` residues used as the anchors of algorithm 2: the first and last `anchor_length` residues,
+/// or without `soft_mask`ed residues if the query is soft-masked. Returns `None` if the query is
+/// shorter than an anchor or no window is free of soft-masked residues.
+fn anchor_offsets(
+    len: usize,
+    anchor_length: usize,
+    soft_mask: Option<&[bool]>,
+) -> Option<(usize, usize)> {
+    let Some(soft_mask) = soft_mask else {
+        return Some((0, len.checked_sub(anchor_length)?));
+    };
+    let start = soft_mask
+        .windows(anchor_length)
+        .position(|w| !w.contains(&true))?;
+    let end = soft_mask
+        .windows(anchor_length)
+        .rposition(|w| !w.contains(&true))?;
+    Some((start, end))
+}
+
+/// Margin of edit distance within which a second match of an anchor of algorithm 2 makes it
+/// ambiguous.
+const ANCHOR_MARGIN: usize = 5;
+
+/// Number of windows tried for each anchor of algorithm 2, moving inwards, before falling back to
+/// algorithm 1.
+const ANCHOR_ATTEMPTS: usize = 4;
+
+/// Locates a query of `options.min_anchored_length` residues or more with algorithm 2: windows of
+/// `options.anchor_length` residues at both ends of the query are anchored to the reference with
+/// `pattern_match`, within `options.anchor_max_dist` edits, and the query is aligned with
+/// algorithm 1 to the reference between the anchors only.
+///
+/// An anchor must match the reference uniquely; an ambiguous window, such as one in the LTRs, is
+/// replaced by the next window inwards, up to `ANCHOR_ATTEMPTS` windows. The anchors must also be
+/// colinear: the reference between them is at least half as long as the query between them, which
+/// still allows large deletions (see `colinear`). When the query between them is long enough, a
+/// third window in its middle is anchored too, and must fall between them and be colinear with
+/// both, so that a query whose ends anchor plausibly but whose middle lies elsewhere, such as a
+/// rearranged one, is not aligned to the wrong window; a middle window that does not anchor, e.g.
+/// across a large deletion, is not required. Otherwise the query is aligned with algorithm 1 over
+/// the whole reference. When the end of the query anchors before its start, the query spans the end
+/// of the reference and is aligned with `circular`.
+fn algorithm2(
+    query: &[u8],
+    ref_seq: &[u8],
+    soft_mask: Option<&[bool]>,
+    options: &LocatorOptions,
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Result<Locator, NotLocated>, BoxError> {
+    let length = options.anchor_length;
+    let Some((first, last)) = anchor_offsets(query.len(), length, soft_mask) else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+    let clean = |offset: &usize| {
+        soft_mask.is_none_or(|soft_mask| !soft_mask[*offset..*offset + length].contains(&true))
+    };
+    let anchor = |offset: usize| {
+        pattern_match(
+            &query[offset..offset + length],
+            ref_seq,
+            options.anchor_max_dist,
+            backend,
+        )
+        .map(|aln| (offset, aln))
+    };
+    let Some((start_offset, aln1)) = (first..=last)
+        .step_by(length)
+        .take(ANCHOR_ATTEMPTS)
+        .filter(clean)
+        .find_map(anchor)
+    else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+    let Some((end_offset, aln2)) = (start_offset + length..=last)
+        .rev()
+        .step_by(length)
+        .take(ANCHOR_ATTEMPTS)
+        .filter(clean)
+        .find_map(anchor)
+    else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+
+    // Residues before the start anchor are aligned before its position.
+    let pos_start = aln1.ystart.saturating_sub(start_offset);
+    let pos_end = (aln2.yend + query.len() - end_offset - length).min(ref_seq.len());
+    if pos_end <= pos_start {
+        // The end of the query anchors before its start: it spans the end of the reference.
+        return circular(query, ref_seq, scoring, backend);
+    }
+    if !colinear((start_offset, aln1.ystart), (end_offset, aln2.ystart)) {
+        return algorithm1(query, ref_seq, scoring, backend);
+    }
+    let middle_offset = start_offset + (end_offset - start_offset) / 2;
+    if middle_offset >= start_offset + length
+        && middle_offset + length <= end_offset
+        && clean(&middle_offset)
+        && let Some((_, aln3)) = anchor(middle_offset)
+        && !(colinear((start_offset, aln1.ystart), (middle_offset, aln3.ystart))
+            && colinear((middle_offset, aln3.ystart), (end_offset, aln2.ystart)))
+    {
+        return algorithm1(query, ref_seq, scoring, backend);
+    }
+    let refined_ref = &ref_seq[pos_start..pos_end];
+
+    let Ok(loc) = algorithm1(query, refined_ref, scoring, backend)? else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+    Ok(Ok(Locator::new(
+        RefPosition::from_zero_based(pos_start),
+        RefPosition::new(pos_end),
+        loc.percent_identity,
+        loc.indel,
+        loc.query_aligned_string,
+        loc.ref_aligned_string,
+    )))
+}
+
+/// Whether two anchors of algorithm 2, pairs of 0-based offsets on the query and start positions on
+/// the reference, are colinear: the second anchor is after the first on the reference, by at least
+/// half the distance between them on the query.
+fn colinear(first: (usize, usize), second: (usize, usize)) -> bool {
+    second.1 > first.1 && (second.1 - first.1) * 2 >= second.0 - first.0
+}
+
+/// Residues of the reference added to either side of the window of algorithm 3, on top of the
+/// query residues before and after the chain and a tenth of the query for its indels.
+const CHAIN_MARGIN: usize = 20;
 
-    Ok(aligner.semiglobal(query, ref_seq))
+/// Locates a query with algorithm 3: the k-mers of the query are looked up in the `index` of the
+/// reference, chained with `seed::chain`, and the query is aligned with algorithm 1 to the window
+/// of the reference around the chain only: from the start of the chain, less the query residues
+/// before it, to its end, plus the query residues after it, widened by `CHAIN_MARGIN` and a tenth
+/// of the query on either side. Queries without seeds, or that do not align within the window,
+/// are aligned with algorithm 1 over the whole reference.
+fn algorithm3(
+    query: &[u8],
+    ref_seq: &[u8],
+    index: &KmerIndex,
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Result<Locator, NotLocated>, BoxError> {
+    let Some(chain) = seed::chain(&index.seeds(query), index.k) else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+    let margin = CHAIN_MARGIN + query.len() / 10;
+    let pos_start = chain.ref_start.saturating_sub(chain.query_start + margin);
+    let pos_end = (chain.ref_end + query.len() - chain.query_end + margin).min(ref_seq.len());
+    let Ok(loc) = algorithm1(query, &ref_seq[pos_start..pos_end], scoring, backend)? else {
+        return algorithm1(query, ref_seq, scoring, backend);
+    };
+    Ok(Ok(Locator::new(
+        loc.ref_start + pos_start,
+        loc.ref_end + pos_start,
+        loc.percent_identity,
+        loc.indel,
+        loc.query_aligned_string,
+        loc.ref_aligned_string,
+    )))
 }
 
-/// Uses the Myers bit-parallel algorithm to find approximate matches of a pattern in a text with a
-/// maximum allowed distance. It returns the best alignment found.
+/// Uses the approximate matches of the backend, the Myers bit-parallel algorithm by default, to
+/// find the matches of a pattern in a text with a maximum allowed distance. It returns the best
+/// alignment found, if it is unique.
 /// The function takes a pattern, text, and maximum distance as input and returns an `Option<Alignment>`.
-/// If a match is found, it returns `Some(alignment)`, otherwise it returns `None`.
-fn pattern_match(pattern: &[u8], text: &[u8], max_dist: usize) -> Option<Alignment> {
-    let mut myers = long::Myers::<u64>::new(pattern);
-    let mut lazy_matches = myers.find_all_lazy(text, max_dist);
-    let mut aln = Alignment::default();
-    match lazy_matches.by_ref().min_by_key(|&(_, dist)| dist) {
-        Some((best_end, _)) => {
-            lazy_matches.alignment_at(best_end, &mut aln);
-            return Some(aln);
-        }
-        None => {
-            return None;
-        }
+/// If a match is found, it returns `Some(alignment)`, otherwise it returns `None`. The match is
+/// ambiguous, and `None` is returned too, when the pattern matches elsewhere in the text, at least
+/// its length away, within `ANCHOR_MARGIN` of the best distance.
+fn pattern_match(
+    pattern: &[u8],
+    text: &[u8],
+    max_dist: usize,
+    backend: &dyn AlignmentBackend,
+) -> Option<Alignment> {
+    let hits = backend.approximate_matches(pattern, text, max_dist);
+    let &(best_end, best_dist) = hits.iter().min_by_key(|&&(_, dist)| dist)?;
+    let ambiguous = hits.iter().any(|&(end, dist)| {
+        end.abs_diff(best_end) >= pattern.len() && dist <= best_dist + ANCHOR_MARGIN
+    });
+    if ambiguous {
+        return None;
     }
+    Some(backend.match_alignment(pattern, text, best_end, max_dist))
 }
 
 /// Converts an alignment path into aligned strings, calculates percent identity, and determines
@@ -229,13 +2104,18 @@ fn pattern_match(pattern: &[u8], text: &[u8], max_dist: usize) -> Option<Alignme
 /// reference sequences. It also counts mismatches and gaps to calculate the percent identity.
 /// The function returns a tuple containing the aligned reference string, aligned query string,
 /// percent identity, and a boolean indicating the presence of indels.
-fn from_path(aln: Alignment, query: &[u8], ref_seq: &[u8]) -> (String, String, f64, bool) {
+fn from_path(
+    aln: Alignment,
+    query: &[u8],
+    ref_seq: &[u8],
+    backend: &dyn AlignmentBackend,
+) -> (String, String, f64, bool) {
     let mut ref_string = String::new();
     let mut query_string = String::new();
     let mut mismatches = 0;
     let mut gaps = 0;
     let mut matches = 0;
-    for p in aln.path().iter() {
+    for p in backend.path(&aln).iter() {
         let (query_pos, ref_pos, state) = p;
 
         if *state == AlignmentOperation::Match {
@@ -256,47 +2136,454 @@ fn from_path(aln: Alignment, query: &[u8], ref_seq: &[u8]) -> (String, String, f
             gaps += 1;
         }
     }
-    let percent_identity = (matches as f64 / (matches + mismatches + gaps) as f64) * 100.0;
+    let percent_identity = (matches as f64 / (matches + mismatches + gaps) as f64) * 100.0;
+
+    let indel = gaps > 0;
+
+    (ref_string, query_string, percent_identity, indel)
+}
+
+/// Aligns the nucleotide `query` to `ref_seq`, a sequence other than the references of
+/// `--reference`, with algorithm 1 and the scoring of `options`. Used by the `subtype` module to
+/// align queries to every sequence of its panel.
+pub(crate) fn align_to(
+    query: &[u8],
+    ref_seq: &[u8],
+    options: &LocatorOptions,
+) -> Result<Result<Locator, NotLocated>, BoxError> {
+    algorithm1(
+        query,
+        ref_seq,
+        Scoring::new(options, "nt", false),
+        &BioBackend,
+    )
+}
+
+/// Implements a specific alignment algorithm to align a query sequence against a reference
+/// sequence.
+/// The function takes the query sequence, reference sequence, and scoring function as input.
+/// It performs a semi-global alignment with the backend (see `AlignmentBackend::semiglobal`) and
+/// then converts the alignment path into aligned strings using the `from_path` function.
+/// The function returns a `Result` containing the `Locator`, or `NotLocated` when the query is
+/// empty or its alignment is degenerate.
+fn algorithm1(
+    query: &[u8],
+    ref_seq: &[u8],
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Result<Locator, NotLocated>, BoxError> {
+    if query.is_empty() {
+        return Ok(Err(NotLocated::new(NotLocatedReason::EmptyQuery)));
+    }
+    let aln = backend.semiglobal(query, ref_seq, &scoring)?;
+    let ref_start = aln.ystart;
+    let ref_end = aln.yend;
+    let best_score = aln.score;
+    if ref_end <= ref_start {
+        // Every query residue is inserted: there is no interval to report.
+        return Ok(Err(NotLocated {
+            best_score: Some(best_score),
+            ..NotLocated::new(NotLocatedReason::NoAlignedResidues)
+        }));
+    }
+    let (ref_aligned_string, query_aligned_string, percent_identity, indel) =
+        from_path(aln, query, ref_seq, backend);
+
+    let loc = Locator::new(
+        RefPosition::from_zero_based(ref_start),
+        RefPosition::new(ref_end),
+        percent_identity,
+        indel,
+        query_aligned_string,
+        ref_aligned_string,
+    );
+    if percent_identity == 0.0 {
+        return Ok(Err(NotLocated {
+            best: Some(Box::new(loc)),
+            best_score: Some(best_score),
+            ..NotLocated::new(NotLocatedReason::NoMatches)
+        }));
+    }
+    Ok(Ok(loc))
+}
+
+/// Finds up to `max` other matches of `query` than `best`, with at least `min_identity` percent
+/// identity, by aligning it again with algorithm 1 while the reference positions of the matches
+/// found are masked. Stops at the first match that overlaps a masked position or is below
+/// `min_identity`, so the matches are in decreasing order of score.
+fn other_hits(
+    query: &[u8],
+    ref_seq: &[u8],
+    best: &Locator,
+    max: usize,
+    min_identity: f64,
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Vec<Locator>, BoxError> {
+    // Not a residue of either alphabet, so a masked position never matches.
+    const MASK: u8 = b'#';
+    let mut masked = ref_seq.to_vec();
+    let mut hits: Vec<Locator> = Vec::new();
+    let mut found = best.ref_segments();
+    while hits.len() < max {
+        for segment in found {
+            masked[segment.start.to_zero_based()..segment.end.get()].fill(MASK);
+        }
+        let Ok(hit) = algorithm1(query, &masked, scoring, backend)? else {
+            break;
+        };
+        if hit.percent_identity < min_identity || hit.ref_aligned_string.contains(MASK as char) {
+            break;
+        }
+        found = hit.ref_segments();
+        hits.push(hit);
+    }
+    Ok(hits)
+}
+
+/// Number of places the query is cut at to place the segments of a split alignment, see `split`.
+const SPLIT_STEPS: usize = 8;
+
+/// Minimum number of residues of either segment of a split alignment.
+const MIN_SPLIT_LENGTH: usize = 20;
+
+/// Aligns `query` as two segments apart on the reference, for a query across a large deletion or
+/// a junction of distant parts of the reference, which aligns with a low identity in one piece.
+///
+/// The query is cut at every `SPLIT_STEPS`th of its length, and its prefix and suffix aligned
+/// separately with algorithm 1; the cut with the best total score places the segments. The whole
+/// query is then aligned to the window of the reference around either placement, and the split
+/// point is the query residue maximizing the score of the columns up to it in the first window
+/// and after it in the second. `None` unless both segments have at least `min_identity` percent
+/// identity and they are not contiguous on the reference.
+fn split(
+    query: &[u8],
+    ref_seq: &[u8],
+    min_identity: f64,
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Option<Locator>, BoxError> {
+    if query.len() < 2 * MIN_SPLIT_LENGTH {
+        return Ok(None);
+    }
+    let score =
+        |loc: &Locator| scoring.score_alignment(&loc.query_aligned_string, &loc.ref_aligned_string);
+    let mut best: Option<(i32, usize, Locator, Locator)> = None;
+    for cut in (1..SPLIT_STEPS).map(|step| query.len() * step / SPLIT_STEPS) {
+        if cut < MIN_SPLIT_LENGTH || query.len() - cut < MIN_SPLIT_LENGTH {
+            continue;
+        }
+        let (Ok(prefix), Ok(suffix)) = (
+            algorithm1(&query[..cut], ref_seq, scoring, backend)?,
+            algorithm1(&query[cut..], ref_seq, scoring, backend)?,
+        ) else {
+            continue;
+        };
+        let total = score(&prefix) + score(&suffix);
+        if best.as_ref().is_none_or(|(best, ..)| total > *best) {
+            best = Some((total, cut, prefix, suffix));
+        }
+    }
+    let Some((_, cut, prefix, suffix)) = best else {
+        return Ok(None);
+    };
+
+    // Either window leaves room for the rest of the query past the placement.
+    let head_offset = prefix.ref_start.to_zero_based();
+    let head_end = (prefix.ref_end.get() + query.len() - cut).min(ref_seq.len());
+    let tail_offset = suffix.ref_start.to_zero_based().saturating_sub(cut);
+    let tail_end = suffix.ref_end.get();
+    let (Ok(head), Ok(tail)) = (
+        algorithm1(query, &ref_seq[head_offset..head_end], scoring, backend)?,
+        algorithm1(query, &ref_seq[tail_offset..tail_end], scoring, backend)?,
+    ) else {
+        return Ok(None);
+    };
+    let head_columns = SplitColumns::new(&head, &scoring);
+    let tail_columns = SplitColumns::new(&tail, &scoring);
+    let Some(point) = (MIN_SPLIT_LENGTH..=query.len() - MIN_SPLIT_LENGTH).max_by_key(|&point| {
+        // `max_by_key` keeps the last maximum: prefer the first one, as `best` does.
+        (
+            head_columns.score_through(point - 1) + tail_columns.score_from(point),
+            std::cmp::Reverse(point),
+        )
+    }) else {
+        return Ok(None);
+    };
+    let first = &head_columns.columns[..=head_columns.residues[point - 1]];
+    let second = &tail_columns.columns[tail_columns.residues[point]..];
+
+    let ref_residues = |columns: &[(u8, u8)]| columns.iter().filter(|&&(_, r)| r != b'-').count();
+    let identity = |columns: &[(u8, u8)]| {
+        let matches = columns.iter().filter(|(q, r)| q == r).count();
+        matches as f64 / columns.len() as f64 * 100.0
+    };
+    let (first_residues, second_residues) = (ref_residues(first), ref_residues(second));
+    if first_residues == 0
+        || second_residues == 0
+        || identity(first) < min_identity
+        || identity(second) < min_identity
+    {
+        return Ok(None);
+    }
+    let first_start = head.ref_start + head_offset;
+    let second_end = tail.ref_end + tail_offset;
+    let segments = vec![
+        RefSegment {
+            start: first_start,
+            end: first_start + (first_residues - 1),
+        },
+        RefSegment {
+            start: RefPosition::new(second_end.get() + 1 - second_residues),
+            end: second_end,
+        },
+    ];
+    if segments[1].start.get() == segments[0].end.get() + 1 {
+        return Ok(None);
+    }
+
+    let columns: Vec<(u8, u8)> = first.iter().chain(second).copied().collect();
+    let mut located = Locator::new(
+        segments[0].start,
+        segments[1].end,
+        identity(&columns[..]),
+        columns.iter().any(|&(q, r)| q == b'-' || r == b'-'),
+        columns.iter().map(|&(q, _)| q as char).collect(),
+        columns.iter().map(|&(_, r)| r as char).collect(),
+    );
+    located.ref_span = segments.iter().map(|s| s.start.span_to(s.end)).sum();
+    located.segments = segments;
+    located.split = true;
+    Ok(Some(located))
+}
+
+/// The columns of an alignment of a whole query, with the column of every query residue and the
+/// running score of the columns, to find the split point of `split`.
+struct SplitColumns {
+    columns: Vec<(u8, u8)>,
+    /// The column of every query residue, 0-based.
+    residues: Vec<usize>,
+    /// The score of the columns before every column, and of all the columns last.
+    scores: Vec<i32>,
+}
+
+impl SplitColumns {
+    fn new(loc: &Locator, scoring: &Scoring) -> Self {
+        let columns: Vec<(u8, u8)> = loc
+            .query_aligned_string
+            .bytes()
+            .zip(loc.ref_aligned_string.bytes())
+            .collect();
+        let residues = (0..columns.len())
+            .filter(|&i| columns[i].0 != b'-')
+            .collect();
+        let mut scores = vec![0];
+        for score in scoring.column_scores(&loc.query_aligned_string, &loc.ref_aligned_string) {
+            scores.push(scores[scores.len() - 1] + score);
+        }
+        SplitColumns {
+            columns,
+            residues,
+            scores,
+        }
+    }
 
-    let indel = if gaps > 0 { true } else { false };
+    /// The score of the columns up to the one of the query residue `residue`, 0-based.
+    fn score_through(&self, residue: usize) -> i32 {
+        self.scores[self.residues[residue] + 1]
+    }
 
-    (ref_string, query_string, percent_identity, indel)
+    /// The score of the columns from the one of the query residue `residue`, 0-based.
+    fn score_from(&self, residue: usize) -> i32 {
+        self.scores[self.scores.len() - 1] - self.scores[self.residues[residue]]
+    }
 }
 
-/// Implements a specific alignment algorithm to align a query sequence against a reference
-/// sequence.
-/// The function takes the query sequence, reference sequence, and scoring function as input.
-/// It performs a semi-global alignment using the `get_aln` function and then converts the
-/// alignment path into aligned strings using the `from_path` function.
-/// The function returns a `Result` containing an `Option<Locator>`.
-/// If the alignment is successful, it returns `Some(locator)`, otherwise it returns `None`.
-fn algorithm1(
+/// Minimum percent identity of the copy of the reference at either end of a circular alignment
+/// for it to be part of the location. Unrelated flanking sequence aligns with about 30% identity.
+const MIN_SEGMENT_IDENTITY: f64 = 50.0;
+
+/// Aligns `query` to copies of `ref_seq` joined end to start, enough for the query to wrap around
+/// the reference, and maps the alignment back to reference positions with `unwrap_circular`.
+fn circular(
     query: &[u8],
     ref_seq: &[u8],
-    score: fn(u8, u8) -> i32,
-) -> Result<Option<Locator>, BoxError> {
-    let aln = get_aln(query, ref_seq, score, -5, -1)?;
-    let ref_start = aln.ystart as usize;
-    let ref_end = aln.yend as usize;
-    let (ref_aligned_string, query_aligned_string, percent_identity, indel) =
-        from_path(aln, query, ref_seq);
+    scoring: Scoring,
+    backend: &dyn AlignmentBackend,
+) -> Result<Result<Locator, NotLocated>, BoxError> {
+    let copies = query.len().div_ceil(ref_seq.len()) + 1;
+    let joined = ref_seq.repeat(copies);
+    Ok(match algorithm1(query, &joined, scoring, backend)? {
+        Ok(loc) => Ok(unwrap_circular(loc, ref_seq.len())),
+        Err(mut not_located) => {
+            not_located.best = not_located
+                .best
+                .map(|best| Box::new(unwrap_circular(*best, ref_seq.len())));
+            Err(not_located)
+        }
+    })
+}
+
+/// The alignment columns of one copy of the reference in a circular alignment.
+struct CopyColumns {
+    copy: usize,
+    /// First and last positions in the joined copies, 1-based.
+    start: usize,
+    end: usize,
+    columns: Vec<(u8, u8)>,
+}
+
+impl CopyColumns {
+    fn identity(&self) -> f64 {
+        let matches = self.columns.iter().filter(|(q, r)| q == r).count();
+        matches as f64 / self.columns.len() as f64 * 100.0
+    }
+
+    /// The query residues of the columns, as insertions.
+    fn overhang(&self) -> Vec<(u8, u8)> {
+        self.columns
+            .iter()
+            .filter(|(q, _)| *q != b'-')
+            .map(|(q, _)| (*q, b'-'))
+            .collect()
+    }
+}
+
+/// Converts a location on `copies` of a reference of length `ref_len` joined end to start into a
+/// location on the reference, wrapping around when it covers more than one copy.
+fn unwrap_circular(loc: Locator, ref_len: usize) -> Locator {
+    let mut copies: Vec<CopyColumns> = Vec::new();
+    let mut ref_pos = loc.ref_start.get();
+    for (q, r) in loc
+        .query_aligned_string
+        .bytes()
+        .zip(loc.ref_aligned_string.bytes())
+    {
+        let copy = (ref_pos - 1) / ref_len;
+        if r != b'-' && copies.last().is_none_or(|last| last.copy != copy) {
+            copies.push(CopyColumns {
+                copy,
+                start: ref_pos,
+                end: ref_pos,
+                columns: Vec::new(),
+            });
+        } else if copies.is_empty() {
+            copies.push(CopyColumns {
+                copy,
+                start: ref_pos,
+                end: ref_pos - 1,
+                columns: Vec::new(),
+            });
+        }
+        let last = copies.last_mut().unwrap();
+        last.columns.push((q, r));
+        if r != b'-' {
+            last.end = ref_pos;
+            ref_pos += 1;
+        }
+    }
+
+    while copies.len() > 1 && copies[0].identity() < MIN_SEGMENT_IDENTITY {
+        let first = copies.remove(0);
+        let mut columns = first.overhang();
+        columns.append(&mut copies[0].columns);
+        copies[0].columns = columns;
+    }
+    while copies.len() > 1 && copies[copies.len() - 1].identity() < MIN_SEGMENT_IDENTITY {
+        let last = copies.pop().unwrap();
+        copies.last_mut().unwrap().columns.extend(last.overhang());
+    }
+
+    let columns: Vec<(u8, u8)> = copies.iter().flat_map(|c| c.columns.clone()).collect();
+    let matches = columns.iter().filter(|(q, r)| q == r).count();
+    let percent_identity = matches as f64 / columns.len() as f64 * 100.0;
+    let indel = columns.iter().any(|&(q, r)| q == b'-' || r == b'-');
+    let segments: Vec<RefSegment> = copies
+        .iter()
+        .map(|c| RefSegment {
+            start: RefPosition::new(c.start - c.copy * ref_len),
+            end: RefPosition::new(c.end - c.copy * ref_len),
+        })
+        .collect();
 
-    let loc = Locator {
-        ref_start: ref_start + 1,
-        ref_end,
+    let mut located = Locator::new(
+        segments[0].start,
+        segments[segments.len() - 1].end,
         percent_identity,
         indel,
-        query_aligned_string,
-        ref_aligned_string,
+        columns.iter().map(|&(q, _)| q as char).collect(),
+        columns.iter().map(|&(_, r)| r as char).collect(),
+    );
+    if segments.len() > 1 {
+        located.ref_span = segments.iter().map(|s| s.start.span_to(s.end)).sum();
+        located.segments = segments;
+    }
+    located
+}
+
+/// Returns the number of columns of an alignment between its first and last aligned pair of
+/// residues whose query and reference residues differ, gaps included.
+fn edit_distance(query_aligned_string: &str, ref_aligned_string: &str) -> usize {
+    let columns: Vec<(u8, u8)> = query_aligned_string
+        .bytes()
+        .zip(ref_aligned_string.bytes())
+        .collect();
+    let aligned = |&(q, r): &(u8, u8)| q != b'-' && r != b'-';
+    let (Some(first), Some(last)) = (
+        columns.iter().position(aligned),
+        columns.iter().rposition(aligned),
+    ) else {
+        return 0;
     };
-    Ok(Some(loc))
+    columns[first..=last].iter().filter(|(q, r)| q != r).count()
+}
+
+/// Returns the first and last query residues of an alignment aligned to a reference residue, or
+/// the first and last query residues when none is.
+fn query_interval(
+    query_aligned_string: &str,
+    ref_aligned_string: &str,
+) -> (QueryPosition, QueryPosition) {
+    let mut residues = 0;
+    let mut aligned: Option<(usize, usize)> = None;
+    for (q, r) in query_aligned_string.bytes().zip(ref_aligned_string.bytes()) {
+        if q != b'-' {
+            residues += 1;
+            if r != b'-' {
+                aligned = Some((aligned.map_or(residues, |(start, _)| start), residues));
+            }
+        }
+    }
+    let (start, end) = aligned.unwrap_or((1, residues.max(1)));
+    (QueryPosition::new(start), QueryPosition::new(end))
+}
+
+/// Returns the fraction of the query residues of an alignment that are aligned to a reference
+/// residue, or 0 for an empty query.
+fn query_coverage(query_aligned_string: &str, ref_aligned_string: &str) -> f64 {
+    let mut residues = 0;
+    let mut covered = 0;
+    for (q, r) in query_aligned_string.bytes().zip(ref_aligned_string.bytes()) {
+        if q != b'-' {
+            residues += 1;
+            if r != b'-' {
+                covered += 1;
+            }
+        }
+    }
+    if residues == 0 {
+        0.0
+    } else {
+        covered as f64 / residues as f64
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::config::AnnotationArgs;
 
-    static ONE_LOC: (i32, i32, f64, bool, &'static str, &'static str) = (
+    static ONE_LOC: (i32, i32, f64, bool, &str, &str) = (
         2648,
         3209,
         83.98576512455516,
@@ -305,7 +2592,7 @@ mod test {
         "ATTAGTAGAAATTTGTACAGAGATGGAAAAGGAAGGGAAAATTTCAAAAATTGGGCCTGAAAATCCATACAATACTCCAGTATTTGCCATAAAGAAAAAAGACAGTACTAAATGGAGAAAATTAGTAGATTTCAGAGAACTTAATAAGAGAACTCAAGACTTCTGGGAAGTTCAATTAGGAATACCACATCCCGCAGGGTTAAAAAAGAAAAAATCAGTAACAGTACTGGATGTGGGTGATGCATATTTTTCAGTTCCCTTAGATGAAGACTTCAGGAAGTATACTGCATTTACCATACCTAGTATAAACAATGAGACACCAGGGATTAGATATCAGTACAATGTGCTTCCACAGGGATGGAAAGGATCACCAGCAATATTCCAAAGTAGCATGACAAAAATCTTAGAGCCTTTTAGAAAACAAAATCCAGACATAGTTATCTATCAATACATGGATGATTTGTATGTAGGATCTGACTTAGAAATAGGGCAGCATAGAACAAAAATAGAGGAGCTGAGACAACATCTGTTGAGGTGGGGACTTACCACACCAGACAAAAAA",
     );
 
-    static TWO_LOC: (i32, i32, f64, bool, &'static str, &'static str) = (
+    static TWO_LOC: (i32, i32, f64, bool, &str, &str) = (
         6585,
         7208,
         83.98576512455516,
@@ -314,38 +2601,25 @@ mod test {
         "ATTAGTAGAAATTTGTACAGAGATGGAAAAGGAAGGGAAAATTTCAAAAATTGGGCCTGAAAATCCATACAATACTCCAGTATTTGCCATAAAGAAAAAAGACAGTACTAAATGGAGAAAATTAGTAGATTTCAGAGAACTTAATAAGAGAACTCAAGACTTCTGGGAAGTTCAATTAGGAATACCACATCCCGCAGGGTTAAAAAAGAAAAAATCAGTAACAGTACTGGATGTGGGTGATGCATATTTTTCAGTTCCCTTAGATGAGACTTCAGGAAGTATACTGCATTTACCATACCTAAGTATAAACAATGAGACACCAGGGATTAGATATCAGTACAATGTGCTTCCACAGGGATGGAAAGGATCACCAGCAATATTCCAAAGTAGCATGACAAAAATCTTAGAGCCTTTTAGAAAACAAAATCCAGACATAGTTATCTATCAATACATGGATGATTTGTATGTAGGATCTGACTTAGAAATAGGGCAGCATAGAACAAAAATAGAGGAGCTGAGACAACATCTGTTGAGGTGGGGACTTACCACACCAGACAAAAAA",
     );
 
-    static MY_ARGS: (&'static str, &'static str, &'static str, u8) = (
+    static MY_ARGS: (&str, &str, &str, u8) = (
         "ATTAACAGAGATTTGTGAAGAAATGGAAAAGGAAGGAAAAATTACAAAAATTGGGCCTGAAAATCCATATAACACTCCAATATTTGCCATAAAAAAGAAGGACAGTACTAAGTGGAGAAAATTAGTAGATTTCAGAGAGCTCAATAAAAGAACTCAAGACTTTTGGGAGGTTCAATTAGGAATACCACACCCAGCAGGGTTAAAAAAGAAAAAATCAGTGACAGTACTGGATGTGGGGGATGCATATTTTTCTGTTCCTTTAGATGAGTGTAAACAATGAAACACCAGGGATTAGATATCAATATAATGTGCTACCACAGGGGTGGAAAGGATCACCATCAATATTCCAGAGTAGCATGACAAAAATCTTAGAGCCCTTTAGAGCAAAAAACCCAGAAATAGTCATCTATCAATATATGGATGACTTATGTGTAGGATCTGACTTAGAAATAGGGCAACATAGAGCAAAAATAGAGGAGTTAAGAGAACATCTATTGAAGTGGGGATTGACCACACCAGACAAGAAA",
         "HXB2",
         "nt",
         1,
     );
 
-    static MY_ARGS2: (&'static str, &'static str, &'static str, u8) = (
+    static MY_ARGS2: (&str, &str, &str, u8) = (
         "AAATTAACCCCACTCTGTGTTGAATTAAATTGTACTAAGTATGAGGGTAATAGTACTACTACCACGAATAGTACTACTGCCACTACGAATAGTACTGCTGCCCCTAACGGGACGGAGACGGGAATGAAAAATTGCTCTTTCTATGTTAACACGGTCACAAACTATAAGGTGCAGAAGAAATATGCACTTTTCTATGATCTTGATATAGTACAAATAGAAGGTAGTAATACTAGCTATAGGATAACAAAGTGTAACACCTCAATCAGCACAGTACAATGCACACATGGTATTAAACCAGTAGTATCAACTCAATTATTGTTAAATGGCAGCTTAGCAGAAGAAAAGATAGTCATCAGATCTAGCAACTTCTCTAGCAACACTGAAAGCATAATAGTACAGCTGAAAAACCCTGTAGAAATTAACTGTACAAGACCCAACAACAATAGAAGACAGAGTATCCATATTGGACCAGGGAGAGCGTTTTTTACAACAGGAGAAATAATAGGAGATATAAGACAA",
         "HXB2",
         "nt",
         1,
     );
 
-    #[test]
-    fn test_get_aln() {
-        let search_string = b"AAATTAACCCCACTCTGTGTTGAATTAAATTGTACTAAGTATGAGGGTAATAGTACTACTACCACGAATAGTACTACTGCCACTACGAATAGTACTGCTGCCCCTAACGGGACGGAGACGGGAATGAAAAATTGCTCTTTCTATGTTAACACGGTCACAAACTATAAGGTGCAGAAGAAATATGCACTTTTCTATGATCTTGATATAGTACAAATAGAAGGTAGTAATACTAGCTATAGGATAACAAAGTGTAACACCTCAATCAGCACAGTACAATGCACACATGGTATTAAACCAGTAGTATCAACTCAATTATTGTTAAATGGCAGCTTAGCAGAAGAAAAGATAGTCATCAGATCTAGCAACTTCTCTAGCAACACTGAAAGCATAATAGTACAGCTGAAAAACCCTGTAGAAATTAACTGTACAAGACCCAACAACAATAGAAGACAGAGTATCCATATTGGACCAGGGAGAGCGTTTTTTACAACAGGAGAAATAATAGGAGATATAAGACAA";
-        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
-        let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
-        let gap_open = -5;
-        let gap_extend = -1;
-
-        let aln = get_aln(search_string, ref_seq, score, gap_open, gap_extend).unwrap();
-        assert_eq!(aln.ystart, 6584);
-        assert_eq!(aln.yend, 7208);
-    }
-
     #[test]
     fn test_locator_1() {
         let targe_loc = Locator::new(
-            ONE_LOC.0 as usize,
-            ONE_LOC.1 as usize,
+            RefPosition::new(ONE_LOC.0 as usize),
+            RefPosition::new(ONE_LOC.1 as usize),
             ONE_LOC.2,
             ONE_LOC.3,
             ONE_LOC.4.to_string(),
@@ -353,10 +2627,14 @@ mod test {
         );
 
         let my_arg = Args {
-            query: vec![MY_ARGS.0.to_string()],
-            reference: MY_ARGS.1.to_string(),
-            type_query: MY_ARGS.2.to_string(),
-            algorithm: MY_ARGS.3,
+            common: CommonArgs {
+                query: vec![MY_ARGS.0.to_string()],
+                reference: MY_ARGS.1.to_string(),
+                type_query: MY_ARGS.2.to_string(),
+                algorithm: MY_ARGS.3,
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let loc = Locator::build(&my_arg).unwrap().pop().unwrap().unwrap();
@@ -373,8 +2651,8 @@ mod test {
     #[should_panic]
     fn test_locator_2() {
         let targe_loc = Locator::new(
-            ONE_LOC.0 as usize,
-            ONE_LOC.1 as usize,
+            RefPosition::new(ONE_LOC.0 as usize),
+            RefPosition::new(ONE_LOC.1 as usize),
             ONE_LOC.2,
             ONE_LOC.3,
             ONE_LOC.4.to_string(),
@@ -382,10 +2660,14 @@ mod test {
         );
 
         let my_arg = Args {
-            query: vec![MY_ARGS.0.to_string()],
-            reference: MY_ARGS.1.to_string(),
-            type_query: MY_ARGS.2.to_string(),
-            algorithm: 2,
+            common: CommonArgs {
+                query: vec![MY_ARGS.0.to_string()],
+                reference: MY_ARGS.1.to_string(),
+                type_query: MY_ARGS.2.to_string(),
+                algorithm: 2,
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let loc = Locator::build(&my_arg).unwrap().pop().unwrap().unwrap();
@@ -401,8 +2683,8 @@ mod test {
     #[test]
     fn test_locator_3() {
         let targe_loc = Locator::new(
-            TWO_LOC.0 as usize,
-            TWO_LOC.1 as usize,
+            RefPosition::new(TWO_LOC.0 as usize),
+            RefPosition::new(TWO_LOC.1 as usize),
             TWO_LOC.2,
             TWO_LOC.3,
             TWO_LOC.4.to_string(),
@@ -410,10 +2692,14 @@ mod test {
         );
 
         let my_arg = Args {
-            query: vec![MY_ARGS2.0.to_string()],
-            reference: MY_ARGS2.1.to_string(),
-            type_query: MY_ARGS2.2.to_string(),
-            algorithm: 1,
+            common: CommonArgs {
+                query: vec![MY_ARGS2.0.to_string()],
+                reference: MY_ARGS2.1.to_string(),
+                type_query: MY_ARGS2.2.to_string(),
+                algorithm: 1,
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let loc = Locator::build(&my_arg).unwrap().pop().unwrap().unwrap();
@@ -425,8 +2711,8 @@ mod test {
     #[test]
     fn test_locator_4() {
         let targe_loc = Locator::new(
-            TWO_LOC.0 as usize,
-            TWO_LOC.1 as usize,
+            RefPosition::new(TWO_LOC.0 as usize),
+            RefPosition::new(TWO_LOC.1 as usize),
             TWO_LOC.2,
             TWO_LOC.3,
             TWO_LOC.4.to_string(),
@@ -434,10 +2720,14 @@ mod test {
         );
 
         let my_arg = Args {
-            query: vec![MY_ARGS2.0.to_string()],
-            reference: MY_ARGS2.1.to_string(),
-            type_query: MY_ARGS2.2.to_string(),
-            algorithm: 2,
+            common: CommonArgs {
+                query: vec![MY_ARGS2.0.to_string()],
+                reference: MY_ARGS2.1.to_string(),
+                type_query: MY_ARGS2.2.to_string(),
+                algorithm: 2,
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
         let loc = Locator::build(&my_arg).unwrap().pop().unwrap().unwrap();
@@ -445,4 +2735,1031 @@ mod test {
         assert_eq!(loc.ref_start, targe_loc.ref_start);
         assert_eq!(loc.ref_end, targe_loc.ref_end);
     }
+
+    #[test]
+    fn test_query_coverage() {
+        // Query: insertion of `TT` after position 11, deletion of reference position 13.
+        let loc = Locator::new(
+            RefPosition::new(10),
+            RefPosition::new(15),
+            62.5,
+            true,
+            "ACTTG-TA".to_string(),
+            "AC--GCTA".to_string(),
+        );
+        assert_eq!(loc.query_coverage, 5.0 / 7.0);
+        assert_eq!(loc.ref_span, 6);
+        assert_eq!(loc.edit_distance, 3);
+        assert_eq!(loc.aligned_length(), 8);
+    }
+
+    #[test]
+    fn test_score_alignment() {
+        // 2 bases deleted and 1 substituted in the middle of SMALL_REF, with an overhang.
+        let query = [
+            b"GG",
+            &SMALL_REF[..20],
+            &SMALL_REF[22..30],
+            b"T",
+            &SMALL_REF[31..],
+        ]
+        .concat();
+        let scoring = Scoring::default();
+        let aln = BioBackend.semiglobal(&query, SMALL_REF, &scoring).unwrap();
+        let loc = algorithm1(&query, SMALL_REF, scoring, &BioBackend)
+            .unwrap()
+            .unwrap();
+        let score = scoring.score_alignment(&loc.query_aligned_string, &loc.ref_aligned_string);
+        assert_eq!(score, aln.score);
+        assert_eq!(loc.edit_distance, 3);
+    }
+
+    static SMALL_REF: &[u8] = b"ACGTTGCAAGGCTTAACCGGATCGATTCGACGGTACCATGAGTCAGCTAGGCATCCGTAA";
+
+    #[test]
+    fn test_circular_wraps_around() {
+        // The last 20 bases of the reference followed by its first 30, like a 2-LTR junction.
+        let query = [&SMALL_REF[40..], &SMALL_REF[..30]].concat();
+        let loc = circular(&query, SMALL_REF, Scoring::default(), &BioBackend)
+            .unwrap()
+            .unwrap();
+        assert!(loc.wraps());
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (41, 30));
+        assert_eq!(
+            loc.segments,
+            vec![
+                RefSegment {
+                    start: RefPosition::new(41),
+                    end: RefPosition::new(60)
+                },
+                RefSegment {
+                    start: RefPosition::new(1),
+                    end: RefPosition::new(30)
+                }
+            ]
+        );
+        assert_eq!(loc.ref_span, 50);
+        assert_eq!(loc.percent_identity, 100.0);
+        let positions = loc.ref_positions();
+        assert_eq!(
+            (positions[19], positions[20]),
+            (Some(RefPosition::new(60)), Some(RefPosition::new(1)))
+        );
+    }
+
+    #[test]
+    fn test_circular_trims_flanks() {
+        // The whole reference with unrelated flanking sequence, longer than the reference.
+        let query = [b"TTTTTTTTTTTTTTTTTTTT", SMALL_REF, b"TTTTTTTTTTTTTTTTTTTT"].concat();
+        let loc = circular(&query, SMALL_REF, Scoring::default(), &BioBackend)
+            .unwrap()
+            .unwrap();
+        assert!(!loc.wraps());
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (1, 60));
+        assert_eq!(loc.query_coverage, 0.6);
+        // The 20 bases of each flank are clipped.
+        assert_eq!((loc.query_start.get(), loc.query_end.get()), (21, 80));
+        assert_eq!(
+            loc.query_aligned_string.replace('-', ""),
+            String::from_utf8(query).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_anchor_offsets() {
+        assert_eq!(anchor_offsets(400, 100, None), Some((0, 300)));
+        assert_eq!(anchor_offsets(400, 60, None), Some((0, 340)));
+        assert_eq!(anchor_offsets(80, 100, None), None);
+        let mut soft_mask = vec![false; 400];
+        soft_mask[..20].fill(true);
+        soft_mask[350..].fill(true);
+        assert_eq!(anchor_offsets(400, 100, Some(&soft_mask)), Some((20, 250)));
+        soft_mask[150..250].fill(true);
+        assert_eq!(anchor_offsets(400, 100, Some(&soft_mask)), Some((20, 250)));
+        soft_mask[100..].fill(true);
+        assert_eq!(anchor_offsets(400, 100, Some(&soft_mask)), None);
+        assert_eq!(anchor_offsets(400, 50, Some(&soft_mask)), Some((20, 50)));
+    }
+
+    /// A pseudo-random nucleotide sequence of `len` bases.
+    fn random_nt(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pattern_match() {
+        let ref_seq = random_nt(1000, 1);
+        let pattern = &ref_seq[200..300];
+        let aln = pattern_match(pattern, &ref_seq, 30, &BioBackend).unwrap();
+        assert_eq!(aln.ystart, 200);
+        // A second copy with 3 substitutions is within the margin.
+        let mut copy = pattern.to_vec();
+        copy[10] = b'N';
+        copy[50] = b'N';
+        copy[90] = b'N';
+        let repeated = [&ref_seq[..], &copy].concat();
+        assert!(pattern_match(pattern, &repeated, 30, &BioBackend).is_none());
+    }
+
+    #[test]
+    fn test_algorithm2_repeat() {
+        // A reference with a repeat R and a copy R' 3 substitutions away from it: A R B R' C.
+        let unique = random_nt(1500, 2);
+        let repeat = random_nt(100, 3);
+        let mut variant = repeat.clone();
+        for i in [20, 50, 80] {
+            variant[i] = if repeat[i] == b'A' { b'C' } else { b'A' };
+        }
+        let ref_seq = [
+            &unique[..500],
+            &repeat,
+            &unique[500..1000],
+            &variant,
+            &unique[1000..],
+        ]
+        .concat();
+        // The end of A followed by R', located at 101-600: the end anchor matches R' best, but R
+        // too, so the query is anchored on A only.
+        let query = [&unique[100..500], &variant[..]].concat();
+        let options = LocatorOptions::default();
+        let loc = algorithm2(
+            &query,
+            &ref_seq,
+            None,
+            &options,
+            Scoring::default(),
+            &BioBackend,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (101, 600));
+        assert_eq!(loc.query_aligned_string.len(), 500);
+    }
+
+    #[test]
+    fn test_algorithm2_middle_anchor() {
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let options = LocatorOptions::default();
+        let scoring = Scoring::default();
+        // HXB2 1001-1200, 7001-7200 and 3001-3200: the ends anchor colinearly, but the middle
+        // anchors after the end, so the query is aligned with algorithm 1.
+        let rearranged = [
+            &ref_seq[1000..1200],
+            &ref_seq[7000..7200],
+            &ref_seq[3000..3200],
+        ]
+        .concat();
+        let loc = algorithm2(&rearranged, ref_seq, None, &options, scoring, &BioBackend)
+            .unwrap()
+            .unwrap();
+        let expected = algorithm1(&rearranged, ref_seq, scoring, &BioBackend)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (loc.ref_start, loc.ref_end),
+            (expected.ref_start, expected.ref_end)
+        );
+        assert_eq!(loc.query_aligned_string, expected.query_aligned_string);
+
+        // HXB2 2001-2300 and 2361-2660, with 60 bases deleted between them: the middle window,
+        // across the deletion, anchors colinearly or not at all, and the query is aligned between
+        // the ends.
+        let deleted = [&ref_seq[2000..2300], &ref_seq[2360..2660]].concat();
+        let loc = algorithm2(&deleted, ref_seq, None, &options, scoring, &BioBackend)
+            .unwrap()
+            .unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (2001, 2660));
+        assert!(loc.indel);
+
+        assert!(colinear((0, 1000), (500, 3100)));
+        assert!(colinear((0, 1000), (500, 1250)));
+        assert!(!colinear((0, 1000), (500, 1249)));
+        assert!(!colinear((250, 7050), (500, 3100)));
+    }
+
+    #[test]
+    fn test_algorithm3() {
+        // HXB2 6001-6300 with 30 bases deleted, and a read of the Pol protein: both are located
+        // like algorithm 1 does.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let read = [&ref_seq[6000..6120], &ref_seq[6150..6300]].concat();
+        let protein = retrieve_reference_sequence("HXB2", "aa").unwrap().sequence;
+        for (query, type_query) in [(read, "nt"), (protein[1000..1080].to_vec(), "aa")] {
+            let args = Args {
+                common: CommonArgs {
+                    query: vec![
+                        String::from_utf8(query).unwrap(),
+                        "NNNNNNNNNNNN".to_string(),
+                    ],
+                    type_query: type_query.to_string(),
+                    algorithm: 3,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let located = Locator::try_build(&args).unwrap();
+            let expected = Locator::try_build(&Args {
+                common: CommonArgs {
+                    algorithm: 1,
+                    ..args.common
+                },
+                ..args
+            })
+            .unwrap();
+            assert_eq!(located, expected);
+            assert!(located[0].is_found());
+        }
+        let loc = algorithm3(
+            &ref_seq[7000..7100],
+            ref_seq,
+            &KmerIndex::new(ref_seq, "nt"),
+            Scoring::default(),
+            &BioBackend,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (7001, 7100));
+    }
+
+    #[test]
+    fn test_soft_mask() {
+        // HXB2 2001-2400 with the first 100 bases soft-masked and mutated, so that they cannot
+        // anchor.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let mut query = String::from_utf8(ref_seq[2000..2400].to_vec()).unwrap();
+        let masked = query[..100]
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i % 3 == 0 {
+                    'n'
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect::<String>();
+        query.replace_range(..100, &masked);
+        let args = Args {
+            common: CommonArgs {
+                query: vec![query],
+                algorithm: 2,
+                ..Default::default()
+            },
+            soft_mask: true,
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().unwrap().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (2001, 2400));
+        assert!(loc.query_aligned_string.starts_with('N'));
+        assert!(!loc.query_aligned_string.contains('n'));
+    }
+
+    #[test]
+    fn test_choose_algorithm() {
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let options = LocatorOptions::default();
+        assert_eq!(
+            choose_algorithm(&ref_seq[4000..4400], ref_seq, "nt", &options),
+            2
+        );
+        assert_eq!(
+            choose_algorithm(&ref_seq[4000..4200], ref_seq, "nt", &options),
+            1
+        );
+        let short_anchors = LocatorOptions {
+            anchor_length: 50,
+            min_anchored_length: 150,
+            ..options
+        };
+        let query = &ref_seq[4000..4200];
+        assert_eq!(choose_algorithm(query, ref_seq, "nt", &short_anchors), 2);
+        // Every fourth base substituted: about 25% divergence.
+        let divergent = ref_seq[4000..4400]
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if i % 4 == 0 { b'N' } else { b })
+            .collect::<Vec<u8>>();
+        assert_eq!(choose_algorithm(&divergent, ref_seq, "nt", &options), 1);
+
+        let args = Args {
+            common: CommonArgs {
+                query: vec![String::from_utf8(ref_seq[4000..4400].to_vec()).unwrap()],
+                algorithm: ALGORITHM_AUTO,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().unwrap().unwrap();
+        assert_eq!(loc.algorithm, Some(2));
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (4001, 4400));
+    }
+
+    #[test]
+    fn test_stop() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["MGARASVLSGGELDRWEKIR*".to_string(), "**".to_string()],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let results = Locator::try_build(&args).unwrap();
+        let loc = results[0].locator().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (1, 20));
+        assert_eq!(loc.percent_identity, 100.0);
+        assert_eq!(loc.warnings[0].kind, artifacts::ArtifactKind::Stop);
+        assert!(!loc.query_aligned_string.contains('*'));
+        assert_eq!(
+            results[1].clone().into_result().unwrap_err().reason,
+            NotLocatedReason::EmptyQuery
+        );
+    }
+
+    #[test]
+    fn test_both_strands() {
+        let gag = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+        let args = Args {
+            common: CommonArgs {
+                query: vec![gag.to_string(), sequence::reverse_complement(gag)],
+                ..Default::default()
+            },
+            both_strands: true,
+            ..Default::default()
+        };
+        let results = Locator::try_build(&args).unwrap();
+        for (result, strand) in results.iter().zip([Strand::Plus, Strand::Minus]) {
+            let loc = result.locator().unwrap();
+            assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (790, 849));
+            assert_eq!(loc.percent_identity, 100.0);
+            assert_eq!(loc.strand, strand);
+        }
+        assert_eq!(results[1].locator().unwrap().query_aligned_string, gag);
+    }
+
+    #[test]
+    fn test_max_hits() {
+        // HXB2 9200-9399, in the 3' LTR, and its copy in the 5' LTR.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let query = String::from_utf8(ref_seq[9199..9399].to_vec()).unwrap();
+        let args = Args {
+            common: CommonArgs {
+                query: vec![query],
+                ..Default::default()
+            },
+            max_hits: Some(3),
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (9200, 9399));
+        assert_eq!(loc.other_hits.len(), 1);
+        let hit = &loc.other_hits[0];
+        assert_eq!((hit.ref_start.get(), hit.ref_end.get()), (115, 314));
+        assert!(hit.percent_identity >= 80.0 && hit.percent_identity < 100.0);
+
+        let strict = Args {
+            min_hit_identity: 99.0,
+            ..args.clone()
+        };
+        let loc = Locator::build(&strict).unwrap().pop().flatten().unwrap();
+        assert!(loc.other_hits.is_empty());
+    }
+
+    #[test]
+    fn test_split() {
+        // HXB2 1001-1200 joined to 4001-4200, as across a deletion of 2800 bases.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let query = [&ref_seq[1000..1200], &ref_seq[4000..4200]].concat();
+        let args = Args {
+            common: CommonArgs {
+                query: vec![String::from_utf8(query.clone()).unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let single = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert!(!single.split);
+        assert!(single.percent_identity < 90.0);
+
+        let args = Args {
+            split_identity: Some(90.0),
+            ..args
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert!(loc.split);
+        assert!(!loc.wraps());
+        let segments: Vec<(usize, usize)> = loc
+            .segments
+            .iter()
+            .map(|s| (s.start.get(), s.end.get()))
+            .collect();
+        assert_eq!(segments, [(1001, 1200), (4001, 4200)]);
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (1001, 4200));
+        assert_eq!(loc.ref_span, 400);
+        assert_eq!(loc.percent_identity, 100.0);
+        assert_eq!(loc.ref_positions()[200], Some(RefPosition::new(4001)));
+
+        // A contiguous query is not split.
+        let contiguous = &ref_seq[1000..1400];
+        assert!(
+            split(contiguous, ref_seq, 90.0, Scoring::default(), &BioBackend)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_back_map() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "MGARASVLSGGELDRWEKIR".to_string(),
+                    "MRVKEKYQHLWRWGWRWGTM".to_string(),
+                ],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let results = Locator::try_build(&args).unwrap();
+        let nt = |i: usize| {
+            results[i]
+                .locator()
+                .unwrap()
+                .nt_interval(Coordinates::OneBased)
+        };
+        assert_eq!(nt(0), Some((790, 849)));
+        assert_eq!(nt(1), Some((6225, 6284)));
+        let loc = results[1].locator().unwrap();
+        assert_eq!(loc.nt_interval(Coordinates::ZeroBased), Some((6224, 6284)));
+
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["ATGCATGCATGC".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.nt_interval(Coordinates::OneBased), None);
+    }
+
+    #[test]
+    fn test_gene() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["MRVKEKYQHLWRWGWRWGTM".to_string()],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            gene: Some("env".to_string()),
+            annotate: true,
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.interval(Coordinates::OneBased), (1, 20));
+        assert_eq!(loc.nt_interval(Coordinates::OneBased), Some((6225, 6284)));
+        assert_eq!(loc.regions[0].to_string(), "env 1-20 (partial)");
+
+        // A gag query is not located on the protein of env.
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["MGARASVLSGGELDRWEKIR".to_string()],
+                ..args.common
+            },
+            ..args
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten();
+        assert!(loc.is_none_or(|loc| loc.percent_identity < 50.0));
+
+        // The whole of Vpr with the end of Vif and the start of Tat, longer than Vpr, overhangs
+        // its ends rather than wrapping around it.
+        let vif = reference::protein_sequence("HXB2", "vif").unwrap();
+        let vpr = reference::protein_sequence("HXB2", "vpr").unwrap();
+        let tat = reference::protein_sequence("HXB2", "tat").unwrap();
+        let query = [&vif[vif.len() - 10..], vpr, &tat[..10]].concat();
+        let args = Args {
+            common: CommonArgs {
+                query: vec![String::from_utf8(query).unwrap()],
+                ..args.common
+            },
+            gene: Some("vpr".to_string()),
+            ..args
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert!(loc.segments.is_empty());
+        assert_eq!(loc.interval(Coordinates::OneBased), (1, vpr.len()));
+        assert_eq!(loc.query_start.get(), 11);
+    }
+
+    #[test]
+    fn test_matrix() {
+        // HXB2 Env 2701-2720 with conservative substitutions at two of every three residues.
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["DATYSDRSMDQVYNYSTYLE".to_string()],
+                type_query: "aa".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (2701, 2720));
+        let identity = Args {
+            common: CommonArgs {
+                matrix: Matrix::Identity,
+                ..args.common
+            },
+            ..args
+        };
+        let loc = Locator::build(&identity).unwrap().pop().flatten().unwrap();
+        assert_ne!(loc.ref_start.get(), 2701);
+        assert_eq!(Matrix::Blosum62.score()(b'W', b'W'), 11);
+        assert_eq!(Matrix::Blosum62.score()(b'W', b'#'), OUTSIDE_MATRIX_SCORE);
+    }
+
+    #[test]
+    fn test_locator_options() {
+        // HXB2 790-849 with 3 bases deleted after 829.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert!(loc.indel);
+        let gap_open = Args {
+            common: CommonArgs {
+                gap_open: Some(-30),
+                ..args.common
+            },
+            ..args
+        };
+        let loc = Locator::build(&gap_open).unwrap().pop().flatten().unwrap();
+        assert!(!loc.indel);
+
+        let options = LocatorOptions {
+            match_score: 2,
+            mismatch_score: -3,
+            ..Default::default()
+        };
+        let scoring = Scoring::new(&options, "nt", true);
+        assert_eq!(scoring.score(b'A', b'A'), 2);
+        assert_eq!(scoring.score(b'A', b'C'), -3);
+        assert_eq!((scoring.score(b'N', b'A'), scoring.gap_open), (0, -5));
+        let scoring = Scoring::new(&options, "aa", true);
+        assert_eq!((scoring.score(b'N', b'N'), scoring.gap_open), (6, -11));
+    }
+
+    #[test]
+    fn test_rna() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "AUGGGUGCGAGAGCGUCAGUAUUAAGCGGGGGAGAAUUAGAUCGAUGGGAAAAAAUUCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+        assert!(args.common.query[0].starts_with("AUGGGU"));
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (790, 849));
+        assert_eq!(loc.percent_identity, 100.0);
+        assert!(loc.rna);
+        assert!(loc.query_aligned_string.starts_with("ATGGGT"));
+        assert!(loc.query_aligned_as_given().starts_with("AUGGGU"));
+    }
+
+    #[test]
+    fn test_iupac_aware() {
+        // HXB2 790-849 with R, W and N in place of G, A and C.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATRGGTGCGAGAGCGTCAGTWTTAAGCGGGGGAGAATTAGATNGATGGGAAAAAATTCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.percent_identity, 95.0);
+        let iupac = Args {
+            iupac_aware: true,
+            ..args
+        };
+        let loc = Locator::build(&iupac).unwrap().pop().flatten().unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (790, 849));
+        assert_eq!(loc.percent_identity, 100.0);
+        assert_eq!(&loc.query_aligned_string[..3], "ATR");
+
+        let options = LocatorOptions {
+            iupac_aware: true,
+            ..Default::default()
+        };
+        let scoring = Scoring::new(&options, "nt", false);
+        assert_eq!(scoring.score(b'R', b'G'), 1);
+        assert_eq!(scoring.score(b'R', b'C'), -1);
+        assert_eq!(scoring.score(b'N', b'T'), 1);
+        let scoring = Scoring::new(&options, "aa", false);
+        assert_eq!(scoring.score(b'R', b'G'), -2);
+    }
+
+    #[test]
+    fn test_linear_space() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let full = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let linear = Args {
+            max_alignment_memory: 0,
+            ..args
+        };
+        let loc = Locator::build(&linear).unwrap().pop().flatten().unwrap();
+        assert_eq!((loc.ref_start, loc.ref_end), (full.ref_start, full.ref_end));
+        assert_eq!(loc.percent_identity, full.percent_identity);
+        assert_eq!(loc.cigar(), "40M3D17M");
+
+        let scoring = Scoring {
+            max_cells: 0,
+            ..Scoring::default()
+        };
+        let query = [&SMALL_REF[40..], &SMALL_REF[..30]].concat();
+        let loc = circular(&query, SMALL_REF, scoring, &BioBackend)
+            .unwrap()
+            .unwrap();
+        assert_eq!((loc.ref_start.get(), loc.ref_end.get()), (41, 30));
+    }
+
+    #[test]
+    fn test_cigar() {
+        // HXB2 790-849 with 3 bases deleted after 829.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.cigar(), "40M3D17M");
+        let overhang = Locator::new(
+            RefPosition::new(1),
+            RefPosition::new(7),
+            85.71428571428571,
+            true,
+            "CCTGGA-GGGA".to_string(),
+            "--TGGAAGG--".to_string(),
+        );
+        assert_eq!(overhang.cigar(), "2S4M1D2M2S");
+    }
+
+    #[test]
+    fn test_variants() {
+        let overhang = Locator::new(
+            RefPosition::new(1),
+            RefPosition::new(7),
+            85.71428571428571,
+            true,
+            "CCTGGA-GGGA".to_string(),
+            "--TGGAAGG--".to_string(),
+        );
+        assert_eq!(
+            overhang.variants(),
+            vec![Variant {
+                kind: VariantKind::Deletion,
+                ref_position: RefPosition::new(5),
+                query_position: QueryPosition::new(6),
+                ref_bases: "A".to_string(),
+                query_bases: String::new(),
+            }]
+        );
+        let loc = Locator::new(
+            RefPosition::new(11),
+            RefPosition::new(16),
+            62.5,
+            true,
+            "AGGTTTCA".to_string(),
+            "ACG--TCA".to_string(),
+        );
+        assert_eq!(
+            loc.variants(),
+            vec![
+                Variant {
+                    kind: VariantKind::Substitution,
+                    ref_position: RefPosition::new(12),
+                    query_position: QueryPosition::new(2),
+                    ref_bases: "C".to_string(),
+                    query_bases: "G".to_string(),
+                },
+                Variant {
+                    kind: VariantKind::Insertion,
+                    ref_position: RefPosition::new(13),
+                    query_position: QueryPosition::new(4),
+                    ref_bases: String::new(),
+                    query_bases: "TT".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identity_mode() {
+        // HXB2 790-849 with 3 bases deleted after 829.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.percent_identity, 95.0);
+        assert_eq!(loc.identity_mode, IdentityMode::Gapped);
+        let ungapped = Args {
+            identity_mode: Some(IdentityMode::Ungapped),
+            ..args
+        };
+        let loc = Locator::build(&ungapped).unwrap().pop().flatten().unwrap();
+        assert_eq!(loc.percent_identity, 100.0);
+        assert_eq!(loc.identity_mode, IdentityMode::Ungapped);
+
+        let columns: Vec<(u8, u8)> = b"CCTGGA-GGGA"
+            .iter()
+            .copied()
+            .zip(*b"--TGGAAGG--")
+            .collect();
+        assert_eq!(
+            IdentityMode::Gapped.identity(&columns),
+            Some(6.0 / 11.0 * 100.0)
+        );
+        assert_eq!(IdentityMode::Ungapped.identity(&columns), Some(100.0));
+        assert_eq!(
+            IdentityMode::Blast.identity(&columns),
+            Some(6.0 / 7.0 * 100.0)
+        );
+        assert_eq!(IdentityMode::Blast.identity(&columns[..2]), None);
+    }
+
+    #[test]
+    fn test_not_located() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![String::new(), "NNNNNNNN".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let results = Locator::try_build(&args).unwrap();
+        assert_eq!(
+            results[0],
+            LocateOutcome::NotFound(NotLocated {
+                screening: Some(Screening {
+                    verdict: ScreeningVerdict::TooShort,
+                    k: 8,
+                    shared_kmers: 0,
+                    query_kmers: 0,
+                }),
+                ..NotLocated::new(NotLocatedReason::EmptyQuery)
+            })
+        );
+        let not_located = results[1].clone().into_result().unwrap_err();
+        assert_eq!(not_located.reason, NotLocatedReason::NoMatches);
+        assert_eq!(not_located.best_score, Some(-8));
+        assert_eq!(
+            not_located.screening.unwrap().verdict,
+            ScreeningVerdict::Unrelated
+        );
+        assert!(
+            not_located
+                .to_string()
+                .ends_with(", score -8); the query shares no 8-mer with the reference")
+        );
+        assert_eq!(not_located.best.unwrap().ref_span, 8);
+        assert_eq!(Locator::build(&args).unwrap(), vec![None, None]);
+    }
+
+    #[test]
+    fn test_min_identity_and_aln_length() {
+        // HXB2 1001-1080 with a substitution at 1041.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAACACTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC"
+                    .to_string(),
+            ],
+                ..Default::default()
+            },
+            min_identity: Some(99.0),
+            ..Default::default()
+        };
+        let not_located = Locator::try_build(&args).unwrap()[0]
+            .clone()
+            .into_result()
+            .unwrap_err();
+        assert_eq!(not_located.reason, NotLocatedReason::BelowMinIdentity);
+        let best = not_located.best.unwrap();
+        assert_eq!((best.ref_start.get(), best.ref_end.get()), (1001, 1080));
+        assert_eq!(not_located.best_score, best.score);
+
+        let args = Args {
+            min_identity: Some(98.0),
+            min_aln_length: Some(81),
+            ..args
+        };
+        let not_located = Locator::try_build(&args).unwrap()[0]
+            .clone()
+            .into_result()
+            .unwrap_err();
+        assert_eq!(not_located.reason, NotLocatedReason::BelowMinAlnLength);
+
+        let args = Args {
+            min_aln_length: Some(80),
+            ..args
+        };
+        assert!(Locator::try_build(&args).unwrap()[0].is_found());
+    }
+
+    #[test]
+    fn test_build_iter() {
+        let mut query = vec!["ATGCATGCATGC".to_string(); BUILD_ITER_CHUNK_SIZE];
+        query.push("NNNNNNNN".to_string());
+        let args = Args {
+            common: CommonArgs {
+                query_name: (1..=query.len()).map(|i| format!("q{}", i)).collect(),
+                query,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let records = Locator::build_iter(&args)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), BUILD_ITER_CHUNK_SIZE + 1);
+        assert!(records.iter().enumerate().all(|(i, r)| r.index == i));
+        let outcomes = records.into_iter().map(|r| r.outcome).collect::<Vec<_>>();
+        assert_eq!(outcomes, Locator::try_build(&args).unwrap());
+        let last = outcomes[BUILD_ITER_CHUNK_SIZE - 1].locator().unwrap();
+        assert_eq!(last.query_id, Some(format!("q{}", BUILD_ITER_CHUNK_SIZE)));
+
+        let args = Args {
+            common: CommonArgs {
+                reference: "unknown".to_string(),
+                ..args.common
+            },
+            ..args
+        };
+        let records = Locator::build_iter(&args).collect::<Vec<_>>();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_err());
+    }
+
+    #[test]
+    fn test_engine() {
+        let queries = [
+            MY_ARGS.0,
+            MY_ARGS2.0,
+            "ATGCATGCATGC",
+            "MGARASVLSGGELDKWEKIRLRPGG",
+        ];
+        let args = Args {
+            common: CommonArgs {
+                type_query: "auto".to_string(),
+                ..Default::default()
+            },
+            annotate: true,
+            ..Default::default()
+        };
+        let engine = LocatorEngine::new("hxb2", &args).unwrap();
+        assert_eq!(engine.reference(), "HXB2");
+        // Twice, so the second round aligns with the aligners of the first one.
+        for _ in 0..2 {
+            for query in queries {
+                let expected = Locator::try_build(&Args {
+                    common: CommonArgs {
+                        query: vec![query.to_string()],
+                        ..args.common.clone()
+                    },
+                    ..args.clone()
+                })
+                .unwrap();
+                assert_eq!(engine.locate(query).unwrap(), expected[0]);
+            }
+        }
+        assert!(engine.locate("ATGC1234").is_err());
+        assert!(LocatorEngine::new("unknown", &args).is_err());
+        assert!(
+            LocatorEngine::new(
+                "HXB2",
+                &Args {
+                    common: CommonArgs {
+                        algorithm: 4,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+            )
+            .is_err()
+        );
+
+        // The amplicon scheme is read once, when the engine is built.
+        let scheme = std::env::temp_dir().join(format!(
+            "virust-locator-engine-scheme-{}.tsv",
+            std::process::id()
+        ));
+        std::fs::write(&scheme, "amp1\t1300\t1400\n").unwrap();
+        let args = Args {
+            annotation: AnnotationArgs {
+                amplicon_scheme: Some(scheme.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let engine = LocatorEngine::new("HXB2", &args).unwrap();
+        std::fs::remove_file(&scheme).unwrap();
+        let loc = engine
+            .locate("ATGCATGCATGC")
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(loc.amplicons[0].amplicon, "amp1");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async() {
+        let args = Args {
+            common: CommonArgs {
+                query: vec!["ATGCATGCATGC".to_string(), String::new()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = Locator::try_build(&args).unwrap();
+        assert_eq!(Locator::try_build_async(args).await.unwrap(), expected);
+
+        let engine = LocatorEngine::new("HXB2", &Args::default()).unwrap();
+        let outcome = engine.locate_async("ATGCATGCATGC").await.unwrap();
+        assert_eq!(outcome, expected[0]);
+        assert!(engine.locate_async("ATGCXYZ").await.is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_with_threads() {
+        let threads = with_threads(Some(2), || {
+            // Nested calls run on the same pool.
+            with_threads(Some(2), rayon::current_num_threads).unwrap()
+        });
+        assert_eq!(threads.unwrap(), 2);
+        assert_eq!(
+            with_threads(None, rayon::current_num_threads).unwrap(),
+            rayon::current_num_threads()
+        );
+
+        let args = Args {
+            common: CommonArgs {
+                query: vec![MY_ARGS.0.to_string(), "ATGCATGCATGC".to_string()],
+                threads: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = Locator::try_build(&Args {
+            common: CommonArgs {
+                threads: None,
+                ..args.common.clone()
+            },
+            ..args.clone()
+        })
+        .unwrap();
+        assert_eq!(Locator::try_build(&args).unwrap(), expected);
+        let engine = LocatorEngine::new("HXB2", &args).unwrap();
+        assert_eq!(engine.locate("ATGCATGCATGC").unwrap(), expected[1]);
+    }
 }
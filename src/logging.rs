@@ -0,0 +1,88 @@
+//! Structured logging with [tracing](https://docs.rs/tracing).
+//!
+//! Every query located by the CLI, the batch and pipe modes, the servers and the consumers is
+//! logged as an `INFO` event with the fields:
+//!
+//! - `event`: `locate`.
+//! - `query_id`: the record or request id, or the `--query-name` or 1-based position of a
+//!   `--query`.
+//! - `reference`, `algorithm`: the location parameters.
+//! - `found`: whether the query was located.
+//! - `duration_ms`: the time spent validating and locating the query. For `--query`, all queries
+//!   are located together and share the duration of the run.
+//! - `error`: why the query is invalid, if it is.
+//!
+//! `init` installs a subscriber writing the events to stderr, either as human-readable lines or as
+//! one JSON object per line for ingestion into ELK, Loki and similar systems. The level is set with
+//! the `RUST_LOG` environment variable (e.g. `RUST_LOG=debug`); by default, text logs only show
+//! warnings and errors, so the regular output is unchanged, and JSON logs include the `INFO`
+//! location events.
+
+use std::fmt::Display;
+use std::time::Instant;
+use tracing_subscriber::EnvFilter;
+
+/// Format of the log records written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Installs the global subscriber writing log records to stderr in `format`. Does nothing if a
+/// subscriber is already installed.
+pub fn init(format: LogFormat) {
+    let default_level = match format {
+        LogFormat::Text => "warn",
+        LogFormat::Json => "info",
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(false)
+            .try_init(),
+    };
+}
+
+/// Logs the location of one query, started at `started`. `outcome` is whether the query was
+/// located, or why it is invalid.
+pub fn locate_event(
+    query_id: &str,
+    reference: &str,
+    algorithm: u8,
+    started: Instant,
+    outcome: Result<bool, &dyn Display>,
+) {
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match outcome {
+        Ok(found) => tracing::info!(
+            event = "locate",
+            query_id,
+            reference,
+            algorithm,
+            found,
+            duration_ms
+        ),
+        Err(error) => tracing::info!(
+            event = "locate",
+            query_id,
+            reference,
+            algorithm,
+            found = false,
+            duration_ms,
+            error = %error
+        ),
+    }
+}
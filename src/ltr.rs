@@ -0,0 +1,186 @@
+//! The features of the long terminal repeats (LTRs) located in the queries: the U3, R and U5
+//! regions, the TAR stem-loop and the polyadenylation signal.
+//!
+//! With `--ltr-features`, the features of `LTR_FEATURES` overlapped by a location on HXB2 are
+//! attached to it as `LtrFeature`s (see `Locator::ltr_features`), with the LTR they are in.
+//!
+//! The two LTRs are copies of the same sequence (in HXB2, they differ at 8 positions of U3): a
+//! location within one of them, such as an LTR amplicon, could be placed in either, and the
+//! alignment picks one. Such a location is flagged `ltr_ambiguous`, and its features are reported
+//! in both LTRs. A location reaching into the genome between the LTRs, or across the 2-LTR circle
+//! junction, is placed unambiguously.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{AnnotationArgs, Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//!
+//! // HXB2 430-490, the end of U3 and the start of R, in either LTR.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["AAGCAGCTGCTTTTTGCCTGTACTGGGTCTCTCTGGTTAGACCAGATCTGAGCCTGGGAGC".to_string()],
+//!         ..Default::default()
+//!     },
+//!     annotation: AnnotationArgs {
+//!         ltr_features: true,
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! assert!(loc.ltr_ambiguous);
+//! let features: Vec<String> = loc.ltr_features.iter().map(ToString::to_string).collect();
+//! assert_eq!(features[0], "5LTR U3 430-454 (partial)");
+//! ```
+
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::reference::{self, Gene};
+use crate::sequence_locator::{self, Region};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// The reference of `LTR_FEATURES`.
+pub const REFERENCE: &str = "HXB2";
+
+/// The features of an LTR, in order: their names and first and last bases in the 5' LTR of HXB2,
+/// as in the Los Alamos HXB2 landmarks. R starts at the transcription start site, HXB2 455; TAR
+/// is the stem-loop of the first 59 bases of the transcripts, and `polyA` the AATAAA
+/// polyadenylation signal, used in the 3' LTR. Those of the 3' LTR are at the same positions from
+/// its start.
+pub static LTR_FEATURES: [(&str, usize, usize); 5] = [
+    ("U3", 1, 454),
+    ("R", 455, 551),
+    ("TAR", 455, 513),
+    ("polyA", 527, 532),
+    ("U5", 552, 634),
+];
+
+/// A feature of an LTR overlapped by a location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LtrFeature {
+    /// The LTR, `5LTR` or `3LTR`, as in `reference::GENES`.
+    pub ltr: String,
+    /// The feature, e.g. `TAR`.
+    pub feature: String,
+    /// First covered position of the feature on the reference.
+    pub ref_start: RefPosition,
+    /// Last covered position of the feature on the reference (inclusive).
+    pub ref_end: RefPosition,
+    /// Whether the whole feature is covered.
+    pub complete: bool,
+}
+
+impl Display for LtrFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}-{}",
+            self.ltr, self.feature, self.ref_start, self.ref_end
+        )?;
+        if !self.complete {
+            write!(f, " (partial)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The LTRs of `REFERENCE`, 5' first.
+fn ltrs() -> Vec<&'static Gene<'static>> {
+    reference::genes(REFERENCE, "nt")
+        .filter(|gene| gene.name.ends_with("LTR"))
+        .collect()
+}
+
+/// The features of `LTR_FEATURES` in `ltr`, as regions on the genome.
+pub fn regions(ltr: &Gene) -> Vec<Region> {
+    let offset = ltr.start.to_zero_based();
+    LTR_FEATURES
+        .iter()
+        .map(|&(name, start, end)| Region::new(name, offset + start, offset + end))
+        .collect()
+}
+
+/// Returns the LTR features overlapped by `loc` on HXB2, in the order of the genome, and whether
+/// the location is within one LTR and could be placed in either: its features are then those of
+/// both LTRs, at the same positions from their starts.
+pub fn locate(loc: &Locator) -> (Vec<LtrFeature>, bool) {
+    let ltrs = ltrs();
+    let segments = loc.ref_segments();
+    let placed = ltrs.iter().find(|ltr| {
+        segments
+            .iter()
+            .all(|segment| ltr.start <= segment.start && segment.end <= ltr.end)
+    });
+    let mut features = Vec::new();
+    for &ltr in &ltrs {
+        // The features are found in the LTR of the location, and moved to the same positions in
+        // this one.
+        let located = placed.copied().unwrap_or(ltr);
+        let shift =
+            |p: RefPosition| RefPosition::new(p.get() + ltr.start.get() - located.start.get());
+        features.extend(
+            sequence_locator::overlaps(loc, &regions(located))
+                .into_iter()
+                .map(|overlap| LtrFeature {
+                    ltr: ltr.name.to_string(),
+                    feature: overlap.region,
+                    ref_start: shift(overlap.start),
+                    ref_end: shift(overlap.end),
+                    complete: overlap.complete,
+                }),
+        );
+    }
+    (features, placed.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(ref_start: usize, ref_end: usize) -> Locator {
+        let len = ref_end - ref_start + 1;
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_end),
+            100.0,
+            false,
+            "A".repeat(len),
+            "A".repeat(len),
+        )
+    }
+
+    #[test]
+    fn test_regions() {
+        let ltrs = ltrs();
+        assert_eq!(regions(ltrs[0])[1], Region::new("R", 455, 551));
+        assert_eq!(regions(ltrs[1])[1], Region::new("R", 9540, 9636));
+        assert_eq!(regions(ltrs[1])[4].end, ltrs[1].end);
+    }
+
+    #[test]
+    fn test_locate() {
+        // TAR and the polyadenylation signal, in the 3' LTR or the 5' LTR.
+        let (features, ambiguous) = locate(&location(9540, 9620));
+        assert!(ambiguous);
+        let features: Vec<String> = features.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            features,
+            [
+                "5LTR R 455-535 (partial)",
+                "5LTR TAR 455-513",
+                "5LTR polyA 527-532",
+                "3LTR R 9540-9620 (partial)",
+                "3LTR TAR 9540-9598",
+                "3LTR polyA 9612-9617",
+            ]
+        );
+
+        // The end of the 5' LTR and the start of gag.
+        let (features, ambiguous) = locate(&location(600, 900));
+        assert!(!ambiguous);
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0].to_string(), "5LTR U5 600-634 (partial)");
+        assert!(locate(&location(2000, 2100)).0.is_empty());
+    }
+}
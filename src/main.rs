@@ -1,27 +1,797 @@
 use clap::Parser;
-use virust_locator::{config::Args, locator};
+use std::io::Write;
+use virust_locator::artifacts::ArtifactKind;
+use virust_locator::config::{Args, Cli, Command, ConvertArgs, IndexArgs, ServeArgs};
+use virust_locator::format::{Formatter, ResultFilter, ResultMapper};
+use virust_locator::position::RefPosition;
+use virust_locator::{
+    BoxError, batch, bed, coords, depth, format, gff, index_cache, input, intactness, lanl,
+    locator, logging, pipe, pretty, primers, reference, resistance, sam, storage, subtype, summary,
+    vcf, verify, workflow,
+};
 
 fn main() {
-    let args = Args::parse().validate().unwrap_or_else(|err| {
-        eprintln!("{} {}", "\x1b[1;91mError:\x1b[0m", err);
+    let args = match Cli::parse().into_command() {
+        Command::Locate(args) => *args,
+        Command::Annotate(args) => Args::from(*args),
+        Command::Intactness(args) => Args::from(*args),
+        Command::Subtype(args) => Args::from(*args),
+        Command::Primers(args) => Args::from(*args),
+        Command::ListReferences => {
+            list_references().unwrap_or_else(|err| {
+                eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+                std::process::exit(1);
+            });
+            return;
+        }
+        Command::Index(args) => {
+            index(&args).unwrap_or_else(|err| {
+                eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+                std::process::exit(1);
+            });
+            return;
+        }
+        Command::Convert(args) => {
+            convert(&args).unwrap_or_else(|err| {
+                eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+                std::process::exit(1);
+            });
+            return;
+        }
+        Command::Serve(args) => {
+            serve(args).unwrap_or_else(|err| {
+                eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+                std::process::exit(1);
+            });
+            return;
+        }
+    };
+    let args = args.validate().unwrap_or_else(|err| {
+        eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
         std::process::exit(1);
     });
+    logging::init(args.common.log_format);
 
-    let loc: Vec<Option<locator::Locator>> = locator::Locator::build(&args).unwrap_or_else(|err| {
-        eprintln!("{} {}", "\x1b[1;91mError:\x1b[0m", err);
+    // Every mode locates its queries on the pool of --threads.
+    locator::with_threads(args.common.threads, move || run(args)).unwrap_or_else(|err| {
+        eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
         std::process::exit(1);
     });
+}
+
+fn run(args: Args) {
+    if args.pipe {
+        pipe::run(std::io::stdin().lock(), std::io::stdout().lock(), &args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if args.workflow {
+        let outdir = args.outdir.clone().unwrap_or_default();
+        match workflow::run(&args.common.input, &outdir, &args) {
+            Ok(manifest) => std::process::exit(manifest.exit_code),
+            Err(err) => {
+                eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+                std::process::exit(workflow::EXIT_TOOL_FAILURE);
+            }
+        }
+    }
+
+    if args.annotate_dr {
+        annotate_dr(&args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if args.intactness {
+        screen_intactness(&args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if args.subtype {
+        classify_subtypes(&args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if args.primers {
+        map_primers(&args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if !args.common.input.is_empty() {
+        locate_inputs(&args).unwrap_or_else(|err| {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+            std::process::exit(1);
+        });
+        return;
+    }
+
+    if args.annotation.format == Some(format::OutputFormat::Ndjson) {
+        exit_unless_found(write_output(&args, |out| print_ndjson(&args, out)));
+        return;
+    }
 
-    print_loc_vec(loc);
+    let started = std::time::Instant::now();
+    let verified = if args.verify {
+        verify::try_build_verified(&args, args.tolerance())
+    } else {
+        locator::Locator::try_build(&args)
+            .map(|loc| loc.into_iter().map(|l| (l, Vec::new())).collect())
+    };
+    let verified = verified.unwrap_or_else(|err| {
+        eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
+        std::process::exit(1);
+    });
+    let ids = args.query_ids();
+    let mut loc = Vec::with_capacity(verified.len());
+    for (i, (l, disagreements)) in verified.into_iter().enumerate() {
+        if args.common.type_query == "auto" {
+            eprintln!(
+                "\x1b[1;94mInfo:\x1b[0m query {}: inferred type {}",
+                ids[i],
+                args.type_of(&args.common.query[i])
+            );
+        }
+        let warnings = l.locator().map_or(&[][..], |loc| &loc.warnings);
+        for warning in warnings.iter().filter(|w| w.kind == ArtifactKind::Stop) {
+            eprintln!("\x1b[1;93mWarning:\x1b[0m query {}: {}", ids[i], warning);
+        }
+        for disagreement in disagreements {
+            eprintln!(
+                "\x1b[1;93mWarning:\x1b[0m query {}: {}",
+                ids[i], disagreement
+            );
+        }
+        if let Some(algorithm) = l.locator().and_then(|loc| loc.algorithm) {
+            eprintln!(
+                "\x1b[1;94mInfo:\x1b[0m query {}: chose algorithm {}",
+                ids[i], algorithm
+            );
+        }
+        loc.push(l);
+    }
+    for (i, l) in loc.iter().enumerate() {
+        logging::locate_event(
+            &ids[i],
+            &args.common.reference,
+            args.common.algorithm,
+            started,
+            Ok(l.is_found()),
+        );
+    }
+
+    let found = write_output(&args, |out| print_locations(loc, &ids, &args, out));
+    exit_unless_found(found);
 }
 
-fn print_loc_vec(loc: Vec<Option<locator::Locator>>) {
-    for l in loc {
-        if l.is_none() {
-            eprintln!("{} {}", "\x1b[1;91mError:\x1b[0m", "Locator not found");
+/// Exits with status 1 unless every query was `found` and written.
+fn exit_unless_found(found: Result<bool, BoxError>) {
+    match found {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("\x1b[1;91mError:\x1b[0m {}", err);
             std::process::exit(1);
+        }
+    }
+}
+
+/// Writes the results of `write` to `--output`, replaced only once they are complete, or to
+/// stdout.
+fn write_output<R>(
+    args: &Args,
+    write: impl FnOnce(&mut dyn Write) -> Result<R, BoxError>,
+) -> Result<R, BoxError> {
+    match &args.common.output {
+        Some(output) => {
+            let mut writer = storage::create(output)?;
+            let written = write(&mut writer)?;
+            writer.finish()?;
+            Ok(written)
+        }
+        None => write(&mut std::io::stdout().lock()),
+    }
+}
+
+fn locate_inputs(args: &Args) -> Result<(), BoxError> {
+    let (formatter, mut filters) = load_plugins(args)?;
+    let (script_filters, mappers) = load_scripts(args)?;
+    filters.extend(script_filters);
+    if let Some(coverage) = args.min_query_coverage {
+        filters.push(Box::new(format::MinQueryCoverage(coverage)));
+    }
+    let mut reports = RunReports::new(args)?;
+    if args.annotation.format == Some(format::OutputFormat::Ndjson) && args.postgres.is_none() {
+        // Every result is written as soon as its chunk is located.
+        write_output(args, |out| {
+            for result in batch::locate_records_iter(read_inputs(args)?, args) {
+                let results = format::apply_filters(vec![result], &filters)?;
+                let results = format::apply_mappers(results, &mappers)?;
+                reports.add(&results);
+                formatter.write(out, &results)?;
+            }
+            Ok(())
+        })?;
+        return reports.write(args);
+    }
+    let results = batch::locate_records(read_inputs(args)?, args);
+    let results = format::apply_filters(results, &filters)?;
+    let results = format::apply_mappers(results, &mappers)?;
+    reports.add(&results);
+    if let Some(url) = &args.postgres {
+        write_postgres(url, &results, args)?;
+    }
+    if args.common.output.is_some() || args.postgres.is_none() {
+        write_output(args, |out| formatter.write(out, &results))?;
+    }
+    reports.write(args)
+}
+
+/// The run-level reports of the results of `--input`, accumulated as they are located: the
+/// summary of `--summary` and the depth track of `--depth-track`.
+struct RunReports {
+    summarizer: Option<summary::Summarizer>,
+    depths: Option<depth::DepthTrack>,
+}
+
+impl RunReports {
+    fn new(args: &Args) -> Result<Self, BoxError> {
+        Ok(RunReports {
+            summarizer: (args.summary || args.summary_file.is_some())
+                .then(|| summary::Summarizer::new(&args.common.reference, &args.common.type_query))
+                .transpose()?,
+            depths: args
+                .depth_track
+                .as_ref()
+                .map(|_| depth::DepthTrack::new(&args.common.reference, &args.common.type_query))
+                .transpose()?,
+        })
+    }
+
+    fn add(&mut self, results: &[batch::BatchResult]) {
+        for result in results {
+            if let Some(summarizer) = &mut self.summarizer {
+                summarizer.add(result);
+            }
+            if let (Some(depths), Some(loc)) = (&mut self.depths, &result.locator) {
+                depths.add(loc);
+            }
+        }
+    }
+
+    /// Writes the summary to `--summary-file`, as JSON or as tab-separated values if its name
+    /// ends in `.tsv`, or to stderr as tab-separated values, and the depth track to
+    /// `--depth-track`, see the `summary` and `depth` modules.
+    fn write(self, args: &Args) -> Result<(), BoxError> {
+        if let Some(summarizer) = self.summarizer {
+            let summary = summarizer.finish();
+            match &args.summary_file {
+                Some(path) => {
+                    let tsv = path
+                        .to_string_lossy()
+                        .to_ascii_lowercase()
+                        .ends_with(".tsv");
+                    let mut writer = storage::create(path)?;
+                    if tsv {
+                        summary::write_tsv(&mut writer, &summary)?;
+                    } else {
+                        serde_json::to_writer_pretty(&mut writer, &summary)?;
+                        writeln!(writer)?;
+                    }
+                    writer.finish()?;
+                }
+                None => summary::write_tsv(std::io::stderr().lock(), &summary)?,
+            }
+        }
+        if let (Some(depths), Some(path)) = (self.depths, &args.depth_track) {
+            let chrom = reference::resolve_reference(&args.common.reference)?;
+            let mut writer = storage::create(path)?;
+            if depth::is_bedgraph(path) {
+                depth::write_bedgraph(&mut writer, chrom, &depths.depths())?;
+            } else {
+                depth::write_tsv(&mut writer, chrom, &depths.depths())?;
+            }
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_inputs(args: &Args) -> Result<Vec<input::Record>, BoxError> {
+    let mut records = Vec::new();
+    for path in &args.common.input {
+        records.extend(input::read_records_with(
+            storage::open(path)?,
+            args.common.strict_parse,
+        )?);
+    }
+    Ok(records)
+}
+
+/// The locations of the queries of `--query` or `--input`, with their IDs.
+fn locate_queries(args: &Args) -> Result<Vec<(String, Option<locator::Locator>)>, BoxError> {
+    Ok(if args.common.input.is_empty() {
+        locator::Locator::try_build(args)?
+            .into_iter()
+            .zip(args.query_ids())
+            .map(|(outcome, id)| (id, outcome.into_result().ok()))
+            .collect()
+    } else {
+        batch::locate_records(read_inputs(args)?, args)
+            .into_iter()
+            .map(|result| (result.id, result.locator))
+            .collect()
+    })
+}
+
+/// Writes the drug resistance positions covered by the queries of `--query` or the records of
+/// `--input` to `--output` or stdout, see the `resistance` module.
+fn annotate_dr(args: &Args) -> Result<(), BoxError> {
+    let located = locate_queries(args)?;
+    write_output(args, |out| {
+        writeln!(out, "{}", resistance::TSV_HEADER)?;
+        for (id, loc) in located {
+            match loc {
+                Some(loc) => {
+                    let calls = resistance::annotate(&loc, &args.common.reference);
+                    resistance::write_tsv(&mut *out, &id, &calls)?;
+                }
+                None => eprintln!("\x1b[1;93mWarning:\x1b[0m query {}: not located", id),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes the intactness of the queries, see the `intactness` module.
+fn screen_intactness(args: &Args) -> Result<(), BoxError> {
+    let located = locate_queries(args)?;
+    write_output(args, |out| {
+        writeln!(out, "{}", intactness::TSV_HEADER)?;
+        for (id, loc) in located {
+            match loc {
+                Some(loc) => intactness::write_tsv(&mut *out, &id, &intactness::screen(&loc))?,
+                None => eprintln!("\x1b[1;93mWarning:\x1b[0m query {}: not located", id),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes the closest subtype of the queries of `--query` or the records of `--input` in
+/// `--subtype-panel`, see the `subtype` module.
+fn classify_subtypes(args: &Args) -> Result<(), BoxError> {
+    let path = args
+        .panel
+        .subtype_panel
+        .as_ref()
+        .ok_or("--subtype requires --subtype-panel")?;
+    let panel = subtype::Panel::read(storage::open(path)?)?;
+    let (ids, queries): (Vec<String>, Vec<String>) = if args.common.input.is_empty() {
+        (args.query_ids(), args.common.query.clone())
+    } else {
+        read_inputs(args)?
+            .into_iter()
+            .map(|record| (record.id, record.sequence))
+            .unzip()
+    };
+    let classifications = subtype::classify_all(&queries, &panel, &args.locator_options())?;
+    write_output(args, |out| {
+        writeln!(out, "{}", subtype::TSV_HEADER)?;
+        for (id, classification) in ids.iter().zip(classifications) {
+            match classification {
+                Some(classification) => subtype::write_tsv(&mut *out, id, &classification)?,
+                None => eprintln!("\x1b[1;93mWarning:\x1b[0m query {}: not aligned", id),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes the binding sites on the reference of the primers of `--query` or the records of
+/// `--input`, see the `primers` module.
+fn map_primers(args: &Args) -> Result<(), BoxError> {
+    let ref_seq = reference::retrieve_reference_sequence(&args.common.reference, "nt")?.sequence;
+    let (ids, queries): (Vec<String>, Vec<String>) = if args.common.input.is_empty() {
+        (args.query_ids(), args.common.query.clone())
+    } else {
+        read_inputs(args)?
+            .into_iter()
+            .map(|record| (record.id, record.sequence))
+            .unzip()
+    };
+    let sites = primers::map_all(&ids, &queries, ref_seq, args.primer.max_primer_mismatches)?;
+    write_output(args, |out| {
+        writeln!(out, "{}", primers::TSV_HEADER)?;
+        for (id, sites) in ids.iter().zip(sites) {
+            if sites.is_empty() {
+                eprintln!("\x1b[1;93mWarning:\x1b[0m primer {}: no binding site", id);
+            }
+            for site in &sites {
+                primers::write_tsv(&mut *out, id, site)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes the reference sequences of `--reference`, one per sequence type, with their lengths and
+/// the other names accepted for them.
+fn list_references() -> Result<(), BoxError> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "reference\ttype\tlength\taliases")?;
+    for ref_seq in &reference::REFS {
+        let aliases = reference::aliases(ref_seq.strain).collect::<Vec<&str>>();
+        writeln!(
+            stdout,
+            "{}\t{}\t{}\t{}",
+            ref_seq.strain,
+            ref_seq.sequence_type,
+            ref_seq.sequence.len(),
+            aliases.join(",")
+        )?;
+    }
+    Ok(())
+}
+
+/// Builds the k-mer indexes of `args` and writes them to the cache directory, printing their
+/// paths.
+fn index(args: &IndexArgs) -> Result<(), BoxError> {
+    let dir = match &args.index_cache {
+        Some(dir) => dir.clone(),
+        None => index_cache::default_dir()
+            .ok_or("no cache directory without HOME or XDG_CACHE_HOME, set --index-cache")?,
+    };
+    let strain = reference::resolve_reference(&args.reference)?;
+    let mut stdout = std::io::stdout().lock();
+    for type_query in &args.type_query {
+        let path = index_cache::build(&dir, strain, type_query)?;
+        writeln!(stdout, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Converts the positions of `args` between the reference and the gene, see `Gene::relative`.
+fn convert(args: &ConvertArgs) -> Result<(), BoxError> {
+    let strain = reference::resolve_reference(&args.reference)?;
+    let length = reference::retrieve_reference_sequence(strain, &args.type_query)?
+        .sequence
+        .len();
+    if args.codons {
+        return convert_codons(args, strain, length);
+    }
+    let name = args.gene.as_deref().unwrap_or_default();
+    let gene = reference::retrieve_gene(strain, &args.type_query, name)?;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "ref_position\tgene\tgene_position")?;
+    for &position in &args.positions {
+        let (ref_position, gene_position) = if args.from_gene {
+            let ref_position = gene
+                .absolute(position)
+                .filter(|p| p.get() <= length)
+                .ok_or_else(|| {
+                    format!(
+                        "Position {} of {} is outside {}",
+                        position, gene.name, strain
+                    )
+                })?;
+            (ref_position, position)
         } else {
-            println!("{}", l.unwrap());
+            if !(1..=length as i64).contains(&position) {
+                return Err(
+                    format!("Position {} is outside {} (1-{})", position, strain, length).into(),
+                );
+            }
+            let ref_position = RefPosition::new(position as usize);
+            (ref_position, gene.relative(ref_position))
+        };
+        writeln!(stdout, "{}\t{}\t{}", ref_position, gene.name, gene_position)?;
+    }
+    Ok(())
+}
+
+/// Converts the positions of `args` to the codons of the genes covering them, or the codons of
+/// `--from-gene` to positions, see the `coords` module.
+fn convert_codons(args: &ConvertArgs, strain: &str, length: usize) -> Result<(), BoxError> {
+    if args.type_query != "nt" {
+        return Err(BoxError::from("--codons requires nucleotide positions"));
+    }
+    let gene = args.gene.as_deref();
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "ref_position\tgene\tcodon\tcodon_position")?;
+    for &position in &args.positions {
+        let bases = match gene {
+            Some(gene) if args.from_gene => {
+                let codon = usize::try_from(position)
+                    .map_err(|_| format!("Codon {} is outside {}", position, gene))?;
+                coords::gene_codon_to_reference(strain, gene, codon)?.to_vec()
+            }
+            _ => {
+                if !(1..=length as i64).contains(&position) {
+                    return Err(format!(
+                        "Position {} is outside {} (1-{})",
+                        position, strain, length
+                    )
+                    .into());
+                }
+                vec![RefPosition::new(position as usize)]
+            }
+        };
+        for base in bases {
+            let codons = coords::reference_to_genes(strain, base);
+            let codons = codons
+                .iter()
+                .filter(|codon| gene.is_none_or(|gene| codon.gene.eq_ignore_ascii_case(gene)));
+            for codon in codons {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}\t{}",
+                    base, codon.gene, codon.codon, codon.codon_position
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the HTTP server of the `server` module until it fails.
+#[cfg(feature = "server")]
+fn serve(args: ServeArgs) -> Result<(), BoxError> {
+    use virust_locator::server::{self, JobQueue};
+
+    logging::init(args.log_format);
+    let jobs = args.jobs_dir.map(JobQueue::open).transpose()?;
+    eprintln!(
+        "viRust-locator HTTP server listening on http://{}",
+        args.addr
+    );
+    tokio::runtime::Runtime::new()?.block_on(server::serve(args.addr, args.limits.into(), jobs))
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_args: ServeArgs) -> Result<(), BoxError> {
+    Err(BoxError::from("serve requires the `server` feature"))
+}
+
+type Plugins = (Box<dyn Formatter>, Vec<Box<dyn ResultFilter>>);
+
+/// The formatter of `--format`.
+fn default_formatter(args: &Args) -> Box<dyn Formatter> {
+    match args.annotation.format {
+        Some(format::OutputFormat::Sam) => Box::new(sam::SamFormatter {
+            reference: args.common.reference.clone(),
+        }),
+        Some(format::OutputFormat::Lanl) => Box::new(lanl::LanlFormatter {
+            reference: args.common.reference.clone(),
+            coordinates: args.coordinates(),
+        }),
+        Some(format::OutputFormat::Vcf) => Box::new(vcf::VcfFormatter {
+            reference: args.common.reference.clone(),
+        }),
+        Some(format::OutputFormat::Gff3) => Box::new(gff::Gff3Formatter {
+            reference: args.common.reference.clone(),
+        }),
+        Some(format::OutputFormat::Bed) => Box::new(bed::BedFormatter {
+            reference: args.common.reference.clone(),
+        }),
+        Some(format::OutputFormat::Pretty) => Box::new(pretty::PrettyFormatter {
+            reference: args.common.reference.clone(),
+        }),
+        Some(format::OutputFormat::Ndjson) => Box::new(format::NdjsonFormatter),
+        _ => Box::new(format::TsvFormatter::from(args.delimited_options())),
+    }
+}
+
+#[cfg(feature = "plugins")]
+fn load_plugins(args: &Args) -> Result<Plugins, BoxError> {
+    use virust_locator::plugin::Plugin;
+
+    let formatter: Box<dyn Formatter> = match &args.format_plugin {
+        Some(path) => Box::new(Plugin::load_formatter(path)?),
+        None => default_formatter(args),
+    };
+    let mut filters: Vec<Box<dyn ResultFilter>> = Vec::new();
+    for path in &args.filter_plugin {
+        filters.push(Box::new(Plugin::load_filter(path)?));
+    }
+    Ok((formatter, filters))
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_plugins(args: &Args) -> Result<Plugins, BoxError> {
+    if args.format_plugin.is_some() || !args.filter_plugin.is_empty() {
+        return Err(BoxError::from(
+            "--format-plugin and --filter-plugin require the `plugins` feature",
+        ));
+    }
+    Ok((default_formatter(args), Vec::new()))
+}
+
+#[cfg(feature = "postgres")]
+fn write_postgres(url: &str, results: &[batch::BatchResult], args: &Args) -> Result<(), BoxError> {
+    use virust_locator::sink::postgres::{PostgresOptions, PostgresSink};
+
+    let mut sink = PostgresSink::connect(&PostgresOptions {
+        url: url.to_string(),
+        schema: args.postgres_schema.clone(),
+        batch_size: args.postgres_batch_size,
+    })?;
+    sink.write(results, args)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn write_postgres(
+    _url: &str,
+    _results: &[batch::BatchResult],
+    _args: &Args,
+) -> Result<(), BoxError> {
+    Err(BoxError::from("--postgres requires the `postgres` feature"))
+}
+
+type Scripts = (Vec<Box<dyn ResultFilter>>, Vec<Box<dyn ResultMapper>>);
+
+#[cfg(feature = "scripting")]
+fn load_scripts(args: &Args) -> Result<Scripts, BoxError> {
+    use virust_locator::script::{ScriptFilter, ScriptMapper};
+
+    let mut filters: Vec<Box<dyn ResultFilter>> = Vec::new();
+    for source in &args.filter {
+        filters.push(Box::new(ScriptFilter::new(source)?));
+    }
+    let mut mappers: Vec<Box<dyn ResultMapper>> = Vec::new();
+    for source in &args.map {
+        mappers.push(Box::new(ScriptMapper::new(source)?));
+    }
+    Ok((filters, mappers))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_scripts(args: &Args) -> Result<Scripts, BoxError> {
+    if !args.filter.is_empty() || !args.map.is_empty() {
+        return Err(BoxError::from(
+            "--filter and --map require the `scripting` feature",
+        ));
+    }
+    Ok((Vec::new(), Vec::new()))
+}
+
+/// Reports the query `id` that is not located, or fails with it with `--fail-fast`.
+fn not_located(
+    id: &str,
+    not_located: &impl std::fmt::Display,
+    fail_fast: bool,
+) -> Result<(), BoxError> {
+    let message = format!("query {}: {}", id, not_located);
+    if fail_fast {
+        return Err(message.into());
+    }
+    eprintln!("\x1b[1;91mError:\x1b[0m {}", message);
+    Ok(())
+}
+
+/// Writes the results of the queries to `out` as JSON lines, each as soon as its chunk of queries
+/// is located (see `Locator::build_iter`), reporting the queries not located like `print_locations`.
+fn print_ndjson(args: &Args, out: &mut dyn Write) -> Result<bool, BoxError> {
+    let ids = args.query_ids();
+    let mut found = true;
+    for record in locator::Locator::build_iter(args) {
+        let record = record?;
+        let id = &ids[record.index];
+        if let locator::LocateOutcome::NotFound(reason) = &record.outcome {
+            not_located(id, reason, args.fail_fast)?;
+            found = false;
+        }
+        let result = batch::BatchResult::from_outcome(id.clone(), record.outcome);
+        format::NdjsonFormatter.write(out, &[result])?;
+    }
+    Ok(found)
+}
+
+/// Writes the results of `loc`, named `ids`, to `out` in the format of `args`, and returns
+/// whether all of them are located. The default format has the columns of `batch::write_delimited`
+/// without those `--query` does not report, and a `NOT_FOUND` row for the queries not located.
+fn print_locations(
+    loc: Vec<locator::LocateOutcome>,
+    ids: &[String],
+    args: &Args,
+    mut out: &mut dyn Write,
+) -> Result<bool, BoxError> {
+    let reference = &args.common.reference;
+    match args.annotation.format {
+        Some(format::OutputFormat::Sam) => {
+            sam::write_header(&mut out, reference)?;
+            write_queries(&loc, ids, args.fail_fast, |id, l| {
+                sam::write_record(&mut out, id, l.locator(), reference)
+            })
+        }
+        Some(format::OutputFormat::Lanl) => {
+            let columns = lanl::columns_with(reference, args.coordinates())?;
+            batch::write_row(&mut out, &columns, '\t')?;
+            write_queries(&loc, ids, args.fail_fast, |id, l| {
+                for row in lanl::rows_with(id, l.locator(), reference, args.coordinates()) {
+                    batch::write_row(&mut out, &row, '\t')?;
+                }
+                Ok(())
+            })
+        }
+        Some(format::OutputFormat::Gff3) => {
+            gff::write_header(&mut out, reference)?;
+            write_queries(&loc, ids, args.fail_fast, |id, l| {
+                gff::write_features(&mut out, id, l.locator(), reference)
+            })
+        }
+        Some(format::OutputFormat::Bed) => write_queries(&loc, ids, args.fail_fast, |id, l| {
+            bed::write_lines(&mut out, id, l.locator(), reference)
+        }),
+        Some(format::OutputFormat::Pretty) => {
+            write_queries(&loc, ids, args.fail_fast, |id, l| match l {
+                locator::LocateOutcome::Found(loc) => {
+                    Ok(pretty::write_alignment(&mut out, id, loc, reference)?)
+                }
+                locator::LocateOutcome::NotFound(reason) => {
+                    Ok(pretty::write_not_located(&mut out, id, reason)?)
+                }
+            })
+        }
+        Some(format::OutputFormat::Vcf) => {
+            let found = write_queries(&loc, ids, args.fail_fast, |_, _| Ok(()))?;
+            let samples: Vec<(&str, Option<&locator::Locator>)> = ids
+                .iter()
+                .zip(&loc)
+                .map(|(id, l)| (id.as_str(), l.locator()))
+                .collect();
+            vcf::write_vcf(out, &samples, reference)?;
+            Ok(found)
+        }
+        _ => {
+            let options = batch::DelimitedOptions {
+                rows: batch::Rows::Queries {
+                    named: !args.common.query_name.is_empty(),
+                },
+                header: args.annotation.format.is_some(),
+                ..args.delimited_options()
+            };
+            batch::write_delimited(&mut out, &[], &options)?;
+            let options = batch::DelimitedOptions {
+                header: false,
+                ..options
+            };
+            write_queries(&loc, ids, args.fail_fast, |id, l| {
+                let result = batch::BatchResult::from_outcome(id.to_string(), l.clone());
+                batch::write_delimited(&mut out, &[result], &options)
+            })
+        }
+    }
+}
+
+/// Calls `write` with each query of `loc`, named `ids`, reporting the queries not located with
+/// `not_located` first, and returns whether all of them are located.
+fn write_queries(
+    loc: &[locator::LocateOutcome],
+    ids: &[String],
+    fail_fast: bool,
+    mut write: impl FnMut(&str, &locator::LocateOutcome) -> Result<(), BoxError>,
+) -> Result<bool, BoxError> {
+    let mut found = true;
+    for (id, l) in ids.iter().zip(loc) {
+        if let locator::LocateOutcome::NotFound(reason) = l {
+            not_located(id, reason, fail_fast)?;
+            found = false;
         }
+        write(id, l)?;
     }
+    Ok(found)
 }
@@ -0,0 +1,131 @@
+//! Prometheus metrics for the server modes, available with the `metrics` feature.
+//!
+//! Metrics are registered in the default `prometheus` registry so that applications embedding the
+//! crate can expose them together with their own metrics. The following series are collected:
+//!
+//! - `virust_locator_requests_total{method}`: number of RPCs received, by method.
+//! - `virust_locator_queries_total{outcome}`: number of queries processed, by outcome (`found`,
+//!   `not_found` or `error`).
+//! - `virust_locator_query_duration_seconds{algorithm}`: histogram of per-query alignment time.
+//! - `virust_locator_queue_depth`: number of queries waiting for a blocking worker thread.
+//! - `virust_locator_reference_lookups_total{reference, sequence_type, result}`: reference lookups,
+//!   with `result` being `hit` or `miss`. References are compiled into the binary, so a miss means
+//!   an unknown reference was requested.
+//!
+//! `serve` exposes the metrics in the Prometheus text format on `GET /metrics`.
+
+use crate::BoxError;
+use axum::Router;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use prometheus::{
+    Encoder, HistogramVec, IntCounterVec, IntGauge, TEXT_FORMAT, TextEncoder, exponential_buckets,
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+};
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+
+/// Number of requests received, by method.
+pub static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "virust_locator_requests_total",
+        "Number of requests received, by method.",
+        &["method"]
+    )
+    .unwrap()
+});
+
+/// Number of queries processed, by outcome.
+pub static QUERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "virust_locator_queries_total",
+        "Number of queries processed, by outcome.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+/// Per-query alignment time, by algorithm.
+pub static QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "virust_locator_query_duration_seconds",
+        "Time spent locating a single query, in seconds.",
+        &["algorithm"],
+        exponential_buckets(0.001, 2.0, 16).unwrap()
+    )
+    .unwrap()
+});
+
+/// Number of queries waiting for a worker thread.
+pub static QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "virust_locator_queue_depth",
+        "Number of queries waiting for a worker thread."
+    )
+    .unwrap()
+});
+
+/// Reference sequence lookups, by reference, sequence type and result.
+pub static REFERENCE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "virust_locator_reference_lookups_total",
+        "Number of reference sequence lookups, by reference, sequence type and result.",
+        &["reference", "sequence_type", "result"]
+    )
+    .unwrap()
+});
+
+/// Renders all metrics of the default registry in the Prometheus text exposition format.
+pub fn render() -> Result<String, BoxError> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    match render() {
+        Ok(body) => ([(header::CONTENT_TYPE, TEXT_FORMAT)], body).into_response(),
+        Err(err) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            err.to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Returns a router serving the metrics on `GET /metrics`.
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route("/metrics", get(metrics_handler))
+}
+
+/// Serves the metrics endpoint on `addr` until the process is terminated.
+pub async fn serve(addr: SocketAddr) -> Result<(), BoxError> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let app: Router = router();
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    #[test]
+    fn test_render_reference_lookups() {
+        retrieve_reference_sequence("HXB2", "nt").unwrap();
+        assert!(retrieve_reference_sequence("HXB3", "nt").is_err());
+
+        let body = render().unwrap();
+        assert!(body.contains(
+            "virust_locator_reference_lookups_total{reference=\"HXB2\",result=\"hit\",sequence_type=\"nt\"}"
+        ));
+        assert!(body.contains(
+            "virust_locator_reference_lookups_total{reference=\"HXB3\",result=\"miss\",sequence_type=\"nt\"}"
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! Pipe protocol: newline-delimited JSON requests in, newline-delimited JSON results out.
+//!
+//! Each input line is a JSON object such as
+//!
+//! ```json
+//! {"id": "q1", "seq": "ATGCATGCATGC", "reference": "HXB2"}
+//! ```
+//!
+//! where `id` may be any JSON value and is echoed back unchanged, and `reference`, `type_query`
+//! and `algorithm` are optional and default to the values given on the command line. For every
+//! non-empty input line exactly one line is written and flushed, in the same order:
+//!
+//! ```json
+//! {"id": "q1", "found": true, "ref_start": 1373, "ref_end": 1384, ...}
+//! {"id": "q2", "found": false, "error": "Invalid nucleotide sequence: unexpected 'X' at position 5"}
+//! ```
+//!
+//! Malformed lines produce an `error` result rather than stopping the stream, so the caller can
+//! keep a single process running for its whole lifetime.
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::locator::{LocateOutcome, Locator, NotLocated};
+use crate::logging;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::time::Instant;
+
+/// A request line of the pipe protocol.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipeRequest {
+    /// Caller-assigned identifier, echoed back in the result.
+    #[serde(default)]
+    pub id: Value,
+    /// Query sequence.
+    pub seq: String,
+    /// Reference genome; defaults to the command-line value.
+    pub reference: Option<String>,
+    /// Type of query, `nt`, `aa` or `auto`; defaults to the command-line value.
+    pub type_query: Option<String>,
+    /// Algorithm, 1, 2 or 3; defaults to the command-line value.
+    pub algorithm: Option<u8>,
+}
+
+/// A result line of the pipe protocol.
+#[derive(Debug, Clone, Serialize)]
+pub struct PipeResponse {
+    /// Identifier copied from the request, `null` if the request could not be parsed.
+    pub id: Value,
+    /// Whether the query was located on the reference.
+    pub found: bool,
+    /// The location, when found.
+    #[serde(flatten)]
+    pub locator: Option<Locator>,
+    /// Why the request could not be processed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why the query could not be located, when its alignment is degenerate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_located: Option<NotLocated>,
+}
+
+impl PipeResponse {
+    pub(crate) fn error(id: Value, error: impl ToString) -> Self {
+        PipeResponse {
+            id,
+            found: false,
+            locator: None,
+            error: Some(error.to_string()),
+            not_located: None,
+        }
+    }
+}
+
+/// Processes one request line, using `defaults` for the options the request leaves out.
+pub fn process_line(line: &str, defaults: &Args) -> PipeResponse {
+    let request: PipeRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return PipeResponse::error(Value::Null, format!("Invalid request: {}", err)),
+    };
+
+    let started = Instant::now();
+    let reference = request
+        .reference
+        .unwrap_or_else(|| defaults.common.reference.clone());
+    let algorithm = request.algorithm.unwrap_or(defaults.common.algorithm);
+    let located = Args {
+        common: CommonArgs {
+            query: vec![request.seq],
+            reference: reference.clone(),
+            type_query: request
+                .type_query
+                .unwrap_or_else(|| defaults.common.type_query.clone()),
+            algorithm,
+            ..Default::default()
+        },
+        mask_artifacts: defaults.mask_artifacts,
+        soft_mask: defaults.soft_mask,
+        ..Default::default()
+    }
+    .validate()
+    .map_err(BoxError::from)
+    .and_then(|args| Locator::try_build(&args))
+    .and_then(|mut located| {
+        located
+            .pop()
+            .map(LocateOutcome::into_result)
+            .ok_or_else(|| BoxError::from("No query to locate"))
+    });
+    let query_id = match &request.id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    logging::locate_event(
+        &query_id,
+        &reference,
+        algorithm,
+        started,
+        located
+            .as_ref()
+            .map(Result::is_ok)
+            .map_err(|err| err as &dyn Display),
+    );
+
+    match located {
+        Ok(Ok(loc)) => PipeResponse {
+            id: request.id,
+            found: true,
+            locator: Some(loc),
+            error: None,
+            not_located: None,
+        },
+        Ok(Err(not_located)) => PipeResponse {
+            id: request.id,
+            found: false,
+            locator: None,
+            error: None,
+            not_located: Some(not_located),
+        },
+        Err(err) => PipeResponse::error(request.id, err),
+    }
+}
+
+/// Runs the pipe protocol until `reader` reaches end of input. Every result is flushed as soon as
+/// it is written.
+pub fn run<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    defaults: &Args,
+) -> Result<(), BoxError> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = process_line(&line, defaults);
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_pipe() {
+        let input = "{\"id\": 1, \"seq\": \"ATGCATGCATGC\"}\n\nnot json\n{\"id\": \"q3\", \"seq\": \"ATGCXYZ\"}\n";
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output, &Args::default()).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+
+        assert_eq!(lines[0]["id"], 1);
+        assert_eq!(lines[0]["found"], true);
+        assert!(lines[0]["ref_start"].as_u64().unwrap() > 0);
+
+        assert_eq!(lines[1]["id"], Value::Null);
+        assert!(
+            lines[1]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid request")
+        );
+
+        assert_eq!(lines[2]["id"], "q3");
+        assert_eq!(lines[2]["found"], false);
+        assert!(
+            lines[2]["error"]
+                .as_str()
+                .unwrap()
+                .contains("Invalid nucleotide sequence")
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Formatter and filter plugins loaded from dynamic libraries, available with the `plugins`
+//! feature.
+//!
+//! A plugin is a dynamic library (`.so`, `.dylib` or `.dll`) exporting C functions. Results are
+//! exchanged as JSON, in the format of the JSON output (see `batch::BatchResult`), so plugins do
+//! not depend on the Rust version or the layout of the crate types, and can be written in any
+//! language with a C ABI:
+//!
+//! ```c
+//! // Required: the plugin ABI version, ABI_VERSION.
+//! uint32_t virust_locator_plugin_abi(void);
+//!
+//! // Formatter: formats a JSON array of results. On success, returns 0 and stores the output in
+//! // *out and *out_len; on failure, returns non-zero and may store an error message there.
+//! int32_t virust_locator_format(const uint8_t *results, size_t len, uint8_t **out, size_t *out_len);
+//!
+//! // Releases a buffer returned by virust_locator_format. Required for formatters.
+//! void virust_locator_free(uint8_t *ptr, size_t len);
+//!
+//! // Filter: returns 1 to keep the result given as a JSON object, 0 to drop it, and a negative
+//! // value on error.
+//! int32_t virust_locator_filter(const uint8_t *result, size_t len);
+//! ```
+//!
+//! A plugin exports the formatter functions, the filter function, or both. See
+//! `examples/plugin.rs` for a plugin written in Rust.
+//!
+//! Loading a plugin runs its code in the process: only load plugins you trust.
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::{Formatter, ResultFilter};
+use libloading::Library;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Version of the plugin ABI described in the module documentation.
+pub const ABI_VERSION: u32 = 1;
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type FormatFn = unsafe extern "C" fn(*const u8, usize, *mut *mut u8, *mut usize) -> i32;
+type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+type FilterFn = unsafe extern "C" fn(*const u8, usize) -> i32;
+
+/// The functions exported by a plugin.
+#[derive(Clone, Copy)]
+struct Functions {
+    format: Option<(FormatFn, FreeFn)>,
+    filter: Option<FilterFn>,
+}
+
+/// A plugin loaded from a dynamic library, usable as a `Formatter` and/or a `ResultFilter`.
+pub struct Plugin {
+    path: PathBuf,
+    functions: Functions,
+    /// Keeps the library loaded while its functions may be called.
+    _library: Option<Library>,
+}
+
+impl Plugin {
+    /// Loads the plugin at `path` and checks its ABI version.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BoxError> {
+        let path = path.as_ref();
+        let error = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+
+        // SAFETY: loading a library runs its initialisation code, and the symbols are trusted to
+        // have the signatures of the plugin ABI. Plugins are code chosen by the user.
+        let (library, functions) = unsafe {
+            let library = Library::new(path)?;
+            let abi = library
+                .get::<AbiFn>(b"virust_locator_plugin_abi\0")
+                .map_err(|err| error(&err))?;
+            let version = abi();
+            if version != ABI_VERSION {
+                return Err(error(&format!(
+                    "plugin ABI version {} is not supported, expected {}",
+                    version, ABI_VERSION
+                ))
+                .into());
+            }
+            let format = match library.get::<FormatFn>(b"virust_locator_format\0") {
+                Ok(format) => {
+                    let free = library
+                        .get::<FreeFn>(b"virust_locator_free\0")
+                        .map_err(|err| error(&err))?;
+                    Some((*format, *free))
+                }
+                Err(_) => None,
+            };
+            let filter = library
+                .get::<FilterFn>(b"virust_locator_filter\0")
+                .ok()
+                .map(|filter| *filter);
+            (library, Functions { format, filter })
+        };
+        if functions.format.is_none() && functions.filter.is_none() {
+            return Err(error(&"exports neither a formatter nor a filter").into());
+        }
+
+        Ok(Plugin {
+            path: path.to_path_buf(),
+            functions,
+            _library: Some(library),
+        })
+    }
+
+    /// Loads the plugin at `path` and checks that it is a formatter.
+    pub fn load_formatter(path: impl AsRef<Path>) -> Result<Self, BoxError> {
+        let plugin = Plugin::load(path)?;
+        if !plugin.is_formatter() {
+            return Err(format!("{}: not a formatter plugin", plugin.path.display()).into());
+        }
+        Ok(plugin)
+    }
+
+    /// Loads the plugin at `path` and checks that it is a filter.
+    pub fn load_filter(path: impl AsRef<Path>) -> Result<Self, BoxError> {
+        let plugin = Plugin::load(path)?;
+        if !plugin.is_filter() {
+            return Err(format!("{}: not a filter plugin", plugin.path.display()).into());
+        }
+        Ok(plugin)
+    }
+
+    /// Path the plugin was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the plugin exports a formatter.
+    pub fn is_formatter(&self) -> bool {
+        self.functions.format.is_some()
+    }
+
+    /// Whether the plugin exports a filter.
+    pub fn is_filter(&self) -> bool {
+        self.functions.filter.is_some()
+    }
+}
+
+impl Formatter for Plugin {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        let (format, free) = self
+            .functions
+            .format
+            .ok_or_else(|| format!("{}: not a formatter plugin", self.path.display()))?;
+        let input = serde_json::to_vec(results)?;
+        let mut out = std::ptr::null_mut();
+        let mut out_len = 0;
+
+        // SAFETY: `input` outlives the call, and the returned buffer is valid for `out_len` bytes
+        // until it is released with the plugin's `free`.
+        let (status, output) = unsafe {
+            let status = format(input.as_ptr(), input.len(), &mut out, &mut out_len);
+            let output = if out.is_null() {
+                Vec::new()
+            } else {
+                let output = std::slice::from_raw_parts(out, out_len).to_vec();
+                free(out, out_len);
+                output
+            };
+            (status, output)
+        };
+
+        if status != 0 {
+            return Err(format!(
+                "{}: formatter failed with status {}: {}",
+                self.path.display(),
+                status,
+                String::from_utf8_lossy(&output)
+            )
+            .into());
+        }
+        writer.write_all(&output)?;
+        Ok(())
+    }
+}
+
+impl ResultFilter for Plugin {
+    fn keep(&self, result: &BatchResult) -> Result<bool, BoxError> {
+        let filter = self
+            .functions
+            .filter
+            .ok_or_else(|| format!("{}: not a filter plugin", self.path.display()))?;
+        let input = serde_json::to_vec(result)?;
+
+        // SAFETY: `input` outlives the call.
+        match unsafe { filter(input.as_ptr(), input.len()) } {
+            0 => Ok(false),
+            1 => Ok(true),
+            status => Err(format!(
+                "{}: filter failed with status {} on {}",
+                self.path.display(),
+                status,
+                result.id
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch;
+    use crate::config::Args;
+    use crate::input::Record;
+    use serde_json::Value;
+
+    unsafe extern "C" fn count_format(
+        results: *const u8,
+        len: usize,
+        out: *mut *mut u8,
+        out_len: *mut usize,
+    ) -> i32 {
+        let input = unsafe { std::slice::from_raw_parts(results, len) };
+        let results: Vec<Value> = serde_json::from_slice(input).unwrap();
+        let output = format!("{} results\n", results.len()).into_bytes();
+        unsafe {
+            *out_len = output.len();
+            *out = Box::into_raw(output.into_boxed_slice()) as *mut u8;
+        }
+        0
+    }
+
+    unsafe extern "C" fn free(ptr: *mut u8, len: usize) {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) });
+    }
+
+    unsafe extern "C" fn found_filter(result: *const u8, len: usize) -> i32 {
+        let input = unsafe { std::slice::from_raw_parts(result, len) };
+        let result: Value = serde_json::from_slice(input).unwrap();
+        result["found"].as_bool().unwrap() as i32
+    }
+
+    #[test]
+    fn test_plugin_functions() {
+        let plugin = Plugin {
+            path: PathBuf::from("test-plugin"),
+            functions: Functions {
+                format: Some((count_format, free)),
+                filter: Some(found_filter),
+            },
+            _library: None,
+        };
+        let records = vec![
+            Record {
+                id: "good".to_string(),
+                sequence: "ATGCATGCATGC".to_string(),
+                quality: None,
+            },
+            Record {
+                id: "bad".to_string(),
+                sequence: "ATGCXYZ".to_string(),
+                quality: None,
+            },
+        ];
+        let results = batch::locate_records(records, &Args::default());
+        assert!(plugin.keep(&results[0]).unwrap());
+        assert!(!plugin.keep(&results[1]).unwrap());
+
+        let mut out = Vec::new();
+        plugin.write(&mut out, &results).unwrap();
+        assert_eq!(out, b"2 results\n");
+
+        assert!(Plugin::load("/nonexistent/libplugin.so").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Typed sequence positions.
+//!
+//! `RefPosition` and `QueryPosition` are 1-based, like the positions reported by the LANL HIV
+//! Locator and by this crate: the first residue of a sequence is at position 1. They keep
+//! reference and query positions apart, and make every conversion from or to a 0-based index
+//! explicit, which is where off-by-one errors come from when mixing this crate's output with
+//! 0-based tools such as BED files or slice indices.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::position::RefPosition;
+//!
+//! let start = RefPosition::new(790);
+//! assert_eq!(start.get(), 790);
+//! assert_eq!(start.to_zero_based(), 789);
+//! assert_eq!(RefPosition::from_zero_based(789), start);
+//! assert_eq!(start + 2, RefPosition::new(792));
+//! assert_eq!(start.to_string(), "790");
+//! ```
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::ops::{Add, AddAssign, Sub};
+
+macro_rules! position {
+    ($(#[$doc:meta])* $name:ident, $sequence:literal) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+        #[serde(transparent)]
+        pub struct $name(usize);
+
+        impl $name {
+            #[doc = concat!("The 1-based position `position` of the ", $sequence, ", which must not be 0.")]
+            pub const fn new(position: usize) -> Self {
+                debug_assert!(position > 0, "positions are 1-based");
+                $name(position)
+            }
+
+            #[doc = concat!("The position of the residue at the 0-based `index` of the ", $sequence, ".")]
+            pub const fn from_zero_based(index: usize) -> Self {
+                $name(index + 1)
+            }
+
+            /// The 1-based position.
+            pub const fn get(self) -> usize {
+                self.0
+            }
+
+            /// The 0-based index of the residue, e.g. to index the sequence.
+            pub const fn to_zero_based(self) -> usize {
+                self.0 - 1
+            }
+
+            /// The number of positions from `self` to `end`, both included, or 0 if `end` is before
+            /// `self`.
+            pub const fn span_to(self, end: $name) -> usize {
+                (end.0 + 1).saturating_sub(self.0)
+            }
+        }
+
+        impl TryFrom<usize> for $name {
+            type Error = String;
+
+            /// Converts a 1-based position, failing on 0.
+            fn try_from(position: usize) -> Result<Self, Self::Error> {
+                if position == 0 {
+                    Err("positions are 1-based and must not be 0".to_string())
+                } else {
+                    Ok($name(position))
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl Add<usize> for $name {
+            type Output = $name;
+
+            fn add(self, offset: usize) -> $name {
+                $name(self.0 + offset)
+            }
+        }
+
+        impl AddAssign<usize> for $name {
+            fn add_assign(&mut self, offset: usize) {
+                self.0 += offset;
+            }
+        }
+
+        impl Sub<usize> for $name {
+            type Output = $name;
+
+            fn sub(self, offset: usize) -> $name {
+                $name::new(self.0 - offset)
+            }
+        }
+    };
+}
+
+position!(
+    /// A 1-based position on the reference sequence.
+    RefPosition,
+    "reference"
+);
+
+position!(
+    /// A 1-based position on the query sequence.
+    QueryPosition,
+    "query"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let pos = RefPosition::new(1);
+        assert_eq!(pos.to_zero_based(), 0);
+        assert_eq!(RefPosition::from_zero_based(0), pos);
+        assert_eq!(pos.span_to(RefPosition::new(12)), 12);
+        assert_eq!(RefPosition::new(12).span_to(pos), 0);
+        assert!(RefPosition::try_from(0).is_err());
+        assert_eq!(QueryPosition::try_from(5), Ok(QueryPosition::new(5)));
+        assert_eq!(
+            serde_json::to_string(&(RefPosition::new(3) + 1)).unwrap(),
+            "4"
+        );
+    }
+}
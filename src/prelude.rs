@@ -1,2 +1,5 @@
 pub use crate::config::Args;
-pub use crate::locator::Locator;
+pub use crate::locator::{LocateOutcome, Locator};
+pub use crate::position::{QueryPosition, RefPosition};
+pub use crate::sequence::SequenceRecord;
+pub use crate::sequence_locator::{BuiltinLocator, SequenceLocator};
@@ -0,0 +1,257 @@
+//! Human-readable alignment view of the locations, for reviewing them by eye.
+//!
+//! With `--format pretty`, every query is written as a header line followed by its alignment in
+//! blocks of `LINE_WIDTH` columns, like BLAST: the reference line, named by its strain (e.g.
+//! `HXB2`), a midline with `|` under the identical residues, and the query line, with the first
+//! and last positions of each line in the margins. A line without residues, only gaps, has the
+//! position before it in both margins.
+//!
+//! ```text
+//! >1 HXB2 790-849 (+), 95% identity
+//!
+//! HXB2  790 ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG 849
+//!           ||||||||||||||||||||||||||||||||||||||||   |||||||||||||||||
+//! Query   1 ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAG---GATGGGAAAAAATTCGG 57
+//! ```
+//!
+//! The reference positions follow the `segments` of the locations wrapping around the end of the
+//! reference, and the query positions are those of the aligned query: of the reverse complement for
+//! queries located on the minus strand with `--both-strands`. Queries that are not located have a
+//! header line with the reason only.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//! use virust_locator::pretty;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGCATGCATGC".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let mut out = Vec::new();
+//! pretty::write_alignment(&mut out, "q1", &loc, "HXB2").unwrap();
+//! let view = String::from_utf8(out).unwrap();
+//! assert!(view.contains("HXB2  1373 AAGCAGCCATGC 1384\n           | |||  |||||\n"));
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::Formatter;
+use crate::locator::Locator;
+use crate::reference::resolve_reference;
+use std::fmt::Display;
+use std::io::Write;
+
+/// Number of alignment columns per line.
+pub const LINE_WIDTH: usize = 60;
+
+/// The label of the query lines.
+const QUERY_LABEL: &str = "Query";
+
+/// The first and last positions of the residues of each block of `LINE_WIDTH` columns, given the
+/// position of every column, `None` for gaps.
+fn margins(positions: &[Option<usize>]) -> Vec<(usize, usize)> {
+    let mut previous = 0;
+    positions
+        .chunks(LINE_WIDTH)
+        .map(|block| {
+            let mut residues = block.iter().flatten();
+            let first = residues.next().copied();
+            let last = residues.last().copied().or(first);
+            let margin = (first.unwrap_or(previous), last.unwrap_or(previous));
+            previous = margin.1;
+            margin
+        })
+        .collect()
+}
+
+/// Writes the alignment of the query `id`, located at `loc` on `reference`, in blocks of
+/// `LINE_WIDTH` columns after its header line.
+pub fn write_alignment<W: Write>(
+    mut writer: W,
+    id: &str,
+    loc: &Locator,
+    reference: &str,
+) -> Result<(), BoxError> {
+    let name = resolve_reference(reference)?;
+    writeln!(
+        writer,
+        ">{} {} {}-{} ({}), {}% identity",
+        id, name, loc.ref_start, loc.ref_end, loc.strand, loc.percent_identity
+    )?;
+
+    let ref_positions: Vec<Option<usize>> = loc
+        .ref_positions()
+        .iter()
+        .map(|position| position.map(|p| p.get()))
+        .collect();
+    let mut residue = 0;
+    let query_positions: Vec<Option<usize>> = loc
+        .query_aligned_string
+        .bytes()
+        .map(|q| {
+            (q != b'-').then(|| {
+                residue += 1;
+                residue
+            })
+        })
+        .collect();
+    let ref_margins = margins(&ref_positions);
+    let query_margins = margins(&query_positions);
+    let label_width = name.len().max(QUERY_LABEL.len());
+    let position_width = ref_margins
+        .iter()
+        .chain(&query_margins)
+        .map(|&(start, _)| start.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let query = loc.query_aligned_string.as_bytes();
+    let ref_seq = loc.ref_aligned_string.as_bytes();
+    let line = |label: &str, start: usize, residues: &[u8], end: usize| {
+        format!(
+            "{:<label_width$} {:>position_width$} {} {}",
+            label,
+            start,
+            String::from_utf8_lossy(residues),
+            end
+        )
+    };
+    for (block, (ref_margin, query_margin)) in ref_margins.iter().zip(&query_margins).enumerate() {
+        let columns = block * LINE_WIDTH..query.len().min((block + 1) * LINE_WIDTH);
+        let midline: String = query[columns.clone()]
+            .iter()
+            .zip(&ref_seq[columns.clone()])
+            .map(|(q, r)| {
+                if *q != b'-' && q.eq_ignore_ascii_case(r) {
+                    '|'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        writeln!(writer)?;
+        writeln!(
+            writer,
+            "{}",
+            line(name, ref_margin.0, &ref_seq[columns.clone()], ref_margin.1)
+        )?;
+        writeln!(
+            writer,
+            "{:width$}{}",
+            "",
+            midline,
+            width = label_width + position_width + 2
+        )?;
+        writeln!(
+            writer,
+            "{}",
+            line(QUERY_LABEL, query_margin.0, &query[columns], query_margin.1)
+        )?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes the header line of the query `id` that is not located, with the `reason`.
+pub fn write_not_located<W: Write>(
+    mut writer: W,
+    id: &str,
+    reason: &dyn Display,
+) -> Result<(), BoxError> {
+    writeln!(writer, ">{} not located: {}", id, reason)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// The alignment view of the results, see `write_alignment`.
+#[derive(Debug, Clone)]
+pub struct PrettyFormatter {
+    /// The reference the results are located on.
+    pub reference: String,
+}
+
+impl Formatter for PrettyFormatter {
+    fn write(&self, mut writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        for result in results {
+            match (&result.locator, &result.not_located, &result.error) {
+                (Some(loc), _, _) => {
+                    write_alignment(&mut writer, &result.id, loc, &self.reference)?
+                }
+                (None, Some(not_located), _) => {
+                    write_not_located(&mut writer, &result.id, not_located)?
+                }
+                (None, None, error) => write_not_located(
+                    &mut writer,
+                    &result.id,
+                    &error.as_deref().unwrap_or_default(),
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, CommonArgs};
+
+    #[test]
+    fn test_margins() {
+        let mut positions: Vec<Option<usize>> = (1..=LINE_WIDTH).map(Some).collect();
+        positions.extend([None, None]);
+        positions.push(Some(LINE_WIDTH + 1));
+        assert_eq!(margins(&positions), [(1, LINE_WIDTH), (61, 61)]);
+        positions.truncate(LINE_WIDTH + 2);
+        assert_eq!(margins(&positions), [(1, LINE_WIDTH), (60, 60)]);
+    }
+
+    #[test]
+    fn test_write_alignment() {
+        // HXB2 790-905 with 3 bases deleted, wrapped over 2 blocks.
+        let args = Args {
+            common: CommonArgs {
+                query: vec![
+                    "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGGTTAAGGCCAGGGGGAAAGAAAAAATATAAATTAAAACATATAGTATGGGCAAGCAG"
+                    .to_string(),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+        let mut out = Vec::new();
+        write_alignment(&mut out, "q1", &loc, "hxb2").unwrap();
+        let view = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = view.lines().collect();
+        assert!(lines[0].starts_with(">q1 HXB2 790-"));
+        assert_eq!(
+            lines[2],
+            "HXB2  790 ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG 849"
+        );
+        assert_eq!(
+            lines[3],
+            "          ||||||||||||||||||||||||||||||||||||||||   |||||||||||||||||"
+        );
+        assert_eq!(
+            lines[4],
+            "Query   1 ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAG---GATGGGAAAAAATTCGG 57"
+        );
+        assert!(lines[6].starts_with("HXB2  850 "));
+        assert!(lines[8].starts_with("Query  58 "));
+        assert_eq!(lines.len(), 10);
+
+        let mut out = Vec::new();
+        write_not_located(&mut out, "q2", &"the query is empty").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">q2 not located: the query is empty\n\n"
+        );
+    }
+}
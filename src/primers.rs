@@ -0,0 +1,184 @@
+//! Primer mapping: the binding sites of PCR primers and probes on a reference sequence.
+//!
+//! `map` compares a primer, and its reverse complement, with every position of the reference,
+//! without gaps, and reports the sites where at most `--max-primer-mismatches` of its bases do not
+//! match, with their strand and the positions of the mismatched bases in the primer. The IUPAC
+//! codes of degenerate primers match every base they stand for (see `sequence::iupac_match`).
+//!
+//! The `primers` subcommand (or `--primers`) writes the sites of the primers given as queries as
+//! tab-separated values, one row per site.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::locator::Strand;
+//! use virust_locator::primers;
+//! use virust_locator::reference::retrieve_reference_sequence;
+//!
+//! let hxb2 = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+//! // HXB2 790-809, with a degenerate base, and its reverse complement with a mismatch.
+//! let sites = primers::map(b"ATGGGTGCGAGAGCGTCRGT", hxb2, 0);
+//! assert_eq!(sites[0].ref_start.get(), 790);
+//! assert_eq!(sites[0].strand, Strand::Plus);
+//! let sites = primers::map(b"ACTCACGCTCTCGCACCCAT", hxb2, 1);
+//! assert_eq!(sites[0].strand, Strand::Minus);
+//! assert_eq!(sites[0].mismatches, [4]);
+//! ```
+
+use crate::BoxError;
+use crate::locator::Strand;
+use crate::parallel::*;
+use crate::position::RefPosition;
+use crate::sequence;
+use serde::Serialize;
+use std::io::Write;
+
+/// The default maximum number of mismatched bases of a binding site.
+pub const DEFAULT_MAX_MISMATCHES: usize = 3;
+
+/// A binding site of a primer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PrimerSite {
+    /// First position of the site on the reference.
+    pub ref_start: RefPosition,
+    /// Last position of the site on the reference (inclusive).
+    pub ref_end: RefPosition,
+    /// `Plus` when the primer is the reference sequence, `Minus` when it is its reverse
+    /// complement.
+    pub strand: Strand,
+    /// The positions of the primer bases not matching the reference, 1-based from its 5' end, in
+    /// increasing order.
+    pub mismatches: Vec<usize>,
+}
+
+/// The binding sites of the normalized nucleotide `primer` on `ref_seq`, on both strands, with at
+/// most `max_mismatches` mismatched bases: fewest mismatches first, then the plus strand first, in
+/// the order of the reference.
+pub fn map(primer: &[u8], ref_seq: &[u8], max_mismatches: usize) -> Vec<PrimerSite> {
+    let len = primer.len();
+    if len == 0 || len > ref_seq.len() {
+        return Vec::new();
+    }
+    let reverse = sequence::reverse_complement(&String::from_utf8_lossy(primer)).into_bytes();
+    let mut sites = Vec::new();
+    for (strand, target) in [(Strand::Plus, primer), (Strand::Minus, &reverse[..])] {
+        for (start, window) in ref_seq.windows(len).enumerate() {
+            let mut mismatches = Vec::new();
+            for (i, (&p, &r)) in target.iter().zip(window).enumerate() {
+                if !sequence::iupac_match(p, r) {
+                    // The reverse complement is read from the 3' end of the primer.
+                    mismatches.push(match strand {
+                        Strand::Plus => i + 1,
+                        Strand::Minus => len - i,
+                    });
+                    if mismatches.len() > max_mismatches {
+                        break;
+                    }
+                }
+            }
+            if mismatches.len() <= max_mismatches {
+                mismatches.sort_unstable();
+                sites.push(PrimerSite {
+                    ref_start: RefPosition::new(start + 1),
+                    ref_end: RefPosition::new(start + len),
+                    strand,
+                    mismatches,
+                });
+            }
+        }
+    }
+    sites.sort_by_key(|site| site.mismatches.len());
+    sites
+}
+
+/// Maps the nucleotide `primers`, normalized first (see `sequence::normalize`), in parallel, see
+/// `map`. Errors on a primer with no more bases than `max_mismatches`, which would bind anywhere.
+pub fn map_all(
+    ids: &[String],
+    primers: &[String],
+    ref_seq: &[u8],
+    max_mismatches: usize,
+) -> Result<Vec<Vec<PrimerSite>>, BoxError> {
+    primers
+        .par_iter()
+        .zip(ids)
+        .map(|(primer, id)| {
+            let primer = sequence::normalize(primer, "nt");
+            if primer.len() <= max_mismatches {
+                return Err(format!(
+                    "Primer {} has no more bases than --max-primer-mismatches ({})",
+                    id, max_mismatches
+                )
+                .into());
+            }
+            Ok(map(primer.as_bytes(), ref_seq, max_mismatches))
+        })
+        .collect()
+}
+
+/// The header of the tab-separated rows of `write_tsv`.
+pub const TSV_HEADER: &str =
+    "primer_id\tref_start\tref_end\tstrand\tmismatches\tmismatch_positions";
+
+/// Writes the binding site `site` of the primer `primer_id` as a tab-separated row, with the
+/// positions of the mismatched bases separated by `,`.
+pub fn write_tsv<W: Write>(
+    mut writer: W,
+    primer_id: &str,
+    site: &PrimerSite,
+) -> Result<(), BoxError> {
+    let positions: Vec<String> = site.mismatches.iter().map(ToString::to_string).collect();
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        primer_id,
+        site.ref_start,
+        site.ref_end,
+        site.strand,
+        site.mismatches.len(),
+        positions.join(",")
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let ref_seq = b"GGGGACGTTACCCCCCCCCTAACGTGGGG";
+        // ACGTTA at 5-10, and its reverse complement TAACGT at 20-25.
+        let sites = map(b"ACGTTA", ref_seq, 0);
+        assert_eq!(sites.len(), 2);
+        assert_eq!((sites[0].ref_start.get(), sites[0].ref_end.get()), (5, 10));
+        assert_eq!(sites[0].strand, Strand::Plus);
+        assert_eq!(
+            (sites[1].ref_start.get(), sites[1].strand),
+            (20, Strand::Minus)
+        );
+
+        // A mismatch at the 3' end of the primer, on either strand, and a degenerate base.
+        let sites = map(b"ACGTWC", ref_seq, 1);
+        assert_eq!(sites.len(), 3);
+        assert_eq!(sites[0].mismatches, [6]);
+        assert_eq!(
+            (sites[2].ref_start.get(), sites[2].strand),
+            (20, Strand::Minus)
+        );
+        assert_eq!(sites[2].mismatches, [6]);
+        assert!(map(b"CCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC", ref_seq, 3).is_empty());
+    }
+
+    #[test]
+    fn test_write_tsv() {
+        let site = PrimerSite {
+            ref_start: RefPosition::new(5),
+            ref_end: RefPosition::new(10),
+            strand: Strand::Minus,
+            mismatches: vec![2, 6],
+        };
+        let mut out = Vec::new();
+        write_tsv(&mut out, "p1", &site).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "p1\t5\t10\t-\t2\t2,6\n");
+    }
+}
@@ -1,5 +1,7 @@
 //! Reference sequences for HIV-1 and SIVmm239
 use crate::BoxError;
+use crate::position::RefPosition;
+use bio::alignment::distance::levenshtein;
 
 /// Struct to hold reference sequences
 /// Contains the strain name, sequence type (nt or aa), and the sequence itself
@@ -14,14 +16,70 @@ pub struct RefSeq<'a> {
     pub sequence: &'a [u8],
 }
 
-/// Function to retrieve a reference sequence by strain and sequence type
+/// Static array of the names accepted for the reference sequences, with the strain they stand for:
+/// the strain names, common aliases and GenBank accessions, matched case-insensitively
+pub static REFERENCE_ALIASES: [(&str, &str); 10] = [
+    ("HXB2", "HXB2"),
+    ("HXB2CG", "HXB2"),
+    ("HIV-1_HXB2", "HXB2"),
+    ("K03455", "HXB2"),
+    ("K03455.1", "HXB2"),
+    ("SIVmm239", "SIVmm239"),
+    ("SIVmac239", "SIVmm239"),
+    ("SIVmac", "SIVmm239"),
+    ("M33262", "SIVmm239"),
+    ("M33262.1", "SIVmm239"),
+];
+
+/// Function to list the strains of the reference sequences, in the order of `REFS`
+pub fn strains() -> impl Iterator<Item = &'static str> {
+    REFS.iter().enumerate().filter(|(i, ref_seq)| REFS[..*i].iter().all(|other| other.strain != ref_seq.strain)).map(|(_, ref_seq)| ref_seq.strain)
+}
+
+/// Function to list the other names accepted for a strain (see `REFERENCE_ALIASES`), without the strain name itself
+pub fn aliases(strain: &str) -> impl Iterator<Item = &'static str> + '_ {
+    REFERENCE_ALIASES.iter().filter(move |(alias, name)| *name == strain && *alias != strain).map(|(alias, _)| *alias)
+}
+
+/// Function to resolve a reference name, alias or accession to the strain name of a reference sequence
+/// The error suggests the closest accepted name when `name` is within two edits of one
+pub fn resolve_reference(name: &str) -> Result<&'static str, BoxError> {
+    if let Some((_, strain)) = REFERENCE_ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(name)) {
+        return Ok(strain);
+    }
+    let names: Vec<String> = strains().map(|strain| format!("'{}'", strain)).collect();
+    let expected = match names.split_last() {
+        Some((last, [first])) => format!("either {} or {}", first, last),
+        _ => format!("one of {}", names.join(", ")),
+    };
+    let mut message = format!("Reference genome must be {}, found '{}'", expected, name);
+    let closest = REFERENCE_ALIASES
+        .iter()
+        .map(|(alias, _)| (levenshtein(alias.to_uppercase().as_bytes(), name.to_uppercase().as_bytes()), alias))
+        .min_by_key(|(distance, _)| *distance);
+    if let Some((distance, alias)) = closest && distance <= 2 {
+        message.push_str(&format!("; did you mean '{}'?", alias));
+    }
+    Err(Box::from(message))
+}
+
+/// Function to retrieve a reference sequence by strain, alias or accession (see `REFERENCE_ALIASES`) and sequence type
 pub fn retrieve_reference_sequence(reference: &str, sequence_type: &str) -> Result<&'static RefSeq<'static>, BoxError> {
+    let reference = resolve_reference(reference).unwrap_or(reference);
     let reference_sequences = &REFS;
     for ref_seq in reference_sequences.iter() {
         if ref_seq.strain.to_uppercase() == reference.to_uppercase() && ref_seq.sequence_type.to_lowercase() == sequence_type.to_lowercase() {
+            #[cfg(feature = "metrics")]
+            crate::metrics::REFERENCE_LOOKUPS
+                .with_label_values(&[reference, sequence_type, "hit"])
+                .inc();
             return Ok(ref_seq);
         }
     }
+    #[cfg(feature = "metrics")]
+    crate::metrics::REFERENCE_LOOKUPS
+        .with_label_values(&[reference, sequence_type, "miss"])
+        .inc();
     Err(Box::from(format!(
         "Reference sequence for {} of type {} not found",
         reference, sequence_type
@@ -54,9 +112,202 @@ pub static REFS: [RefSeq; 4] = [
        
 ];
 
+/// A gene or region of a reference sequence
+/// Positions are 1-based and inclusive, on the nucleotide sequence for `nt` and on the
+/// concatenated protein sequence for `aa`
+/// The spliced genes tat and rev span from the start of their first exon to the end of their second
+#[derive(Debug)]
+pub struct Gene<'a> {
+    /// The strain name
+    pub strain: &'a str,
+    /// The type of the reference sequence (nt or aa)
+    pub sequence_type: &'a str,
+    /// The gene name, e.g. `gag` or `5LTR`
+    pub name: &'a str,
+    /// The first position of the gene
+    pub start: RefPosition,
+    /// The last position of the gene
+    pub end: RefPosition,
+}
+
+impl Gene<'_> {
+    /// Function to number `position` relative to the gene
+    /// The first position of the gene is 1; positions before it are negative, -1 being the
+    /// position just before the gene, so there is no position 0
+    pub fn relative(&self, position: RefPosition) -> i64 {
+        let offset = position.get() as i64 - self.start.get() as i64;
+        if offset >= 0 { offset + 1 } else { offset }
+    }
+
+    /// Function to find the reference position numbered `relative` relative to the gene, the inverse of `relative`
+    /// Returns `None` for 0, which numbers no position, and for positions before the start of the reference
+    pub fn absolute(&self, relative: i64) -> Option<RefPosition> {
+        let position = match relative {
+            0 => return None,
+            1.. => self.start.get() as i64 + relative - 1,
+            _ => self.start.get() as i64 + relative,
+        };
+        (position >= 1).then(|| RefPosition::new(position as usize))
+    }
+}
+
+/// Function to list the genes and LTRs of a reference by strain, alias or accession and sequence type,
+/// in the order of the reference
+pub fn genes<'a>(reference: &'a str, sequence_type: &'a str) -> impl Iterator<Item = &'static Gene<'static>> + 'a {
+    let reference = resolve_reference(reference).unwrap_or(reference);
+    GENES
+        .iter()
+        .filter(move |gene| gene.strain.eq_ignore_ascii_case(reference) && gene.sequence_type.eq_ignore_ascii_case(sequence_type))
+}
+
+/// Function to retrieve a gene by strain, alias or accession, sequence type and name (case-insensitive)
+pub fn retrieve_gene(reference: &str, sequence_type: &str, name: &str) -> Result<&'static Gene<'static>, BoxError> {
+    let reference = resolve_reference(reference).unwrap_or(reference);
+    genes(reference, sequence_type)
+        .find(|gene| gene.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<&str> = genes(reference, sequence_type).map(|gene| gene.name).collect();
+            Box::from(format!(
+                "Gene {} not found for {} of type {}, expected one of: {}",
+                name, reference, sequence_type, names.join(", ")
+            ))
+        })
+}
+
+/// Function to retrieve the protein sequence of a gene by strain, alias or accession and gene name (case-insensitive)
+/// The proteins are stored concatenated in the `aa` reference sequence, in the order of `GENES`
+pub fn protein_sequence(reference: &str, name: &str) -> Result<&'static [u8], BoxError> {
+    let gene = retrieve_gene(reference, "aa", name)?;
+    let sequence = retrieve_reference_sequence(reference, "aa")?.sequence;
+    Ok(&sequence[gene.start.to_zero_based()..gene.end.get()])
+}
+
+const fn gene(strain: &'static str, sequence_type: &'static str, name: &'static str, start: usize, end: usize) -> Gene<'static> {
+    Gene {
+        strain,
+        sequence_type,
+        name,
+        start: RefPosition::new(start),
+        end: RefPosition::new(end),
+    }
+}
+
+/// Static array of the genes and LTRs of the reference sequences, following the LANL HXB2 and
+/// SIVmm239 annotations
+pub static GENES: [Gene; 40] = [
+    gene("HXB2", "nt", "5LTR", 1, 634),
+    gene("HXB2", "nt", "gag", 790, 2292),
+    gene("HXB2", "nt", "pol", 2085, 5096),
+    gene("HXB2", "nt", "vif", 5041, 5619),
+    gene("HXB2", "nt", "vpr", 5559, 5850),
+    gene("HXB2", "nt", "tat", 5831, 8469),
+    gene("HXB2", "nt", "rev", 5970, 8653),
+    gene("HXB2", "nt", "vpu", 6062, 6310),
+    gene("HXB2", "nt", "env", 6225, 8795),
+    gene("HXB2", "nt", "nef", 8797, 9417),
+    gene("HXB2", "nt", "3LTR", 9086, 9719),
+    gene("HXB2", "aa", "gag", 1, 500),
+    gene("HXB2", "aa", "pol", 501, 1503),
+    gene("HXB2", "aa", "vif", 1504, 1695),
+    gene("HXB2", "aa", "vpr", 1696, 1791),
+    gene("HXB2", "aa", "tat", 1792, 1892),
+    gene("HXB2", "aa", "rev", 1893, 2008),
+    gene("HXB2", "aa", "vpu", 2009, 2090),
+    gene("HXB2", "aa", "env", 2091, 2946),
+    gene("HXB2", "aa", "nef", 2947, 3152),
+    gene("SIVmm239", "nt", "5LTR", 1, 818),
+    gene("SIVmm239", "nt", "gag", 1053, 2585),
+    gene("SIVmm239", "nt", "pol", 2351, 5410),
+    gene("SIVmm239", "nt", "vif", 5340, 5984),
+    gene("SIVmm239", "nt", "vpx", 5812, 6150),
+    gene("SIVmm239", "nt", "vpr", 6151, 6456),
+    gene("SIVmm239", "nt", "tat", 6302, 8902),
+    gene("SIVmm239", "nt", "rev", 6528, 9059),
+    gene("SIVmm239", "nt", "env", 6604, 9243),
+    gene("SIVmm239", "nt", "nef", 9077, 9868),
+    gene("SIVmm239", "nt", "3LTR", 9461, 10278),
+    gene("SIVmm239", "aa", "gag", 1, 510),
+    gene("SIVmm239", "aa", "pol", 511, 1529),
+    gene("SIVmm239", "aa", "vif", 1530, 1743),
+    gene("SIVmm239", "aa", "vpx", 1744, 1855),
+    gene("SIVmm239", "aa", "vpr", 1856, 1956),
+    gene("SIVmm239", "aa", "tat", 1957, 2086),
+    gene("SIVmm239", "aa", "rev", 2087, 2193),
+    gene("SIVmm239", "aa", "env", 2194, 3072),
+    gene("SIVmm239", "aa", "nef", 3073, 3335),
+];
+
+/// Static array of the nucleotide stretches coding for the proteins that are not read in one frame
+/// from the start to the end of their gene: the two exons of the spliced tat and rev, and the two
+/// frames of HXB2 vpr, whose reading frame is shifted by the extra base at 5775
+/// The other genes code from their start to their end
+pub static CODING_SEGMENTS: [CodingSegments; 5] = [
+    CodingSegments { strain: "HXB2", name: "vpr", segments: &[(5559, 5774), (5776, 5850)] },
+    CodingSegments { strain: "HXB2", name: "tat", segments: &[(5831, 6045), (8379, 8469)] },
+    CodingSegments { strain: "HXB2", name: "rev", segments: &[(5970, 6045), (8379, 8653)] },
+    CodingSegments { strain: "SIVmm239", name: "tat", segments: &[(6302, 6597), (8806, 8902)] },
+    CodingSegments { strain: "SIVmm239", name: "rev", segments: &[(6528, 6597), (8806, 9059)] },
+];
+
+/// The nucleotide stretches coding for a protein, in reading order
+#[derive(Debug)]
+pub struct CodingSegments {
+    /// The strain name
+    pub strain: &'static str,
+    /// The gene name
+    pub name: &'static str,
+    /// The first and last positions of the stretches, 1-based and inclusive
+    pub segments: &'static [(usize, usize)],
+}
+
+/// Function to list the nucleotide stretches coding for a nucleotide gene, in reading order
+pub fn coding_segments(gene: &Gene) -> Vec<(RefPosition, RefPosition)> {
+    match CODING_SEGMENTS.iter().find(|coding| coding.strain == gene.strain && coding.name == gene.name) {
+        Some(coding) => coding.segments.iter().map(|&(start, end)| (RefPosition::new(start), RefPosition::new(end))).collect(),
+        None => vec![(gene.start, gene.end)],
+    }
+}
+
+/// Static array of the genes spliced from two exons, the coding segments of `CODING_SEGMENTS`
+pub static SPLICED_GENES: [&str; 2] = ["tat", "rev"];
+
+/// Function to list the exons of a gene, in reading order: the coding segments of the spliced
+/// nucleotide genes (see `SPLICED_GENES`), and the whole gene for the others, including HXB2 vpr,
+/// whose two frames are not exons
+pub fn exons(gene: &Gene) -> Vec<(RefPosition, RefPosition)> {
+    if gene.sequence_type == "nt" && SPLICED_GENES.contains(&gene.name) {
+        coding_segments(gene)
+    } else {
+        vec![(gene.start, gene.end)]
+    }
+}
+
+/// Function to map a position of the concatenated protein sequence of a reference to the protein
+/// containing it and the nucleotide positions of the first and last bases of its codon
+/// The bases of a codon split by a splice junction are in different exons
+pub fn codon(reference: &str, position: RefPosition) -> Option<(&'static Gene<'static>, RefPosition, RefPosition)> {
+    let protein = genes(reference, "aa").find(|gene| gene.start <= position && position <= gene.end)?;
+    let gene = retrieve_gene(reference, "nt", protein.name).ok()?;
+    let offset = 3 * (position.get() - protein.start.get());
+    let segments = coding_segments(gene);
+    let base = |offset: usize| {
+        let mut offset = offset;
+        for &(start, end) in &segments {
+            let len = start.span_to(end);
+            if offset < len {
+                return Some(RefPosition::new(start.get() + offset));
+            }
+            offset -= len;
+        }
+        None
+    };
+    Some((protein, base(offset)?, base(offset + 2)?))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::reference::retrieve_reference_sequence;
+    use crate::position::RefPosition;
+    use crate::reference::{aliases, codon, genes, protein_sequence, resolve_reference, retrieve_gene, retrieve_reference_sequence, strains};
 
     #[test]
     fn test_retrieve_reference_sequence() { 
@@ -65,5 +316,92 @@ mod tests {
         assert!(seq.is_ok());
     }
 
+    #[test]
+    fn test_strains() {
+        assert_eq!(strains().collect::<Vec<_>>(), ["HXB2", "SIVmm239"]);
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert_eq!(aliases("SIVmm239").collect::<Vec<_>>(), ["SIVmac239", "SIVmac", "M33262", "M33262.1"]);
+    }
+
+    #[test]
+    fn test_retrieve_gene() {
+        let pol = retrieve_gene("HXB2", "nt", "POL").unwrap();
+        assert_eq!(pol.relative(RefPosition::new(2085)), 1);
+        assert_eq!(pol.relative(RefPosition::new(2550)), 466);
+        assert_eq!(pol.relative(RefPosition::new(2084)), -1);
+        for position in [2084, 2085, 2550, 1] {
+            assert_eq!(pol.absolute(pol.relative(RefPosition::new(position))), Some(RefPosition::new(position)));
+        }
+        assert_eq!(pol.absolute(0), None);
+        assert_eq!(pol.absolute(-2085), None);
+
+        let nef = retrieve_gene("SIVmm239", "aa", "nef").unwrap();
+        assert_eq!(nef.relative(nef.end), 263);
+        assert!(retrieve_gene("HXB2", "nt", "vpx").is_err());
+    }
+
+    #[test]
+    fn test_protein_sequence() {
+        let gag = protein_sequence("HXB2", "gag").unwrap();
+        assert!(gag.starts_with(b"MGARASVLSGGELDRWEKIR"));
+        assert_eq!(gag.len(), 500);
+        let nef = protein_sequence("SIVmac239", "NEF").unwrap();
+        assert_eq!(nef.len(), 263);
+        assert!(protein_sequence("HXB2", "5LTR").is_err());
+    }
+
+    #[test]
+    fn test_codon() {
+        let nt = |reference: &str, position: usize| {
+            let (gene, first, last) = codon(reference, RefPosition::new(position)).unwrap();
+            (gene.name, first.get(), last.get())
+        };
+        assert_eq!(nt("HXB2", 1), ("gag", 790, 792));
+        assert_eq!(nt("HXB2", 500), ("gag", 2287, 2289));
+        assert_eq!(nt("HXB2", 2091), ("env", 6225, 6227));
+        // The codons of HXB2 vpr after the frameshift, and the codon of tat across its splice junction.
+        assert_eq!(nt("HXB2", 1696 + 72), ("vpr", 5776, 5778));
+        assert_eq!(nt("HXB2", 1792 + 71), ("tat", 6044, 8379));
+        assert_eq!(nt("HXB2", 1892), ("tat", 8464, 8466));
+        assert_eq!(nt("SIVmm239", 3335), ("nef", 9863, 9865));
+        assert!(codon("HXB2", RefPosition::new(3153)).is_none());
+    }
+
+    #[test]
+    fn test_exons() {
+        let exons = |reference: &str, sequence_type: &str, name: &str| {
+            let gene = retrieve_gene(reference, sequence_type, name).unwrap();
+            super::exons(gene).iter().map(|&(start, end)| (start.get(), end.get())).collect::<Vec<_>>()
+        };
+        assert_eq!(exons("HXB2", "nt", "tat"), [(5831, 6045), (8379, 8469)]);
+        assert_eq!(exons("SIVmm239", "nt", "rev"), [(6528, 6597), (8806, 9059)]);
+        assert_eq!(exons("HXB2", "nt", "vpr"), [(5559, 5850)]);
+        assert_eq!(exons("HXB2", "aa", "tat"), [(1792, 1892)]);
+    }
+
+    #[test]
+    fn test_genes() {
+        let names: Vec<&str> = genes("K03455", "nt").map(|gene| gene.name).collect();
+        assert_eq!(names, ["5LTR", "gag", "pol", "vif", "vpr", "tat", "rev", "vpu", "env", "nef", "3LTR"]);
+        assert_eq!(genes("SIVmm239", "aa").count(), 9);
+        assert_eq!(genes("INVALID", "nt").count(), 0);
+    }
+
+    #[test]
+    fn test_resolve_reference() {
+        assert_eq!(resolve_reference("hxb2").unwrap(), "HXB2");
+        assert_eq!(resolve_reference("K03455").unwrap(), "HXB2");
+        assert_eq!(resolve_reference("sivmac239").unwrap(), "SIVmm239");
+        assert_eq!(retrieve_reference_sequence("M33262", "nt").unwrap().strain, "SIVmm239");
+        assert_eq!(
+            resolve_reference("HBX2").unwrap_err().to_string(),
+            "Reference genome must be either 'HXB2' or 'SIVmm239', found 'HBX2'; did you mean 'HXB2'?"
+        );
+        assert!(!resolve_reference("INVALID").unwrap_err().to_string().contains("did you mean"));
+    }
+
 }
         
\ No newline at end of file
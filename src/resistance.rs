@@ -0,0 +1,447 @@
+//! Drug resistance mutations of located HIV-1 queries.
+//!
+//! With `--annotate-dr`, the codons of the known drug resistance positions of the capsid (CA),
+//! protease (PR), reverse transcriptase (RT) and integrase (IN) covered by the location of a
+//! nucleotide query are translated in the reading frame of the HXB2 gene, and the amino acids
+//! observed in the query are reported against those of HXB2 (`annotate`). The positions are
+//! numbered within each protein, as in the drug resistance literature (e.g. RT 184), and their
+//! codons on HXB2 are found with `reference::codon`.
+//!
+//! `POSITIONS` lists the major positions of the protease inhibitors (PI), nucleoside and
+//! non-nucleoside reverse transcriptase inhibitors (NRTI, NNRTI), integrase strand transfer
+//! inhibitors (INSTI) and the capsid inhibitor lenacapavir (CAI). Every covered position is
+//! reported, so that wild-type residues can be told apart from positions the query does not cover;
+//! `ResistanceCall::mutation` is set for the positions that differ from HXB2.
+//!
+//! The observed amino acids of a codon with IUPAC ambiguity codes are all those its resolutions
+//! encode, e.g. `IM` for `ATR` (see `sequence::translate_codon`); a codon deleted in the query is
+//! `-`, and `X` stands for a codon broken by a frameshift or too ambiguous to translate.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//! use virust_locator::resistance;
+//!
+//! // HXB2 RT 181-190 with the M184V mutation (ATG -> GTG)
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["TATCAATACGTGGATGATTTGTATGTAGGA".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let calls = resistance::annotate(&loc, "HXB2");
+//! let mutations: Vec<String> = calls.iter().filter_map(|c| c.mutation()).collect();
+//! assert_eq!(mutations, ["M184V"]);
+//! ```
+
+use crate::BoxError;
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::reference::{self, resolve_reference, retrieve_gene};
+use crate::sequence::translate_codon;
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::Write;
+
+/// The reference the resistance positions are numbered on.
+pub const REFERENCE: &str = "HXB2";
+
+/// The largest number of amino acids of an ambiguous codon reported as a mixture; codons encoding
+/// more are reported as `X`.
+pub const MAX_MIXTURE: usize = 4;
+
+/// Drug class of a resistance position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DrugClass {
+    /// Capsid inhibitors (lenacapavir).
+    Cai,
+    /// Protease inhibitors.
+    Pi,
+    /// Nucleoside reverse transcriptase inhibitors.
+    Nrti,
+    /// Non-nucleoside reverse transcriptase inhibitors.
+    Nnrti,
+    /// Integrase strand transfer inhibitors.
+    Insti,
+}
+
+impl Display for DrugClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DrugClass::Cai => "CAI",
+            DrugClass::Pi => "PI",
+            DrugClass::Nrti => "NRTI",
+            DrugClass::Nnrti => "NNRTI",
+            DrugClass::Insti => "INSTI",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A protein cleaved from a polyprotein of HXB2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protein {
+    /// The protein name, e.g. `RT`.
+    pub name: &'static str,
+    /// The gene of the polyprotein, in `reference::GENES`.
+    pub gene: &'static str,
+    /// The number of codons of the gene before the protein.
+    pub offset: usize,
+    /// The number of amino acids of the protein.
+    pub length: usize,
+}
+
+/// The proteins with resistance positions, following the LANL HXB2 annotation.
+pub static PROTEINS: [Protein; 4] = [
+    Protein {
+        name: "CA",
+        gene: "gag",
+        offset: 132,
+        length: 231,
+    },
+    Protein {
+        name: "PR",
+        gene: "pol",
+        offset: 56,
+        length: 99,
+    },
+    Protein {
+        name: "RT",
+        gene: "pol",
+        offset: 155,
+        length: 560,
+    },
+    Protein {
+        name: "IN",
+        gene: "pol",
+        offset: 715,
+        length: 288,
+    },
+];
+
+/// A drug resistance position of a protein.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResistancePosition {
+    /// The protein name, in `PROTEINS`.
+    pub protein: &'static str,
+    /// The 1-based position in the protein.
+    pub position: usize,
+    /// The drug class selecting mutations at the position.
+    pub class: DrugClass,
+}
+
+const fn position(protein: &'static str, position: usize, class: DrugClass) -> ResistancePosition {
+    ResistancePosition {
+        protein,
+        position,
+        class,
+    }
+}
+
+/// The major drug resistance positions, in the order of the genome.
+pub static POSITIONS: [ResistancePosition; 69] = [
+    position("CA", 56, DrugClass::Cai),
+    position("CA", 66, DrugClass::Cai),
+    position("CA", 67, DrugClass::Cai),
+    position("CA", 70, DrugClass::Cai),
+    position("CA", 74, DrugClass::Cai),
+    position("CA", 105, DrugClass::Cai),
+    position("CA", 107, DrugClass::Cai),
+    position("PR", 23, DrugClass::Pi),
+    position("PR", 24, DrugClass::Pi),
+    position("PR", 30, DrugClass::Pi),
+    position("PR", 32, DrugClass::Pi),
+    position("PR", 33, DrugClass::Pi),
+    position("PR", 46, DrugClass::Pi),
+    position("PR", 47, DrugClass::Pi),
+    position("PR", 48, DrugClass::Pi),
+    position("PR", 50, DrugClass::Pi),
+    position("PR", 53, DrugClass::Pi),
+    position("PR", 54, DrugClass::Pi),
+    position("PR", 73, DrugClass::Pi),
+    position("PR", 76, DrugClass::Pi),
+    position("PR", 82, DrugClass::Pi),
+    position("PR", 84, DrugClass::Pi),
+    position("PR", 88, DrugClass::Pi),
+    position("PR", 90, DrugClass::Pi),
+    position("RT", 41, DrugClass::Nrti),
+    position("RT", 62, DrugClass::Nrti),
+    position("RT", 65, DrugClass::Nrti),
+    position("RT", 67, DrugClass::Nrti),
+    position("RT", 69, DrugClass::Nrti),
+    position("RT", 70, DrugClass::Nrti),
+    position("RT", 74, DrugClass::Nrti),
+    position("RT", 75, DrugClass::Nrti),
+    position("RT", 77, DrugClass::Nrti),
+    position("RT", 100, DrugClass::Nnrti),
+    position("RT", 101, DrugClass::Nnrti),
+    position("RT", 103, DrugClass::Nnrti),
+    position("RT", 106, DrugClass::Nnrti),
+    position("RT", 115, DrugClass::Nrti),
+    position("RT", 116, DrugClass::Nrti),
+    position("RT", 138, DrugClass::Nnrti),
+    position("RT", 151, DrugClass::Nrti),
+    position("RT", 179, DrugClass::Nnrti),
+    position("RT", 181, DrugClass::Nnrti),
+    position("RT", 184, DrugClass::Nrti),
+    position("RT", 188, DrugClass::Nnrti),
+    position("RT", 190, DrugClass::Nnrti),
+    position("RT", 210, DrugClass::Nrti),
+    position("RT", 215, DrugClass::Nrti),
+    position("RT", 219, DrugClass::Nrti),
+    position("RT", 221, DrugClass::Nnrti),
+    position("RT", 225, DrugClass::Nnrti),
+    position("RT", 227, DrugClass::Nnrti),
+    position("RT", 230, DrugClass::Nnrti),
+    position("IN", 51, DrugClass::Insti),
+    position("IN", 66, DrugClass::Insti),
+    position("IN", 74, DrugClass::Insti),
+    position("IN", 92, DrugClass::Insti),
+    position("IN", 97, DrugClass::Insti),
+    position("IN", 118, DrugClass::Insti),
+    position("IN", 121, DrugClass::Insti),
+    position("IN", 138, DrugClass::Insti),
+    position("IN", 140, DrugClass::Insti),
+    position("IN", 143, DrugClass::Insti),
+    position("IN", 147, DrugClass::Insti),
+    position("IN", 148, DrugClass::Insti),
+    position("IN", 155, DrugClass::Insti),
+    position("IN", 157, DrugClass::Insti),
+    position("IN", 163, DrugClass::Insti),
+    position("IN", 263, DrugClass::Insti),
+];
+
+impl ResistancePosition {
+    /// The nucleotide positions of the first and last bases of the codon of the position on HXB2.
+    pub fn codon(&self) -> (RefPosition, RefPosition) {
+        let protein = PROTEINS
+            .iter()
+            .find(|protein| protein.name == self.protein)
+            .unwrap();
+        let gene = retrieve_gene(REFERENCE, "aa", protein.gene).unwrap();
+        let aa_position = RefPosition::new(gene.start.get() + protein.offset + self.position - 1);
+        let (_, first, last) = reference::codon(REFERENCE, aa_position).unwrap();
+        (first, last)
+    }
+}
+
+/// The amino acids observed in a query at a resistance position.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResistanceCall {
+    /// The protein name, e.g. `RT`.
+    pub protein: &'static str,
+    /// The 1-based position in the protein.
+    pub position: usize,
+    /// The drug class selecting mutations at the position.
+    pub class: DrugClass,
+    /// The nucleotide position of the first base of the codon on HXB2.
+    pub codon_start: RefPosition,
+    /// The amino acid of HXB2.
+    pub reference: char,
+    /// The amino acids of the query: one, or several for an ambiguous codon, `-` for a deleted
+    /// codon and `X` for a codon that cannot be translated.
+    pub observed: String,
+}
+
+impl ResistanceCall {
+    /// The mutation in the usual notation, e.g. `M184V` or `M184IM` for a mixture, or `None` when
+    /// the query has the amino acid of HXB2 only.
+    pub fn mutation(&self) -> Option<String> {
+        if self.observed.len() == 1 && self.observed.starts_with(self.reference) {
+            return None;
+        }
+        Some(format!(
+            "{}{}{}",
+            self.reference, self.position, self.observed
+        ))
+    }
+}
+
+impl Display for ResistanceCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}{}{} ({})",
+            self.protein, self.reference, self.position, self.observed, self.class
+        )
+    }
+}
+
+/// Whether `reference` is the reference of the resistance positions, by strain, alias or
+/// accession.
+pub fn supports(reference: &str) -> bool {
+    resolve_reference(reference).is_ok_and(|strain| strain == REFERENCE)
+}
+
+/// The resistance positions whose codon is covered by `loc`, located on `reference`, with the amino
+/// acids of the query. Empty unless `reference` is HXB2, and for locations wrapping around the end
+/// of the reference.
+pub fn annotate(loc: &Locator, reference: &str) -> Vec<ResistanceCall> {
+    if !supports(reference) || !loc.segments.is_empty() {
+        return Vec::new();
+    }
+    let Ok(ref_seq) = reference::retrieve_reference_sequence(REFERENCE, "nt") else {
+        return Vec::new();
+    };
+    let covered = |position: RefPosition| loc.ref_start <= position && position <= loc.ref_end;
+    POSITIONS
+        .iter()
+        .filter_map(|resistance| {
+            let (first, last) = resistance.codon();
+            if !covered(first) || !covered(last) {
+                return None;
+            }
+            let reference_codon = std::str::from_utf8(
+                &ref_seq.sequence[first.to_zero_based()..=last.to_zero_based()],
+            )
+            .ok()?;
+            let reference = translate_codon(reference_codon).chars().next()?;
+            Some(ResistanceCall {
+                protein: resistance.protein,
+                position: resistance.position,
+                class: resistance.class,
+                codon_start: first,
+                reference,
+                observed: observed(&aligned_codon(loc, first, last)),
+            })
+        })
+        .collect()
+}
+
+/// The bases of the query aligned to the reference positions `first` to `last`, with those
+/// inserted between them and `-` for deleted bases.
+fn aligned_codon(loc: &Locator, first: RefPosition, last: RefPosition) -> String {
+    let mut position = loc.ref_start.get();
+    let mut codon = String::new();
+    let columns = loc
+        .query_aligned_string
+        .chars()
+        .zip(loc.ref_aligned_string.chars());
+    for (query, reference) in columns {
+        if reference == '-' {
+            if first.get() < position && position <= last.get() {
+                codon.push(query);
+            }
+            continue;
+        }
+        if first.get() <= position && position <= last.get() {
+            codon.push(query);
+        }
+        position += 1;
+    }
+    codon
+}
+
+/// The amino acids of an aligned codon, see `ResistanceCall::observed`. In-frame insertions give
+/// the inserted amino acids after the one of the codon.
+fn observed(codon: &str) -> String {
+    let bases: Vec<char> = codon.chars().filter(|&c| c != '-').collect();
+    if bases.is_empty() {
+        return "-".to_string();
+    }
+    if !bases.len().is_multiple_of(3) {
+        return "X".to_string();
+    }
+    bases
+        .chunks(3)
+        .map(|codon| {
+            let amino_acids = translate_codon(&codon.iter().collect::<String>());
+            match amino_acids.len() {
+                1..=MAX_MIXTURE => amino_acids,
+                _ => "X".to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Header line of the tab-separated output of `--annotate-dr`.
+pub const TSV_HEADER: &str =
+    "query_id\tprotein\tposition\tdrug_class\tcodon_start\treference\tobserved\tmutation";
+
+/// Writes the resistance calls of the query `query_id` as tab-separated values, one row per call.
+pub fn write_tsv<W: Write>(
+    mut writer: W,
+    query_id: &str,
+    calls: &[ResistanceCall],
+) -> Result<(), BoxError> {
+    for call in calls {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            query_id,
+            call.protein,
+            call.position,
+            call.class,
+            call.codon_start,
+            call.reference,
+            call.observed,
+            call.mutation().unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Args, CommonArgs};
+
+    fn locate(query: &str) -> Locator {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![query.to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Locator::build(&args).unwrap().pop().flatten().unwrap()
+    }
+
+    #[test]
+    fn test_codon() {
+        let m184 = POSITIONS
+            .iter()
+            .find(|p| p.protein == "RT" && p.position == 184)
+            .unwrap();
+        assert_eq!(
+            m184.codon(),
+            (RefPosition::new(3099), RefPosition::new(3101))
+        );
+        let n74 = POSITIONS
+            .iter()
+            .find(|p| p.protein == "CA" && p.position == 74)
+            .unwrap();
+        assert_eq!(n74.codon().0, RefPosition::new(1186 + 3 * 73));
+    }
+
+    #[test]
+    fn test_annotate() {
+        // HXB2 2827-3277, RT 93-243, with K103N (AAA -> AAC) and M184 ATG -> RTG
+        let loc = locate(
+            "GAATACCACATCCCGCAGGGTTAAAAAAGAACAAATCAGTAACAGTACTGGATGTGGGTGATGCATATTTTTCAGTTCCCTTAGATGAAGACTTCAGGAAGTATACTGCATTTACCATACCTAGTATAAACAATGAGACACCAGGGATTAGATATCAGTACAATGTGCTTCCACAGGGATGGAAAGGATCACCAGCAATATTCCAAAGTAGCATGACAAAAATCTTAGAGCCTTTTAGAAAACAAAATCCAGACATAGTTATCTATCAATACRTGGATGATTTGTATGTAGGATCTGACTTAGAAATAGGGCAGCATAGAACAAAAATAGAGGAGCTGAGACAACATCTGTTGAGGTGGGGACTTACCACACCAGACAAAAAACATCAGAAAGAACCTCCATTCCTTTGGATGGGTTATGAACTCCATCCTGATAAATGGACAGTACAGCC",
+        );
+        let calls = annotate(&loc, "HXB2");
+        let mutations: Vec<String> = calls.iter().filter_map(|c| c.mutation()).collect();
+        assert_eq!(mutations, ["K103N", "M184MV"]);
+        assert!(calls.iter().all(|c| c.protein == "RT"));
+        assert_eq!(calls.first().unwrap().position, 100);
+        assert_eq!(calls.last().unwrap().position, 230);
+        let m184 = calls.iter().find(|c| c.position == 184).unwrap();
+        assert_eq!(m184.to_string(), "RT M184MV (NRTI)");
+        assert!(annotate(&loc, "SIVmm239").is_empty());
+        assert!(supports("k03455"));
+    }
+
+    #[test]
+    fn test_observed() {
+        assert_eq!(observed("AAC"), "N");
+        assert_eq!(observed("---"), "-");
+        assert_eq!(observed("A-C"), "X");
+        assert_eq!(observed("NNN"), "X");
+        assert_eq!(observed("ACTAGTAGT"), "TSS");
+    }
+}
@@ -0,0 +1,297 @@
+//! SAM output of the locations, for samtools, IGV and the other tools reading alignments.
+//!
+//! With `--format sam`, every query is written as a SAM record (SAM v1.6) aligned to the reference,
+//! after a header with the `@SQ` line of the reference, named by its strain (e.g. `HXB2`), so the
+//! records can be viewed against the reference sequences of the LANL locator:
+//!
+//! - `POS` is the 1-based start of the location and `CIGAR` encodes the alignment as
+//!   `Locator::cigar` does: `M` for aligned residues, `I` and `D` for insertions and deletions,
+//!   and `S` for the ends of the query overhanging the reference, which are soft-clipped.
+//! - Queries located on the reverse complement with `--both-strands` have the `0x10` flag, and
+//!   their `SEQ` is the reverse complement, as SAM requires.
+//! - A location wrapping around the end of the reference is split in one record per segment: the
+//!   first one is primary, the others supplementary (`0x800`), with the query residues of the
+//!   other segments soft-clipped.
+//! - The other matches of `--max-hits` are secondary records (`0x100`) without `SEQ`.
+//! - Queries that are not located are unmapped records (`0x4`).
+//!
+//! `MAPQ` is 255 (unavailable) and `QUAL` `*`. The `NM` tag is the number of mismatches and gap
+//! positions of the record.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//! use virust_locator::sam;
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGCATGCATGC".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let mut out = Vec::new();
+//! sam::write_record(&mut out, "q1", Some(&loc), "HXB2").unwrap();
+//! let record = String::from_utf8(out).unwrap();
+//! assert!(record.starts_with("q1\t0\tHXB2\t1373\t255\t12M\t"));
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::Formatter;
+use crate::locator::{self, Locator, Strand};
+use crate::reference::{resolve_reference, retrieve_reference_sequence};
+use std::io::Write;
+
+/// The SAM flag of reads aligned to the reverse strand.
+pub const FLAG_REVERSE: u16 = 0x10;
+/// The SAM flag of unmapped reads.
+pub const FLAG_UNMAPPED: u16 = 0x4;
+/// The SAM flag of secondary alignments.
+pub const FLAG_SECONDARY: u16 = 0x100;
+/// The SAM flag of supplementary alignments.
+pub const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// Mapping quality of the records: unavailable.
+const MAPQ: u8 = 255;
+
+/// Writes the header of the SAM output against `reference`: the `@HD`, `@SQ` and `@PG` lines.
+pub fn write_header<W: Write>(mut writer: W, reference: &str) -> Result<(), BoxError> {
+    let name = resolve_reference(reference)?;
+    let length = retrieve_reference_sequence(name, "nt")?.sequence.len();
+    writeln!(writer, "@HD\tVN:1.6\tSO:unsorted")?;
+    writeln!(writer, "@SQ\tSN:{}\tLN:{}", name, length)?;
+    writeln!(
+        writer,
+        "@PG\tID:virust-locator\tPN:virust-locator\tVN:{}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    Ok(())
+}
+
+/// One alignment of a query on the reference: the start and the CIGAR operations.
+struct SamAlignment {
+    pos: usize,
+    cigar: Vec<(usize, char)>,
+    edit_distance: usize,
+}
+
+impl SamAlignment {
+    fn cigar(&self) -> String {
+        locator::cigar_string(&self.cigar)
+    }
+}
+
+/// The alignments of `loc`, one per reference segment: its columns are aligned, and the query
+/// residues of the other columns, and the insertions at the ends of the segment, soft-clipped.
+fn alignments(loc: &Locator) -> Vec<SamAlignment> {
+    let segments: Vec<(usize, usize)> = if loc.segments.is_empty() {
+        vec![(loc.ref_start.get(), loc.ref_end.get())]
+    } else {
+        loc.segments
+            .iter()
+            .map(|segment| (segment.start.get(), segment.end.get()))
+            .collect()
+    };
+    let columns: Vec<(u8, u8)> = loc
+        .query_aligned_string
+        .bytes()
+        .zip(loc.ref_aligned_string.bytes())
+        .collect();
+    // The segment of every column: insertions belong to the segment of the reference residue
+    // before them, or after them at the start of the alignment.
+    let mut column_segments = Vec::with_capacity(columns.len());
+    let (mut segment, mut ref_pos) = (0, segments[0].0);
+    for &(_, r) in &columns {
+        if r != b'-' {
+            if ref_pos > segments[segment].1 && segment + 1 < segments.len() {
+                segment += 1;
+                ref_pos = segments[segment].0;
+            }
+            ref_pos += 1;
+        }
+        column_segments.push(segment);
+    }
+    segments
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, _))| {
+            let mut cigar = Vec::new();
+            let mut edit_distance = 0;
+            let own: Vec<bool> = column_segments.iter().map(|&s| s == index).collect();
+            let first = own.iter().position(|&o| o).unwrap_or(0);
+            let first_aligned = (first..columns.len())
+                .find(|&i| own[i] && columns[i].1 != b'-')
+                .unwrap_or(first);
+            let last_aligned = (0..columns.len())
+                .rev()
+                .find(|&i| own[i] && columns[i].1 != b'-')
+                .unwrap_or(first_aligned);
+            for (i, &(q, r)) in columns.iter().enumerate() {
+                let clipped = i < first_aligned || i > last_aligned || !own[i];
+                if clipped && q == b'-' {
+                    continue;
+                }
+                if !clipped && q != r {
+                    edit_distance += 1;
+                }
+                locator::push_cigar_op(&mut cigar, locator::cigar_op(q, r, clipped));
+            }
+            SamAlignment {
+                pos: start,
+                cigar,
+                edit_distance,
+            }
+        })
+        .collect()
+}
+
+/// Writes the records of the query `id`, located at `loc` on `reference`, or an unmapped record
+/// when `loc` is `None`.
+pub fn write_record<W: Write>(
+    mut writer: W,
+    id: &str,
+    loc: Option<&Locator>,
+    reference: &str,
+) -> Result<(), BoxError> {
+    let Some(loc) = loc else {
+        writeln!(
+            writer,
+            "{}\t{}\t*\t0\t0\t*\t*\t0\t0\t*\t*",
+            id, FLAG_UNMAPPED
+        )?;
+        return Ok(());
+    };
+    let name = resolve_reference(reference)?;
+    let strand = match loc.strand {
+        Strand::Plus => 0,
+        Strand::Minus => FLAG_REVERSE,
+    };
+    let sequence: String = loc
+        .query_aligned_string
+        .chars()
+        .filter(|&c| c != '-')
+        .collect();
+    for (index, alignment) in alignments(loc).iter().enumerate() {
+        let flag = match index {
+            0 => strand,
+            _ => strand | FLAG_SUPPLEMENTARY,
+        };
+        write_alignment(&mut writer, id, flag, name, alignment, &sequence)?;
+    }
+    for hit in &loc.other_hits {
+        let strand = match hit.strand {
+            Strand::Plus => 0,
+            Strand::Minus => FLAG_REVERSE,
+        };
+        for alignment in alignments(hit) {
+            let flag = strand | FLAG_SECONDARY;
+            write_alignment(&mut writer, id, flag, name, &alignment, "*")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_alignment<W: Write>(
+    mut writer: W,
+    id: &str,
+    flag: u16,
+    reference: &str,
+    alignment: &SamAlignment,
+    sequence: &str,
+) -> Result<(), BoxError> {
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}\t{}\t{}\t*\t0\t0\t{}\t*\tNM:i:{}",
+        id,
+        flag,
+        reference,
+        alignment.pos,
+        MAPQ,
+        alignment.cigar(),
+        sequence,
+        alignment.edit_distance
+    )?;
+    Ok(())
+}
+
+/// SAM records of the results, after the header of the reference, see `write_record`.
+#[derive(Debug, Clone)]
+pub struct SamFormatter {
+    /// The reference the results are located on.
+    pub reference: String,
+}
+
+impl Formatter for SamFormatter {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        write_header(&mut *writer, &self.reference)?;
+        for result in results {
+            write_record(
+                &mut *writer,
+                &result.id,
+                result.locator.as_ref(),
+                &self.reference,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::RefSegment;
+    use crate::position::RefPosition;
+
+    fn locator(ref_start: usize, ref_end: usize, query: &str, reference: &str) -> Locator {
+        Locator {
+            query_aligned_string: query.to_string(),
+            ref_aligned_string: reference.to_string(),
+            ..Locator::new(
+                RefPosition::new(ref_start),
+                RefPosition::new(ref_end),
+                100.0,
+                false,
+                String::new(),
+                String::new(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_cigar() {
+        let loc = locator(10, 18, "TTAC-GTAACGGC", "--ACTGTA-CGG-");
+        let alignments = alignments(&loc);
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].pos, 10);
+        assert_eq!(alignments[0].cigar(), "2S2M1D3M1I3M1S");
+        assert_eq!(loc.cigar(), alignments[0].cigar());
+        assert_eq!(alignments[0].edit_distance, 2);
+    }
+
+    #[test]
+    fn test_wrapped() {
+        let loc = Locator {
+            segments: vec![
+                RefSegment {
+                    start: RefPosition::new(98),
+                    end: RefPosition::new(100),
+                },
+                RefSegment {
+                    start: RefPosition::new(1),
+                    end: RefPosition::new(4),
+                },
+            ],
+            ..locator(98, 4, "ACGTACG", "ACGTACG")
+        };
+        let alignments = alignments(&loc);
+        let cigars: Vec<(usize, String)> = alignments.iter().map(|a| (a.pos, a.cigar())).collect();
+        assert_eq!(cigars, [(98, "3M4S".to_string()), (1, "3S4M".to_string())]);
+
+        let mut out = Vec::new();
+        write_record(&mut out, "q1", None, "HXB2").unwrap();
+        assert_eq!(out, b"q1\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\n");
+    }
+}
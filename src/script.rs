@@ -0,0 +1,265 @@
+//! Result filters and transformations written as [rhai](https://rhai.rs) expressions, available
+//! with the `scripting` feature.
+//!
+//! Every result is exposed to the script as variables:
+//!
+//! | Variable                                     | Type    | When the record was not located |
+//! |----------------------------------------------|---------|---------------------------------|
+//! | `id`                                         | string  |                                 |
+//! | `found`                                      | bool    | `false`                         |
+//! | `ref_start`, `ref_end`                       | integer | `0`                             |
+//! | `percent_identity`                           | float   | `0.0`                           |
+//! | `indel`                                      | bool    | `false`                         |
+//! | `query_coverage`                             | float   | `0.0`                           |
+//! | `ref_span`                                   | integer | `0`                             |
+//! | `query_aligned_string`, `ref_aligned_string` | string  | `""`                            |
+//! | `error`                                      | string  | the validation error, or `""`   |
+//!
+//! A filter is an expression evaluating to a boolean, e.g. `percent_identity > 90.0 && !indel`.
+//! A map is a script that assigns new values to the variables, e.g. `ref_start -= 789; ref_end -=
+//! 789`; the result is rebuilt from the variables afterwards. `found`, `query_coverage` and
+//! `ref_span` are read-only, and the location variables are only read back for located records.
+//!
+//! Scripts are compiled once, before any record is processed, and each evaluation is limited in
+//! the number of operations it may run.
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::{ResultFilter, ResultMapper};
+use crate::position::RefPosition;
+use rhai::{AST, Dynamic, Engine, INT, Scope};
+
+/// Maximum number of operations of one evaluation, so a runaway script fails instead of hanging.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine
+}
+
+fn to_int(value: usize) -> INT {
+    INT::try_from(value).unwrap_or(INT::MAX)
+}
+
+/// Returns a scope holding the variables of `result`.
+fn scope(result: &BatchResult) -> Scope<'static> {
+    let mut scope = Scope::new();
+    let loc = result.locator.as_ref();
+    scope.push("id", result.id.clone());
+    scope.push_constant("found", result.found);
+    scope.push(
+        "ref_start",
+        loc.map(|loc| to_int(loc.ref_start.get())).unwrap_or(0),
+    );
+    scope.push(
+        "ref_end",
+        loc.map(|loc| to_int(loc.ref_end.get())).unwrap_or(0),
+    );
+    scope.push(
+        "percent_identity",
+        loc.map(|loc| loc.percent_identity).unwrap_or(0.0),
+    );
+    scope.push("indel", loc.is_some_and(|loc| loc.indel));
+    scope.push_constant(
+        "query_coverage",
+        loc.map(|loc| loc.query_coverage).unwrap_or(0.0),
+    );
+    scope.push_constant("ref_span", loc.map(|loc| to_int(loc.ref_span)).unwrap_or(0));
+    scope.push(
+        "query_aligned_string",
+        loc.map(|loc| loc.query_aligned_string.clone())
+            .unwrap_or_default(),
+    );
+    scope.push(
+        "ref_aligned_string",
+        loc.map(|loc| loc.ref_aligned_string.clone())
+            .unwrap_or_default(),
+    );
+    scope.push("error", result.error.clone().unwrap_or_default());
+    scope
+}
+
+/// Reads the variable `name` from `scope`.
+fn get<T: Clone + 'static>(scope: &Scope, name: &str, type_name: &str) -> Result<T, BoxError> {
+    scope
+        .get_value::<T>(name)
+        .ok_or_else(|| format!("`{}` must be {}", name, type_name).into())
+}
+
+fn get_position(scope: &Scope, name: &str) -> Result<RefPosition, BoxError> {
+    let value: INT = get(scope, name, "an integer")?;
+    usize::try_from(value)
+        .ok()
+        .and_then(|value| RefPosition::try_from(value).ok())
+        .ok_or_else(|| format!("`{}` must be a 1-based position", name).into())
+}
+
+/// A filter expression.
+pub struct ScriptFilter {
+    engine: Engine,
+    ast: AST,
+    source: String,
+}
+
+impl ScriptFilter {
+    /// Compiles the filter expression `source`.
+    pub fn new(source: &str) -> Result<Self, BoxError> {
+        let engine = engine();
+        let ast = engine
+            .compile_expression(source)
+            .map_err(|err| format!("Invalid filter `{}`: {}", source, err))?;
+        Ok(ScriptFilter {
+            engine,
+            ast,
+            source: source.to_string(),
+        })
+    }
+}
+
+impl ResultFilter for ScriptFilter {
+    fn keep(&self, result: &BatchResult) -> Result<bool, BoxError> {
+        let value: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope(result), &self.ast)
+            .map_err(|err| format!("Filter `{}` failed on {}: {}", self.source, result.id, err))?;
+        value.as_bool().map_err(|type_name| {
+            format!(
+                "Filter `{}` must evaluate to a bool, not {}",
+                self.source, type_name
+            )
+            .into()
+        })
+    }
+}
+
+/// A map script.
+pub struct ScriptMapper {
+    engine: Engine,
+    ast: AST,
+    source: String,
+}
+
+impl ScriptMapper {
+    /// Compiles the map script `source`.
+    pub fn new(source: &str) -> Result<Self, BoxError> {
+        let engine = engine();
+        let ast = engine
+            .compile(source)
+            .map_err(|err| format!("Invalid map `{}`: {}", source, err))?;
+        Ok(ScriptMapper {
+            engine,
+            ast,
+            source: source.to_string(),
+        })
+    }
+
+    fn read_back(scope: &Scope, mut result: BatchResult) -> Result<BatchResult, BoxError> {
+        result.id = get(scope, "id", "a string")?;
+        let error: String = get(scope, "error", "a string")?;
+        result.error = (!error.is_empty()).then_some(error);
+        if let Some(loc) = result.locator.as_mut() {
+            loc.ref_start = get_position(scope, "ref_start")?;
+            loc.ref_end = get_position(scope, "ref_end")?;
+            loc.percent_identity = get(scope, "percent_identity", "a float")?;
+            loc.indel = get(scope, "indel", "a bool")?;
+            loc.query_aligned_string = get(scope, "query_aligned_string", "a string")?;
+            loc.ref_aligned_string = get(scope, "ref_aligned_string", "a string")?;
+        }
+        Ok(result)
+    }
+}
+
+impl ResultMapper for ScriptMapper {
+    fn map(&self, result: BatchResult) -> Result<BatchResult, BoxError> {
+        let id = result.id.clone();
+        let failed = |err: &dyn std::fmt::Display| {
+            format!("Map `{}` failed on {}: {}", self.source, id, err)
+        };
+        let mut scope = scope(&result);
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| failed(&err))?;
+        Ok(ScriptMapper::read_back(&scope, result).map_err(|err| failed(&err))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch;
+    use crate::config::Args;
+    use crate::input::Record;
+
+    fn results() -> Vec<BatchResult> {
+        let records = vec![
+            Record {
+                id: "good".to_string(),
+                sequence: "ATGCATGCATGC".to_string(),
+                quality: None,
+            },
+            Record {
+                id: "bad".to_string(),
+                sequence: "ATGCXYZ".to_string(),
+                quality: None,
+            },
+        ];
+        batch::locate_records(records, &Args::default())
+    }
+
+    #[test]
+    fn test_filter() {
+        let results = results();
+        let filter = ScriptFilter::new("found && percent_identity > 50 && !indel").unwrap();
+        assert!(filter.keep(&results[0]).unwrap());
+        assert!(!filter.keep(&results[1]).unwrap());
+
+        let filter = ScriptFilter::new("error.contains(\"Invalid\")").unwrap();
+        assert!(!filter.keep(&results[0]).unwrap());
+        assert!(filter.keep(&results[1]).unwrap());
+
+        assert!(ScriptFilter::new("percent_identity >").is_err());
+        assert!(
+            ScriptFilter::new("ref_start")
+                .unwrap()
+                .keep(&results[0])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let mut results = results().into_iter();
+        let mapper =
+            ScriptMapper::new("id = \"p01_\" + id; ref_start -= 789; ref_end -= 789").unwrap();
+
+        let good = mapper.map(results.next().unwrap()).unwrap();
+        assert_eq!(good.id, "p01_good");
+        let loc = good.locator.unwrap();
+        assert_eq!(
+            (loc.ref_start.get(), loc.ref_end.get()),
+            (1373 - 789, 1384 - 789)
+        );
+
+        let bad = mapper.map(results.next().unwrap()).unwrap();
+        assert_eq!(bad.id, "p01_bad");
+        assert!(bad.locator.is_none());
+
+        assert!(
+            ScriptMapper::new("found = true")
+                .unwrap()
+                .map(good_result())
+                .is_err()
+        );
+        assert!(
+            ScriptMapper::new("ref_start = -1")
+                .unwrap()
+                .map(good_result())
+                .is_err()
+        );
+    }
+
+    fn good_result() -> BatchResult {
+        results().remove(0)
+    }
+}
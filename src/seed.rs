@@ -0,0 +1,310 @@
+//! Seeding and chaining of the queries located with algorithm 3.
+//!
+//! Algorithm 1 aligns every query to the whole reference, and algorithm 2 anchors the ends of long
+//! queries with approximate matches of 100 residues. Algorithm 3 instead looks the k-mers of the
+//! query up in a `KmerIndex` of the reference, built once for all the queries located on it, and
+//! chains the seeds found with `chain`: the colinear seeds of the highest score, where the query
+//! and the reference advance together, with penalties for the gaps between them. Only the window
+//! of the reference around the chain is then aligned, so that a read of a few hundred bases is
+//! aligned to a few hundred bases of the reference instead of the whole genome.
+//!
+//! The k-mers are of `NT_K` bases for nucleotide queries and `AA_K` residues for amino acid
+//! queries. k-mers found more than `MAX_OCCURRENCES` times in the reference are not seeds.
+//!
+//! The index is written to and read from files with `KmerIndex::write` and `KmerIndex::read`, see
+//! the `index_cache` module.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::reference::retrieve_reference_sequence;
+//! use virust_locator::seed::{self, KmerIndex};
+//!
+//! let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+//! let index = KmerIndex::new(ref_seq, "nt");
+//! let seeds = index.seeds(&ref_seq[4000..4200]);
+//! let chain = seed::chain(&seeds, index.k).unwrap();
+//! assert_eq!((chain.query_start, chain.ref_start), (0, 4000));
+//! assert_eq!((chain.query_end, chain.ref_end), (200, 4200));
+//! ```
+
+use crate::BoxError;
+use flate2::Crc;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Length of the k-mers of nucleotide queries.
+pub const NT_K: usize = 11;
+
+/// Length of the k-mers of amino acid queries.
+pub const AA_K: usize = 4;
+
+/// Largest number of occurrences in the reference of a k-mer used as a seed, so that the repeats,
+/// such as the LTRs, do not multiply the seeds.
+pub const MAX_OCCURRENCES: usize = 8;
+
+/// Number of preceding seeds considered for chaining to each seed.
+const MAX_PREDECESSORS: usize = 64;
+
+/// Largest distance, on the query or the reference, between two chained seeds.
+const MAX_SEED_DISTANCE: usize = 2000;
+
+/// The first bytes of the files of `KmerIndex::write`, ending with the version of the format.
+const FILE_MAGIC: &[u8; 8] = b"VLKMER\x00\x01";
+
+/// The CRC-32 of a reference sequence, identifying it in the files of `KmerIndex::write`.
+fn checksum(ref_seq: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(ref_seq);
+    crc.sum()
+}
+
+/// Reads `N` bytes from `reader`.
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], BoxError> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The length of the k-mers of `type_query`: `AA_K` for `aa`, otherwise `NT_K`.
+pub fn kmer_length(type_query: &str) -> usize {
+    if type_query == "aa" { AA_K } else { NT_K }
+}
+
+/// The positions of the k-mers of a reference sequence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KmerIndex {
+    /// The length of the k-mers.
+    pub k: usize,
+    positions: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl KmerIndex {
+    /// Indexes the k-mers of `ref_seq` with the k-mer length of `type_query`.
+    pub fn new(ref_seq: &[u8], type_query: &str) -> Self {
+        let k = kmer_length(type_query);
+        let mut positions: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+        for (pos, kmer) in ref_seq.windows(k).enumerate() {
+            positions.entry(kmer.to_vec()).or_default().push(pos);
+        }
+        KmerIndex { k, positions }
+    }
+
+    /// The 0-based positions of `kmer` in the reference.
+    pub fn positions(&self, kmer: &[u8]) -> &[usize] {
+        self.positions.get(kmer).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// The seeds of `query`: the pairs of 0-based positions, on the query and on the reference, of
+    /// its k-mers found in the reference at most `MAX_OCCURRENCES` times, sorted by reference and
+    /// query position.
+    pub fn seeds(&self, query: &[u8]) -> Vec<Seed> {
+        let mut seeds: Vec<Seed> = query
+            .windows(self.k)
+            .enumerate()
+            .flat_map(|(query_pos, kmer)| {
+                let positions = self.positions(kmer);
+                let positions = if positions.len() > MAX_OCCURRENCES {
+                    &[][..]
+                } else {
+                    positions
+                };
+                positions
+                    .iter()
+                    .map(move |&ref_pos| Seed { query_pos, ref_pos })
+            })
+            .collect();
+        seeds.sort_unstable_by_key(|seed| (seed.ref_pos, seed.query_pos));
+        seeds
+    }
+
+    /// Writes the index of `ref_seq` to `writer`, for `KmerIndex::read`: `FILE_MAGIC`, the length
+    /// of the k-mers, the length and CRC-32 of `ref_seq` and the number of k-mers, then every k-mer
+    /// with the number of its positions and the positions, as little-endian integers.
+    pub fn write(&self, mut writer: impl Write, ref_seq: &[u8]) -> Result<(), BoxError> {
+        writer.write_all(FILE_MAGIC)?;
+        writer.write_all(&(self.k as u32).to_le_bytes())?;
+        writer.write_all(&(ref_seq.len() as u64).to_le_bytes())?;
+        writer.write_all(&checksum(ref_seq).to_le_bytes())?;
+        writer.write_all(&(self.positions.len() as u64).to_le_bytes())?;
+        for (kmer, positions) in &self.positions {
+            writer.write_all(kmer)?;
+            writer.write_all(&(positions.len() as u32).to_le_bytes())?;
+            for &pos in positions {
+                writer.write_all(&(pos as u32).to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an index written by `KmerIndex::write`. Returns an error if `reader` is not an index
+    /// in this format, or is the index of another sequence than `ref_seq` or with another length
+    /// of k-mers than that of `type_query`.
+    pub fn read(mut reader: impl Read, ref_seq: &[u8], type_query: &str) -> Result<Self, BoxError> {
+        if &read_bytes::<8>(&mut reader)? != FILE_MAGIC {
+            return Err("not a k-mer index of this version".into());
+        }
+        let k = u32::from_le_bytes(read_bytes(&mut reader)?) as usize;
+        let expected = KmerIndex {
+            k: kmer_length(type_query),
+            positions: HashMap::new(),
+        };
+        if k != expected.k {
+            return Err(format!("k-mers of {} residues instead of {}", k, expected.k).into());
+        }
+        let length = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let crc = u32::from_le_bytes(read_bytes(&mut reader)?);
+        if length != ref_seq.len() as u64 || crc != checksum(ref_seq) {
+            return Err("index of another reference sequence".into());
+        }
+        let kmers = u64::from_le_bytes(read_bytes(&mut reader)?) as usize;
+        let mut positions = HashMap::with_capacity(kmers.min(ref_seq.len()));
+        for _ in 0..kmers {
+            let mut kmer = vec![0; k];
+            reader.read_exact(&mut kmer)?;
+            let count = u32::from_le_bytes(read_bytes(&mut reader)?) as usize;
+            let mut kmer_positions = Vec::with_capacity(count.min(ref_seq.len()));
+            for _ in 0..count {
+                kmer_positions.push(u32::from_le_bytes(read_bytes(&mut reader)?) as usize);
+            }
+            positions.insert(kmer, kmer_positions);
+        }
+        Ok(KmerIndex {
+            positions,
+            ..expected
+        })
+    }
+}
+
+/// A k-mer of the query found in the reference, at 0-based positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seed {
+    pub query_pos: usize,
+    pub ref_pos: usize,
+}
+
+/// The colinear seeds of the highest score, see `chain`: the query from `query_start` to
+/// `query_end` matches the reference from `ref_start` to `ref_end`, 0-based and half-open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chain {
+    pub query_start: usize,
+    pub query_end: usize,
+    pub ref_start: usize,
+    pub ref_end: usize,
+    /// The number of residues covered by the seeds, less the penalties of the gaps between them.
+    pub score: f64,
+}
+
+/// The penalty of a gap of `gap` residues between two chained seeds, on the query or on the
+/// reference: linear in the length of the gap, with a logarithmic opening, as in minimap2.
+fn gap_penalty(gap: usize, k: usize) -> f64 {
+    if gap == 0 {
+        return 0.0;
+    }
+    0.01 * k as f64 * gap as f64 + 0.5 * (gap as f64).log2()
+}
+
+/// Chains `seeds` of k-mers of `k` residues, sorted as by `KmerIndex::seeds`: every seed extends
+/// the best chain ending at one of the `MAX_PREDECESSORS` seeds before it that precede it on both
+/// the query and the reference, by at most `MAX_SEED_DISTANCE` residues. Returns the chain of the
+/// highest score, or `None` without seeds.
+pub fn chain(seeds: &[Seed], k: usize) -> Option<Chain> {
+    let mut scores: Vec<f64> = Vec::with_capacity(seeds.len());
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(seeds.len());
+    for (i, seed) in seeds.iter().enumerate() {
+        let mut best = (k as f64, None);
+        for j in (i.saturating_sub(MAX_PREDECESSORS)..i).rev() {
+            let other = seeds[j];
+            if other.query_pos >= seed.query_pos || other.ref_pos >= seed.ref_pos {
+                continue;
+            }
+            let query_distance = seed.query_pos - other.query_pos;
+            let ref_distance = seed.ref_pos - other.ref_pos;
+            if query_distance > MAX_SEED_DISTANCE || ref_distance > MAX_SEED_DISTANCE {
+                continue;
+            }
+            let covered = query_distance.min(ref_distance).min(k) as f64;
+            let score = scores[j] + covered - gap_penalty(query_distance.abs_diff(ref_distance), k);
+            if score > best.0 {
+                best = (score, Some(j));
+            }
+        }
+        scores.push(best.0);
+        previous.push(best.1);
+    }
+    let (last, &score) = scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let mut first = last;
+    while let Some(j) = previous[first] {
+        first = j;
+    }
+    Some(Chain {
+        query_start: seeds[first].query_pos,
+        query_end: seeds[last].query_pos + k,
+        ref_start: seeds[first].ref_pos,
+        ref_end: seeds[last].ref_pos + k,
+        score,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::retrieve_reference_sequence;
+
+    #[test]
+    fn test_seeds() {
+        let index = KmerIndex::new(b"ACGTACGTTTACGTACGTAA", "nt");
+        assert_eq!(index.k, NT_K);
+        assert_eq!(index.positions(b"ACGTACGTTTA"), [0]);
+        assert_eq!(
+            index.seeds(b"GGACGTACGTTTACGG"),
+            [
+                Seed {
+                    query_pos: 2,
+                    ref_pos: 0
+                },
+                Seed {
+                    query_pos: 3,
+                    ref_pos: 1
+                },
+                Seed {
+                    query_pos: 4,
+                    ref_pos: 2
+                },
+            ]
+        );
+        assert!(index.seeds(b"ACGT").is_empty());
+    }
+
+    #[test]
+    fn test_write_read() {
+        let ref_seq = b"ACGTACGTTTACGTACGTAA";
+        let index = KmerIndex::new(ref_seq, "nt");
+        let mut file = Vec::new();
+        index.write(&mut file, ref_seq).unwrap();
+        assert_eq!(KmerIndex::read(&file[..], ref_seq, "nt").unwrap(), index);
+        let err = KmerIndex::read(&file[..], b"ACGTACGTTTACGTACGTAC", "nt").unwrap_err();
+        assert_eq!(err.to_string(), "index of another reference sequence");
+        assert!(KmerIndex::read(&file[..], ref_seq, "aa").is_err());
+        assert!(KmerIndex::read(&file[..file.len() - 1], ref_seq, "nt").is_err());
+        assert!(KmerIndex::read(&b"ACGT"[..], ref_seq, "nt").is_err());
+    }
+
+    #[test]
+    fn test_chain() {
+        // HXB2 5001-5200 with 60 bases deleted after the first 100: the seeds of both parts chain
+        // across the deletion.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let query = [&ref_seq[5000..5100], &ref_seq[5160..5260]].concat();
+        let index = KmerIndex::new(ref_seq, "nt");
+        let chain = chain(&index.seeds(&query), index.k).unwrap();
+        assert_eq!((chain.query_start, chain.query_end), (0, 200));
+        assert_eq!((chain.ref_start, chain.ref_end), (5000, 5260));
+        assert!(chain.score < 200.0);
+
+        assert_eq!(super::chain(&[], NT_K), None);
+    }
+}
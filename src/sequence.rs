@@ -0,0 +1,482 @@
+//! Normalization and validation of query sequences.
+//!
+//! Every entry point — the `--query` and `--input` modes of the CLI, the pipe protocol, the
+//! servers, the consumers and `SequenceLocator` — turns its input into a `SequenceRecord`, so a
+//! sequence is accepted and located the same way whichever way it was submitted:
+//!
+//! - a leading FASTA header line (`>` up to the first line break) is removed, see
+//!   `strip_fasta_header`, and so are whitespace and alignment gaps (`-` and `.`), so FASTA
+//!   records, wrapped or aligned sequences can be pasted as they are;
+//! - letters are folded to upper case, as the references are;
+//! - for nucleotide queries, `U` is read as `T`, so RNA sequences are accepted. `is_rna` tells
+//!   RNA sequences, which keep their `U` in the validated queries and the output (see `to_rna`),
+//!   but are aligned as DNA;
+//! - for amino acid queries, `*` is accepted as a stop, as found in translated consensus
+//!   sequences. The locator does not align terminal stops and flags the others as warnings, see
+//!   the `artifacts` module.
+//!
+//! `infer_type` tells nucleotide from amino acid sequences, for `--type-query auto`, and
+//! `reverse_complement` gives the other strand of nucleotide queries, for `--both-strands`.
+//! `translate_codon` translates a codon with the standard genetic code, for `--annotate-dr`, and
+//! `iupac_match` tells whether two nucleotide codes can stand for the same base, for
+//! `--iupac-aware`.
+//!
+//! Soft-masked queries, whose lower-case residues mark repeats or low-quality regions, keep their
+//! case with `normalize_soft_masked`; `soft_mask` tells which residues are masked.
+//!
+//! The normalized sequence must then be a word of the IUPAC nucleotide or amino acid alphabet and
+//! be longer than 3 residues. `validate_nt` and `validate_aa` check a sequence without an
+//! identifier or CLI arguments; their `SequenceError` tells which character is invalid and where.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::sequence::SequenceRecord;
+//!
+//! let record = SequenceRecord::new("q1", "augc-augc\naugc", "nt").unwrap();
+//! assert_eq!(record.sequence(), "ATGCATGCATGC");
+//!
+//! let err = virust_locator::sequence::validate_nt("ATGC ATXY").unwrap_err();
+//! assert_eq!(err.to_string(), "Invalid nucleotide sequence: unexpected 'X' at position 8");
+//! ```
+
+use bio::alphabets::{self, Alphabet};
+use std::fmt::{self, Display};
+
+/// Why a sequence is not a valid query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The type of query is neither `nt` nor `aa`.
+    UnknownType(String),
+    /// `character`, at the 1-based `position` of the sequence as submitted (counting whitespace
+    /// and gaps), is not in the alphabet of the query type.
+    InvalidCharacter {
+        type_query: &'static str,
+        character: char,
+        position: usize,
+    },
+    /// The normalized sequence has `length` residues, 3 or fewer.
+    TooShort {
+        type_query: &'static str,
+        length: usize,
+    },
+}
+
+fn molecule(type_query: &str) -> &'static str {
+    if type_query == "aa" {
+        "amino acid"
+    } else {
+        "nucleotide"
+    }
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::UnknownType(_) => write!(f, "Type of query must be either 'nt' or 'aa'"),
+            SequenceError::InvalidCharacter {
+                type_query,
+                character,
+                position,
+            } => write!(
+                f,
+                "Invalid {} sequence: unexpected {:?} at position {}",
+                molecule(type_query),
+                character,
+                position
+            ),
+            SequenceError::TooShort { type_query, length } => {
+                let molecule = match *type_query {
+                    "aa" => "Amino acid",
+                    _ => "Nucleotide",
+                };
+                write!(
+                    f,
+                    "{} sequence length too short: {} residues, at least 4 are required",
+                    molecule, length
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+/// Minimum length of a normalized query.
+const MIN_LENGTH: usize = 4;
+
+/// The stop symbol accepted in amino acid queries.
+pub const STOP: char = '*';
+
+/// Normalizes `sequence` and checks every residue against `alphabet`. `type_query` is `nt` or
+/// `aa`. The positions of the invalid characters are counted after the FASTA header, if any.
+fn check(
+    sequence: &str,
+    type_query: &'static str,
+    alphabet: Alphabet,
+) -> Result<String, SequenceError> {
+    let sequence = strip_fasta_header(sequence);
+    let mut normalized = String::with_capacity(sequence.len());
+    for (i, original) in sequence.chars().enumerate() {
+        let Some(c) = normalize_char(original, type_query) else {
+            continue;
+        };
+        let stop = type_query == "aa" && c == STOP;
+        if !c.is_ascii() || !(stop || alphabet.is_word([c as u8])) {
+            return Err(SequenceError::InvalidCharacter {
+                type_query,
+                character: original,
+                position: i + 1,
+            });
+        }
+        normalized.push(c);
+    }
+    if normalized.len() < MIN_LENGTH {
+        return Err(SequenceError::TooShort {
+            type_query,
+            length: normalized.len(),
+        });
+    }
+    Ok(normalized)
+}
+
+/// Validates a nucleotide query: after normalization, it must be a word of the IUPAC nucleotide
+/// alphabet and be longer than 3 bases. Returns the normalized sequence.
+pub fn validate_nt(sequence: &str) -> Result<String, SequenceError> {
+    check(sequence, "nt", alphabets::dna::iupac_alphabet())
+}
+
+/// Validates an amino acid query: after normalization, it must be a word of the IUPAC protein
+/// alphabet, with `*` stops, and be longer than 3 residues. Returns the normalized sequence.
+pub fn validate_aa(sequence: &str) -> Result<String, SequenceError> {
+    check(sequence, "aa", alphabets::protein::iupac_alphabet())
+}
+
+/// Validates `sequence` as a query of `type_query`, `nt` or `aa`, with `validate_nt` or
+/// `validate_aa`. Returns the normalized sequence.
+pub fn validate(sequence: &str, type_query: &str) -> Result<String, SequenceError> {
+    match type_query {
+        "nt" => validate_nt(sequence),
+        "aa" => validate_aa(sequence),
+        other => Err(SequenceError::UnknownType(other.to_string())),
+    }
+}
+
+/// A query sequence with its identifier, normalized and validated for one type of query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceRecord {
+    id: String,
+    sequence: String,
+}
+
+impl SequenceRecord {
+    /// Normalizes `sequence` and checks it against the alphabet of `type_query`, `nt` or `aa`.
+    /// Returns the reason when the type of query or the sequence is invalid.
+    pub fn new(
+        id: impl Into<String>,
+        sequence: impl AsRef<str>,
+        type_query: &str,
+    ) -> Result<Self, SequenceError> {
+        Ok(SequenceRecord {
+            id: id.into(),
+            sequence: validate(sequence.as_ref(), type_query)?,
+        })
+    }
+
+    /// The record identifier.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The normalized sequence.
+    pub fn sequence(&self) -> &str {
+        &self.sequence
+    }
+
+    /// Returns the identifier and the normalized sequence.
+    pub fn into_parts(self) -> (String, String) {
+        (self.id, self.sequence)
+    }
+}
+
+/// Strips the header line of a FASTA record pasted as a query: when `sequence` starts with `>`,
+/// after any whitespace, everything up to the first line break. A header without a line break
+/// leaves nothing. Other sequences are returned as they are.
+pub fn strip_fasta_header(sequence: &str) -> &str {
+    match sequence.trim_start().strip_prefix('>') {
+        Some(record) => record.split_once('\n').map_or("", |(_, sequence)| sequence),
+        None => sequence,
+    }
+}
+
+/// Rejoins the FASTA records of `queries` split at the spaces of their header, as the
+/// space-delimited values of `--query` are: a query starting with `>` without a line break is
+/// joined by spaces with the following ones, up to the one with a line break.
+pub fn rejoin_fasta_headers(queries: Vec<String>) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::with_capacity(queries.len());
+    let mut open = false;
+    for query in queries {
+        match joined.last_mut() {
+            Some(last) if open => {
+                last.push(' ');
+                last.push_str(&query);
+            }
+            _ => joined.push(query),
+        }
+        let last = &joined[joined.len() - 1];
+        open = last.trim_start().starts_with('>') && !last.contains('\n');
+    }
+    joined
+}
+
+/// Normalizes one character: `None` for whitespace and gaps, otherwise the upper-case character,
+/// with `U` read as `T` for `nt`.
+fn normalize_char(c: char, type_query: &str) -> Option<char> {
+    if c.is_whitespace() || c == '-' || c == '.' {
+        return None;
+    }
+    match c.to_ascii_uppercase() {
+        'U' if type_query == "nt" => Some('T'),
+        c => Some(c),
+    }
+}
+
+/// Normalizes `sequence` for `type_query` without validating it: removes a FASTA header,
+/// whitespace and gaps, folds to upper case and, for `nt`, reads `U` as `T`.
+pub fn normalize(sequence: &str, type_query: &str) -> String {
+    strip_fasta_header(sequence)
+        .chars()
+        .filter_map(|c| normalize_char(c, type_query))
+        .collect()
+}
+
+/// Whether the nucleotide `sequence` is RNA: it has `U` bases, in any case, and no `T`.
+pub fn is_rna(sequence: &str) -> bool {
+    sequence.contains(['U', 'u']) && !sequence.contains(['T', 't'])
+}
+
+/// Writes the normalized nucleotide `sequence` as RNA: `T` as `U`, keeping the case.
+pub fn to_rna(sequence: &str) -> String {
+    sequence
+        .chars()
+        .map(|c| match c {
+            'T' => 'U',
+            't' => 'u',
+            c => c,
+        })
+        .collect()
+}
+
+/// Minimum fraction of `A`, `C`, `G`, `T`, `U` and `N` among the letters of a sequence inferred to be
+/// a nucleotide sequence.
+const MIN_NUCLEOTIDE_FRACTION: f64 = 0.9;
+
+/// Infers the type of query of `sequence`: `nt` if at least 90% of its letters are `A`, `C`, `G`,
+/// `T`, `U` or `N` (in any case), `aa` otherwise. Proteins made almost only of alanine, cysteine,
+/// glycine and threonine are inferred to be nucleotide sequences, so the type should be set
+/// explicitly for such queries.
+pub fn infer_type(sequence: &str) -> &'static str {
+    let letters = strip_fasta_header(sequence)
+        .chars()
+        .filter(char::is_ascii_alphabetic);
+    let (nucleotides, total) = letters.fold((0, 0), |(nucleotides, total), c| {
+        let nucleotide = matches!(c.to_ascii_uppercase(), 'A' | 'C' | 'G' | 'T' | 'U' | 'N');
+        (nucleotides + usize::from(nucleotide), total + 1)
+    });
+    if total > 0 && nucleotides as f64 >= MIN_NUCLEOTIDE_FRACTION * total as f64 {
+        "nt"
+    } else {
+        "aa"
+    }
+}
+
+/// Normalizes `sequence` like `normalize`, but keeps lower-case letters in lower case, for
+/// soft-masked queries.
+pub fn normalize_soft_masked(sequence: &str, type_query: &str) -> String {
+    strip_fasta_header(sequence)
+        .chars()
+        .filter_map(|c| {
+            let normalized = normalize_char(c, type_query)?;
+            Some(if c.is_lowercase() {
+                normalized.to_ascii_lowercase()
+            } else {
+                normalized
+            })
+        })
+        .collect()
+}
+
+/// Returns which residues of the normalized `sequence` are soft-masked, i.e. in lower case in
+/// `sequence`.
+pub fn soft_mask(sequence: &str, type_query: &str) -> Vec<bool> {
+    strip_fasta_header(sequence)
+        .chars()
+        .filter(|&c| normalize_char(c, type_query).is_some())
+        .map(|c| c.is_lowercase())
+        .collect()
+}
+
+/// Returns the reverse complement of the nucleotide `sequence`, before normalization: IUPAC
+/// codes are complemented in their case (`U` into `A`), other characters are kept.
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence
+        .chars()
+        .rev()
+        .map(|c| {
+            let complement = match c.to_ascii_uppercase() {
+                'A' => 'T',
+                'T' | 'U' => 'A',
+                'C' => 'G',
+                'G' => 'C',
+                'R' => 'Y',
+                'Y' => 'R',
+                'K' => 'M',
+                'M' => 'K',
+                'B' => 'V',
+                'V' => 'B',
+                'D' => 'H',
+                'H' => 'D',
+                other => other,
+            };
+            if c.is_ascii_lowercase() {
+                complement.to_ascii_lowercase()
+            } else {
+                complement
+            }
+        })
+        .collect()
+}
+
+/// The amino acids of the standard genetic code, for the codons in `TCAG` order.
+const GENETIC_CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// The nucleotides an IUPAC nucleotide code stands for, as indices in `TCAG` order.
+fn resolutions(base: u8) -> &'static [usize] {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => &[0],
+        b'C' => &[1],
+        b'A' => &[2],
+        b'G' => &[3],
+        b'Y' => &[0, 1],
+        b'R' => &[2, 3],
+        b'W' => &[0, 2],
+        b'S' => &[1, 3],
+        b'K' => &[0, 3],
+        b'M' => &[1, 2],
+        b'B' => &[0, 1, 3],
+        b'D' => &[0, 2, 3],
+        b'H' => &[0, 1, 2],
+        b'V' => &[1, 2, 3],
+        b'N' => &[0, 1, 2, 3],
+        _ => &[],
+    }
+}
+
+/// Whether the IUPAC nucleotide codes `a` and `b` can stand for the same base, e.g. `R` and `A` or
+/// `N` and any base. Gaps and other characters match nothing.
+pub fn iupac_match(a: u8, b: u8) -> bool {
+    let b = resolutions(b);
+    resolutions(a).iter().any(|base| b.contains(base))
+}
+
+/// Translates `codon` with the standard genetic code. A codon with IUPAC ambiguity codes gives
+/// every amino acid its resolutions encode, sorted, e.g. `IM` for `ATR`, as mixtures are reported
+/// in drug resistance interpretation. Stops are `*`. Empty when `codon` is not three nucleotides.
+pub fn translate_codon(codon: &str) -> String {
+    let codon = codon.as_bytes();
+    if codon.len() != 3 {
+        return String::new();
+    }
+    let mut amino_acids = Vec::new();
+    for &first in resolutions(codon[0]) {
+        for &second in resolutions(codon[1]) {
+            for &third in resolutions(codon[2]) {
+                amino_acids.push(GENETIC_CODE[16 * first + 4 * second + third]);
+            }
+        }
+    }
+    amino_acids.sort_unstable();
+    amino_acids.dedup();
+    String::from_utf8(amino_acids).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_and_validate() {
+        let record = SequenceRecord::new("q1", " atgc-ATGC.\r\naugc ", "nt").unwrap();
+        assert_eq!(record.id(), "q1");
+        assert_eq!(record.sequence(), "ATGCATGCATGC");
+
+        let record = SequenceRecord::new("p1", "mgar-k", "aa").unwrap();
+        assert_eq!(record.sequence(), "MGARK");
+        assert_eq!(validate_aa("MGA*RK.*").unwrap(), "MGA*RK*");
+        assert!(validate_nt("ATGC*").is_err());
+
+        assert!(SequenceRecord::new("q4", "ATGCATGC", "rna").is_err());
+
+        let record = SequenceRecord::new("q5", "\n>q5 gag, HXB2\r\nATGC\r\nATGC\n", "nt").unwrap();
+        assert_eq!(record.sequence(), "ATGCATGC");
+        assert_eq!(strip_fasta_header(">MGARASVLSG"), "");
+        assert_eq!(strip_fasta_header("ATGC>"), "ATGC>");
+        let split = [">q5", "gag,", "HXB2\nATGC", "ATGC", ">q6", "\nMHAC"].map(String::from);
+        assert_eq!(
+            rejoin_fasta_headers(split.to_vec()),
+            [">q5 gag, HXB2\nATGC", "ATGC", ">q6 \nMHAC"]
+        );
+        assert_eq!(infer_type(">MHAC protein\natgcatgc"), "nt");
+
+        assert_eq!(normalize_soft_masked("ATgc-aU\nu", "nt"), "ATgcaTt");
+        assert_eq!(infer_type("atgc-augc\natgcR"), "nt");
+        assert_eq!(reverse_complement("ATgc-uRN\n"), "\nNYa-gcAT");
+        assert!(is_rna("augc-AUGC") && !is_rna("AUGT") && !is_rna("ACGN"));
+        assert_eq!(to_rna("ATgt-N"), "AUgu-N");
+        assert_eq!(translate_codon("ATG"), "M");
+        assert_eq!(translate_codon("atr"), "IM");
+        assert_eq!(translate_codon("TAR"), "*");
+        assert_eq!(translate_codon("GGN"), "G");
+        assert_eq!(translate_codon("AT-"), "");
+        assert_eq!(translate_codon("ATGA"), "");
+        assert!(iupac_match(b'R', b'G') && iupac_match(b'A', b'R') && iupac_match(b'N', b'T'));
+        assert!(iupac_match(b'Y', b'B') && iupac_match(b'U', b'T'));
+        assert!(!iupac_match(b'R', b'C') && !iupac_match(b'A', b'-') && !iupac_match(b'-', b'-'));
+        assert_eq!(infer_type("MGARASVLSGGELDRWEKIR"), "aa");
+        assert_eq!(infer_type("MHAC"), "aa");
+        assert_eq!(
+            soft_mask("ATgc-aU\nu", "nt"),
+            [false, false, true, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert_eq!(
+            validate_nt("AT-GC\nXYZ").unwrap_err(),
+            SequenceError::InvalidCharacter {
+                type_query: "nt",
+                character: 'X',
+                position: 7
+            }
+        );
+        assert_eq!(
+            validate_aa("MGA\u{e9}K").unwrap_err(),
+            SequenceError::InvalidCharacter {
+                type_query: "aa",
+                character: '\u{e9}',
+                position: 4
+            }
+        );
+        assert_eq!(
+            validate_nt("AT-G").unwrap_err(),
+            SequenceError::TooShort {
+                type_query: "nt",
+                length: 3
+            }
+        );
+        assert_eq!(
+            validate_aa("MG").unwrap_err().to_string(),
+            "Amino acid sequence length too short: 2 residues, at least 4 are required"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! The `SequenceLocator` trait, the interface shared by the viRust/ViralSeq crates for locating
+//! sequences on a reference, and its implementation for the built-in engine.
+//!
+//! Crates that only need to locate and annotate sequences can depend on the trait and accept any
+//! implementation, e.g. `&dyn SequenceLocator`, so that the built-in engine can be swapped for
+//! another one without changing the calling code.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::sequence_locator::{BuiltinLocator, Region, SequenceLocator, gene_regions};
+//!
+//! let locator = BuiltinLocator::new("HXB2", "nt", 1);
+//! let location = locator.locate("ATGCATGCATGC").unwrap().unwrap();
+//!
+//! let regions = [Region::new("gag", 790, 2292)];
+//! let overlaps = locator.annotate(&location, &regions);
+//! assert_eq!(overlaps.len(), 1);
+//!
+//! let positions = locator.coordinates(&location);
+//! assert_eq!(positions.len(), 12);
+//!
+//! // The genes and LTRs of the reference, as in the region table of the LANL locator.
+//! let overlaps = locator.annotate(&location, &gene_regions("HXB2", "nt"));
+//! assert_eq!(overlaps[0].to_string(), "gag 1373-1384 (partial)");
+//! ```
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::reference::{self, Gene};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// A named interval on a reference sequence, such as a gene. Positions are inclusive, like the
+/// positions of `Locator`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub name: String,
+    pub start: RefPosition,
+    pub end: RefPosition,
+    /// The exon of a spliced gene, numbered from 1, see `reference::exons`; `None` for the other
+    /// regions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exon: Option<usize>,
+}
+
+impl Region {
+    /// The region `name` from the 1-based positions `start` to `end`.
+    pub fn new(name: impl Into<String>, start: usize, end: usize) -> Self {
+        Region {
+            name: name.into(),
+            start: RefPosition::new(start),
+            end: RefPosition::new(end),
+            exon: None,
+        }
+    }
+}
+
+impl From<&Gene<'_>> for Region {
+    fn from(gene: &Gene<'_>) -> Self {
+        Region {
+            name: gene.name.to_string(),
+            start: gene.start,
+            end: gene.end,
+            exon: None,
+        }
+    }
+}
+
+/// The genes and LTRs of `reference` for `sequence_type` (see `reference::GENES`) as regions, in
+/// the order of the reference. The spliced nucleotide genes, tat and rev, are one region per exon,
+/// so their intron is not part of them. Empty for an unknown reference.
+pub fn gene_regions(reference: &str, sequence_type: &str) -> Vec<Region> {
+    reference::genes(reference, sequence_type)
+        .flat_map(|gene| {
+            let exons = reference::exons(gene);
+            let spliced = exons.len() > 1;
+            exons
+                .into_iter()
+                .enumerate()
+                .map(move |(i, (start, end))| Region {
+                    name: gene.name.to_string(),
+                    start,
+                    end,
+                    exon: spliced.then_some(i + 1),
+                })
+        })
+        .collect()
+}
+
+/// The part of a region covered by a located sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegionOverlap {
+    /// Name of the region.
+    pub region: String,
+    /// First covered position of the region on the reference.
+    pub start: RefPosition,
+    /// Last covered position of the region on the reference (inclusive).
+    pub end: RefPosition,
+    /// Whether the whole region is covered.
+    pub complete: bool,
+    /// The exon of the region, for the exons of a spliced gene.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exon: Option<usize>,
+    /// Whether the location continues from this exon to the next or previous one of its gene
+    /// across the splice junction, as a spliced transcript does, instead of through the intron.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub spliced: bool,
+}
+
+impl Display for RegionOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let coverage = if self.complete { "complete" } else { "partial" };
+        write!(f, "{}", self.region)?;
+        if let Some(exon) = self.exon {
+            write!(f, " exon {}", exon)?;
+        }
+        write!(f, " {}-{} ({}", self.start, self.end, coverage)?;
+        if self.spliced {
+            write!(f, ", spliced")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Returns the regions overlapped by `location`, see `SequenceLocator::annotate`.
+pub fn overlaps(location: &Locator, regions: &[Region]) -> Vec<RegionOverlap> {
+    let segments = location.ref_segments();
+    let junctions = splice_junctions(location, regions);
+    regions
+        .iter()
+        .flat_map(|region| {
+            let spliced = region.exon.is_some()
+                && junctions.iter().any(|&(name, end, start)| {
+                    name == region.name && (end == region.end || start == region.start)
+                });
+            segments
+                .iter()
+                .filter(|segment| region.start <= segment.end && segment.start <= region.end)
+                .map(move |segment| RegionOverlap {
+                    region: region.name.clone(),
+                    start: region.start.max(segment.start),
+                    end: region.end.min(segment.end),
+                    complete: segment.start <= region.start && region.end <= segment.end,
+                    exon: region.exon,
+                    spliced,
+                })
+        })
+        .collect()
+}
+
+/// The splice junctions between consecutive exons of `regions` that `location` spans: the name
+/// of the gene, the end of the first exon and the start of the second, when two consecutive query
+/// residues are aligned to them, the intron deleted or skipped between split segments.
+fn splice_junctions<'a>(
+    location: &Locator,
+    regions: &'a [Region],
+) -> Vec<(&'a str, RefPosition, RefPosition)> {
+    let junctions: Vec<(&str, RefPosition, RefPosition)> = regions
+        .windows(2)
+        .filter(|pair| pair[0].name == pair[1].name && pair[0].exon.is_some())
+        .map(|pair| (pair[0].name.as_str(), pair[0].end, pair[1].start))
+        .collect();
+    if junctions.is_empty() {
+        return junctions;
+    }
+    let aligned: Vec<RefPosition> = location
+        .query_aligned_string
+        .bytes()
+        .zip(location.ref_positions())
+        .filter(|&(q, _)| q != b'-')
+        .filter_map(|(_, position)| position)
+        .collect();
+    junctions
+        .into_iter()
+        .filter(|&(_, end, start)| aligned.windows(2).any(|pair| pair == [end, start]))
+        .collect()
+}
+
+/// Locates query sequences on a reference.
+pub trait SequenceLocator {
+    /// Locates `query` on the reference. Returns `Ok(None)` if the query is valid but could not be
+    /// located, and an error if it is invalid.
+    fn locate(&self, query: &str) -> Result<Option<Locator>, BoxError>;
+
+    /// Returns the regions overlapped by `location`, in the order of `regions`. When the location
+    /// wraps around the end of the reference, a region is reported once per segment it overlaps.
+    fn annotate(&self, location: &Locator, regions: &[Region]) -> Vec<RegionOverlap> {
+        overlaps(location, regions)
+    }
+
+    /// Returns the reference position of every query residue of `location`, or `None` for residues
+    /// inserted relative to the reference.
+    fn coordinates(&self, location: &Locator) -> Vec<Option<RefPosition>> {
+        location
+            .query_aligned_string
+            .bytes()
+            .zip(location.ref_positions())
+            .filter(|&(q, _)| q != b'-')
+            .map(|(_, position)| position)
+            .collect()
+    }
+}
+
+/// The built-in engine: the CLI pipeline (`Args::validate` and `Locator::build`) for a fixed
+/// reference, query type and algorithm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinLocator {
+    pub reference: String,
+    pub type_query: String,
+    pub algorithm: u8,
+}
+
+impl BuiltinLocator {
+    pub fn new(reference: impl Into<String>, type_query: impl Into<String>, algorithm: u8) -> Self {
+        BuiltinLocator {
+            reference: reference.into(),
+            type_query: type_query.into(),
+            algorithm,
+        }
+    }
+}
+
+impl Default for BuiltinLocator {
+    fn default() -> Self {
+        BuiltinLocator::from(&Args::default())
+    }
+}
+
+impl From<&Args> for BuiltinLocator {
+    fn from(args: &Args) -> Self {
+        BuiltinLocator::new(
+            args.common.reference.clone(),
+            args.common.type_query.clone(),
+            args.common.algorithm,
+        )
+    }
+}
+
+impl SequenceLocator for BuiltinLocator {
+    fn locate(&self, query: &str) -> Result<Option<Locator>, BoxError> {
+        let args = Args {
+            common: CommonArgs {
+                query: vec![query.to_string()],
+                reference: self.reference.clone(),
+                type_query: self.type_query.clone(),
+                algorithm: self.algorithm,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .validate()?;
+        Ok(Locator::build(&args)?.pop().flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::RefSegment;
+
+    fn location(ref_start: usize, query: &str, reference: &str) -> Locator {
+        let ref_len = reference.bytes().filter(|&b| b != b'-').count();
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_start + ref_len - 1),
+            100.0,
+            true,
+            query.to_string(),
+            reference.to_string(),
+        )
+    }
+
+    fn positions(locator: &BuiltinLocator, loc: &Locator) -> Vec<Option<usize>> {
+        locator
+            .coordinates(loc)
+            .into_iter()
+            .map(|pos| pos.map(RefPosition::get))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_locate() {
+        let locator = BuiltinLocator::default();
+        let loc = locator.locate("ATGCATGCATGC").unwrap().unwrap();
+        assert_eq!(loc.ref_start.get(), 1373);
+        assert!(locator.locate("ATGCXYZ").is_err());
+
+        let dyn_locator: &dyn SequenceLocator = &locator;
+        assert_eq!(dyn_locator.locate("ATGCATGCATGC").unwrap(), Some(loc));
+    }
+
+    #[test]
+    fn test_annotate() {
+        let loc = location(100, "ACGTACGTAC", "ACGTACGTAC");
+        let regions = [
+            Region::new("before", 1, 99),
+            Region::new("left", 50, 104),
+            Region::new("inner", 102, 105),
+            Region::new("right", 109, 200),
+        ];
+        let overlaps = BuiltinLocator::default().annotate(&loc, &regions);
+        assert_eq!(
+            overlaps,
+            vec![
+                RegionOverlap {
+                    region: "left".to_string(),
+                    start: RefPosition::new(100),
+                    end: RefPosition::new(104),
+                    complete: false,
+                    exon: None,
+                    spliced: false
+                },
+                RegionOverlap {
+                    region: "inner".to_string(),
+                    start: RefPosition::new(102),
+                    end: RefPosition::new(105),
+                    complete: true,
+                    exon: None,
+                    spliced: false
+                },
+                RegionOverlap {
+                    region: "right".to_string(),
+                    start: RefPosition::new(109),
+                    end: RefPosition::new(109),
+                    complete: false,
+                    exon: None,
+                    spliced: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gene_regions() {
+        let regions = gene_regions("SIVmac239", "nt");
+        assert_eq!(regions[1], Region::new("gag", 1053, 2585));
+        // HXB2 2000-2200, the end of gag and the start of pol.
+        let loc = location(2000, "A", &"A".repeat(201));
+        let genes: Vec<String> = overlaps(&loc, &gene_regions("HXB2", "nt"))
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            genes,
+            ["gag 2000-2200 (partial)", "pol 2085-2200 (partial)"]
+        );
+        let loc = location(5000, "A", &"A".repeat(1000));
+        let genes = overlaps(&loc, &gene_regions("HXB2", "nt"));
+        assert_eq!(genes[2].region, "vpr");
+        assert!(genes[2].complete);
+    }
+
+    #[test]
+    fn test_spliced_genes() {
+        let regions = gene_regions("HXB2", "nt");
+        let tat: Vec<&Region> = regions.iter().filter(|r| r.name == "tat").collect();
+        assert_eq!(tat.len(), 2);
+        assert_eq!((tat[1].start.get(), tat[1].exon), (8379, Some(2)));
+        // The intron is in neither exon.
+        let loc = location(7000, "A", &"A".repeat(101));
+        let genes = overlaps(&loc, &regions);
+        assert!(genes.iter().all(|g| g.region != "tat" && g.region != "rev"));
+
+        // The end of tat exon 1 spliced to the start of exon 2.
+        let query = format!("{}{}{}", "A".repeat(46), "-".repeat(2333), "A".repeat(42));
+        let loc = location(6000, &query, &"A".repeat(46 + 2333 + 42));
+        let genes: Vec<String> = overlaps(&loc, &regions)
+            .iter()
+            .filter(|g| g.region == "tat")
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            genes,
+            [
+                "tat exon 1 6000-6045 (partial, spliced)",
+                "tat exon 2 8379-8420 (partial, spliced)"
+            ]
+        );
+        // Without the deletion, the location reads through the intron.
+        let loc = location(6000, "A", &"A".repeat(46 + 2333 + 42));
+        assert!(overlaps(&loc, &regions).iter().all(|g| !g.spliced));
+    }
+
+    #[test]
+    fn test_coordinates_with_indels() {
+        // Query: insertion of `TT` after position 11, deletion of reference position 13.
+        let loc = location(10, "ACTTG-TA", "AC--GCTA");
+        assert_eq!(
+            positions(&BuiltinLocator::default(), &loc),
+            vec![Some(10), Some(11), None, None, Some(12), Some(14), Some(15)]
+        );
+    }
+
+    #[test]
+    fn test_wrapped_location() {
+        let mut loc = Locator::new(
+            RefPosition::new(97),
+            RefPosition::new(4),
+            100.0,
+            false,
+            "ACGTACGT".to_string(),
+            "ACGTACGT".to_string(),
+        );
+        loc.segments = vec![
+            RefSegment {
+                start: RefPosition::new(97),
+                end: RefPosition::new(100),
+            },
+            RefSegment {
+                start: RefPosition::new(1),
+                end: RefPosition::new(4),
+            },
+        ];
+        let locator = BuiltinLocator::default();
+        let overlaps = locator.annotate(
+            &loc,
+            &[Region::new("ltr", 1, 10), Region::new("end", 90, 100)],
+        );
+        assert_eq!(
+            overlaps,
+            vec![
+                RegionOverlap {
+                    region: "ltr".to_string(),
+                    start: RefPosition::new(1),
+                    end: RefPosition::new(4),
+                    complete: false,
+                    exon: None,
+                    spliced: false
+                },
+                RegionOverlap {
+                    region: "end".to_string(),
+                    start: RefPosition::new(97),
+                    end: RefPosition::new(100),
+                    complete: false,
+                    exon: None,
+                    spliced: false
+                },
+            ]
+        );
+        assert_eq!(
+            positions(&locator, &loc),
+            vec![
+                Some(97),
+                Some(98),
+                Some(99),
+                Some(100),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,739 @@
+//! HTTP server for the locator, available with the `router` feature (the `server` feature also
+//! builds the `virust-locator-server` binary).
+//!
+//! The routes can be mounted inside an existing axum application with `router`, or served on
+//! their own with `serve`.
+//!
+//! # Routes
+//!
+//! - `POST /locate`: a JSON object with the `sequences` to locate, each either a sequence or an
+//!   object with its `id` and `sequence` (sequences without an ID are named after their 1-based
+//!   position), and optionally the `reference`, `type_query` and `algorithm`, with the same values
+//!   and defaults as the CLI options. Every sequence is validated and located like a `--query` of
+//!   the CLI, and its result is streamed back as soon as it is located, as one JSON object per line
+//!   (`application/x-ndjson`), in completion order. The stream ends with an `{"error": "..."}`
+//!   line if it was aborted, e.g. by the alignment timeout.
+//! - `POST /locate/batch`: multipart upload of a FASTA or FASTQ file, optionally gzip-compressed.
+//!   The file is read with the `input` module and every record is validated and located exactly
+//!   like a `--query` of the CLI. The response is a downloadable file (`Content-Disposition:
+//!   attachment`) with one row per record, in upload order. Form fields:
+//!   - `file` (required): the sequence file.
+//!   - `format`: `tsv` (default) or `json`.
+//!   - `reference`, `type_query`, `algorithm`: same values and defaults as the CLI options.
+//! - `POST /locate/batch/stream`: same upload as `/locate/batch`, but results are streamed back as
+//!   server-sent events while the batch is processed, so front-ends can show live progress. Records
+//!   are processed in parallel, so results arrive in completion order. Events:
+//!   - `result`: `{"index": i, "result": {...}}` for the record at upload position `i`.
+//!   - `progress`: `{"completed": n, "total": t}` after each result.
+//!   - `done`: `{"total": t, "found": f}` once every record has been processed.
+//!   - `error`: `{"error": "..."}` if the batch was aborted, e.g. by the alignment timeout.
+//! - `POST /jobs`: same upload as `/locate/batch`, processed in the background. Returns `202` with
+//!   the job, including its `id`. Only available when the router is built with a `JobQueue`; see
+//!   the `jobs` module. With the `webhooks` feature, an optional `webhook` form field sets an
+//!   `http(s)://` URL that receives a summary of the job when it finishes.
+//! - `GET /jobs/{id}`: job status (`queued`, `running`, `done` or `failed`) with its progress.
+//! - `GET /jobs/{id}/results`: the results of a `done` job, in the format of the upload; `409` if
+//!   the job is not done.
+//! - `GET /metrics`: Prometheus metrics, with the `metrics` feature. Only added by `serve`; an
+//!   application embedding `router` can merge `metrics::router()` itself.
+//!
+//! Errors are returned as JSON objects of the form `{"error": "..."}`.
+//!
+//! Requests are subject to `ServerLimits`: the request body size is capped, uploads with more
+//! records than the maximum number of sequences are rejected with `413`, clients over their rate
+//! limit receive `429` (each upload counts as one request), and uploads that are not processed
+//! within the alignment timeout fail with `503`. Jobs are subject to the body size and rate limits
+//! only, since they are processed in the background.
+
+use crate::BoxError;
+use crate::batch::{self, BatchResult};
+use crate::config::{Args, CommonArgs};
+use crate::input::{self, Record};
+use crate::limits::{RateLimiter, ServerLimits};
+use axum::body::{Body, Bytes};
+use axum::extract::multipart::MultipartError;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Multipart, State};
+use axum::http::{StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+mod jobs;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+
+pub use jobs::{JobQueue, JobStatus, JobSummary};
+
+#[derive(Debug, Clone)]
+struct AppState {
+    limits: ServerLimits,
+    limiter: Option<Arc<RateLimiter>>,
+    jobs: Option<JobQueue>,
+}
+
+/// An error response, serialized as `{"error": message}`.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(err: MultipartError) -> Self {
+        ApiError::new(err.status(), err.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(err: JsonRejection) -> Self {
+        ApiError::new(err.status(), err.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(serde_json::json!({ "error": self.message })),
+        )
+            .into_response()
+    }
+}
+
+/// Output formats of the batch endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BatchFormat {
+    Tsv,
+    Json,
+}
+
+impl BatchFormat {
+    fn parse(format: &str) -> Result<Self, ApiError> {
+        match format.trim().to_lowercase().as_str() {
+            "tsv" => Ok(BatchFormat::Tsv),
+            "json" => Ok(BatchFormat::Json),
+            _ => Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Format must be either 'tsv' or 'json'",
+            )),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            BatchFormat::Tsv => "text/tab-separated-values",
+            BatchFormat::Json => "application/json",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            BatchFormat::Tsv => "tsv",
+            BatchFormat::Json => "json",
+        }
+    }
+}
+
+/// Options of a batch upload, taken from the multipart form fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BatchOptions {
+    reference: String,
+    type_query: String,
+    algorithm: u8,
+    format: BatchFormat,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            reference: "HXB2".to_string(),
+            type_query: "nt".to_string(),
+            algorithm: 1,
+            format: BatchFormat::Tsv,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Returns the CLI arguments matching these options, without a query.
+    fn args(&self) -> Args {
+        Args {
+            common: CommonArgs {
+                reference: self.reference.clone(),
+                type_query: self.type_query.clone(),
+                algorithm: self.algorithm,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Validates and locates one record with `batch::BatchResult::locate`, recording the outcome in
+/// the metrics.
+fn locate_record(record: Record, options: &BatchOptions) -> BatchResult {
+    let result = BatchResult::locate(record, &options.args());
+
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = match &result {
+            BatchResult { found: true, .. } => "found",
+            BatchResult { error: None, .. } => "not_found",
+            _ => "error",
+        };
+        crate::metrics::QUERIES.with_label_values(&[outcome]).inc();
+    }
+
+    result
+}
+
+/// Locates every record in parallel, keeping the input order.
+fn locate_records(records: Vec<Record>, options: &BatchOptions) -> Vec<BatchResult> {
+    records
+        .into_par_iter()
+        .map(|record| locate_record(record, options))
+        .collect()
+}
+
+/// Renders the results of a batch in the requested format.
+fn render(results: &[BatchResult], format: BatchFormat) -> Result<Vec<u8>, BoxError> {
+    match format {
+        BatchFormat::Json => Ok(serde_json::to_vec_pretty(results)?),
+        BatchFormat::Tsv => {
+            let mut out = Vec::new();
+            batch::write_tsv(&mut out, results)?;
+            Ok(out)
+        }
+    }
+}
+
+/// A batch upload, read from a multipart form.
+struct Upload {
+    file: Bytes,
+    options: BatchOptions,
+    /// The `webhook` form field, only used by `POST /jobs`.
+    webhook: Option<String>,
+}
+
+/// Counts a request of the client against the rate limit.
+fn check_rate_limit(
+    state: &AppState,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+) -> Result<(), ApiError> {
+    let client = connect_info
+        .map(|Extension(ConnectInfo(addr))| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    if let Some(limiter) = &state.limiter
+        && !limiter.check(client)
+    {
+        return Err(ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Rate limit exceeded",
+        ));
+    }
+    Ok(())
+}
+
+/// Applies the rate limit and reads the multipart form of a batch upload.
+async fn read_upload(
+    state: &AppState,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    mut multipart: Multipart,
+) -> Result<Upload, ApiError> {
+    check_rate_limit(state, connect_info)?;
+
+    let mut file = None;
+    let mut options = BatchOptions::default();
+    let mut webhook = None;
+    while let Some(field) = multipart.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "file" => file = Some(field.bytes().await?),
+            "format" => options.format = BatchFormat::parse(&field.text().await?)?,
+            "reference" => options.reference = field.text().await?.trim().to_string(),
+            "type_query" => options.type_query = field.text().await?.trim().to_string(),
+            "algorithm" => {
+                options.algorithm = field.text().await?.trim().parse().map_err(|_| {
+                    ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "Algorithm must be either 1, 2 or 3",
+                    )
+                })?
+            }
+            "webhook" => webhook = Some(field.text().await?.trim().to_string()),
+            _ => {}
+        }
+    }
+    let file =
+        file.ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Missing 'file' field"))?;
+    Ok(Upload {
+        file,
+        options,
+        webhook,
+    })
+}
+
+/// Reads the records of an uploaded file, enforcing the maximum number of sequences.
+fn read_upload_records(file: &[u8], limits: &ServerLimits) -> Result<Vec<Record>, ApiError> {
+    let records = input::read_records(file)
+        .map_err(|err| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))?;
+    check_max_sequences(records.len(), limits)?;
+    Ok(records)
+}
+
+/// Rejects requests with more than the maximum number of sequences.
+fn check_max_sequences(sequences: usize, limits: &ServerLimits) -> Result<(), ApiError> {
+    if sequences > limits.max_sequences {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Too many sequences in one request, the maximum is {}",
+                limits.max_sequences
+            ),
+        ));
+    }
+    Ok(())
+}
+
+async fn locate_batch(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["locate_batch"])
+        .inc();
+
+    let Upload { file, options, .. } = read_upload(&state, connect_info, multipart).await?;
+    let limits = state.limits;
+    let format = options.format;
+    let task = tokio::task::spawn_blocking(move || -> Result<Vec<BatchResult>, ApiError> {
+        let records = read_upload_records(&file, &limits)?;
+        Ok(locate_records(records, &options))
+    });
+    let joined = match limits.alignment_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, task)
+            .await
+            .map_err(|_| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Alignment timed out"))?,
+        None => task.await,
+    };
+    let results = joined
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))??;
+
+    let body = render(&results, format)
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"locator_results.{}\"",
+                    format.extension()
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// A sequence of `POST /locate`: an object with its ID, or the sequence alone.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum LocateSequence {
+    Named { id: String, sequence: String },
+    Sequence(String),
+}
+
+/// The body of `POST /locate`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct LocateRequest {
+    sequences: Vec<LocateSequence>,
+    reference: String,
+    type_query: String,
+    algorithm: u8,
+}
+
+impl Default for LocateRequest {
+    fn default() -> Self {
+        let options = BatchOptions::default();
+        LocateRequest {
+            sequences: Vec::new(),
+            reference: options.reference,
+            type_query: options.type_query,
+            algorithm: options.algorithm,
+        }
+    }
+}
+
+impl LocateRequest {
+    /// The records of the sequences, named after their 1-based position when they have no ID,
+    /// and the options to locate them with.
+    fn into_records(self) -> (Vec<Record>, BatchOptions) {
+        let records = self
+            .sequences
+            .into_iter()
+            .enumerate()
+            .map(|(i, sequence)| match sequence {
+                LocateSequence::Named { id, sequence } => Record {
+                    id,
+                    sequence,
+                    quality: None,
+                },
+                LocateSequence::Sequence(sequence) => Record {
+                    id: (i + 1).to_string(),
+                    sequence,
+                    quality: None,
+                },
+            })
+            .collect();
+        let options = BatchOptions {
+            reference: self.reference,
+            type_query: self.type_query,
+            algorithm: self.algorithm,
+            ..Default::default()
+        };
+        (records, options)
+    }
+}
+
+/// Serializes `data` as a line of JSON.
+fn json_line(data: &impl Serialize) -> Bytes {
+    let mut line = serde_json::to_vec(data).unwrap_or_else(|err| {
+        serde_json::to_vec(&serde_json::json!({ "error": err.to_string() }))
+            .expect("an error message serializes")
+    });
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Locates the records on the calling (blocking) thread pool and sends the line of each result as
+/// it completes, followed by an `error` line if the stream is aborted. Stops early when the client
+/// disconnects or the deadline passes.
+fn stream_results(
+    records: Vec<Record>,
+    options: BatchOptions,
+    deadline: Option<Instant>,
+    tx: mpsc::Sender<Result<Bytes, Infallible>>,
+) {
+    let outcome = records
+        .into_par_iter()
+        .try_for_each_with(tx.clone(), |tx, record| {
+            if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                return Err("Alignment timed out".to_string());
+            }
+            let result = locate_record(record, &options);
+            tx.blocking_send(Ok(json_line(&result)))
+                .map_err(|_| "Client disconnected".to_string())
+        });
+    if let Err(message) = outcome {
+        let _ = tx.blocking_send(Ok(json_line(&serde_json::json!({ "error": message }))));
+    }
+}
+
+async fn locate(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    request: Result<Json<LocateRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["locate"])
+        .inc();
+
+    check_rate_limit(&state, connect_info)?;
+    let Json(request) = request?;
+    check_max_sequences(request.sequences.len(), &state.limits)?;
+    let (records, options) = request.into_records();
+
+    let deadline = state
+        .limits
+        .alignment_timeout
+        .map(|timeout| Instant::now() + timeout);
+    let (tx, rx) = mpsc::channel(64);
+    tokio::task::spawn_blocking(move || stream_results(records, options, deadline, tx));
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response())
+}
+
+/// Builds a server-sent event carrying `data` as JSON.
+fn json_event(name: &str, data: &impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|err| Event::default().event("error").data(err.to_string()))
+}
+
+/// Locates the records on the calling (blocking) thread pool and sends a `result` and a
+/// `progress` event as each record completes, followed by a final `done` or `error` event. Stops
+/// early when the client disconnects or the deadline passes.
+fn stream_records(
+    records: Vec<Record>,
+    options: BatchOptions,
+    deadline: Option<Instant>,
+    tx: mpsc::Sender<Result<Event, Infallible>>,
+) {
+    let total = records.len();
+    let completed = AtomicUsize::new(0);
+    let found = AtomicUsize::new(0);
+
+    let outcome =
+        records
+            .into_par_iter()
+            .enumerate()
+            .try_for_each_with(tx.clone(), |tx, (index, record)| {
+                if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                    return Err("Alignment timed out".to_string());
+                }
+                let result = locate_record(record, &options);
+                if result.found {
+                    found.fetch_add(1, Ordering::Relaxed);
+                }
+                let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+
+                let result_event = json_event(
+                    "result",
+                    &serde_json::json!({ "index": index, "result": result }),
+                );
+                let progress_event = json_event(
+                    "progress",
+                    &serde_json::json!({ "completed": done, "total": total }),
+                );
+                tx.blocking_send(Ok(result_event))
+                    .and_then(|_| tx.blocking_send(Ok(progress_event)))
+                    .map_err(|_| "Client disconnected".to_string())
+            });
+
+    let last_event = match outcome {
+        Ok(()) => json_event(
+            "done",
+            &serde_json::json!({ "total": total, "found": found.into_inner() }),
+        ),
+        Err(message) => json_event("error", &serde_json::json!({ "error": message })),
+    };
+    let _ = tx.blocking_send(Ok(last_event));
+}
+
+async fn locate_batch_stream(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["locate_batch_stream"])
+        .inc();
+
+    let Upload { file, options, .. } = read_upload(&state, connect_info, multipart).await?;
+    let limits = state.limits;
+    let records = tokio::task::spawn_blocking(move || read_upload_records(&file, &limits))
+        .await
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))??;
+
+    let deadline = limits
+        .alignment_timeout
+        .map(|timeout| Instant::now() + timeout);
+    let (tx, rx) = mpsc::channel(64);
+    tokio::task::spawn_blocking(move || stream_records(records, options, deadline, tx));
+
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+/// Returns the router with the locator routes, enforcing `limits`.
+///
+/// The router is generic over the state of the application it is mounted in, so it can be merged
+/// or nested into an existing axum `Router`:
+///
+/// ```no_run
+/// use axum::Router;
+/// use axum::routing::get;
+/// use virust_locator::limits::ServerLimits;
+///
+/// #[derive(Clone)]
+/// struct AppState;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let app: Router = Router::new()
+///     .route("/health", get(|| async { "ok" }))
+///     .nest("/locator", virust_locator::server::router(ServerLimits::default()))
+///     .with_state(AppState);
+///
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+/// axum::serve(
+///     listener,
+///     app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The client IP address used for rate limiting is taken from `ConnectInfo<SocketAddr>`; when the
+/// router is served without connect info, all clients share a single rate-limit bucket.
+pub fn router<S>(limits: ServerLimits) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    routes(limits, None)
+}
+
+/// Returns the router with the locator routes and the job routes backed by `jobs`, enforcing
+/// `limits`. See `router` for how to mount it.
+pub fn router_with_jobs<S>(limits: ServerLimits, jobs: JobQueue) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    routes(limits, Some(jobs))
+}
+
+fn routes<S>(limits: ServerLimits, jobs: Option<JobQueue>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let mut router = Router::new()
+        .route("/locate", post(locate))
+        .route("/locate/batch", post(locate_batch))
+        .route("/locate/batch/stream", post(locate_batch_stream));
+    if jobs.is_some() {
+        router = router
+            .route("/jobs", post(jobs::submit_job))
+            .route("/jobs/{id}", get(jobs::job_status))
+            .route("/jobs/{id}/results", get(jobs::job_results));
+    }
+
+    let state = AppState {
+        limits,
+        limiter: limits.rate_limiter().map(Arc::new),
+        jobs,
+    };
+    router
+        .layer(DefaultBodyLimit::max(limits.max_request_size))
+        .with_state(state)
+}
+
+/// Serves the HTTP API on `addr`, enforcing `limits`, until the process is terminated. The job
+/// routes are served when `jobs` is given. With the `metrics` feature, `GET /metrics` is served as
+/// well.
+pub async fn serve(
+    addr: SocketAddr,
+    limits: ServerLimits,
+    jobs: Option<JobQueue>,
+) -> Result<(), BoxError> {
+    let app: Router = routes(limits, jobs);
+
+    #[cfg(feature = "metrics")]
+    let app = app.merge(crate::metrics::router());
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, sequence: &str) -> Record {
+        Record {
+            id: id.to_string(),
+            sequence: sequence.to_string(),
+            quality: None,
+        }
+    }
+
+    #[test]
+    fn test_stream_records_events() {
+        let records = vec![record("good", "ATGCATGCATGC"), record("bad", "ATGCXYZ")];
+        let (tx, mut rx) = mpsc::channel(16);
+        stream_records(records, BatchOptions::default(), None, tx);
+
+        let mut events = 0;
+        while rx.try_recv().is_ok() {
+            events += 1;
+        }
+        // One result and one progress event per record, plus the final `done` event.
+        assert_eq!(events, 5);
+    }
+
+    #[test]
+    fn test_stream_results() {
+        let request: LocateRequest = serde_json::from_str(
+            r#"{"sequences": ["ATGCATGCATGC", {"id": "bad", "sequence": "ATGCXYZ"}]}"#,
+        )
+        .unwrap();
+        let (records, options) = request.into_records();
+        assert_eq!(options.reference, "HXB2");
+        let (tx, mut rx) = mpsc::channel(16);
+        stream_results(records, options, None, tx);
+
+        let mut lines = Vec::new();
+        while let Ok(Ok(line)) = rx.try_recv() {
+            let value: serde_json::Value = serde_json::from_slice(&line).unwrap();
+            let id = value["id"].as_str().unwrap().to_string();
+            lines.push((id, value["found"].as_bool().unwrap()));
+        }
+        lines.sort();
+        assert_eq!(lines, [("1".to_string(), true), ("bad".to_string(), false)]);
+    }
+
+    #[test]
+    fn test_render_tsv() {
+        let records = vec![record("good", "ATGCATGCATGC"), record("bad", "ATGCXYZ")];
+        let results = locate_records(records, &BatchOptions::default());
+        let body = String::from_utf8(render(&results, BatchFormat::Tsv).unwrap()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], batch::TSV_HEADER);
+        assert_eq!(lines[1].split('\t').count(), 11);
+        assert!(lines[1].starts_with("good\t"));
+        assert_eq!(lines[2].split('\t').count(), 11);
+        assert!(lines[2].starts_with("bad\t"));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let results = locate_records(
+            vec![record("good", "ATGCATGCATGC")],
+            &BatchOptions::default(),
+        );
+        let body = render(&results, BatchFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(value[0]["id"], "good");
+        assert_eq!(value[0]["found"], true);
+        assert!(value[0]["ref_start"].as_u64().unwrap() > 0);
+    }
+}
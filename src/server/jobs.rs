@@ -0,0 +1,607 @@
+//! Asynchronous batch jobs, so that large submissions do not require holding an HTTP connection
+//! open while they are processed.
+//!
+//! A job is submitted with the same multipart form as `POST /locate/batch` and processed in the
+//! background, one job at a time, in submission order. Every job is persisted in its own
+//! directory under the job directory:
+//!
+//! - `upload`: the uploaded file, as received.
+//! - `job.json`: the job status and options.
+//! - `results.tsv` or `results.json`: the results, once the job is done.
+//!
+//! When the server restarts, jobs that were queued or running are queued again (running jobs are
+//! restarted from the beginning), and finished jobs remain available for download. Jobs are never
+//! deleted by the server.
+//!
+//! With the `webhooks` feature, a job submitted with a `webhook` URL is announced to that URL when
+//! it finishes, with a `POST` of a JSON `JobSummary`, so orchestration systems don't need to poll
+//! the status endpoint. Failed deliveries are retried with exponential backoff; a notification
+//! that could not be delivered before a restart is sent again when the queue is reopened.
+
+use super::{
+    ApiError, AppState, BatchFormat, BatchOptions, BatchResult, Upload, locate_record, read_upload,
+    render,
+};
+use crate::BoxError;
+use crate::input;
+use axum::Extension;
+use axum::Json;
+use axum::extract::{ConnectInfo, Multipart, Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+const JOB_FILE: &str = "job.json";
+const UPLOAD_FILE: &str = "upload";
+
+/// Status of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A batch job, as returned by the status endpoint and persisted in `job.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Job {
+    id: String,
+    status: JobStatus,
+    #[serde(flatten)]
+    options: BatchOptions,
+    /// Number of records in the upload, once it has been read.
+    total: Option<usize>,
+    completed: usize,
+    found: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Submission time, in seconds since the Unix epoch.
+    submitted_at: u64,
+    /// Completion time, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    finished_at: Option<u64>,
+    /// URL notified when the job finishes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook: Option<String>,
+    /// Time the webhook was delivered, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook_delivered_at: Option<u64>,
+}
+
+/// Summary of a finished job, the body of its webhook notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub id: String,
+    pub status: JobStatus,
+    /// Number of records in the upload, if it could be read.
+    pub total: Option<usize>,
+    pub completed: usize,
+    pub found: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Path of the results, relative to the root of the routes, for jobs that are done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results: Option<String>,
+    /// Completion time, in seconds since the Unix epoch.
+    pub finished_at: Option<u64>,
+}
+
+impl From<&Job> for JobSummary {
+    fn from(job: &Job) -> Self {
+        JobSummary {
+            id: job.id.clone(),
+            status: job.status,
+            total: job.total,
+            completed: job.completed,
+            found: job.found,
+            error: job.error.clone(),
+            results: (job.status == JobStatus::Done).then(|| format!("/jobs/{}/results", job.id)),
+            finished_at: job.finished_at,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    job: Job,
+    /// Records processed so far, updated while the job is running.
+    progress: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    jobs: Mutex<HashMap<String, Entry>>,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+/// A persistent queue of batch jobs, processed by a background task.
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    inner: Arc<Inner>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns a new job id: the submission time in nanoseconds followed by a per-process counter,
+/// in hexadecimal.
+fn new_job_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!(
+        "{:x}{:04x}",
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed) & 0xffff
+    )
+}
+
+/// Writes `contents` to `path` through a temporary file, so readers never see a partial file.
+fn write_atomic(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+fn results_file(format: BatchFormat) -> String {
+    format!("results.{}", format.extension())
+}
+
+impl JobQueue {
+    /// Opens the job queue persisted in `dir`, creating the directory if needed, and starts
+    /// processing the jobs left queued or running by a previous run. Must be called from within a
+    /// Tokio runtime.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<JobQueue, BoxError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut jobs = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path().join(JOB_FILE);
+            if !path.is_file() {
+                continue;
+            }
+            let job: Job = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            let progress = Arc::new(AtomicUsize::new(0));
+            jobs.insert(job.id.clone(), Entry { job, progress });
+        }
+
+        let mut pending: Vec<&Job> = jobs
+            .values()
+            .map(|entry| &entry.job)
+            .filter(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+            .collect();
+        pending.sort_by(|a, b| (a.submitted_at, &a.id).cmp(&(b.submitted_at, &b.id)));
+        let pending: Vec<String> = pending.into_iter().map(|job| job.id.clone()).collect();
+
+        #[cfg(feature = "webhooks")]
+        let unannounced: Vec<String> = jobs
+            .values()
+            .map(|entry| &entry.job)
+            .filter(|job| matches!(job.status, JobStatus::Done | JobStatus::Failed))
+            .filter(|job| job.webhook.is_some() && job.webhook_delivered_at.is_none())
+            .map(|job| job.id.clone())
+            .collect();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for id in pending {
+            tx.send(id)?;
+        }
+        let queue = JobQueue {
+            inner: Arc::new(Inner {
+                dir,
+                jobs: Mutex::new(jobs),
+                tx,
+            }),
+        };
+
+        #[cfg(feature = "webhooks")]
+        for id in unannounced {
+            queue.notify(id);
+        }
+
+        let worker = queue.clone();
+        tokio::spawn(async move {
+            while let Some(id) = rx.recv().await {
+                let worker = worker.clone();
+                let _ = tokio::task::spawn_blocking(move || worker.run(&id)).await;
+            }
+        });
+
+        Ok(queue)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.inner.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn job_dir(&self, id: &str) -> PathBuf {
+        self.inner.dir.join(id)
+    }
+
+    fn persist(&self, job: &Job) {
+        let written = serde_json::to_vec_pretty(job)
+            .map_err(BoxError::from)
+            .and_then(|json| Ok(write_atomic(&self.job_dir(&job.id).join(JOB_FILE), &json)?));
+        if let Err(err) = written {
+            tracing::warn!("could not persist job {}: {}", job.id, err);
+        }
+    }
+
+    /// Returns the current state of a job.
+    fn get(&self, id: &str) -> Option<Job> {
+        self.lock().get(id).map(|entry| {
+            let mut job = entry.job.clone();
+            if job.status == JobStatus::Running {
+                job.completed = entry.progress.load(Ordering::Relaxed);
+            }
+            job
+        })
+    }
+
+    /// Stores an upload as a new queued job.
+    fn submit(
+        &self,
+        file: &[u8],
+        options: BatchOptions,
+        webhook: Option<String>,
+    ) -> Result<Job, BoxError> {
+        let job = Job {
+            id: new_job_id(),
+            status: JobStatus::Queued,
+            options,
+            total: None,
+            completed: 0,
+            found: 0,
+            error: None,
+            submitted_at: now(),
+            finished_at: None,
+            webhook,
+            webhook_delivered_at: None,
+        };
+        let dir = self.job_dir(&job.id);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(UPLOAD_FILE), file)?;
+        write_atomic(&dir.join(JOB_FILE), &serde_json::to_vec_pretty(&job)?)?;
+
+        self.lock().insert(
+            job.id.clone(),
+            Entry {
+                job: job.clone(),
+                progress: Arc::new(AtomicUsize::new(0)),
+            },
+        );
+        self.inner.tx.send(job.id.clone())?;
+
+        Ok(job)
+    }
+
+    /// Processes a queued job on the calling thread.
+    fn run(&self, id: &str) {
+        let (options, progress) = {
+            let mut jobs = self.lock();
+            let Some(entry) = jobs.get_mut(id) else {
+                return;
+            };
+            entry.job.status = JobStatus::Running;
+            entry.progress.store(0, Ordering::Relaxed);
+            self.persist(&entry.job);
+            (entry.job.options.clone(), entry.progress.clone())
+        };
+
+        let outcome = self.execute(id, &options, &progress);
+
+        if let Some(entry) = self.lock().get_mut(id) {
+            let job = &mut entry.job;
+            match outcome {
+                Ok(found) => {
+                    job.status = JobStatus::Done;
+                    job.completed = progress.load(Ordering::Relaxed);
+                    job.found = found;
+                }
+                Err(err) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(err.to_string());
+                }
+            }
+            job.finished_at = Some(now());
+            self.persist(job);
+        }
+
+        #[cfg(feature = "webhooks")]
+        self.notify(id.to_string());
+    }
+
+    /// Locates the records of a job and writes its results. Returns the number of records found.
+    fn execute(
+        &self,
+        id: &str,
+        options: &BatchOptions,
+        progress: &AtomicUsize,
+    ) -> Result<usize, BoxError> {
+        let dir = self.job_dir(id);
+        let records = input::read_records(fs::File::open(dir.join(UPLOAD_FILE))?)?;
+        if let Some(entry) = self.lock().get_mut(id) {
+            entry.job.total = Some(records.len());
+        }
+
+        let results: Vec<BatchResult> = records
+            .into_par_iter()
+            .map(|record| {
+                let result = locate_record(record, options);
+                progress.fetch_add(1, Ordering::Relaxed);
+                result
+            })
+            .collect();
+        let body = render(&results, options.format)?;
+        write_atomic(&dir.join(results_file(options.format)), &body)?;
+
+        Ok(results.iter().filter(|result| result.found).count())
+    }
+
+    /// Sends the webhook notification of a finished job in the background, if it has a webhook
+    /// that was not delivered yet. Must be called from within a Tokio runtime.
+    #[cfg(feature = "webhooks")]
+    fn notify(&self, id: String) {
+        let Some(job) = self.get(&id) else {
+            return;
+        };
+        let Some(url) = job.webhook.clone() else {
+            return;
+        };
+        if job.webhook_delivered_at.is_some() {
+            return;
+        }
+        let queue = self.clone();
+        tokio::spawn(async move {
+            match super::webhooks::deliver(&url, &JobSummary::from(&job)).await {
+                Ok(()) => {
+                    let mut jobs = queue.lock();
+                    if let Some(entry) = jobs.get_mut(&id) {
+                        entry.job.webhook_delivered_at = Some(now());
+                        queue.persist(&entry.job);
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("could not deliver the webhook of job {}: {}", id, err)
+                }
+            }
+        });
+    }
+}
+
+/// Checks the `webhook` field of a job submission.
+fn check_webhook(url: &str) -> Result<(), ApiError> {
+    if !cfg!(feature = "webhooks") {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Webhooks are not enabled",
+        ));
+    }
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Webhook must be an http:// or https:// URL",
+        ));
+    }
+    Ok(())
+}
+
+fn job_queue(state: &AppState) -> Result<&JobQueue, ApiError> {
+    state
+        .jobs
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Jobs are not enabled"))
+}
+
+fn unknown_job() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, "Unknown job")
+}
+
+/// `POST /jobs`: queues an upload and returns the job with `202 Accepted`.
+pub(super) async fn submit_job(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["submit_job"])
+        .inc();
+
+    let queue = job_queue(&state)?.clone();
+    let Upload {
+        file,
+        options,
+        webhook,
+    } = read_upload(&state, connect_info, multipart).await?;
+    if let Some(url) = &webhook {
+        check_webhook(url)?;
+    }
+    let job = tokio::task::spawn_blocking(move || queue.submit(&file, options, webhook))
+        .await
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
+
+/// `GET /jobs/{id}`: returns the status of a job.
+pub(super) async fn job_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["job_status"])
+        .inc();
+
+    let job = job_queue(&state)?.get(&id).ok_or_else(unknown_job)?;
+    Ok(Json(job).into_response())
+}
+
+/// `GET /jobs/{id}/results`: downloads the results of a finished job.
+pub(super) async fn job_results(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    #[cfg(feature = "metrics")]
+    crate::metrics::REQUESTS
+        .with_label_values(&["job_results"])
+        .inc();
+
+    let queue = job_queue(&state)?;
+    let job = queue.get(&id).ok_or_else(unknown_job)?;
+    match job.status {
+        JobStatus::Done => {}
+        JobStatus::Failed => {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!("Job failed: {}", job.error.unwrap_or_default()),
+            ));
+        }
+        JobStatus::Queued | JobStatus::Running => {
+            return Err(ApiError::new(StatusCode::CONFLICT, "Job is not finished"));
+        }
+    }
+
+    let format = job.options.format;
+    let path = queue.job_dir(&id).join(results_file(format));
+    let body = tokio::task::spawn_blocking(move || fs::read(path))
+        .await
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", id, format.extension()),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn wait_until_finished(queue: &JobQueue, id: &str) -> Job {
+        for _ in 0..1000 {
+            let job = queue.get(id).unwrap();
+            if matches!(job.status, JobStatus::Done | JobStatus::Failed) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_jobs_are_processed_and_persisted() {
+        let dir = std::env::temp_dir().join(format!("virust-locator-jobs-{}", std::process::id()));
+        let upload = b">good\nATGCATGCATGC\n>bad\nATGCXYZ\n";
+
+        let queue = JobQueue::open(&dir).unwrap();
+        let done = queue.submit(upload, BatchOptions::default(), None).unwrap();
+        let failed = queue
+            .submit(b"not a sequence file", BatchOptions::default(), None)
+            .unwrap();
+
+        let job = wait_until_finished(&queue, &done.id).await;
+        assert_eq!(job.status, JobStatus::Done);
+        assert_eq!(job.total, Some(2));
+        assert_eq!(job.completed, 2);
+        assert_eq!(job.found, 1);
+        let results = fs::read_to_string(dir.join(&done.id).join("results.tsv")).unwrap();
+        assert_eq!(results.lines().count(), 3);
+
+        let job = wait_until_finished(&queue, &failed.id).await;
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.unwrap().contains("Unrecognized input format"));
+
+        // A job left running by a previous process is queued again on restart.
+        let mut interrupted = queue.get(&done.id).unwrap();
+        interrupted.id = new_job_id();
+        interrupted.status = JobStatus::Running;
+        fs::create_dir_all(dir.join(&interrupted.id)).unwrap();
+        fs::write(dir.join(&interrupted.id).join(UPLOAD_FILE), upload).unwrap();
+        fs::write(
+            dir.join(&interrupted.id).join(JOB_FILE),
+            serde_json::to_vec(&interrupted).unwrap(),
+        )
+        .unwrap();
+
+        let restarted = JobQueue::open(&dir).unwrap();
+        assert_eq!(restarted.get(&done.id).unwrap().status, JobStatus::Done);
+        assert_eq!(restarted.get(&failed.id).unwrap().status, JobStatus::Failed);
+        let job = wait_until_finished(&restarted, &interrupted.id).await;
+        assert_eq!(job.status, JobStatus::Done);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[tokio::test]
+    async fn test_webhook_is_delivered() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let receiver = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(move |Json(summary): Json<JobSummary>| async move {
+                tx.send(summary).unwrap();
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let dir =
+            std::env::temp_dir().join(format!("virust-locator-webhook-{}", std::process::id()));
+        let queue = JobQueue::open(&dir).unwrap();
+        let job = queue
+            .submit(b">good\nATGCATGCATGC\n", BatchOptions::default(), Some(url))
+            .unwrap();
+
+        let summary = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.id, job.id);
+        assert_eq!(summary.status, JobStatus::Done);
+        assert_eq!(summary.found, 1);
+        assert_eq!(summary.results, Some(format!("/jobs/{}/results", job.id)));
+
+        for _ in 0..100 {
+            if queue.get(&job.id).unwrap().webhook_delivered_at.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(queue.get(&job.id).unwrap().webhook_delivered_at.is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
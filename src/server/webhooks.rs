@@ -0,0 +1,49 @@
+//! Delivery of webhook notifications, available with the `webhooks` feature.
+
+use crate::BoxError;
+use serde::Serialize;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Number of delivery attempts before a notification is given up.
+const ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Timeout of one delivery attempt.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()
+        .expect("failed to build the webhook client")
+});
+
+/// Posts `payload` as JSON to `url`, retrying with exponential backoff until the receiver answers
+/// with a success status or the attempts are exhausted.
+pub(super) async fn deliver<T: Serialize>(url: &str, payload: &T) -> Result<(), BoxError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let outcome = CLIENT
+            .post(url)
+            .json(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match outcome {
+            Ok(_) => return Ok(()),
+            Err(err) if attempt >= ATTEMPTS => return Err(err.into()),
+            Err(_) => {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Output sinks writing batch results to external systems.
+//!
+//! - `postgres` (with the `postgres` feature): inserts results and their mutation lists into a
+//!   PostgreSQL schema.
+//!
+//! The mutation list of a located sequence is derived from its alignment with `mutations`.
+
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::reference::Gene;
+use serde::Serialize;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+/// Kind of a difference between a query and the reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MutationKind {
+    Substitution,
+    Insertion,
+    Deletion,
+}
+
+impl MutationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MutationKind::Substitution => "substitution",
+            MutationKind::Insertion => "insertion",
+            MutationKind::Deletion => "deletion",
+        }
+    }
+}
+
+/// A difference between a located query and the reference.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mutation {
+    pub kind: MutationKind,
+    /// Reference position (1-based) of the substituted or deleted residue, or of the residue
+    /// after which the insertion occurs.
+    pub position: usize,
+    /// The reference residue, empty for insertions.
+    pub reference: String,
+    /// The query residue or the inserted residues, empty for deletions.
+    pub query: String,
+    /// `position` relative to the start of a gene, see `mutations_relative_to`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_position: Option<i64>,
+}
+
+/// Returns the substitutions, insertions and deletions of `location`, in alignment order.
+/// Consecutive inserted residues are reported as one insertion.
+pub fn mutations(location: &Locator) -> Vec<Mutation> {
+    mutations_relative_to(location, None)
+}
+
+/// Returns the mutations of `location` like `mutations`, with their `relative_position` to
+/// `relative_to` (see `Gene::relative`) when it is set. Insertions before the first position of
+/// the reference have no relative position.
+pub fn mutations_relative_to(location: &Locator, relative_to: Option<&Gene>) -> Vec<Mutation> {
+    let mut mutations: Vec<Mutation> = Vec::new();
+    let mut prev_ref_pos = location.ref_start.get() - 1;
+    let mut in_insertion = false;
+    for ((q, r), position) in location
+        .query_aligned_string
+        .chars()
+        .zip(location.ref_aligned_string.chars())
+        .zip(location.ref_positions())
+    {
+        let Some(ref_pos) = position else {
+            if q == '-' {
+                continue;
+            }
+            match mutations.last_mut() {
+                Some(last) if in_insertion => last.query.push(q),
+                _ => mutations.push(Mutation {
+                    kind: MutationKind::Insertion,
+                    position: prev_ref_pos,
+                    reference: String::new(),
+                    query: q.to_string(),
+                    relative_position: None,
+                }),
+            }
+            in_insertion = true;
+            continue;
+        };
+        match (q, r) {
+            ('-', r) => mutations.push(Mutation {
+                kind: MutationKind::Deletion,
+                position: ref_pos.get(),
+                reference: r.to_string(),
+                query: String::new(),
+                relative_position: None,
+            }),
+            (q, r) if !q.eq_ignore_ascii_case(&r) => mutations.push(Mutation {
+                kind: MutationKind::Substitution,
+                position: ref_pos.get(),
+                reference: r.to_string(),
+                query: q.to_string(),
+                relative_position: None,
+            }),
+            _ => {}
+        }
+        in_insertion = false;
+        prev_ref_pos = ref_pos.get();
+    }
+    if let Some(gene) = relative_to {
+        for mutation in &mut mutations {
+            mutation.relative_position = RefPosition::try_from(mutation.position)
+                .ok()
+                .map(|position| gene.relative(position));
+        }
+    }
+    mutations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations() {
+        let loc = Locator::new(
+            RefPosition::new(10),
+            RefPosition::new(16),
+            80.0,
+            true,
+            "ACTTGGT-A".to_string(),
+            "AC--GCTAA".to_string(),
+        );
+        assert_eq!(
+            mutations(&loc),
+            vec![
+                Mutation {
+                    kind: MutationKind::Insertion,
+                    position: 11,
+                    reference: String::new(),
+                    query: "TT".to_string(),
+                    relative_position: None,
+                },
+                Mutation {
+                    kind: MutationKind::Substitution,
+                    position: 13,
+                    reference: "C".to_string(),
+                    query: "G".to_string(),
+                    relative_position: None,
+                },
+                Mutation {
+                    kind: MutationKind::Deletion,
+                    position: 15,
+                    reference: "A".to_string(),
+                    query: String::new(),
+                    relative_position: None,
+                },
+            ]
+        );
+
+        let gag = crate::reference::retrieve_gene("HXB2", "nt", "gag").unwrap();
+        let relative: Vec<Option<i64>> = mutations_relative_to(&loc, Some(gag))
+            .iter()
+            .map(|mutation| mutation.relative_position)
+            .collect();
+        assert_eq!(relative, vec![Some(-779), Some(-777), Some(-775)]);
+    }
+}
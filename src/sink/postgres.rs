@@ -0,0 +1,242 @@
+//! PostgreSQL sink.
+//!
+//! Results are written to two tables of the configured schema, which are created if they do not
+//! exist:
+//!
+//! - `locator_results`: one row per query id, with the parameters of the run, the location and
+//!   the validation error, if any.
+//! - `locator_mutations`: the mutation list of every located query (see `sink::mutations`),
+//!   numbered by `ordinal` in alignment order. With `--relative-to`, `relative_position` holds the
+//!   position relative to the gene; it is added to existing tables if they lack it.
+//!
+//! Writes are upserts by query id: writing a query id again replaces its result and its whole
+//! mutation list, so a run can be repeated or resumed without creating duplicates. Results are
+//! written in batches, one transaction per batch.
+
+use super::mutations_relative_to;
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::config::Args;
+use postgres::{Client, NoTls};
+use std::collections::HashMap;
+
+/// Options of the PostgreSQL sink.
+#[derive(Debug, Clone)]
+pub struct PostgresOptions {
+    /// Connection string, as a URL (`postgresql://user@host/db`) or as `key=value` pairs.
+    pub url: String,
+    /// Schema holding the result tables.
+    pub schema: String,
+    /// Number of results written per transaction.
+    pub batch_size: usize,
+}
+
+/// Writes batch results to a PostgreSQL database.
+pub struct PostgresSink {
+    client: Client,
+    results_table: String,
+    mutations_table: String,
+    batch_size: usize,
+}
+
+/// Quotes a PostgreSQL identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn to_i32(value: usize) -> Result<i32, BoxError> {
+    Ok(i32::try_from(value)?)
+}
+
+impl PostgresSink {
+    /// Connects to the database and creates the schema and tables if they do not exist.
+    pub fn connect(options: &PostgresOptions) -> Result<Self, BoxError> {
+        let mut client = Client::connect(&options.url, NoTls)?;
+        let schema = quote_identifier(&options.schema);
+        let results_table = format!("{}.locator_results", schema);
+        let mutations_table = format!("{}.locator_mutations", schema);
+        client.batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS {schema};
+             CREATE TABLE IF NOT EXISTS {results_table} (
+                 query_id text PRIMARY KEY,
+                 reference text NOT NULL,
+                 type_query text NOT NULL,
+                 algorithm smallint NOT NULL,
+                 found boolean NOT NULL,
+                 ref_start integer,
+                 ref_end integer,
+                 percent_identity double precision,
+                 indel boolean,
+                 query_aligned_string text,
+                 ref_aligned_string text,
+                 error text,
+                 updated_at timestamptz NOT NULL DEFAULT now()
+             );
+             CREATE TABLE IF NOT EXISTS {mutations_table} (
+                 query_id text NOT NULL REFERENCES {results_table} (query_id) ON DELETE CASCADE,
+                 ordinal integer NOT NULL,
+                 kind text NOT NULL,
+                 position integer NOT NULL,
+                 reference text NOT NULL,
+                 query text NOT NULL,
+                 PRIMARY KEY (query_id, ordinal)
+             );
+             ALTER TABLE {mutations_table} ADD COLUMN IF NOT EXISTS relative_position integer;"
+        ))?;
+        Ok(PostgresSink {
+            client,
+            results_table,
+            mutations_table,
+            batch_size: options.batch_size.max(1),
+        })
+    }
+
+    /// Writes `results`, located with the reference, query type and algorithm of `options`.
+    /// When a query id occurs more than once, its last result is kept.
+    pub fn write(&mut self, results: &[BatchResult], options: &Args) -> Result<(), BoxError> {
+        let mut latest: HashMap<&str, &BatchResult> = HashMap::new();
+        let mut ids = Vec::new();
+        for result in results {
+            if latest.insert(&result.id, result).is_none() {
+                ids.push(result.id.as_str());
+            }
+        }
+        for chunk in ids.chunks(self.batch_size) {
+            let batch: Vec<&BatchResult> = chunk.iter().map(|id| latest[id]).collect();
+            self.write_batch(&batch, options)?;
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self, batch: &[&BatchResult], options: &Args) -> Result<(), BoxError> {
+        let mut ids = Vec::with_capacity(batch.len());
+        let mut found = Vec::with_capacity(batch.len());
+        let mut ref_start = Vec::with_capacity(batch.len());
+        let mut ref_end = Vec::with_capacity(batch.len());
+        let mut percent_identity = Vec::with_capacity(batch.len());
+        let mut indel = Vec::with_capacity(batch.len());
+        let mut query_aligned = Vec::with_capacity(batch.len());
+        let mut ref_aligned = Vec::with_capacity(batch.len());
+        let mut errors = Vec::with_capacity(batch.len());
+
+        let mut m_ids = Vec::new();
+        let mut m_ordinals = Vec::new();
+        let mut m_kinds = Vec::new();
+        let mut m_positions = Vec::new();
+        let mut m_references = Vec::new();
+        let mut m_queries = Vec::new();
+        let mut m_relative_positions = Vec::new();
+        let relative_to = options.relative_gene();
+
+        for result in batch {
+            ids.push(result.id.clone());
+            found.push(result.found);
+            errors.push(result.error.clone());
+            let loc = result.locator.as_ref();
+            ref_start.push(loc.map(|loc| to_i32(loc.ref_start.get())).transpose()?);
+            ref_end.push(loc.map(|loc| to_i32(loc.ref_end.get())).transpose()?);
+            percent_identity.push(loc.map(|loc| loc.percent_identity));
+            indel.push(loc.map(|loc| loc.indel));
+            query_aligned.push(loc.map(|loc| loc.query_aligned_string.clone()));
+            ref_aligned.push(loc.map(|loc| loc.ref_aligned_string.clone()));
+
+            for (ordinal, mutation) in loc
+                .map(|loc| mutations_relative_to(loc, relative_to))
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+            {
+                m_ids.push(result.id.clone());
+                m_ordinals.push(to_i32(ordinal + 1)?);
+                m_kinds.push(mutation.kind.as_str());
+                m_positions.push(to_i32(mutation.position)?);
+                m_references.push(mutation.reference);
+                m_queries.push(mutation.query);
+                m_relative_positions
+                    .push(mutation.relative_position.map(i32::try_from).transpose()?);
+            }
+        }
+
+        let mut transaction = self.client.transaction()?;
+        transaction.execute(
+            &format!(
+                "INSERT INTO {} (query_id, reference, type_query, algorithm, found, ref_start,
+                     ref_end, percent_identity, indel, query_aligned_string, ref_aligned_string,
+                     error)
+                 SELECT t.query_id, $2::text, $3::text, $4::smallint, t.found, t.ref_start,
+                     t.ref_end, t.percent_identity, t.indel, t.query_aligned_string,
+                     t.ref_aligned_string, t.error
+                 FROM UNNEST($1::text[], $5::boolean[], $6::integer[], $7::integer[],
+                     $8::double precision[], $9::boolean[], $10::text[], $11::text[], $12::text[])
+                     AS t(query_id, found, ref_start, ref_end, percent_identity, indel,
+                          query_aligned_string, ref_aligned_string, error)
+                 ON CONFLICT (query_id) DO UPDATE SET
+                     reference = EXCLUDED.reference,
+                     type_query = EXCLUDED.type_query,
+                     algorithm = EXCLUDED.algorithm,
+                     found = EXCLUDED.found,
+                     ref_start = EXCLUDED.ref_start,
+                     ref_end = EXCLUDED.ref_end,
+                     percent_identity = EXCLUDED.percent_identity,
+                     indel = EXCLUDED.indel,
+                     query_aligned_string = EXCLUDED.query_aligned_string,
+                     ref_aligned_string = EXCLUDED.ref_aligned_string,
+                     error = EXCLUDED.error,
+                     updated_at = now()",
+                self.results_table
+            ),
+            &[
+                &ids,
+                &options.common.reference,
+                &options.common.type_query,
+                &i16::from(options.common.algorithm),
+                &found,
+                &ref_start,
+                &ref_end,
+                &percent_identity,
+                &indel,
+                &query_aligned,
+                &ref_aligned,
+                &errors,
+            ],
+        )?;
+        transaction.execute(
+            &format!(
+                "DELETE FROM {} WHERE query_id = ANY($1)",
+                self.mutations_table
+            ),
+            &[&ids],
+        )?;
+        transaction.execute(
+            &format!(
+                "INSERT INTO {} (query_id, ordinal, kind, position, reference, query,
+                     relative_position)
+                 SELECT * FROM UNNEST($1::text[], $2::integer[], $3::text[], $4::integer[],
+                     $5::text[], $6::text[], $7::integer[])",
+                self.mutations_table
+            ),
+            &[
+                &m_ids,
+                &m_ordinals,
+                &m_kinds,
+                &m_positions,
+                &m_references,
+                &m_queries,
+                &m_relative_positions,
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("public"), "\"public\"");
+        assert_eq!(quote_identifier("lab\"data"), "\"lab\"\"data\"");
+    }
+}
@@ -0,0 +1,405 @@
+//! Reading inputs from and writing outputs to local files or object stores.
+//!
+//! A location is a local path or, with the `object-store` feature, an object-store URL:
+//!
+//! - `s3://bucket/key`: Amazon S3 and S3-compatible stores, configured with the usual `AWS_*`
+//!   environment variables (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `AWS_ENDPOINT`, ...).
+//! - `gs://bucket/key`: Google Cloud Storage, configured with the `GOOGLE_*` environment variables.
+//! - `az://container/key` and `abfs://`: Azure Blob Storage, configured with the `AZURE_*`
+//!   environment variables.
+//! - `http://` and `https://`: plain HTTP (WebDAV for writing).
+//! - `file:///path`: local files.
+//!
+//! Objects are streamed in both directions: inputs are read as they are downloaded, and outputs
+//! are uploaded in parts (multipart upload) while they are written, so no local staging is needed.
+//! An output only becomes visible once `Output::finish` succeeds.
+//!
+//! Local files are written atomically too: an output is written to a temporary file next to it,
+//! renamed over it by `Output::finish`, so readers never see a partial file and a failed run leaves
+//! any previous file in place. Outputs that are not regular files, such as `/dev/stdout` or named
+//! pipes, are written directly.
+//!
+//! The object-store functions run on an internal Tokio runtime and must not be called from within
+//! another Tokio runtime.
+
+use crate::BoxError;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Returns `true` if `location` is a URL (`scheme://...`) rather than a local path.
+pub fn is_url(location: &Path) -> bool {
+    location
+        .to_str()
+        .and_then(|s| s.split_once("://"))
+        .is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+        })
+}
+
+/// Opens `location` for reading.
+pub fn open(location: &Path) -> Result<Box<dyn Read + Send>, BoxError> {
+    if is_url(location) {
+        #[cfg(feature = "object-store")]
+        return object::open(location);
+        #[cfg(not(feature = "object-store"))]
+        return Err(unsupported(location));
+    }
+    let file = File::open(location)
+        .map_err(|err| BoxError::from(format!("{}: {}", location.display(), err)))?;
+    Ok(Box::new(file))
+}
+
+/// Creates `location` for writing, replacing any existing file or object.
+pub fn create(location: &Path) -> Result<Output, BoxError> {
+    if is_url(location) {
+        #[cfg(feature = "object-store")]
+        return Ok(Output {
+            inner: OutputInner::Object(object::ObjectWriter::new(location)?),
+        });
+        #[cfg(not(feature = "object-store"))]
+        return Err(unsupported(location));
+    }
+    let special = std::fs::metadata(location).is_ok_and(|metadata| !metadata.is_file());
+    let temp = match location.file_name() {
+        Some(name) if !special => Some(location.with_file_name(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ))),
+        _ => None,
+    };
+    let file = File::create(temp.as_deref().unwrap_or(location))
+        .map_err(|err| BoxError::from(format!("{}: {}", location.display(), err)))?;
+    Ok(Output {
+        inner: OutputInner::File(LocalFile {
+            writer: BufWriter::new(file),
+            temp,
+            path: location.to_path_buf(),
+        }),
+    })
+}
+
+/// Creates the directory `location` and its parents if they do not exist. Object stores have no
+/// directories, so nothing is done for URLs.
+pub fn create_dir_all(location: &Path) -> Result<(), BoxError> {
+    if !is_url(location) {
+        std::fs::create_dir_all(location)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "object-store"))]
+fn unsupported(location: &Path) -> BoxError {
+    BoxError::from(format!(
+        "{}: object-store URLs require the `object-store` feature",
+        location.display()
+    ))
+}
+
+/// An output file or object. Call `finish` once everything has been written; a file or object
+/// that is dropped without being finished is discarded.
+pub struct Output {
+    inner: OutputInner,
+}
+
+enum OutputInner {
+    File(LocalFile),
+    #[cfg(feature = "object-store")]
+    Object(object::ObjectWriter),
+}
+
+impl Output {
+    /// Flushes the output and, for files, renames the temporary file over the output or, for
+    /// objects, completes the upload.
+    pub fn finish(self) -> Result<(), BoxError> {
+        match self.inner {
+            OutputInner::File(file) => file.finish(),
+            #[cfg(feature = "object-store")]
+            OutputInner::Object(object) => object.finish(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            OutputInner::File(file) => file.writer.write(buf),
+            #[cfg(feature = "object-store")]
+            OutputInner::Object(object) => object.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            OutputInner::File(file) => file.writer.flush(),
+            #[cfg(feature = "object-store")]
+            OutputInner::Object(object) => object.flush(),
+        }
+    }
+}
+
+/// A local file, written to `temp` and renamed over `path` by `finish`, or written directly when
+/// `temp` is `None`.
+struct LocalFile {
+    writer: BufWriter<File>,
+    temp: Option<PathBuf>,
+    path: PathBuf,
+}
+
+impl LocalFile {
+    fn finish(mut self) -> Result<(), BoxError> {
+        self.writer.flush()?;
+        if let Some(temp) = &self.temp {
+            self.writer.get_ref().sync_all()?;
+            std::fs::rename(temp, &self.path)
+                .map_err(|err| BoxError::from(format!("{}: {}", self.path.display(), err)))?;
+            self.temp = None;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LocalFile {
+    /// Removes the temporary file of a file that is not finished.
+    fn drop(&mut self) {
+        if let Some(temp) = &self.temp {
+            let _ = std::fs::remove_file(temp);
+        }
+    }
+}
+
+#[cfg(feature = "object-store")]
+mod object {
+    use crate::BoxError;
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+    use object_store::ObjectStore;
+    use object_store::buffered::BufWriter;
+    use object_store::path::Path as ObjectPath;
+    use std::io::{self, Read, Write};
+    use std::path::Path;
+    use std::sync::{Arc, LazyLock};
+    use tokio::io::AsyncWriteExt;
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc;
+    use tokio::task::JoinHandle;
+    use url::Url;
+
+    /// Size of the chunks handed to the upload task.
+    const CHUNK_SIZE: usize = 1024 * 1024;
+
+    static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("failed to start the object-store runtime")
+    });
+
+    /// Environment variable prefixes holding object-store configuration.
+    const ENV_PREFIXES: [&str; 3] = ["AWS_", "GOOGLE_", "AZURE_"];
+
+    fn parse(location: &Path) -> Result<(Arc<dyn ObjectStore>, ObjectPath), BoxError> {
+        let url = Url::parse(&location.to_string_lossy())?;
+        let options = std::env::vars().filter_map(|(key, value)| {
+            ENV_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+                .then(|| (key.to_ascii_lowercase(), value))
+        });
+        let (store, path) = object_store::parse_url_opts(&url, options)?;
+        Ok((Arc::from(store), path))
+    }
+
+    /// Reads an object as it is downloaded.
+    struct ObjectReader {
+        rx: mpsc::Receiver<object_store::Result<Bytes>>,
+        current: Bytes,
+    }
+
+    impl Read for ObjectReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.current.is_empty() {
+                match self.rx.blocking_recv() {
+                    Some(chunk) => self.current = chunk.map_err(io::Error::other)?,
+                    None => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.current.len());
+            buf[..len].copy_from_slice(&self.current.split_to(len));
+            Ok(len)
+        }
+    }
+
+    pub(super) fn open(location: &Path) -> Result<Box<dyn Read + Send>, BoxError> {
+        let (store, path) = parse(location)?;
+        let result = RUNTIME
+            .block_on(store.get(&path))
+            .map_err(|err| BoxError::from(format!("{}: {}", location.display(), err)))?;
+
+        let (tx, rx) = mpsc::channel(16);
+        RUNTIME.spawn(async move {
+            let mut stream = result.into_stream();
+            while let Some(chunk) = stream.next().await {
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Box::new(ObjectReader {
+            rx,
+            current: Bytes::new(),
+        }))
+    }
+
+    enum Message {
+        Data(Bytes),
+        Finish,
+    }
+
+    /// Uploads an object while it is written.
+    pub(super) struct ObjectWriter {
+        tx: mpsc::Sender<Message>,
+        task: JoinHandle<Result<(), BoxError>>,
+        buf: Vec<u8>,
+        location: String,
+    }
+
+    impl ObjectWriter {
+        pub(super) fn new(location: &Path) -> Result<Self, BoxError> {
+            let (store, path) = parse(location)?;
+            let (tx, mut rx) = mpsc::channel(4);
+            let task = RUNTIME.spawn(async move {
+                let mut writer = BufWriter::new(store, path);
+                while let Some(message) = rx.recv().await {
+                    match message {
+                        Message::Data(bytes) => {
+                            if let Err(err) = writer.put(bytes).await {
+                                let _ = writer.abort().await;
+                                return Err(err.into());
+                            }
+                        }
+                        Message::Finish => {
+                            writer.shutdown().await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                // The writer was dropped without being finished.
+                Ok(writer.abort().await?)
+            });
+            Ok(ObjectWriter {
+                tx,
+                task,
+                buf: Vec::with_capacity(CHUNK_SIZE),
+                location: location.display().to_string(),
+            })
+        }
+
+        fn send(&mut self, message: Message) -> io::Result<()> {
+            self.tx
+                .blocking_send(message)
+                .map_err(|_| io::Error::other(format!("{}: upload was interrupted", self.location)))
+        }
+
+        fn send_buffer(&mut self) -> io::Result<()> {
+            if self.buf.is_empty() {
+                return Ok(());
+            }
+            let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+            self.send(Message::Data(Bytes::from(chunk)))
+        }
+
+        pub(super) fn finish(mut self) -> Result<(), BoxError> {
+            let sent = self.send_buffer().and_then(|_| self.send(Message::Finish));
+            let uploaded = RUNTIME
+                .block_on(self.task)?
+                .map_err(|err| BoxError::from(format!("{}: {}", self.location, err)));
+            // An upload error explains a failed send better than the send error itself.
+            uploaded?;
+            Ok(sent?)
+        }
+    }
+
+    impl Write for ObjectWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(buf);
+            if self.buf.len() >= CHUNK_SIZE {
+                self.send_buffer()?;
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.send_buffer()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url(Path::new("s3://bucket/reads.fastq.gz")));
+        assert!(is_url(Path::new("https://example.org/reads.fasta")));
+        assert!(!is_url(Path::new("reads.fasta")));
+        assert!(!is_url(Path::new("/data/run://1/reads.fasta")));
+    }
+
+    #[test]
+    fn test_local_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("virust-locator-storage-{}", std::process::id()));
+        let mut output = create(&path).unwrap();
+        output.write_all(b">q1\nATGC\n").unwrap();
+        output.finish().unwrap();
+
+        let mut content = String::new();
+        open(&path).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, ">q1\nATGC\n");
+
+        // An output dropped before it is finished leaves the previous file, and no temporary file.
+        let mut output = create(&path).unwrap();
+        output.write_all(b">q2\n").unwrap();
+        drop(output);
+        let mut content = String::new();
+        open(&path).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, ">q1\nATGC\n");
+        let prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+        let temp_files = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(&prefix)
+            })
+            .count();
+        assert_eq!(temp_files, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "object-store")]
+    #[test]
+    fn test_object_store_round_trip() {
+        let dir =
+            std::env::temp_dir().join(format!("virust-locator-object-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = format!("file://{}/results.tsv", dir.display());
+        let location = Path::new(&url);
+
+        let data = vec![b'A'; 3 * 1024 * 1024 + 17];
+        let mut output = create(location).unwrap();
+        output.write_all(&data).unwrap();
+        output.finish().unwrap();
+
+        let mut content = Vec::new();
+        open(location).unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Subtype classification of nucleotide queries against a panel of subtype reference or consensus
+//! sequences.
+//!
+//! `classify` aligns a query to every sequence of a `Panel` with algorithm 1, and reports the
+//! subtype of the closest sequence, with the closest other subtype and the identity margin between
+//! them: a small margin flags a query close to several subtypes, such as a recombinant.
+//!
+//! The panel is `--subtype-panel`, a FASTA file whose record IDs follow the LANL naming, the
+//! subtype first and separated by `.`, e.g. `B.FR.83.HXB2` or `C.ZA.04.04ZASK146`. There is no
+//! built-in panel: the references of `--reference` are a single strain of each virus, which cannot
+//! tell the subtypes of HIV-1 apart.
+//!
+//! The `subtype` subcommand (or `--subtype`) writes the classifications as tab-separated values,
+//! one row per query.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::locator::LocatorOptions;
+//! use virust_locator::subtype::{self, Panel};
+//!
+//! let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+//! let mutant = query.replace("GCG", "GAG");
+//! let fasta = format!(">B.FR.83.HXB2\n{}\n>C.XX.00.TEST\n{}\n", query, mutant);
+//! let panel = Panel::read(fasta.as_bytes()).unwrap();
+//! let classification = subtype::classify(query.as_bytes(), &panel, &LocatorOptions::default())
+//!     .unwrap()
+//!     .unwrap();
+//! assert_eq!(classification.subtype, "B");
+//! assert_eq!(classification.reference, "B.FR.83.HXB2");
+//! assert_eq!(classification.next_subtype.as_deref(), Some("C"));
+//! ```
+
+use crate::BoxError;
+use crate::input;
+use crate::locator::{self, LocatorOptions};
+use crate::parallel::*;
+use crate::sequence;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+/// A sequence of a subtype panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelEntry {
+    /// The name of the sequence, the ID of its FASTA record.
+    pub name: String,
+    /// The subtype of the sequence.
+    pub subtype: String,
+    /// The sequence, normalized.
+    pub sequence: Vec<u8>,
+}
+
+/// The sequences queries are classified against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panel {
+    /// The sequences, in the order of the panel.
+    pub entries: Vec<PanelEntry>,
+}
+
+impl Panel {
+    /// Reads a panel from FASTA data, which may be gzip-compressed. The subtype of a record is
+    /// its ID up to the first `.`, see `subtype_of`. Errors on an invalid sequence or an empty
+    /// panel.
+    pub fn read<R: Read>(reader: R) -> Result<Self, BoxError> {
+        let mut entries = Vec::new();
+        for record in input::read_records(reader)? {
+            let sequence = sequence::validate_nt(&record.sequence)
+                .map_err(|err| format!("Subtype panel record {}: {}", record.id, err))?;
+            entries.push(PanelEntry {
+                subtype: subtype_of(&record.id).to_string(),
+                name: record.id,
+                sequence: sequence.into_bytes(),
+            });
+        }
+        if entries.is_empty() {
+            return Err("Subtype panel has no records".into());
+        }
+        Ok(Panel { entries })
+    }
+}
+
+/// The subtype of the panel record `id`: its first `.`-separated field, e.g. `B` for
+/// `B.FR.83.HXB2` and `01_AE` for `01_AE.TH.90.CM240`.
+pub fn subtype_of(id: &str) -> &str {
+    id.split('.').next().unwrap_or(id)
+}
+
+/// The classification of a query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Classification {
+    /// The subtype of the closest sequence of the panel.
+    pub subtype: String,
+    /// The name of the closest sequence of the panel.
+    pub reference: String,
+    /// The percent identity of the query to the closest sequence.
+    pub percent_identity: f64,
+    /// The closest other subtype, `None` when the query aligns to a single subtype.
+    pub next_subtype: Option<String>,
+    /// The percent identity of the query to the closest sequence of `next_subtype`.
+    pub next_identity: Option<f64>,
+}
+
+impl Classification {
+    /// How much closer the query is to `subtype` than to `next_subtype`, in percentage points.
+    pub fn margin(&self) -> Option<f64> {
+        self.next_identity
+            .map(|identity| self.percent_identity - identity)
+    }
+}
+
+/// Classifies the normalized nucleotide `query` against `panel`, aligning it with the scoring of
+/// `options`. Ties keep the order of the panel. `None` when the query aligns to no sequence of
+/// the panel.
+pub fn classify(
+    query: &[u8],
+    panel: &Panel,
+    options: &LocatorOptions,
+) -> Result<Option<Classification>, BoxError> {
+    // The best identity of each subtype, with the sequence giving it, in the order of the panel.
+    let mut best: Vec<(&str, &str, f64)> = Vec::new();
+    for entry in &panel.entries {
+        let Ok(loc) = locator::align_to(query, &entry.sequence, options)? else {
+            continue;
+        };
+        let identity = loc.percent_identity;
+        match best
+            .iter()
+            .position(|(subtype, ..)| *subtype == entry.subtype)
+        {
+            Some(i) if best[i].2 >= identity => {}
+            Some(i) => best[i] = (entry.subtype.as_str(), entry.name.as_str(), identity),
+            None => best.push((entry.subtype.as_str(), entry.name.as_str(), identity)),
+        }
+    }
+    best.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let mut best = best.into_iter();
+    let Some((subtype, reference, percent_identity)) = best.next() else {
+        return Ok(None);
+    };
+    let next = best.next();
+    Ok(Some(Classification {
+        subtype: subtype.to_string(),
+        reference: reference.to_string(),
+        percent_identity,
+        next_subtype: next.map(|(subtype, ..)| subtype.to_string()),
+        next_identity: next.map(|(.., identity)| identity),
+    }))
+}
+
+/// Classifies the nucleotide `queries`, normalized first (see `sequence::normalize`), in
+/// parallel.
+pub fn classify_all(
+    queries: &[String],
+    panel: &Panel,
+    options: &LocatorOptions,
+) -> Result<Vec<Option<Classification>>, BoxError> {
+    queries
+        .par_iter()
+        .map(|query| classify(sequence::normalize(query, "nt").as_bytes(), panel, options))
+        .collect()
+}
+
+/// The header of the tab-separated rows of `write_tsv`.
+pub const TSV_HEADER: &str =
+    "query_id\tsubtype\treference\tpercent_identity\tnext_subtype\tnext_identity\tmargin";
+
+/// Writes the classification of the query `query_id` as a tab-separated row, with empty
+/// `next_subtype`, `next_identity` and `margin` fields when there is no other subtype.
+pub fn write_tsv<W: Write>(
+    mut writer: W,
+    query_id: &str,
+    classification: &Classification,
+) -> Result<(), BoxError> {
+    let field = |value: Option<f64>| value.map_or(String::new(), |value| format!("{:.2}", value));
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{:.2}\t{}\t{}\t{}",
+        query_id,
+        classification.subtype,
+        classification.reference,
+        classification.percent_identity,
+        classification.next_subtype.as_deref().unwrap_or(""),
+        field(classification.next_identity),
+        field(classification.margin())
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+
+    #[test]
+    fn test_subtype_of() {
+        assert_eq!(subtype_of("B.FR.83.HXB2"), "B");
+        assert_eq!(subtype_of("01_AE.TH.90.CM240"), "01_AE");
+        assert_eq!(subtype_of("consensus"), "consensus");
+    }
+
+    #[test]
+    fn test_classify() {
+        let panel = Panel::read(
+            format!(
+                ">B.FR.83.HXB2\n{}\n>C.XX.00.MUTANT\n{}\n",
+                QUERY,
+                QUERY.replace("GCG", "GAG")
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(panel.entries[1].subtype, "C");
+
+        let options = LocatorOptions::default();
+        let classification = classify(QUERY.as_bytes(), &panel, &options)
+            .unwrap()
+            .unwrap();
+        assert_eq!(classification.subtype, "B");
+        assert_eq!(classification.reference, "B.FR.83.HXB2");
+        assert_eq!(classification.percent_identity, 100.0);
+        assert_eq!(classification.next_subtype.as_deref(), Some("C"));
+        assert!(classification.margin().unwrap() > 0.0);
+
+        let mut out = Vec::new();
+        write_tsv(&mut out, "q1", &classification).unwrap();
+        let row = String::from_utf8(out).unwrap();
+        assert!(row.starts_with("q1\tB\tB.FR.83.HXB2\t100.00\tC\t"));
+    }
+
+    #[test]
+    fn test_panel_errors() {
+        assert!(Panel::read(&b""[..]).is_err());
+        assert!(Panel::read(&b">B.bad\nACGT!@#$\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! Run-level summary of the results of a batch, for the QC of a run without post-processing the
+//! results of every record.
+//!
+//! With `--summary`, the results of `--input` are summarized once they are located, and the
+//! summary written to stderr, or with `--summary-file` to a file: as JSON, or as tab-separated
+//! values if its name ends in `.tsv`. A `Summary` has:
+//!
+//! - the record `counts`: located, not located and invalid, as in the workflow manifest;
+//! - the mean and median percent identity of the located records;
+//! - the number of located records overlapping every gene and LTR of the reference;
+//! - a coverage histogram: the mean depth of every `COVERAGE_BIN_SIZE` positions of the
+//!   reference, the number of locations covering each position (see `depth::DepthTrack`).
+//!
+//! The tab-separated summary has three columns, `section`, `name` and `value`, with one row per
+//! count, statistic, gene and bin.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::batch::BatchResult;
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//! use virust_locator::summary::Summarizer;
+//!
+//! // HXB2 790-849, in gag.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let outcome = Locator::try_build(&args).unwrap().remove(0);
+//! let mut summarizer = Summarizer::new("HXB2", "nt").unwrap();
+//! summarizer.add(&BatchResult::from_outcome("read1".to_string(), outcome));
+//! let summary = summarizer.finish();
+//! assert_eq!(summary.counts.found, 1);
+//! assert_eq!(summary.median_identity, Some(100.0));
+//! assert_eq!(summary.genes[0].gene, "5LTR");
+//! assert_eq!(summary.genes.iter().find(|g| g.gene == "gag").unwrap().located, 1);
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::depth::DepthTrack;
+use crate::position::RefPosition;
+use crate::sequence_locator::{self, Region};
+use crate::workflow::Counts;
+use serde::Serialize;
+use std::io::Write;
+
+/// The number of reference positions of every bin of the coverage histogram.
+pub const COVERAGE_BIN_SIZE: usize = 100;
+
+/// The header of the tab-separated summary of `write_tsv`.
+pub const TSV_HEADER: &str = "section\tname\tvalue";
+
+/// The summary of the results of a run, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    /// The reference the records were located on.
+    pub reference: String,
+    /// The record counts.
+    pub counts: Counts,
+    /// The mean percent identity of the located records, if any.
+    pub mean_identity: Option<f64>,
+    /// The median percent identity of the located records, if any.
+    pub median_identity: Option<f64>,
+    /// The genes and LTRs of the reference, in its order, with the located records overlapping
+    /// them.
+    pub genes: Vec<GeneCount>,
+    /// The coverage histogram, from the start of the reference.
+    pub coverage: Vec<CoverageBin>,
+}
+
+/// The number of located records overlapping a gene or LTR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GeneCount {
+    /// The gene or LTR, e.g. `gag`.
+    pub gene: String,
+    /// The number of located records overlapping it.
+    pub located: usize,
+}
+
+/// A bin of the coverage histogram.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageBin {
+    /// First position of the bin on the reference.
+    pub start: RefPosition,
+    /// Last position of the bin on the reference (inclusive).
+    pub end: RefPosition,
+    /// The mean number of locations covering the positions of the bin.
+    pub mean_depth: f64,
+}
+
+/// Summarizes results as they are located, one at a time, see `Summary`.
+#[derive(Debug, Clone)]
+pub struct Summarizer {
+    reference: String,
+    counts: Counts,
+    identities: Vec<f64>,
+    regions: Vec<Region>,
+    genes: Vec<GeneCount>,
+    depths: DepthTrack,
+}
+
+impl Summarizer {
+    /// A summarizer of the results of queries of type `type_query` located on `reference`.
+    pub fn new(reference: &str, type_query: &str) -> Result<Self, BoxError> {
+        let depths = DepthTrack::new(reference, type_query)?;
+        let regions = sequence_locator::gene_regions(reference, type_query);
+        let mut genes: Vec<GeneCount> = Vec::new();
+        for region in &regions {
+            // The exons of a spliced gene are counted together.
+            if genes.iter().all(|gene| gene.gene != region.name) {
+                genes.push(GeneCount {
+                    gene: region.name.clone(),
+                    located: 0,
+                });
+            }
+        }
+        Ok(Summarizer {
+            reference: reference.to_string(),
+            counts: Counts::default(),
+            identities: Vec::new(),
+            regions,
+            genes,
+            depths,
+        })
+    }
+
+    /// Adds `result` to the summary.
+    pub fn add(&mut self, result: &BatchResult) {
+        self.counts
+            .add(Counts::from_results(std::slice::from_ref(result)));
+        let Some(loc) = &result.locator else {
+            return;
+        };
+        self.identities.push(loc.percent_identity);
+        self.depths.add(loc);
+        let overlaps = sequence_locator::overlaps(loc, &self.regions);
+        for gene in &mut self.genes {
+            if overlaps.iter().any(|overlap| overlap.region == gene.gene) {
+                gene.located += 1;
+            }
+        }
+    }
+
+    /// The summary of the results added.
+    pub fn finish(mut self) -> Summary {
+        let coverage = self
+            .depths
+            .depths()
+            .chunks(COVERAGE_BIN_SIZE)
+            .enumerate()
+            .map(|(i, bin)| CoverageBin {
+                start: RefPosition::new(i * COVERAGE_BIN_SIZE + 1),
+                end: RefPosition::new(i * COVERAGE_BIN_SIZE + bin.len()),
+                mean_depth: bin.iter().sum::<usize>() as f64 / bin.len() as f64,
+            })
+            .collect();
+        let count = self.identities.len();
+        let mean_identity = (count > 0).then(|| self.identities.iter().sum::<f64>() / count as f64);
+        self.identities.sort_by(f64::total_cmp);
+        let median_identity = (count > 0).then(|| {
+            if count % 2 == 1 {
+                self.identities[count / 2]
+            } else {
+                (self.identities[count / 2 - 1] + self.identities[count / 2]) / 2.0
+            }
+        });
+        Summary {
+            reference: self.reference,
+            counts: self.counts,
+            mean_identity,
+            median_identity,
+            genes: self.genes,
+            coverage,
+        }
+    }
+}
+
+/// The summary of `results` of queries of type `type_query` located on `reference`.
+pub fn summarize(
+    results: &[BatchResult],
+    reference: &str,
+    type_query: &str,
+) -> Result<Summary, BoxError> {
+    let mut summarizer = Summarizer::new(reference, type_query)?;
+    for result in results {
+        summarizer.add(result);
+    }
+    Ok(summarizer.finish())
+}
+
+/// Writes `summary` as tab-separated values with a header line, see the module documentation.
+/// The statistics of a run without located records are empty.
+pub fn write_tsv<W: Write>(mut writer: W, summary: &Summary) -> Result<(), BoxError> {
+    writeln!(writer, "{}", TSV_HEADER)?;
+    let counts = &summary.counts;
+    for (name, count) in [
+        ("records", counts.records),
+        ("found", counts.found),
+        ("not_found", counts.not_found),
+        ("invalid", counts.invalid),
+    ] {
+        writeln!(writer, "counts\t{}\t{}", name, count)?;
+    }
+    for (name, identity) in [
+        ("mean", summary.mean_identity),
+        ("median", summary.median_identity),
+    ] {
+        let identity = identity.map(|identity| identity.to_string());
+        writeln!(
+            writer,
+            "identity\t{}\t{}",
+            name,
+            identity.unwrap_or_default()
+        )?;
+    }
+    for gene in &summary.genes {
+        writeln!(writer, "gene\t{}\t{}", gene.gene, gene.located)?;
+    }
+    for bin in &summary.coverage {
+        writeln!(
+            writer,
+            "coverage\t{}-{}\t{}",
+            bin.start, bin.end, bin.mean_depth
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::Locator;
+
+    fn result(id: &str, ref_start: usize, ref_end: usize, identity: f64) -> BatchResult {
+        let len = ref_end - ref_start + 1;
+        let loc = Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_end),
+            identity,
+            false,
+            "A".repeat(len),
+            "A".repeat(len),
+        );
+        BatchResult {
+            id: id.to_string(),
+            found: true,
+            locator: Some(loc),
+            error: None,
+            not_located: None,
+            inferred_type: None,
+            disagreements: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let invalid = BatchResult {
+            found: false,
+            locator: None,
+            error: Some("Invalid query".to_string()),
+            ..result("read3", 1, 1, 0.0)
+        };
+        let results = [
+            result("read1", 1, 150, 90.0),
+            result("read2", 51, 100, 100.0),
+            invalid,
+        ];
+        let summary = summarize(&results, "HXB2", "nt").unwrap();
+        assert_eq!(summary.counts.records, 3);
+        assert_eq!(summary.counts.found, 2);
+        assert_eq!(summary.counts.invalid, 1);
+        assert_eq!(summary.mean_identity, Some(95.0));
+        assert_eq!(summary.median_identity, Some(95.0));
+        assert_eq!(summary.genes[0].located, 2);
+        assert!(summary.genes[1..].iter().all(|gene| gene.located == 0));
+        assert_eq!(summary.coverage.len(), 98);
+        assert_eq!(summary.coverage[0].mean_depth, 1.5);
+        assert_eq!(summary.coverage[1].mean_depth, 0.5);
+        assert_eq!(summary.coverage[97].end.get(), 9719);
+
+        let mut out = Vec::new();
+        write_tsv(&mut out, &summary).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], TSV_HEADER);
+        assert_eq!(lines[2], "counts\tfound\t2");
+        assert_eq!(lines[6], "identity\tmedian\t95");
+        assert_eq!(lines[7], "gene\t5LTR\t2");
+        assert!(out.contains("\ncoverage\t101-200\t0.5\n"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Helpers for the tests of crates embedding the locator, available with the `test-utils` feature.
+//!
+//! - `KNOWN_QUERIES`: queries cut from the references, with the coordinates they locate at, to
+//!   check that a pipeline passes queries and results through correctly.
+//! - `MiniLocator`: a `SequenceLocator` over a miniature reference, the first 120 bases of HXB2
+//!   gag, that only locates exact substrings. It answers instantly, for tests of code accepting any
+//!   `SequenceLocator` that do not need real alignments.
+//! - `assert_located`, `assert_not_located` and `KnownQuery::assert_matches`: assertions on
+//!   results with messages that show the location found.
+//!
+//! Enable the feature for the tests only:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! virust-locator = { version = "0.1", features = ["test-utils"] }
+//! ```
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::prelude::*;
+//! use virust_locator::test_utils::{self, KNOWN_QUERIES};
+//!
+//! let query = &KNOWN_QUERIES[0];
+//! let locator = BuiltinLocator::new(query.reference, query.type_query, 1);
+//! query.assert_matches(locator.locate(query.sequence).unwrap().as_ref());
+//!
+//! let mini = test_utils::MiniLocator;
+//! test_utils::assert_located(mini.locate("GCGAGAGCG").unwrap().as_ref(), 7, 15);
+//! ```
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::locator::Locator;
+use crate::position::RefPosition;
+use crate::sequence;
+use crate::sequence_locator::SequenceLocator;
+
+/// A query cut from a reference, located at `ref_start`-`ref_end` with 100% identity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnownQuery {
+    pub name: &'static str,
+    pub reference: &'static str,
+    pub type_query: &'static str,
+    pub sequence: &'static str,
+    pub ref_start: usize,
+    pub ref_end: usize,
+}
+
+/// Queries with known coordinates on HXB2 and SIVmm239: the first 60 bases or 20 amino acids of
+/// genes.
+pub static KNOWN_QUERIES: [KnownQuery; 6] = [
+    KnownQuery {
+        name: "HXB2 gag start",
+        reference: "HXB2",
+        type_query: "nt",
+        sequence: "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG",
+        ref_start: 790,
+        ref_end: 849,
+    },
+    KnownQuery {
+        name: "HXB2 env start",
+        reference: "HXB2",
+        type_query: "nt",
+        sequence: "ATGAGAGTGAAGGAGAAATATCAGCACTTGTGGAGATGGGGGTGGAGATGGGGCACCATG",
+        ref_start: 6225,
+        ref_end: 6284,
+    },
+    KnownQuery {
+        name: "HXB2 nef start",
+        reference: "HXB2",
+        type_query: "nt",
+        sequence: "ATGGGTGGCAAGTGGTCAAAAAGTAGTGTGATTGGATGGCCTACTGTAAGGGAAAGAATG",
+        ref_start: 8797,
+        ref_end: 8856,
+    },
+    KnownQuery {
+        name: "HXB2 Gag start",
+        reference: "HXB2",
+        type_query: "aa",
+        sequence: "MGARASVLSGGELDRWEKIR",
+        ref_start: 1,
+        ref_end: 20,
+    },
+    KnownQuery {
+        name: "HXB2 Env start",
+        reference: "HXB2",
+        type_query: "aa",
+        sequence: "MRVKEKYQHLWRWGWRWGTM",
+        ref_start: 2091,
+        ref_end: 2110,
+    },
+    KnownQuery {
+        name: "SIVmm239 gag start",
+        reference: "SIVmm239",
+        type_query: "nt",
+        sequence: "ATGGGCGTGAGAAACTCCGTCTTGTCAGGGAAGAAAGCAGATGAATTAGAAAAAATTAGG",
+        ref_start: 1053,
+        ref_end: 1112,
+    },
+];
+
+impl KnownQuery {
+    /// The validated CLI arguments locating the query with `algorithm`.
+    pub fn args(&self, algorithm: u8) -> Args {
+        Args {
+            common: CommonArgs {
+                query: vec![self.sequence.to_string()],
+                reference: self.reference.to_string(),
+                type_query: self.type_query.to_string(),
+                algorithm,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .validate()
+        .expect("known queries are valid")
+    }
+
+    /// Asserts that `location` is the known location of the query, with 100% identity.
+    #[track_caller]
+    pub fn assert_matches(&self, location: Option<&Locator>) {
+        assert_located(location, self.ref_start, self.ref_end);
+        let location = location.unwrap();
+        assert_eq!(
+            location.percent_identity, 100.0,
+            "{} located with {}% identity",
+            self.name, location.percent_identity
+        );
+    }
+}
+
+/// The miniature reference of `MiniLocator`: HXB2 790-909, the first 120 bases of gag.
+pub const MINI_REFERENCE: &str = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGGTTAAGGCCAGGGGGAAAGAAAAAATATAAATTAAAACATATAGTATGGGCAAGCAGGGAG";
+
+/// A `SequenceLocator` over `MINI_REFERENCE` that locates the nucleotide queries found as they are
+/// in it, without alignment. Other valid queries are not located.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MiniLocator;
+
+impl SequenceLocator for MiniLocator {
+    fn locate(&self, query: &str) -> Result<Option<Locator>, BoxError> {
+        let query = sequence::validate_nt(query)?;
+        Ok(MINI_REFERENCE.find(&query).map(|index| {
+            Locator::new(
+                RefPosition::from_zero_based(index),
+                RefPosition::new(index + query.len()),
+                100.0,
+                false,
+                query.clone(),
+                query.clone(),
+            )
+        }))
+    }
+}
+
+/// Asserts that `location` was located at `ref_start`-`ref_end`, 1-based and inclusive.
+#[track_caller]
+pub fn assert_located(location: Option<&Locator>, ref_start: usize, ref_end: usize) {
+    let Some(location) = location else {
+        panic!(
+            "expected a location at {}-{}, not located",
+            ref_start, ref_end
+        );
+    };
+    assert_eq!(
+        (location.ref_start.get(), location.ref_end.get()),
+        (ref_start, ref_end),
+        "expected a location at {}-{}, located at {}-{}",
+        ref_start,
+        ref_end,
+        location.ref_start,
+        location.ref_end
+    );
+}
+
+/// Asserts that the query was not located.
+#[track_caller]
+pub fn assert_not_located(location: Option<&Locator>) {
+    if let Some(location) = location {
+        panic!(
+            "expected no location, located at {}-{} with {}% identity",
+            location.ref_start, location.ref_end, location.percent_identity
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence_locator::BuiltinLocator;
+
+    #[test]
+    fn test_known_queries() {
+        for query in &KNOWN_QUERIES {
+            for algorithm in [1, 2] {
+                let location = Locator::build(&query.args(algorithm))
+                    .unwrap()
+                    .pop()
+                    .flatten();
+                query.assert_matches(location.as_ref());
+            }
+        }
+        let locator = BuiltinLocator::default();
+        assert_not_located(locator.locate("NNNNNNNN").unwrap().as_ref());
+    }
+
+    #[test]
+    fn test_mini_locator() {
+        assert!(MINI_REFERENCE.starts_with(KNOWN_QUERIES[0].sequence));
+        let location = MiniLocator.locate("ttaaggccag").unwrap();
+        assert_located(location.as_ref(), 61, 70);
+        assert_not_located(MiniLocator.locate("CCCCCCCC").unwrap().as_ref());
+        assert!(MiniLocator.locate("ATGX").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! VCF output of the differences between the queries and the reference, for bcftools and the other
+//! tools reading variants.
+//!
+//! With `--format vcf`, the substitutions, insertions and deletions of the locations (see
+//! `Locator::variants`) are written as a VCF 4.3 file on the reference, named by its strain (e.g.
+//! `HXB2`), with one sample per query:
+//!
+//! - Every distinct allele is a record, sorted by position. Queries with different alleles at the
+//!   same position have one record per allele.
+//! - Indels are left-normalized, as `bcftools norm` does: shifted to the leftmost position where
+//!   the same sequence results, and written with the reference base before them, or after them at
+//!   the start of the reference.
+//! - The genotype (`GT`) of a query is haploid: `1` when it has the allele, `0` when its location
+//!   covers the reference bases of the record without it, and `.` otherwise, e.g. for the queries
+//!   that are not located.
+//! - Substitutions to ambiguous query bases, such as `N` or `R`, are not variants; ambiguous bases
+//!   in insertions are written as `N`.
+//!
+//! Only the best location of every query is written, not the other matches of `--max-hits`.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::locator::Locator;
+//! use virust_locator::vcf;
+//!
+//! // HXB2 790-849 with 3 bases deleted after 829.
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let loc = Locator::build(&args).unwrap().pop().flatten().unwrap();
+//! let mut out = Vec::new();
+//! vcf::write_vcf(&mut out, &[("q1", Some(&loc))], "HXB2").unwrap();
+//! let vcf = String::from_utf8(out).unwrap();
+//! assert!(vcf.ends_with("HXB2\t829\t.\tGATC\tG\t.\t.\t.\tGT\t1\n"));
+//! ```
+
+use crate::BoxError;
+use crate::batch::BatchResult;
+use crate::format::Formatter;
+use crate::locator::{Locator, Variant, VariantKind};
+use crate::position::RefPosition;
+use crate::reference::{resolve_reference, retrieve_reference_sequence};
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+
+/// A VCF allele: the position of its first reference base, and its reference and alternate bases.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Allele {
+    pub position: RefPosition,
+    pub reference: String,
+    pub alternate: String,
+}
+
+impl Allele {
+    /// Whether the reference bases of the allele are all covered by `loc`.
+    fn covered_by(&self, loc: &Locator) -> bool {
+        let first = self.position;
+        let last = self.position + (self.reference.len() - 1);
+        loc.ref_segments()
+            .iter()
+            .any(|segment| segment.start <= first && last <= segment.end)
+    }
+}
+
+/// A base as written in the alleles: upper case `A`, `C`, `G` or `T`, and `N` for the others.
+fn vcf_base(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        base @ (b'A' | b'C' | b'G' | b'T') => base,
+        _ => b'N',
+    }
+}
+
+/// Converts `bases` to a string of VCF bases, see `vcf_base`.
+fn vcf_bases(bases: impl IntoIterator<Item = u8>) -> String {
+    bases
+        .into_iter()
+        .map(|base| vcf_base(base) as char)
+        .collect()
+}
+
+/// The allele of `variant` on the reference sequence `ref_seq`, its indels left-normalized, or
+/// `None` for a substitution to an ambiguous base or a deletion of the whole reference.
+pub fn allele(variant: &Variant, ref_seq: &[u8]) -> Option<Allele> {
+    match variant.kind {
+        VariantKind::Substitution => {
+            let alternate = variant.query_bases.bytes().next()?;
+            (vcf_base(alternate) != b'N').then(|| Allele {
+                position: variant.ref_position,
+                reference: vcf_bases(variant.ref_bases.bytes()),
+                alternate: vcf_bases([alternate]),
+            })
+        }
+        VariantKind::Deletion => {
+            let mut deleted = variant.ref_bases.as_bytes().to_vec();
+            let mut start = variant.ref_position.to_zero_based();
+            // Shift left while the base before the deletion ends it.
+            while start > 0 && deleted.last() == Some(&ref_seq[start - 1]) {
+                deleted.rotate_right(1);
+                start -= 1;
+            }
+            if start > 0 {
+                let anchor = ref_seq[start - 1];
+                Some(Allele {
+                    position: RefPosition::new(start),
+                    reference: vcf_bases([anchor].into_iter().chain(deleted)),
+                    alternate: vcf_bases([anchor]),
+                })
+            } else {
+                let anchor = *ref_seq.get(deleted.len())?;
+                Some(Allele {
+                    position: RefPosition::new(1),
+                    reference: vcf_bases(deleted.into_iter().chain([anchor])),
+                    alternate: vcf_bases([anchor]),
+                })
+            }
+        }
+        VariantKind::Insertion => {
+            let mut inserted = variant.query_bases.as_bytes().to_vec();
+            // The insertion is between the 0-based reference bases `point - 1` and `point`.
+            let mut point = variant.ref_position.get();
+            while point > 0 && vcf_base(*inserted.last()?) == vcf_base(ref_seq[point - 1]) {
+                inserted.rotate_right(1);
+                point -= 1;
+            }
+            if point > 0 {
+                let anchor = ref_seq[point - 1];
+                Some(Allele {
+                    position: RefPosition::new(point),
+                    reference: vcf_bases([anchor]),
+                    alternate: vcf_bases([anchor].into_iter().chain(inserted)),
+                })
+            } else {
+                let anchor = ref_seq[0];
+                Some(Allele {
+                    position: RefPosition::new(1),
+                    reference: vcf_bases([anchor]),
+                    alternate: vcf_bases(inserted.into_iter().chain([anchor])),
+                })
+            }
+        }
+    }
+}
+
+/// Writes the VCF of the `samples`, named queries located on `reference` or `None` for those that
+/// are not located, see the module documentation. Returns an error if two samples have the same
+/// name.
+pub fn write_vcf<W: Write>(
+    mut writer: W,
+    samples: &[(&str, Option<&Locator>)],
+    reference: &str,
+) -> Result<(), BoxError> {
+    let name = resolve_reference(reference)?;
+    let ref_seq = retrieve_reference_sequence(name, "nt")?.sequence;
+    let mut names = HashSet::new();
+    if let Some((duplicate, _)) = samples.iter().find(|(id, _)| !names.insert(*id)) {
+        return Err(format!("duplicate query ID '{}' in the VCF samples", duplicate).into());
+    }
+
+    let sample_alleles: Vec<HashSet<Allele>> = samples
+        .iter()
+        .map(|(_, loc)| {
+            loc.iter()
+                .flat_map(|loc| loc.variants())
+                .filter_map(|variant| allele(&variant, ref_seq))
+                .collect()
+        })
+        .collect();
+    let alleles: BTreeSet<&Allele> = sample_alleles.iter().flatten().collect();
+
+    writeln!(writer, "##fileformat=VCFv4.3")?;
+    writeln!(
+        writer,
+        "##source=virust-locator {}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "##reference={}", name)?;
+    writeln!(writer, "##contig=<ID={},length={}>", name, ref_seq.len())?;
+    writeln!(
+        writer,
+        "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
+    )?;
+    write!(
+        writer,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT"
+    )?;
+    for (id, _) in samples {
+        write!(writer, "\t{}", id)?;
+    }
+    writeln!(writer)?;
+    for allele in alleles {
+        write!(
+            writer,
+            "{}\t{}\t.\t{}\t{}\t.\t.\t.\tGT",
+            name, allele.position, allele.reference, allele.alternate
+        )?;
+        for ((_, loc), own) in samples.iter().zip(&sample_alleles) {
+            let genotype = match loc {
+                _ if own.contains(allele) => "1",
+                Some(loc) if allele.covered_by(loc) => "0",
+                _ => ".",
+            };
+            write!(writer, "\t{}", genotype)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// The VCF of the results, one sample per result, see `write_vcf`.
+#[derive(Debug, Clone)]
+pub struct VcfFormatter {
+    /// The reference the results are located on.
+    pub reference: String,
+}
+
+impl Formatter for VcfFormatter {
+    fn write(&self, writer: &mut dyn Write, results: &[BatchResult]) -> Result<(), BoxError> {
+        let samples: Vec<(&str, Option<&Locator>)> = results
+            .iter()
+            .map(|result| (result.id.as_str(), result.locator.as_ref()))
+            .collect();
+        write_vcf(writer, &samples, &self.reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::QueryPosition;
+
+    fn variant(
+        kind: VariantKind,
+        ref_position: usize,
+        ref_bases: &str,
+        query_bases: &str,
+    ) -> Variant {
+        Variant {
+            kind,
+            ref_position: RefPosition::new(ref_position),
+            query_position: QueryPosition::new(1),
+            ref_bases: ref_bases.to_string(),
+            query_bases: query_bases.to_string(),
+        }
+    }
+
+    fn allele(position: usize, reference: &str, alternate: &str) -> Option<Allele> {
+        Some(Allele {
+            position: RefPosition::new(position),
+            reference: reference.to_string(),
+            alternate: alternate.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_allele() {
+        let ref_seq = b"GATTTACAGCAG";
+        let substitution = variant(VariantKind::Substitution, 2, "A", "c");
+        assert_eq!(super::allele(&substitution, ref_seq), allele(2, "A", "C"));
+        let ambiguous = variant(VariantKind::Substitution, 2, "A", "R");
+        assert_eq!(super::allele(&ambiguous, ref_seq), None);
+        // One of the Ts deleted, shifted to the first one.
+        let deletion = variant(VariantKind::Deletion, 5, "T", "");
+        assert_eq!(super::allele(&deletion, ref_seq), allele(2, "AT", "A"));
+        // CAG repeated after 9, shifted before the first copy.
+        let deletion = variant(VariantKind::Deletion, 10, "CAG", "");
+        assert_eq!(super::allele(&deletion, ref_seq), allele(6, "ACAG", "A"));
+        let insertion = variant(VariantKind::Insertion, 12, "", "CAG");
+        assert_eq!(super::allele(&insertion, ref_seq), allele(6, "A", "ACAG"));
+        // At the start of the reference, the base after the indel is the anchor.
+        let deletion = variant(VariantKind::Deletion, 1, "G", "");
+        assert_eq!(super::allele(&deletion, ref_seq), allele(1, "GA", "A"));
+        let insertion = variant(VariantKind::Insertion, 1, "", "TG");
+        assert_eq!(super::allele(&insertion, ref_seq), allele(1, "G", "GTG"));
+    }
+
+    #[test]
+    fn test_write_vcf() {
+        let mut loc = Locator::new(
+            RefPosition::new(790),
+            RefPosition::new(795),
+            100.0,
+            false,
+            "ATGGGT".to_string(),
+            "ATGGGT".to_string(),
+        );
+        loc.query_aligned_string = "ATCGGT".to_string();
+        let mut out = Vec::new();
+        write_vcf(&mut out, &[("q1", Some(&loc)), ("q2", None)], "HXB2").unwrap();
+        let vcf = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = vcf.lines().collect();
+        assert_eq!(lines[0], "##fileformat=VCFv4.3");
+        assert_eq!(lines[3], "##contig=<ID=HXB2,length=9719>");
+        assert_eq!(
+            lines[5],
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tq1\tq2"
+        );
+        assert_eq!(lines[6], "HXB2\t792\t.\tG\tC\t.\t.\t.\tGT\t1\t.");
+        assert_eq!(lines.len(), 7);
+
+        let duplicate = write_vcf(Vec::new(), &[("q1", None), ("q1", None)], "HXB2");
+        assert!(duplicate.is_err());
+    }
+}
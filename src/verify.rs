@@ -0,0 +1,204 @@
+//! Cross-checking the two location algorithms.
+//!
+//! Algorithm 2 anchors the ends of long queries before aligning them, which is faster but can
+//! misplace a query that algorithm 1 would locate correctly. With `--verify`, every query is
+//! located with both algorithms and the results of the selected one are reported as usual, along
+//! with the `Disagreement`s between them: a query found by one algorithm only, or reference
+//! positions or percent identities that differ by more than the `Tolerance`.
+//!
+//! Queries shorter than `--min-anchored-length` residues, 300 by default, are always aligned with
+//! algorithm 1, so both algorithms agree on them.
+//!
+//! # Example
+//! ```rust
+//! use virust_locator::config::{Args, CommonArgs};
+//! use virust_locator::verify::{self, Tolerance};
+//!
+//! let args = Args {
+//!     common: CommonArgs {
+//!         query: vec!["ATGCATGCATGC".to_string()],
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! let verified = verify::try_build_verified(&args, Tolerance::default()).unwrap();
+//! assert!(verified[0].1.is_empty());
+//! ```
+
+use crate::BoxError;
+use crate::config::{Args, CommonArgs};
+use crate::locator::{LocateOutcome, Locator};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// The largest differences between the results of the two algorithms that are not reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Tolerance {
+    /// Reference positions.
+    pub positions: usize,
+    /// Percentage points of identity.
+    pub identity: f64,
+}
+
+/// A difference between the results of algorithm 1 and algorithm 2 for one query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Disagreement {
+    /// The result field: `found`, `ref_start`, `ref_end` or `percent_identity`.
+    pub field: &'static str,
+    /// The value found by algorithm 1.
+    pub algorithm1: String,
+    /// The value found by algorithm 2.
+    pub algorithm2: String,
+}
+
+impl Disagreement {
+    fn new(field: &'static str, algorithm1: impl Display, algorithm2: impl Display) -> Self {
+        Disagreement {
+            field,
+            algorithm1: algorithm1.to_string(),
+            algorithm2: algorithm2.to_string(),
+        }
+    }
+}
+
+impl Display for Disagreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "algorithms disagree on {}: {} (algorithm 1) vs {} (algorithm 2)",
+            self.field, self.algorithm1, self.algorithm2
+        )
+    }
+}
+
+/// Returns the differences between the location of a query by algorithm 1 and by algorithm 2 that
+/// exceed `tolerance`.
+pub fn compare(
+    algorithm1: Option<&Locator>,
+    algorithm2: Option<&Locator>,
+    tolerance: Tolerance,
+) -> Vec<Disagreement> {
+    let (loc1, loc2) = match (algorithm1, algorithm2) {
+        (Some(loc1), Some(loc2)) => (loc1, loc2),
+        (None, None) => return Vec::new(),
+        (loc1, loc2) => {
+            return vec![Disagreement::new("found", loc1.is_some(), loc2.is_some())];
+        }
+    };
+    let mut disagreements = Vec::new();
+    if loc1.ref_start.get().abs_diff(loc2.ref_start.get()) > tolerance.positions {
+        disagreements.push(Disagreement::new(
+            "ref_start",
+            loc1.ref_start,
+            loc2.ref_start,
+        ));
+    }
+    if loc1.ref_end.get().abs_diff(loc2.ref_end.get()) > tolerance.positions {
+        disagreements.push(Disagreement::new("ref_end", loc1.ref_end, loc2.ref_end));
+    }
+    if (loc1.percent_identity - loc2.percent_identity).abs() > tolerance.identity {
+        disagreements.push(Disagreement::new(
+            "percent_identity",
+            loc1.percent_identity,
+            loc2.percent_identity,
+        ));
+    }
+    disagreements
+}
+
+/// Builds the locations of the queries of `args` like `Locator::try_build`, and locates them with
+/// the other algorithm too. Returns the outcome of `args.algorithm` for every query, with the
+/// disagreements between the two algorithms beyond `tolerance`.
+pub fn try_build_verified(
+    args: &Args,
+    tolerance: Tolerance,
+) -> Result<Vec<(LocateOutcome, Vec<Disagreement>)>, BoxError> {
+    let with_algorithm = |algorithm| {
+        Locator::try_build(&Args {
+            common: CommonArgs {
+                algorithm,
+                ..args.common.clone()
+            },
+            ..args.clone()
+        })
+    };
+    let algorithm1 = with_algorithm(1)?;
+    let algorithm2 = with_algorithm(2)?;
+    Ok(algorithm1
+        .into_iter()
+        .zip(algorithm2)
+        .map(|(outcome1, outcome2)| {
+            let disagreements = compare(outcome1.locator(), outcome2.locator(), tolerance);
+            let outcome = if args.common.algorithm == 2 {
+                outcome2
+            } else {
+                outcome1
+            };
+            (outcome, disagreements)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::RefPosition;
+    use crate::reference::retrieve_reference_sequence;
+
+    fn locator(ref_start: usize, ref_end: usize, percent_identity: f64) -> Locator {
+        Locator::new(
+            RefPosition::new(ref_start),
+            RefPosition::new(ref_end),
+            percent_identity,
+            false,
+            "ATGC".to_string(),
+            "ATGC".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_compare() {
+        let loc1 = locator(790, 2292, 99.5);
+        let loc2 = locator(792, 2292, 97.0);
+        let disagreements = compare(Some(&loc1), Some(&loc2), Tolerance::default());
+        assert_eq!(disagreements.len(), 2);
+        assert_eq!(
+            disagreements[0].to_string(),
+            "algorithms disagree on ref_start: 790 (algorithm 1) vs 792 (algorithm 2)"
+        );
+        assert_eq!(disagreements[1].field, "percent_identity");
+        let tolerance = Tolerance {
+            positions: 2,
+            identity: 3.0,
+        };
+        assert!(compare(Some(&loc1), Some(&loc2), tolerance).is_empty());
+        assert_eq!(
+            compare(Some(&loc1), None, tolerance),
+            vec![Disagreement::new("found", true, false)]
+        );
+        assert!(compare(None, None, tolerance).is_empty());
+    }
+
+    #[test]
+    fn test_try_build_verified() {
+        // HXB2 1001-1100 joined to HXB2 4101-4400: algorithm 2 anchors the start 3000 bases
+        // upstream of where algorithm 1 places it.
+        let ref_seq = retrieve_reference_sequence("HXB2", "nt").unwrap().sequence;
+        let query = [&ref_seq[1000..1100], &ref_seq[4100..4400]].concat();
+        let args = Args {
+            common: CommonArgs {
+                query: vec![String::from_utf8(query).unwrap()],
+                algorithm: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let verified = try_build_verified(&args, Tolerance::default()).unwrap();
+        let (outcome, disagreements) = &verified[0];
+        assert_eq!(outcome.locator().unwrap().ref_start, RefPosition::new(1001));
+        assert_eq!(
+            disagreements,
+            &vec![Disagreement::new("ref_start", 4003, 1001)]
+        );
+    }
+}
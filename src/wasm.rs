@@ -0,0 +1,60 @@
+//! JavaScript bindings of the locator, with the `wasm` feature, to locate queries entirely in the
+//! browser.
+//!
+//! The locator and the references compile to `wasm32-unknown-unknown` without the default `cli`
+//! and `parallel` features: the queries are then located on the calling thread. The package is
+//! built with `wasm-bindgen`:
+//!
+//! ```bash
+//! cargo build --lib --release --target wasm32-unknown-unknown --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/virust_locator.wasm
+//! ```
+//!
+//! and used from JavaScript:
+//!
+//! ```js
+//! import init, { locate } from "./pkg/virust_locator.js";
+//!
+//! await init();
+//! const outcome = JSON.parse(locate("ATGCATGCATGC", "HXB2", "nt"));
+//! console.log(outcome.found.ref_start);
+//! ```
+
+use crate::config::{Args, CommonArgs};
+use crate::locator::Locator;
+use wasm_bindgen::prelude::*;
+
+/// Locates `query` on `reference` (`HXB2` or `SIVmm239`, or one of their aliases) as a query of
+/// type `type_query` (`nt`, `aa` or `auto`), with the default options of the CLI. Returns the
+/// JSON of its `LocateOutcome`: `{"found": {...}}` with the location, or `{"not_found": {...}}`
+/// with the diagnostics. Throws an error if the query or the options are invalid.
+#[wasm_bindgen]
+pub fn locate(query: &str, reference: &str, type_query: &str) -> Result<String, JsError> {
+    let args = Args {
+        common: CommonArgs {
+            query: vec![query.to_string()],
+            reference: reference.to_string(),
+            type_query: type_query.to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+    .validate()
+    .map_err(|err| JsError::new(&err))?;
+    let outcome = Locator::try_build(&args)
+        .map_err(|err| JsError::new(&err.to_string()))?
+        .remove(0);
+    serde_json::to_string(&outcome).map_err(|err| JsError::new(&err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let outcome: serde_json::Value =
+            serde_json::from_str(&locate("ATGCATGCATGC", "HXB2", "nt").unwrap()).unwrap();
+        assert!(outcome["found"]["ref_start"].is_number());
+    }
+}
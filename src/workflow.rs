@@ -0,0 +1,300 @@
+//! Execution mode for workflow managers such as Nextflow and Snakemake.
+//!
+//! In workflow mode every input file is read with the `input` module and located with the `batch`
+//! module, and its results are written to `<outdir>/<stem>.locator.tsv`, where `<stem>` is the
+//! input file name without its compression (`.gz`, `.bgz`) and format extensions. For example,
+//! `samples/p01.fasta.gz` produces `p01.locator.tsv`. The output names only depend on the input
+//! names, so rules can declare them up front.
+//!
+//! Inputs and the output directory may also be object-store URLs (see the `storage` module).
+//!
+//! A run manifest is written to `<outdir>/manifest.json` with the tool version, the parameters,
+//! the reference used (with its length and CRC-32 checksum), and the record counts per input and
+//! in total.
+//!
+//! The exit code tells data problems apart from tool failures:
+//!
+//! - `0` (`EXIT_SUCCESS`): every input was read and every record was valid.
+//! - `1` (`EXIT_TOOL_FAILURE`): the run could not complete, e.g. an input file is missing or an
+//!   output could not be written. Retrying may help.
+//! - `65` (`EXIT_DATA_ERROR`, as `EX_DATAERR` in `sysexits.h`): an input file could not be parsed
+//!   or contains invalid sequences. All outputs and the manifest are still written; retrying will
+//!   not help.
+//!
+//! Records that are valid but could not be located on the reference are results, not errors.
+
+use crate::BoxError;
+use crate::batch::{self, BatchResult};
+use crate::config::Args;
+use crate::input;
+use crate::reference::retrieve_reference_sequence;
+use crate::storage;
+use flate2::Crc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Exit code of a run where every input and record was processed.
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code of a run that could not complete.
+pub const EXIT_TOOL_FAILURE: i32 = 1;
+/// Exit code of a run that completed, but found unparsable inputs or invalid sequences.
+pub const EXIT_DATA_ERROR: i32 = 65;
+
+/// File name of the run manifest in the output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Overall status of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    DataError,
+}
+
+impl RunStatus {
+    /// Returns the process exit code for this status.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunStatus::Success => EXIT_SUCCESS,
+            RunStatus::DataError => EXIT_DATA_ERROR,
+        }
+    }
+}
+
+/// Parameters of a run, as recorded in the manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct Parameters {
+    pub reference: String,
+    pub type_query: String,
+    pub algorithm: u8,
+}
+
+/// The reference sequence used by a run, as recorded in the manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceInfo {
+    pub name: String,
+    pub sequence_type: String,
+    /// Length of the reference sequence.
+    pub length: usize,
+    /// CRC-32 checksum of the reference sequence, in hexadecimal.
+    pub crc32: String,
+}
+
+/// Record counts of an input file or of a whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub records: usize,
+    pub found: usize,
+    pub not_found: usize,
+    pub invalid: usize,
+}
+
+impl Counts {
+    pub(crate) fn from_results(results: &[BatchResult]) -> Self {
+        let mut counts = Counts {
+            records: results.len(),
+            ..Default::default()
+        };
+        for result in results {
+            if result.found {
+                counts.found += 1;
+            } else if result.error.is_some() {
+                counts.invalid += 1;
+            } else {
+                counts.not_found += 1;
+            }
+        }
+        counts
+    }
+
+    pub(crate) fn add(&mut self, other: Counts) {
+        self.records += other.records;
+        self.found += other.found;
+        self.not_found += other.not_found;
+        self.invalid += other.invalid;
+    }
+}
+
+/// Summary of one input file, as recorded in the manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSummary {
+    pub path: PathBuf,
+    /// The output file, or `None` if the input could not be parsed.
+    pub output: Option<PathBuf>,
+    #[serde(flatten)]
+    pub counts: Counts,
+    /// Why the input could not be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The run manifest written to `<outdir>/manifest.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub tool: String,
+    pub version: String,
+    pub parameters: Parameters,
+    pub reference: ReferenceInfo,
+    pub inputs: Vec<InputSummary>,
+    pub totals: Counts,
+    pub status: RunStatus,
+    pub exit_code: i32,
+}
+
+/// Returns the output file name for `input`: its file name without the compression and format
+/// extensions, followed by `.locator.tsv`.
+pub fn output_name(input: &Path) -> String {
+    let name = input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".bgz"))
+        .unwrap_or(&name);
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    format!("{}.locator.tsv", stem)
+}
+
+fn reference_info(options: &Args) -> Result<ReferenceInfo, BoxError> {
+    let ref_seq =
+        retrieve_reference_sequence(&options.common.reference, &options.common.type_query)?;
+    let mut crc = Crc::new();
+    crc.update(ref_seq.sequence);
+    Ok(ReferenceInfo {
+        name: ref_seq.strain.to_string(),
+        sequence_type: ref_seq.sequence_type.to_string(),
+        length: ref_seq.sequence.len(),
+        crc32: format!("{:08x}", crc.sum()),
+    })
+}
+
+/// Locates the records of every file in `inputs` with the reference, query type and algorithm of
+/// `options`, writing one result file per input and the manifest to `outdir`.
+///
+/// Returns the manifest of a completed run, whose status may still report data errors. Returns an
+/// error if the run could not complete, e.g. because an input is missing, two inputs map to the
+/// same output name, or an output could not be written.
+pub fn run(inputs: &[PathBuf], outdir: &Path, options: &Args) -> Result<Manifest, BoxError> {
+    let reference = reference_info(options)?;
+
+    let mut outputs: HashMap<String, &Path> = HashMap::new();
+    for path in inputs {
+        if let Some(other) = outputs.insert(output_name(path), path) {
+            return Err(Box::from(format!(
+                "Inputs {} and {} would both be written to {}",
+                other.display(),
+                path.display(),
+                output_name(path)
+            )));
+        }
+    }
+    storage::create_dir_all(outdir)?;
+
+    let mut summaries = Vec::with_capacity(inputs.len());
+    let mut totals = Counts::default();
+    let mut status = RunStatus::Success;
+    for path in inputs {
+        let summary =
+            match input::read_records_with(storage::open(path)?, options.common.strict_parse) {
+                Ok(records) => {
+                    let results = batch::locate_records(records, options);
+                    let output = outdir.join(output_name(path));
+                    let mut writer = storage::create(&output)?;
+                    batch::write_delimited(&mut writer, &results, &options.delimited_options())?;
+                    writer.finish()?;
+                    InputSummary {
+                        path: path.clone(),
+                        output: Some(output),
+                        counts: Counts::from_results(&results),
+                        error: None,
+                    }
+                }
+                Err(err) => InputSummary {
+                    path: path.clone(),
+                    output: None,
+                    counts: Counts::default(),
+                    error: Some(err.to_string()),
+                },
+            };
+        if summary.error.is_some() || summary.counts.invalid > 0 {
+            status = RunStatus::DataError;
+        }
+        totals.add(summary.counts);
+        summaries.push(summary);
+    }
+
+    let manifest = Manifest {
+        tool: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        parameters: Parameters {
+            reference: options.common.reference.clone(),
+            type_query: options.common.type_query.clone(),
+            algorithm: options.common.algorithm,
+        },
+        reference,
+        inputs: summaries,
+        totals,
+        status,
+        exit_code: status.exit_code(),
+    };
+    let mut writer = storage::create(&outdir.join(MANIFEST_FILE))?;
+    serde_json::to_writer_pretty(&mut writer, &manifest)?;
+    writer.finish()?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_output_name() {
+        assert_eq!(output_name(Path::new("p01.fasta")), "p01.locator.tsv");
+        assert_eq!(
+            output_name(Path::new("samples/p01.fastq.gz")),
+            "p01.locator.tsv"
+        );
+        assert_eq!(
+            output_name(Path::new("p01.v2.fa.bgz")),
+            "p01.v2.locator.tsv"
+        );
+        assert_eq!(output_name(Path::new("reads")), "reads.locator.tsv");
+        assert_eq!(output_name(Path::new(".hidden")), ".hidden.locator.tsv");
+    }
+
+    #[test]
+    fn test_run_reports_data_errors() {
+        let dir =
+            std::env::temp_dir().join(format!("virust-locator-workflow-{}", std::process::id()));
+        let indir = dir.join("in");
+        let outdir = dir.join("out");
+        fs::create_dir_all(&indir).unwrap();
+        let good = indir.join("good.fasta");
+        let bad = indir.join("bad.fa");
+        let broken = indir.join("broken.txt");
+        fs::write(&good, ">q1\nATGCATGCATGC\n").unwrap();
+        fs::write(&bad, ">q1\nATGCATGCATGC\n>q2\nATGCXYZ\n").unwrap();
+        fs::write(&broken, "not a sequence file\n").unwrap();
+
+        let manifest = run(&[good, bad, broken], &outdir, &Args::default()).unwrap();
+        assert_eq!(manifest.status, RunStatus::DataError);
+        assert_eq!(manifest.exit_code, EXIT_DATA_ERROR);
+        assert_eq!(manifest.inputs[0].counts.found, 1);
+        assert_eq!(manifest.inputs[1].counts.invalid, 1);
+        assert!(manifest.inputs[2].error.is_some());
+        assert_eq!(manifest.totals.records, 3);
+
+        assert!(outdir.join("good.locator.tsv").exists());
+        assert!(outdir.join("bad.locator.tsv").exists());
+        assert!(!outdir.join("broken.locator.tsv").exists());
+        assert!(outdir.join(MANIFEST_FILE).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,8 +7,9 @@
 //! - Multiple queries in a single run
 //! - Error cases and edge conditions
 
+use std::io::Write;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 /// Helper function to get the path to the binary executable
 fn get_binary_path() -> &'static str {
@@ -33,6 +34,30 @@ fn run_virust_locator(args: &[&str]) -> (String, String, i32) {
     (stdout, stderr, exit_code)
 }
 
+/// Helper function to run the viRust-locator binary with given arguments and stdin content
+fn run_virust_locator_with_stdin(args: &[&str], stdin: &str) -> (String, String, i32) {
+    let mut child = Command::new(get_binary_path())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .expect("Failed to write to stdin");
+    let output = child.wait_with_output().expect("Failed to wait on binary");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let exit_code = output.status.code().unwrap_or(-1);
+
+    (stdout, stderr, exit_code)
+}
+
 /// Helper function to parse locator output
 /// Expected format: start_pos end_pos similarity reverse_complement query_seq reference_match
 fn parse_locator_output(output: &str) -> Option<(i32, i32, i32, bool, String, String)> {
@@ -81,7 +106,7 @@ fn test_nucleotide_query_success() {
         "End position should be greater than start position"
     );
     assert!(
-        similarity >= 0 && similarity <= 100,
+        (0..=100).contains(&similarity),
         "Similarity should be between 0 and 100"
     );
     assert_eq!(
@@ -122,7 +147,7 @@ fn test_amino_acid_query_success() {
         "End position should be greater than start position"
     );
     assert!(
-        similarity >= 0 && similarity <= 100,
+        (0..=100).contains(&similarity),
         "Similarity should be between 0 and 100"
     );
     assert_eq!(query_seq, "MHAC", "Query sequence should match input");
@@ -321,7 +346,7 @@ fn test_error_invalid_query_type() {
 #[test]
 fn test_error_invalid_algorithm() {
     let (stdout, stderr, exit_code) =
-        run_virust_locator(&["--query", "ATGCATGCATGC", "--algorithm", "3"]);
+        run_virust_locator(&["--query", "ATGCATGCATGC", "--algorithm", "4"]);
 
     assert_eq!(
         exit_code, 1,
@@ -332,11 +357,48 @@ fn test_error_invalid_algorithm() {
         "No output should be produced for invalid input"
     );
     assert!(
-        stderr.contains("Algorithm must be either 1 or 2"),
+        stderr.contains("Algorithm must be either 1, 2, 3 or auto"),
         "Should show appropriate error message"
     );
 }
 
+/// Test the anchors of algorithm 2 set with --anchor-length, --anchor-max-dist and
+/// --min-anchored-length
+#[test]
+fn test_anchor_options() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--algorithm",
+        "2",
+        "--anchor-length",
+        "20",
+        "--anchor-max-dist",
+        "5",
+        "--min-anchored-length",
+        "40",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0, "{}", stderr);
+    assert!(stdout.starts_with("790\t849\t100\tfalse"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--anchor-length",
+        "20",
+        "--anchor-max-dist",
+        "20",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Anchor edit distance must be less than the anchor length"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", query, "--min-anchored-length", "150"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Minimum anchored length must be at least twice the anchor length"));
+}
+
 /// Test help flag
 #[test]
 fn test_help_flag() {
@@ -393,6 +455,1810 @@ fn test_comprehensive_scenario() {
     assert_eq!(lines.len(), 2, "Should produce output for both queries");
 }
 
+/// Test pipe mode with one JSON request per line, including a per-request reference and an invalid
+/// sequence
+#[test]
+fn test_pipe_mode() {
+    let input = concat!(
+        "{\"id\": \"q1\", \"seq\": \"ATGCATGCATGC\"}\n",
+        "{\"id\": \"q2\", \"seq\": \"ATGCATGCATGC\", \"reference\": \"SIVmm239\"}\n",
+        "{\"id\": \"q3\", \"seq\": \"ATGCXYZ\"}\n",
+    );
+    let (stdout, stderr, exit_code) = run_virust_locator_with_stdin(&["--pipe"], input);
+
+    assert_eq!(exit_code, 0, "Pipe mode should exit with code 0");
+    assert!(stderr.is_empty(), "No error messages should be printed");
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Should produce one line per request");
+    assert!(lines[0].starts_with("{\"id\":\"q1\",\"found\":true"));
+    assert!(lines[1].starts_with("{\"id\":\"q2\",\"found\":true"));
+    assert!(lines[2].starts_with("{\"id\":\"q3\",\"found\":false"));
+    assert!(lines[2].contains("Invalid nucleotide sequence"));
+}
+
+/// Test that pipe mode cannot be combined with a query on the command line
+#[test]
+fn test_pipe_mode_conflicts_with_query() {
+    let (_, stderr, exit_code) = run_virust_locator(&["--pipe", "--query", "ATGCATGCATGC"]);
+
+    assert_ne!(
+        exit_code, 0,
+        "Should fail when --pipe and --query are both given"
+    );
+    assert!(stderr.contains("cannot be used with"));
+}
+
+/// Test workflow mode: one result file per input, a manifest, and the data error exit code for an
+/// input with an invalid sequence
+#[test]
+fn test_workflow_mode() {
+    let dir =
+        std::env::temp_dir().join(format!("virust-locator-it-workflow-{}", std::process::id()));
+    let outdir = dir.join("out");
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.fasta");
+    let bad = dir.join("bad.fasta");
+    std::fs::write(&good, ">q1\nATGCATGCATGC\n").unwrap();
+    std::fs::write(&bad, ">q1\nATGCXYZ\n").unwrap();
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "--workflow",
+        "--input",
+        good.to_str().unwrap(),
+        "--outdir",
+        outdir.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0, "All records valid should exit with code 0");
+    assert!(stderr.is_empty(), "No error messages should be printed");
+    assert!(outdir.join("good.locator.tsv").exists());
+
+    let (_, _, exit_code) = run_virust_locator(&[
+        "--workflow",
+        "--input",
+        good.to_str().unwrap(),
+        bad.to_str().unwrap(),
+        "--outdir",
+        outdir.to_str().unwrap(),
+    ]);
+    assert_eq!(
+        exit_code, 65,
+        "Invalid sequences should exit with the data error code"
+    );
+    assert!(outdir.join("bad.locator.tsv").exists());
+    let manifest = std::fs::read_to_string(outdir.join("manifest.json")).unwrap();
+    assert!(manifest.contains("\"status\": \"data_error\""));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test --input mode writing the results to stdout and to --output
+#[test]
+fn test_input_mode() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-input-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    let output = dir.join("reads.tsv");
+    std::fs::write(&input, ">q1\nATGCATGCATGC\n>q2\nATGCXYZ\n").unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&["--input", input.to_str().unwrap()]);
+    assert_eq!(exit_code, 0, "--input mode should succeed");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Expected a header and one row per record");
+    assert!(lines[1].starts_with("q1\t1373\t"));
+    assert!(lines[2].starts_with("q2\t\t"));
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0, "--input mode with --output should succeed");
+    assert!(
+        stdout.is_empty(),
+        "Results should not be printed with --output"
+    );
+    let written = std::fs::read_to_string(&output).unwrap();
+    assert_eq!(written.lines().count(), 3);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test --format-plugin and --filter-plugin with the example plugin, built by `cargo test`
+#[cfg(all(feature = "plugins", target_os = "linux"))]
+#[test]
+fn test_plugins() {
+    let plugin = Path::new(get_binary_path())
+        .with_file_name("examples")
+        .join("libplugin.so");
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-plugin-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, ">q1\nATGCATGCATGC\n>q2\nATGCXYZ\n").unwrap();
+
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--format-plugin",
+        plugin.to_str().unwrap(),
+        "--filter-plugin",
+        plugin.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0, "Plugins should load: {}", stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines.len(),
+        2,
+        "Expected a CSV header and the located record"
+    );
+    assert!(lines[0].starts_with("id,found,"));
+    assert!(lines[1].starts_with("q1,true,1373,1384,"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test --filter and --map expressions
+#[cfg(feature = "scripting")]
+#[test]
+fn test_filter_and_map() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-script-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, ">q1\nATGCATGCATGC\n>q2\nATGCXYZ\n").unwrap();
+
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--filter",
+        "found && percent_identity > 70.0",
+        "--map",
+        "id = \"p01_\" + id; ref_start -= 789; ref_end -= 789",
+    ]);
+    assert_eq!(exit_code, 0, "Scripts should run: {}", stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "Expected a header and the located record");
+    assert!(lines[1].starts_with("p01_q1\t584\t595\t"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--filter", "found &&"]);
+    assert_eq!(exit_code, 1, "Invalid filters should be rejected");
+    assert!(stderr.contains("Invalid filter"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test structured JSON logging on stderr
+#[test]
+fn test_json_logging() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--log-format", "json"]);
+    assert_eq!(exit_code, 0, "Should locate with JSON logging");
+    assert!(
+        stdout.starts_with("1373\t1384\t"),
+        "Results should stay on stdout"
+    );
+
+    let record: serde_json::Value =
+        serde_json::from_str(stderr.lines().next().expect("Expected a log record")).unwrap();
+    assert_eq!(record["event"], "locate");
+    assert_eq!(record["query_id"], "1");
+    assert_eq!(record["algorithm"], 1);
+    assert_eq!(record["found"], true);
+    assert!(record["duration_ms"].is_number());
+}
+
+/// Test that lowercase, gapped and RNA queries are normalized, RNA queries keeping their U in the
+/// output
+#[test]
+fn test_query_normalization() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&["-q", "augc-augc.augc"]);
+    assert_eq!(
+        exit_code, 0,
+        "Normalized query should be located: {}",
+        stderr
+    );
+    assert_eq!(
+        stdout.trim(),
+        "1373\t1384\t75\tfalse\tAUGCAUGCAUGC\tAAGCAGCCATGC"
+    );
+
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "augc-augc.augc", "--format", "ndjson"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.contains("\"rna\":true"));
+}
+
+/// Test a FASTA record pasted into --query, with its header and wrapped lines
+#[test]
+fn test_pasted_fasta_record() {
+    let record =
+        ">HXB2 790-849 gag\nATGGGTGCGAGAGCGTCAGTATTAAGCGG\r\nGGGAGAATTAGATCGATGGGAAAAAATTCGG\n";
+    let (stdout, stderr, exit_code) = run_virust_locator(&["-q", record, "--no-align-strings"]);
+    assert_eq!(exit_code, 0, "Pasted record should be located: {}", stderr);
+    assert!(stdout.starts_with("790\t849\t100\tfalse"));
+}
+
+/// Test 0-based, half-open coordinates
+#[test]
+fn test_zero_based() {
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--zero-based"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1372\t1384\t"));
+
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-bed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, ">q1\nATGCATGCATGC\n").unwrap();
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--zero-based"]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("query_id\tref_start_0based\tref_end_exclusive\t"));
+    assert!(lines[1].starts_with("q1\t1372\t1384\t"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the conventions of --coords across the output formats
+#[test]
+fn test_coords() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--coords",
+        "0-half-open",
+        "--query-coordinates",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout, "1372\t1384\t75\tfalse\t0\t12\n");
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--coords",
+        "1-inclusive",
+        "--format",
+        "bed",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stdout.is_empty());
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--coords",
+        "0-half-open",
+        "--format",
+        "lanl",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].contains("\tHXB2 start (0-based)\tHXB2 end (exclusive)\t"));
+    assert!(lines[1].starts_with("1\tgag\t1372\t1384\t0\t12\t"));
+
+    for format in ["sam", "vcf", "gff3", "pretty", "ndjson"] {
+        let (_, stderr, exit_code) =
+            run_virust_locator(&["-q", "ATGCATGCATGC", "--zero-based", "--format", format]);
+        assert_eq!(exit_code, 1);
+        assert!(stderr.contains(&format!(
+            "--format {} always reports 1-inclusive coordinates, not 0-half-open",
+            format
+        )));
+    }
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--coords",
+        "0-half-open",
+        "--relative-to",
+        "gag",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--relative-to requires 1-inclusive coordinates"));
+}
+
+/// Test gzip- and bgzip-compressed files with --input
+#[test]
+fn test_compressed_input() {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-gz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let gz = dir.join("reads.fasta.gz");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b">q1\nATGCATGCATGC\n").unwrap();
+    std::fs::write(&gz, encoder.finish().unwrap()).unwrap();
+    // Two gzip members, as written by bgzip.
+    let bgz = dir.join("reads.fastq.bgz");
+    let mut data = Vec::new();
+    for member in [&b"@r1\nATGCATGCATGC\n+\n"[..], b"IIIIIIIIIIII\n"] {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(member).unwrap();
+        data.extend(encoder.finish().unwrap());
+    }
+    std::fs::write(&bgz, data).unwrap();
+
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["--input", gz.to_str().unwrap(), bgz.to_str().unwrap()]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("q1\t1373\t1384\t"));
+    assert!(lines[2].starts_with("r1\t1373\t1384\t"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the coverage columns and --min-query-coverage
+#[test]
+fn test_min_query_coverage() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-cov-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    // q2 is q1 with 10 bases inserted in the middle: 80 of its 90 bases cover HXB2 1001-1080.
+    let left = "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAAC";
+    let right = "CCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC";
+    std::fs::write(
+        &input,
+        format!(">q1\n{left}{right}\n>q2\n{left}TTTTTTTTTT{right}\n"),
+    )
+    .unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&["--input", input.to_str().unwrap()]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0].split('\t').nth(5), Some("query_coverage"));
+    assert_eq!(lines[1].split('\t').nth(5), Some("1"));
+    assert!(lines[2].split('\t').nth(5).unwrap().starts_with("0.888"));
+    assert_eq!(lines[2].split('\t').nth(6), Some("80"));
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--min-query-coverage",
+        "0.9",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "Only q1 should be kept: {}", stdout);
+    assert!(lines[1].starts_with("q1\t"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--min-query-coverage",
+        "90",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("between 0 and 1"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the run summary of --summary and --summary-file
+#[test]
+fn test_summary() {
+    let dir =
+        std::env::temp_dir().join(format!("virust-locator-it-summary-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    // q1 is HXB2 1001-1080 and q2 its first 40 bases, in gag.
+    let left = "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAAC";
+    let right = "CCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC";
+    std::fs::write(&input, format!(">q1\n{left}{right}\n>q2\n{left}\n")).unwrap();
+
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--summary"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.lines().count(), 3);
+    assert!(stderr.contains("section\tname\tvalue\n"));
+    assert!(stderr.contains("\ncounts\tfound\t2\n"));
+    assert!(stderr.contains("\nidentity\tmedian\t100\n"));
+    assert!(stderr.contains("\ngene\tgag\t2\n"));
+    assert!(stderr.contains("\ngene\tpol\t0\n"));
+    assert!(stderr.contains("\ncoverage\t1001-1100\t1.2\n"));
+
+    let summary = dir.join("summary.json");
+    let (_, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--summary-file",
+        summary.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0);
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&summary).unwrap()).unwrap();
+    assert_eq!(summary["counts"]["records"], 2);
+    assert_eq!(summary["mean_identity"], 100.0);
+    assert_eq!(summary["coverage"][10]["start"], 1001);
+    assert_eq!(summary["coverage"][10]["mean_depth"], 1.2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the per-base depth of --depth-track
+#[test]
+fn test_depth_track() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-depth-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    // q1 is HXB2 1001-1080 and q2 its first 40 bases.
+    let left = "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAAC";
+    let right = "CCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC";
+    std::fs::write(&input, format!(">q1\n{left}{right}\n>q2\n{left}\n")).unwrap();
+
+    let track = dir.join("depth.bedgraph");
+    let (_, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--depth-track",
+        track.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        std::fs::read_to_string(&track).unwrap(),
+        "HXB2\t0\t1000\t0\nHXB2\t1000\t1040\t2\nHXB2\t1040\t1080\t1\nHXB2\t1080\t9719\t0\n"
+    );
+
+    let track = dir.join("depth.tsv");
+    let (_, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--depth-track",
+        track.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0);
+    let depths = std::fs::read_to_string(&track).unwrap();
+    let lines: Vec<&str> = depths.lines().collect();
+    assert_eq!(lines.len(), 9719);
+    assert_eq!(lines[1000], "HXB2\t1001\t2");
+    assert_eq!(lines[1079], "HXB2\t1080\t1");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the results filtered by --min-identity and --min-aln-length
+#[test]
+fn test_min_identity_and_aln_length() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-minid-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    // q1 is HXB2 1001-1080, q2 its first 30 bases with a substitution.
+    std::fs::write(
+        &input,
+        ">q1\nGATCAGAAGAACTTAGATCATTATATAATACAGTAGCAACCCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACC\n>q2\nGATCAGAAGAACTTACATCATTATATAATA\n",
+    )
+    .unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--min-identity",
+        "99",
+        "--min-aln-length",
+        "50",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("q1\t1001\t1080\t100\t"));
+    assert!(lines[2].starts_with("q2\t\t\t"));
+    assert!(lines[2].contains("the percent identity is below --min-identity"));
+
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "GATCAGAAGAACTTAGATCATTATATAATA",
+        "--min-aln-length",
+        "50",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stdout.contains("NOT_FOUND"));
+    assert!(stderr.contains("the alignment is shorter than --min-aln-length"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "GATCAGAAGAACTTAGATCATTATATAATA",
+        "--min-identity",
+        "101",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("between 0 and 100"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test gene-relative positions with --relative-to
+#[test]
+fn test_relative_to() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--relative-to", "gag"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1373\t1384\t"));
+    assert!(stdout.trim_end().ends_with("\t584\t595"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--relative-to", "vpx"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Gene vpx not found for HXB2"));
+}
+
+/// Test the reason reported for a query that cannot be located
+#[test]
+fn test_not_located() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&["-q", "NNNNNNNN"]);
+    assert_eq!(exit_code, 1);
+    assert_eq!(stdout, "NOT_FOUND\t\t\t\t\t\n");
+    assert!(stderr.contains("query 1: Locator not found: no query residue matches the reference"));
+}
+
+/// Test that the queries after one not located are reported, unless --fail-fast is set
+#[test]
+fn test_fail_fast() {
+    let queries = ["-q", "ATGCATGCATGC", "NNNNNNNN", "ATGCATGCATGC"];
+    let (stdout, _, exit_code) = run_virust_locator(&queries);
+    assert_eq!(exit_code, 1);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("1373\t1384\t"));
+    assert!(lines[1].starts_with("NOT_FOUND\t"));
+    assert!(lines[2].starts_with("1373\t1384\t"));
+
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&[&queries[..], &["--fail-fast"]].concat());
+    assert_eq!(exit_code, 1);
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stderr.contains("query 2: Locator not found"));
+}
+
+/// Test that soft-masked queries are located like upper-case ones
+#[test]
+fn test_soft_mask() {
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", "atgcATGCATGC", "--soft-mask"]);
+    assert_eq!(exit_code, 0);
+    let fields: Vec<&str> = stdout.trim().split('\t').collect();
+    assert_eq!(&fields[..2], ["1373", "1384"]);
+    assert_eq!(fields[4], "ATGCATGCATGC");
+}
+
+/// Test that irregular input files are accepted, unless --strict-parse is set
+#[test]
+fn test_strict_parse() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-strict-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, ">q1\r\nATGCAT\r\nGCATGC\r\n\r\n").unwrap();
+
+    let (stdout, stderr, exit_code) = run_virust_locator(&["--input", input.to_str().unwrap()]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.lines().nth(1).unwrap().starts_with("q1\t1373\t"));
+    assert!(stderr.contains("CRLF line endings"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--strict-parse"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("not allowed with --strict-parse"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test --verify and its tolerance options
+#[test]
+fn test_verify() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--verify"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1373\t1384\t"));
+    assert!(!stderr.contains("algorithms disagree"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--verify-tolerance", "3"]);
+    assert_eq!(exit_code, 2, "--verify-tolerance requires --verify");
+    assert!(stderr.contains("--verify"));
+}
+
+/// Test reference aliases and the suggestion for a misspelled reference
+#[test]
+fn test_reference_aliases() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--reference", "k03455"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1373\t1384\t"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--reference", "SIVmm293"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("did you mean 'SIVmm239'?"));
+}
+
+/// Test --type-query auto on a protein and a nucleotide query
+#[test]
+fn test_type_query_auto() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "MGARASVLSGGELDRWEKIR",
+        "ATGCATGCATGC",
+        "--type-query",
+        "auto",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("1\t20\t100\t"));
+    assert!(lines[1].starts_with("1373\t1384\t"));
+    assert!(stderr.contains("query 1: inferred type aa"));
+    assert!(stderr.contains("query 2: inferred type nt"));
+}
+
+/// Test --algorithm auto and the validation of the algorithm
+#[test]
+fn test_algorithm_auto() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--algorithm", "auto"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1373\t1384\t"));
+    assert!(stderr.contains("query 1: chose algorithm 1"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--algorithm", "fast"]);
+    assert_eq!(exit_code, 2);
+    assert!(stderr.contains("expected 1, 2, 3 or auto"));
+}
+
+/// Test stops in amino acid queries
+#[test]
+fn test_stop_in_aa_query() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR*", "-t", "aa"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1\t20\t100\t"));
+    assert!(stderr.contains("query 1: stop *x1 at query 21-21"));
+}
+
+/// Test --no-align-strings, --delimiter and --format
+#[test]
+fn test_delimited_output() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--no-align-strings",
+        "--delimiter",
+        ",",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.trim_end(), "1373,1384,75,false");
+
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--delimiter", "tab"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("1373\t1384\t75\tfalse\tATGCATGCATGC\t"));
+
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--format", "csv"]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ref_start,ref_end,percent_identity,indel,query_aligned_string,ref_aligned_string"
+    );
+    assert!(lines[1].starts_with("1373,1384,75,false,"));
+
+    let (_, _, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--format", "csv", "--delimiter", ";"]);
+    assert_eq!(exit_code, 2, "--format conflicts with --delimiter");
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--delimiter", "ab"]);
+    assert_eq!(exit_code, 2);
+    assert!(stderr.contains("invalid delimiter 'ab'"));
+}
+
+/// Test --min-base-quality on FASTQ input
+#[test]
+fn test_min_base_quality() {
+    let dir =
+        std::env::temp_dir().join(format!("virust-locator-it-quality-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fastq");
+    std::fs::write(&input, "@read1\nATGCATGCATGC\n+\nIIIIIIIIII##\n").unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--min-base-quality",
+        "20",
+    ]);
+    assert_eq!(exit_code, 0);
+    let row: Vec<&str> = stdout.lines().nth(1).unwrap().split('\t').collect();
+    assert!(row[7].ends_with("NN"), "masked bases: {}", row[7]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test --both-strands with the reverse complement of HXB2 790-849
+#[test]
+fn test_both_strands() {
+    let reverse = "CCGAATTTTTTCCCATCGATCTAATTCTCCCCCGCTTAATACTGACGCTCTCGCACCCAT";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        reverse,
+        "--both-strands",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ref_start\tref_end\tpercent_identity\tindel\tstrand"
+    );
+    assert_eq!(lines[1], "790\t849\t100\tfalse\t-");
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSG", "-t", "aa", "--both-strands"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--both-strands requires nucleotide queries"));
+}
+
+/// Test --iupac-aware with ambiguity codes in HXB2 790-849
+#[test]
+fn test_iupac_aware() {
+    let query = "ATRGGTGCGAGAGCGTCAGTWTTAAGCGGGGGAGAATTAGATNGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", query, "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t849\t95\tfalse"));
+
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--iupac-aware", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t849\t100\tfalse"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSG", "-t", "aa", "--iupac-aware"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--iupac-aware requires nucleotide queries"));
+}
+
+/// Test --annotate with the start of HXB2 gag
+#[test]
+fn test_annotate() {
+    let gag = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", gag, "--annotate", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        stdout.trim_end(),
+        "790\t849\t100\tfalse\tgag 790-849 (partial)"
+    );
+
+    // Amino acid positions are on the concatenated proteins.
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "MGARASVLSGGELDRWEKIR",
+        "-t",
+        "aa",
+        "--annotate",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.trim_end(), "1\t20\t100\tfalse\tgag 1-20 (partial)");
+}
+
+/// Test --max-hits with a query from the 3' LTR of HXB2, which also matches the 5' LTR
+#[test]
+fn test_max_hits() {
+    let ltr = "TGGAAGGGCTAATTCACTCCCAACGAAGACAAGATATCCTTGATCTGTGGATCTACCACACACAAGGCTACTTCCCTGATTAG";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        ltr,
+        "--max-hits",
+        "2",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ref_start\tref_end\tpercent_identity\tindel\tother_hits"
+    );
+    let fields: Vec<&str> = lines[1].split('\t').collect();
+    assert_eq!(fields.len(), 5);
+    let mut hits = vec![format!("{}-{}", fields[0], fields[1])];
+    hits.push(fields[4].split(' ').next().unwrap().to_string());
+    hits.sort();
+    assert_eq!(hits, ["1-83", "9086-9168"]);
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", ltr, "--max-hits", "0"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("Maximum number of hits must be at least 1"));
+}
+
+/// Test --split-identity with HXB2 1001-1200 joined to 4001-4200, as across a large deletion
+#[test]
+fn test_split_identity() {
+    let query = "GATCAGAAGAACTTAGATCATTATATAATACAGTAGCAACCCTCTATTGTGTGCATCAAAGGATAGAGATAAAAGACACCAAGGAAGCTTTAGACAAGATAGAGGAAGAGCAAAACAAAAGTAAGAAAAAAGCACAGCAAGCAGCAGCTGACACAGGACACAGCAATCAGGTCAGCCAAAATTACCCTATAGTGCAGAACAGCTTTGCAGGATTCGGGATTAGAAGTAAACATAGTAACAGACTCACAATATGCATTAGGAATCATTCAAGCACAACCAGATCAAAGTGAATCAGAGTTAGTCAATCAAATAATAGAGCAGTTAATAAAAAAGGAAAAGGTCTATCTGGCATGGGTACCAGCACACAAAGGAATTGGAGGAAATGAACAAGTAGATAAAT";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--split-identity", "90", "--format", "ndjson"]);
+    assert_eq!(exit_code, 0);
+    let result: serde_json::Value = serde_json::from_str(stdout.trim_end()).unwrap();
+    assert_eq!(result["ref_start"], 1001);
+    assert_eq!(result["ref_end"], 4200);
+    assert_eq!(result["split"], true);
+    assert_eq!(
+        result["segments"],
+        serde_json::json!([{"start": 1001, "end": 1200}, {"start": 4001, "end": 4200}])
+    );
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", query, "--split-identity", "101"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("Split identity must be between 0 and 100"));
+}
+
+/// Test --nt-coordinates with the start of HXB2 Env
+#[test]
+fn test_nt_coordinates() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "MRVKEKYQHLWRWGWRWGTM",
+        "-t",
+        "aa",
+        "--nt-coordinates",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.trim_end(), "2091\t2110\t100\tfalse\t6225\t6284");
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--nt-coordinates"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--nt-coordinates requires amino acid queries"));
+}
+
+/// Test --query-coordinates with a His tag clipped before the start of Env
+#[test]
+fn test_query_coordinates() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "HHHHHHMRVKEKYQHLWRWGWRWGTM",
+        "-t",
+        "aa",
+        "--gene",
+        "env",
+        "--query-coordinates",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tquery_start\tquery_end"));
+    assert!(lines[1].ends_with("\ttrue\t7\t26"));
+}
+
+/// Test the score and edit distance columns of --score
+#[test]
+fn test_score() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--score", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout.trim_end(), "790\t849\t95\ttrue\t49\t3");
+
+    // A larger gap open penalty lowers the score of the deletion only.
+    let (stdout, _, _) = run_virust_locator(&[
+        "-q",
+        query,
+        "--score",
+        "--gap-open",
+        "-20",
+        "--no-align-strings",
+    ]);
+    assert!(stdout.trim_end().ends_with("\t34\t3"));
+}
+
+/// Test --matrix with a divergent protein query
+#[test]
+fn test_matrix() {
+    let query = "DATYSDRSMDQVYNYSTYLE";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "-t", "aa", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("2701\t2720\t"));
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "-t",
+        "aa",
+        "--no-align-strings",
+        "--matrix",
+        "identity",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert!(!stdout.starts_with("2701\t"));
+
+    let (_, _, exit_code) = run_virust_locator(&["-q", query, "-t", "aa", "--matrix", "pam1"]);
+    assert_eq!(exit_code, 2);
+}
+
+/// Test --annotate-dr on a query with the RT M184V mutation
+#[test]
+fn test_annotate_dr() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "TATCAATACGTGGATGATTTGTATGTAGGA", "--annotate-dr"]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("query_id\tprotein\tposition"));
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[2], "1\tRT\t184\tNRTI\t3099\tM\tV\tM184V");
+    assert!(lines[1].ends_with("\tY\tY\t"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "TATCAATACGTGGATGATTTGTATGTAGGA",
+        "--annotate-dr",
+        "--reference",
+        "SIVmm239",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--annotate-dr requires the HXB2 reference"));
+}
+
+/// Test --gap-open and the validation of the alignment scores
+#[test]
+fn test_gap_penalties() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", query]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t849\t95\ttrue\t"));
+
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", query, "--gap-open", "-30"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t846\t"));
+    assert!(stdout.contains("\tfalse\t"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", query, "--mismatch", "1"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("must not be positive"));
+}
+
+/// Test --format sam for a located and a not located query
+#[test]
+fn test_sam_format() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "NNNNNNNN", "--format", "sam"]);
+    assert_eq!(exit_code, 1);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "@HD\tVN:1.6\tSO:unsorted");
+    assert_eq!(lines[1], "@SQ\tSN:HXB2\tLN:9719");
+    assert!(lines[2].starts_with("@PG\tID:virust-locator"));
+    assert_eq!(
+        lines[3],
+        "1\t0\tHXB2\t1373\t255\t12M\t*\t0\t0\tATGCATGCATGC\t*\tNM:i:3"
+    );
+    assert_eq!(lines[4], "2\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*");
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR", "-t", "aa", "--format", "sam"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format sam requires nucleotide queries"));
+}
+
+/// Test --format lanl for a located and a not located query
+#[test]
+fn test_lanl_format() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", query, "NNNNNNNN", "--format", "lanl"]);
+    assert_eq!(exit_code, 1);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Query\tRegion\tHXB2 start\tHXB2 end\tQuery start\tQuery end\tProtein start\tProtein end\tPercent identity"
+    );
+    assert_eq!(lines[1], "1\tgag\t790\t849\t1\t60\t1\t20\t100");
+    assert_eq!(lines[2], "2\tNOT_FOUND\t\t\t\t\t\t\t");
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR", "-t", "aa", "--format", "lanl"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format lanl requires nucleotide queries"));
+}
+
+/// Test --format vcf for a query with a deletion and a query that is not located
+#[test]
+fn test_vcf_format() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&["-q", query, "NNNNNNNN", "--format", "vcf"]);
+    assert_eq!(exit_code, 1);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "##fileformat=VCFv4.3");
+    assert_eq!(
+        lines[5],
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t1\t2"
+    );
+    assert_eq!(lines[6], "HXB2\t829\t.\tGATC\tG\t.\t.\t.\tGT\t1\t.");
+    assert_eq!(lines.len(), 7);
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR", "-t", "aa", "--format", "vcf"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format vcf requires nucleotide queries"));
+}
+
+/// Test --format gff3 for a query overlapping two genes and a query that is not located
+#[test]
+fn test_gff3_format() {
+    let query = "GACAGGCTAATTTTTTAGGGAAGATCTGGCCTTCCTACAAGGGAAGGCCAGGGAATTTTCTTCAGAGCAGACCAGAGCCAACAGCCCCACCAGAAGAGAGC";
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", query, "NNNNNNNN", "--format", "gff3"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("query 2: Locator not found"));
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "##gff-version 3");
+    assert_eq!(lines[1], "##sequence-region HXB2 1 9719");
+    assert_eq!(
+        lines[2],
+        "HXB2\tvirust-locator\tmatch\t2075\t2175\t100\t+\t.\tID=1;Name=1;Target=1 1 101"
+    );
+    assert!(lines[4].starts_with("HXB2\tvirust-locator\tmatch_part\t2085\t2175\t"));
+    assert!(lines[4].ends_with("ID=1.pol;Parent=1;Name=pol;Target=1 11 101"));
+    assert_eq!(lines.len(), 5);
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR", "-t", "aa", "--format", "gff3"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format gff3 requires nucleotide queries"));
+}
+
+/// Test --format bed for --query and --input
+#[test]
+fn test_bed_format() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "NNNNNNNN", "--format", "bed"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("query 2: Locator not found"));
+    assert_eq!(stdout, "HXB2\t1372\t1384\t1\t75\t+\n");
+
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-bed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(
+        &input,
+        ">q1\nATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG\n>q2\nATGCXYZ\n",
+    )
+    .unwrap();
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--format", "bed"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout, "HXB2\t789\t849\tq1\t100\t+\n");
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASVLSGGELDRWEKIR", "-t", "aa", "--format", "bed"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format bed requires nucleotide queries"));
+}
+
+/// Test --format pretty for a located query and a query that is not located
+#[test]
+fn test_pretty_format() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "NNNNNNNN", "--format", "pretty"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("query 2: Locator not found"));
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], ">1 HXB2 1373-1384 (+), 75% identity");
+    assert_eq!(lines[2], "HXB2  1373 AAGCAGCCATGC 1384");
+    assert_eq!(lines[3], "           | |||  |||||");
+    assert_eq!(lines[4], "Query    1 ATGCATGCATGC 12");
+    assert!(lines[6].starts_with(">2 not located: Locator not found"));
+}
+
+/// Test --format ndjson for --query and --input
+#[test]
+fn test_ndjson_format() {
+    let (stdout, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "NNNNNNNN", "--format", "ndjson"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("query 2: Locator not found"));
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["id"], "1");
+    assert_eq!(lines[0]["ref_start"], 1373);
+    assert_eq!(lines[1]["found"], false);
+    assert_eq!(lines[1]["not_located"]["reason"], "no_matches");
+
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-ndjson-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, ">q1\nATGCATGCATGC\n>q2\nATGCXYZ\n").unwrap();
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["--input", input.to_str().unwrap(), "--format", "ndjson"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0);
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["id"], "q1");
+    assert_eq!(lines[0]["ref_end"], 1384);
+    assert!(
+        lines[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid nucleotide sequence")
+    );
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--verify", "--format", "ndjson"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--format ndjson is not supported"));
+}
+
+/// Test the cigar column of --cigar
+#[test]
+fn test_cigar() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--cigar",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ref_start\tref_end\tpercent_identity\tindel\tcigar"
+    );
+    assert_eq!(lines[1], "790\t849\t95\ttrue\t40M3D17M");
+}
+
+/// Test the percent identity and the identity_mode column of --identity-mode
+#[test]
+fn test_identity_mode() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--identity-mode",
+        "ungapped",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ref_start\tref_end\tpercent_identity\tindel\tidentity_mode"
+    );
+    assert_eq!(lines[1], "790\t849\t100\ttrue\tungapped");
+
+    // The records of --input are located with the same definition.
+    let dir =
+        std::env::temp_dir().join(format!("virust-locator-it-identity-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, format!(">q1\n{}\n", query)).unwrap();
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--identity-mode",
+        "ungapped",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tidentity_mode\terror"));
+    assert!(lines[1].starts_with("q1\t790\t849\t100\ttrue\t"));
+    assert!(lines[1].ends_with("\tungapped\t"));
+}
+
+/// Test the frameshifts and premature stops of `--defects`
+#[test]
+fn test_defects() {
+    let query = "ATGGGTGCGAGAGCGCAGTATTAAGCGGGGGAGAATTAGGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--defects",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tdefects"));
+    assert!(
+        lines[1]
+            .ends_with("\tgag frameshift at 805 (codon 6); gag premature stop at 812 (codon 8)")
+    );
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "MGARASVLSG", "-t", "aa", "--defects"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--defects requires --type-query nt"));
+}
+
+/// Test the annotation columns of `--defects`, `--env-regions`, `--glycosylation` and
+/// `--name-insertions` with `--input`
+#[test]
+fn test_annotations_input() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-ann-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    // HXB2 7080-7250, with 6 bases inserted in V3 after 7160.
+    std::fs::write(
+        &input,
+        ">q1\nGTACAGCTGAACACATCTGTAGAAATTAATTGTACAAGACCCAACAACAATACAAGAAAAAGAATCCGTATCCAGAGAGGAAATAATCCAGGGAGAGCATTTGTTACAATAGGAAAAATAGGAAATATGAGACAAGCACATTGTAACATTAGTAGAGCAAAATGGAATAACACTTTA\n",
+    )
+    .unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--defects",
+        "--env-regions",
+        "--glycosylation",
+        "--name-insertions",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tdefects\tenv_regions\tglycosylation_sites\tinsertions\terror"));
+    let fields: Vec<&str> = lines[1].split('\t').collect();
+    assert_eq!(fields[8], "");
+    assert!(fields[9].contains("V3 7110-7217 (query 31-144, length 114)"));
+    assert!(fields[10].contains("env N301 (NNT)"));
+    assert!(fields[11].contains("env:312ins"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test the gp120 regions of `--env-regions`
+#[test]
+fn test_env_regions() {
+    let query = "GTACAGCTGAACACATCTGTAGAAATTAATTGTACAAGACCCAACAACAATACAAGAAAAAGAATCCGTATCCAGAGAGGAAATAATCCAGGGAGAGCATTTGTTACAATAGGAAAAATAGGAAATATGAGACAAGCACATTGTAACATTAGTAGAGCAAAATGGAATAACACTTTA";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--env-regions",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tenv_regions"));
+    assert!(lines[1].contains("; V3 7110-7217 (query 31-144, length 114); "));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "-r", "SIVmm239", "--env-regions"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--env-regions requires the HXB2 reference"));
+}
+
+/// Test the LTR features of `--ltr-features`
+#[test]
+fn test_ltr_features() {
+    // HXB2 430-490, in either LTR.
+    let query = "AAGCAGCTGCTTTTTGCCTGTACTGGGTCTCTCTGGTTAGACCAGATCTGAGCCTGGGAGC";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--ltr-features",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tltr_features\tltr_ambiguous"));
+    assert!(lines[1].contains("5LTR TAR 455-490 (partial); "));
+    assert!(lines[1].contains("; 3LTR TAR 9540-9575 (partial)\ttrue"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "MGARASV", "-t", "aa", "--ltr-features"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--ltr-features requires --type-query nt"));
+}
+
+/// Test the amplicons of `--amplicon-scheme`
+#[test]
+fn test_amplicon_scheme() {
+    let dir = std::env::temp_dir().join(format!(
+        "virust-locator-it-amplicons-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scheme = dir.join("scheme.bed");
+    std::fs::write(
+        &scheme,
+        "K03455\t699\t720\tHIV_1_LEFT\nK03455\t880\t900\tHIV_1_RIGHT\n\
+         K03455\t849\t870\tHIV_2_LEFT\nK03455\t1080\t1100\tHIV_2_RIGHT\n",
+    )
+    .unwrap();
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG",
+        "--amplicon-scheme",
+        scheme.to_str().unwrap(),
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0, "{}", stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tamplicons"));
+    assert!(lines[1].ends_with("\tHIV_1 700-900 (29.9%)"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "MGARASV",
+        "-t",
+        "aa",
+        "--amplicon-scheme",
+        "scheme.bed",
+    ]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--amplicon-scheme requires --type-query nt"));
+}
+
+/// Test the alignment to a slice of the reference of `--region`
+#[test]
+fn test_region() {
+    // HXB2 430-490, in either LTR, placed in the 3' LTR.
+    let query = "AAGCAGCTGCTTTTTGCCTGTACTGGGTCTCTCTGGTTAGACCAGATCTGAGCCTGGGAGC";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--region", "3LTR", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("9515\t9575\t100\t"));
+
+    // HXB2 790-849, on an interval.
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--region", "700-2292", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t849\t100\t"));
+
+    // The same query is longer than 800-840: it overhangs the region rather than wrapping around
+    // it.
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", query, "--region", "800-840", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("800\t840\t"), "{}", stdout);
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", query, "--region", "2253-10000"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--region 2253-10000 must be within the reference, 1-9719"));
+}
+
+/// Test the packaging signal, splice donor and RRE of `--cis-elements`
+#[test]
+fn test_cis_elements() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "AGGGGCGGCGACTGGTGAGTACGCCAAAAAT",
+        "--cis-elements",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tcis_elements"));
+    assert!(lines[1].ends_with("\tpsi 730-760 (partial); MSD 744-745 (intact)"));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "-r", "SIVmm239", "--cis-elements"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--cis-elements requires the HXB2 reference"));
+}
+
+/// Test the N-linked glycosylation sites of `--glycosylation`
+#[test]
+fn test_glycosylation() {
+    let query = "GTACAGCTGAACACATCTGTAGAAATTAATTGTACAAGACCCAACAACAATACAAGAAAAAGAATCCGTATCCAGAGAGGAAACGGTACTCCAGGGAGAGCATTTGTTACAATAGGAAAAATAGGAAATATGAGACAAGCACATTGTAACATTAGTAGAGCAAAATGGAATAACACTTTA";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--glycosylation",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tglycosylation_sites"));
+    assert!(lines[1].contains("; env N312a (NGT, gained); "));
+
+    // Env 290-340 as amino acids, with the site at 301 lost.
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "TSVEINCTRPNNNNRKRIRIQRGPGRAFVTIGKIGNMRQAHCNISRAKWNN",
+        "-t",
+        "aa",
+        "--gene",
+        "env",
+        "--glycosylation",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.contains("env N295 (NCT); env N301 (NNT, lost); env N332 (NIS)"));
+}
+
+/// Test the insertion names of `--name-insertions`
+#[test]
+fn test_name_insertions() {
+    let query = "GGGCCTGAAAATCCATACAATACTCCAGTATTTGCCATAAAGAAAAAAGACAGTACTAGTAGTAAATGGAGAAAATTAGTAGATTTCAGAGAACTTAATAAGAGAAC";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--name-insertions",
+        "--no-align-strings",
+        "--format",
+        "tsv",
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("\tinsertions"));
+    assert!(lines[1].ends_with("\t2755insTAGTAG; pol:224insSS; RT:69insSS"));
+
+    // Without the flag, the insertions are only flagged in the indel column.
+    let (stdout, _, _) = run_virust_locator(&["-q", query, "--no-align-strings"]);
+    assert!(!stdout.contains("RT:69insSS"));
+}
+
+/// Test the intactness subcommand
+#[test]
+fn test_intactness() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "intactness",
+        "-q",
+        "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        stdout,
+        "query_id\tverdict\treasons\n1\tdefective\tincomplete genome 790-849\n"
+    );
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["intactness", "-q", "ATGCATGCATGC", "-r", "SIVmm239"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--intactness requires the HXB2 reference"));
+}
+
+/// Test the subtype subcommand on --subtype-panel, which is required
+#[test]
+fn test_subtype() {
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (_, stderr, exit_code) = run_virust_locator(&["subtype", "-q", query]);
+    assert_eq!(exit_code, 2);
+    assert!(stderr.contains("--subtype-panel"));
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", query, "--subtype"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--subtype requires --subtype-panel"));
+
+    let dir =
+        std::env::temp_dir().join(format!("virust-locator-it-subtype-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let panel = dir.join("panel.fasta");
+    std::fs::write(
+        &panel,
+        format!(
+            ">B.FR.83.HXB2\n{}\n>C.XX.00.TEST\n{}\n",
+            query,
+            query.replace("GCG", "GAG")
+        ),
+    )
+    .unwrap();
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "subtype",
+        "-q",
+        &query.replace("GCG", "GAG"),
+        "--subtype-panel",
+        panel.to_str().unwrap(),
+    ]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "query_id\tsubtype\treference\tpercent_identity\tnext_subtype\tnext_identity\tmargin"
+    );
+    assert!(lines[1].starts_with("1\tC\tC.XX.00.TEST\t100.00\tB\t"));
+
+    let (_, stderr, exit_code) = run_virust_locator(&[
+        "subtype",
+        "-t",
+        "aa",
+        "-q",
+        "MGARASVLSGGELDRWEK",
+        "--subtype-panel",
+        panel.to_str().unwrap(),
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("--subtype requires --type-query nt"));
+}
+
+/// Test the binding sites of the `primers` subcommand
+#[test]
+fn test_primers() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "primers",
+        "-q",
+        "ATGGGTGCGAGAGCGTCRGT",
+        "ACTCACGCTCTCGCACCCAT",
+        "--max-primer-mismatches",
+        "1",
+    ]);
+    assert_eq!(exit_code, 0, "{}", stderr);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "primer_id\tref_start\tref_end\tstrand\tmismatches\tmismatch_positions",
+            "1\t790\t809\t+\t0\t",
+            "2\t790\t809\t-\t1\t4",
+        ]
+    );
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["primers", "-q", "ACGT", "--max-primer-mismatches", "4"]);
+    assert_ne!(exit_code, 0);
+    assert!(stderr.contains("Primer 1 has no more bases than --max-primer-mismatches (4)"));
+}
+
+/// Test the subcommands, and that the options without a subcommand still run `locate`
+#[test]
+fn test_subcommands() {
+    let flat = run_virust_locator(&["-q", "ATGCATGCATGC"]);
+    let locate = run_virust_locator(&["locate", "-q", "ATGCATGCATGC"]);
+    assert_eq!(locate, flat);
+
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["annotate", "-q", "ATGCATGCATGC", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout, "1373\t1384\t75\tfalse\tgag 1373-1384 (partial)\n");
+
+    let (stdout, _, exit_code) = run_virust_locator(&["list-references"]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "reference\ttype\tlength\taliases");
+    assert_eq!(
+        lines[1],
+        "HXB2\tnt\t9719\tHXB2CG,HIV-1_HXB2,K03455,K03455.1"
+    );
+
+    let (stdout, _, exit_code) = run_virust_locator(&["convert", "--gene", "pol", "2550", "2084"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        stdout,
+        "ref_position\tgene\tgene_position\n2550\tpol\t466\n2084\tpol\t-1\n"
+    );
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["convert", "--gene", "pol", "--from-gene", "466", "-1"]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.ends_with("2550\tpol\t466\n2084\tpol\t-1\n"));
+    let (_, stderr, exit_code) = run_virust_locator(&["convert", "--gene", "pol", "10000"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Position 10000 is outside HXB2"));
+
+    // The top-level options are those of `locate`, they conflict with the other subcommands.
+    let (_, _, exit_code) = run_virust_locator(&["-r", "HXB2", "list-references"]);
+    assert_eq!(exit_code, 2);
+
+    // The mode subcommands only accept the common options and their own.
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "annotate",
+        "-q",
+        "ATGCATGCATGC",
+        "--no-align-strings",
+        "--format",
+        "csv",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.lines().next().unwrap().ends_with(",regions"));
+    for args in [
+        &["primers", "-q", "ACGT", "--intactness"][..],
+        &["primers", "-q", "ACGT", "--format", "sam"],
+        &["intactness", "-q", "ACGT", "--max-primer-mismatches", "1"],
+        &["subtype", "-q", "ACGT", "--annotate-dr"],
+        &["annotate", "-q", "ACGT", "--subtype-panel", "panel.fasta"],
+    ] {
+        let (_, stderr, exit_code) = run_virust_locator(args);
+        assert_eq!(exit_code, 2, "{:?}", args);
+        assert!(stderr.contains("unexpected argument"), "{:?}", args);
+    }
+}
+
+/// Test the index subcommand and --algorithm 3 reading the prebuilt index
+#[test]
+fn test_index_subcommand() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-it-index-{}", std::process::id()));
+    let cache = dir.to_str().unwrap();
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["index", "--reference", "hxb2", "--index-cache", cache]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        stdout,
+        format!(
+            "{}\n{}\n",
+            dir.join("HXB2.nt.k11.idx").display(),
+            dir.join("HXB2.aa.k4.idx").display()
+        )
+    );
+
+    let query = "ATGGGTGCGAGAGCGTCAGTATTAAGCGGGGGAGAATTAGATCGATGGGAAAAAATTCGG";
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        query,
+        "--algorithm",
+        "3",
+        "--index-cache",
+        cache,
+        "--no-align-strings",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0);
+    assert!(stdout.starts_with("790\t849\t100\tfalse"));
+
+    // The records of --input write the index they build to --index-cache.
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("reads.fasta");
+    std::fs::write(&input, format!(">q1\n{}\n", query)).unwrap();
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "--input",
+        input.to_str().unwrap(),
+        "--algorithm",
+        "3",
+        "--index-cache",
+        cache,
+    ]);
+    let cached = dir.join("HXB2.nt.k11.idx").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit_code, 0);
+    assert!(stdout.lines().nth(1).unwrap().starts_with("q1\t790\t849\t"));
+    assert!(cached);
+
+    let (_, stderr, exit_code) = run_virust_locator(&["index", "--reference", "HXB3"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Reference genome must be"));
+}
+
+/// Test the codons of convert --codons
+#[test]
+fn test_convert_codons() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["convert", "--codons", "--gene", "RT", "--from-gene", "215"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(
+        stdout,
+        "ref_position\tgene\tcodon\tcodon_position\n\
+         3192\tRT\t215\t1\n3193\tRT\t215\t2\n3194\tRT\t215\t3\n"
+    );
+
+    let (stdout, _, exit_code) = run_virust_locator(&["convert", "--codons", "2253"]);
+    assert_eq!(exit_code, 0);
+    let lines: Vec<&str> = stdout.lines().skip(1).collect();
+    assert_eq!(
+        lines,
+        ["2253\tgag\t488\t3", "2253\tpol\t57\t1", "2253\tPR\t1\t1"]
+    );
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["convert", "--codons", "--gene", "RT", "--from-gene", "561"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Codon 561 is outside RT (1-560)"));
+}
+
+/// Test the protein coordinates of --gene
+#[test]
+fn test_gene() {
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "MRVKEKYQHLWRWGWRWGTM",
+        "-t",
+        "aa",
+        "--gene",
+        "env",
+        "--nt-coordinates",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout, "1\t20\t100\tfalse\t6225\t6284\n");
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--gene", "env"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--gene requires --type-query aa"));
+}
+
+/// Test --threads
+#[test]
+fn test_threads() {
+    let (stdout, _, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--threads", "1", "--no-align-strings"]);
+    assert_eq!(exit_code, 0);
+    assert_eq!(stdout, "1373\t1384\t75\tfalse\n");
+
+    let (_, stderr, exit_code) = run_virust_locator(&["-q", "ATGCATGCATGC", "--threads", "0"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("Number of threads must be at least 1"));
+}
+
+/// Test that --output writes the results to a file, and replaces it only when they are complete
+#[test]
+fn test_output_file() {
+    let dir = std::env::temp_dir().join(format!("virust-locator-output-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("result.csv");
+    let path = output.to_str().unwrap();
+
+    let (stdout, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "--output-format",
+        "csv",
+        "--no-align-strings",
+        "--output",
+        path,
+    ]);
+    assert_eq!(exit_code, 0);
+    assert!(stdout.is_empty());
+    let expected = "ref_start,ref_end,percent_identity,indel\n1373,1384,75,false\n";
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), expected);
+
+    // A run failing with --fail-fast leaves the previous results.
+    let (_, _, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC",
+        "-q",
+        "NNNNNNNNNNNN",
+        "--format",
+        "csv",
+        "--fail-fast",
+        "--output",
+        path,
+    ]);
+    assert_eq!(exit_code, 1);
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), expected);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that --query-name names the rows of the queries, including those not located
+#[test]
+fn test_query_name() {
+    let (stdout, stderr, exit_code) = run_virust_locator(&[
+        "-q",
+        "ATGCATGCATGC NNNNNNNNNNNN",
+        "--query-name",
+        "p01 p02",
+        "--format",
+        "tsv",
+        "--no-align-strings",
+    ]);
+    assert_eq!(exit_code, 1);
+    assert_eq!(
+        stdout,
+        "query_id\tref_start\tref_end\tpercent_identity\tindel\n\
+         p01\t1373\t1384\t75\tfalse\n\
+         p02\tNOT_FOUND\t\t\t\n"
+    );
+    assert!(stderr.contains("query p02: "));
+
+    let (_, stderr, exit_code) =
+        run_virust_locator(&["-q", "ATGCATGCATGC", "--query-name", "p01 p02"]);
+    assert_eq!(exit_code, 1);
+    assert!(stderr.contains("--query-name must name every query (2 names for 1 queries)"));
+}
+
 /// Test that binary exists and is executable
 #[test]
 fn test_binary_exists() {